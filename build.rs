@@ -0,0 +1,27 @@
+// Rebuild when migrations change so `sqlx::migrate!` embeds the latest set.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=migrations_sqlite");
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Generates the gRPC server from `proto/` with the vendored `protoc`, so building doesn't
+/// need one installed.
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    use std::path::PathBuf;
+
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform");
+    let well_known_types = protoc_bin_vendored::include_path().expect("no vendored protoc for this platform");
+    let mut config = tonic_prost_build::Config::new();
+    config.protoc_executable(protoc);
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_with_config(
+            config,
+            &[PathBuf::from("proto/indexer.proto")],
+            &[PathBuf::from("proto"), well_known_types],
+        )
+        .expect("failed to compile proto/indexer.proto");
+}
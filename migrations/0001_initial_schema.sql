@@ -0,0 +1,33 @@
+-- Table for storing NodeDevice accounts
+CREATE TABLE IF NOT EXISTS public.nodes (
+    pubkey TEXT PRIMARY KEY,
+    authority TEXT NOT NULL,
+    uri TEXT NOT NULL,
+    program_id TEXT,
+    data_hash BYTEA
+);
+
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS program_id TEXT;
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS data_hash BYTEA;
+CREATE INDEX IF NOT EXISTS nodes_program_id_idx ON public.nodes (program_id);
+
+-- Table for storing network stats
+CREATE TABLE IF NOT EXISTS public.network_stats (
+    id SERIAL PRIMARY KEY,
+    total_nodes BIGINT NOT NULL,
+    last_synced_at TIMESTAMPTZ,
+    last_indexed_slot BIGINT
+);
+
+ALTER TABLE public.network_stats ADD COLUMN IF NOT EXISTS last_synced_at TIMESTAMPTZ;
+ALTER TABLE public.network_stats ADD COLUMN IF NOT EXISTS last_indexed_slot BIGINT;
+
+-- Dead-letter table for accounts that could not be decoded
+CREATE TABLE IF NOT EXISTS public.decode_failures (
+    pubkey TEXT PRIMARY KEY,
+    program_id TEXT NOT NULL,
+    raw_data BYTEA NOT NULL,
+    error TEXT NOT NULL,
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+-- Indexes backing lookups and filters on node authority and URI
+CREATE INDEX IF NOT EXISTS nodes_authority_idx ON public.nodes (authority);
+CREATE INDEX IF NOT EXISTS nodes_uri_idx ON public.nodes (uri);
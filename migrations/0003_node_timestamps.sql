@@ -0,0 +1,6 @@
+-- Per-node freshness tracking maintained by the sync loop
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS last_seen_slot BIGINT;
+
+CREATE INDEX IF NOT EXISTS nodes_updated_at_idx ON public.nodes (updated_at);
@@ -0,0 +1,14 @@
+-- Audit trail of every node change observed by the sync loop
+CREATE TABLE IF NOT EXISTS public.nodes_history (
+    id BIGSERIAL PRIMARY KEY,
+    pubkey TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    old_authority TEXT,
+    new_authority TEXT,
+    old_uri TEXT,
+    new_uri TEXT,
+    slot BIGINT,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS nodes_history_pubkey_idx ON public.nodes_history (pubkey, id);
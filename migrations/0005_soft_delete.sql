@@ -0,0 +1,4 @@
+-- Soft-delete support: pruned nodes keep their row with a deletion timestamp
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS nodes_live_idx ON public.nodes (pubkey) WHERE deleted_at IS NULL;
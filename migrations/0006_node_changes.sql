@@ -0,0 +1,11 @@
+-- Append-only change log consumed incrementally through GET /changes?since=<cursor>
+CREATE TABLE IF NOT EXISTS public.node_changes (
+    id BIGSERIAL PRIMARY KEY,
+    pubkey TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    authority TEXT,
+    uri TEXT,
+    program_id TEXT,
+    slot BIGINT,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
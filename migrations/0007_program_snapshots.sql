@@ -0,0 +1,7 @@
+-- Slot context of the latest getProgramAccounts snapshot reconciled for each program
+CREATE TABLE IF NOT EXISTS public.program_snapshots (
+    program_id TEXT PRIMARY KEY,
+    snapshot_slot BIGINT NOT NULL,
+    account_count BIGINT NOT NULL,
+    synced_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,11 @@
+-- Every program account the loaded Anchor IDL can decode, stored as JSON
+CREATE TABLE IF NOT EXISTS public.accounts (
+    pubkey TEXT PRIMARY KEY,
+    program_id TEXT NOT NULL,
+    account_type TEXT NOT NULL,
+    data JSONB NOT NULL,
+    slot BIGINT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS accounts_program_type_idx ON public.accounts (program_id, account_type);
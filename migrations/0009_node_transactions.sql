@@ -0,0 +1,17 @@
+-- Transactions that touched each NodeDevice account, from getSignaturesForAddress
+CREATE TABLE IF NOT EXISTS public.node_transactions (
+    pubkey TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    program_id TEXT,
+    slot BIGINT NOT NULL,
+    block_time TIMESTAMPTZ,
+    fee_payer TEXT NOT NULL,
+    signers TEXT[] NOT NULL,
+    instructions TEXT[] NOT NULL,
+    succeeded BOOLEAN NOT NULL,
+    error TEXT,
+    indexed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (pubkey, signature)
+);
+
+CREATE INDEX IF NOT EXISTS node_transactions_pubkey_slot_idx ON public.node_transactions (pubkey, slot DESC);
@@ -0,0 +1,15 @@
+-- Anchor events emitted by the program, parsed from transaction logs
+CREATE TABLE IF NOT EXISTS public.program_events (
+    id BIGSERIAL PRIMARY KEY,
+    program_id TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    event_index INTEGER NOT NULL,
+    slot BIGINT NOT NULL,
+    name TEXT NOT NULL,
+    data JSONB,
+    raw_data BYTEA NOT NULL,
+    observed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (signature, program_id, event_index)
+);
+
+CREATE INDEX IF NOT EXISTS program_events_program_name_idx ON public.program_events (program_id, name, id);
@@ -0,0 +1,12 @@
+-- Latest HTTP probe of each node's URI, written by the liveness prober
+CREATE TABLE IF NOT EXISTS public.node_liveness (
+    pubkey TEXT PRIMARY KEY REFERENCES public.nodes (pubkey) ON DELETE CASCADE,
+    -- URI that was probed; a node whose URI changed since counts as unprobed
+    uri TEXT NOT NULL,
+    online BOOLEAN NOT NULL,
+    status_code INTEGER,
+    latency_ms INTEGER,
+    error TEXT,
+    checked_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_online TIMESTAMPTZ
+);
@@ -0,0 +1,10 @@
+-- URI probe outcomes per node and hour, for rolling uptime percentages (kept for 30 days)
+CREATE TABLE IF NOT EXISTS public.node_uptime_hourly (
+    pubkey TEXT NOT NULL REFERENCES public.nodes (pubkey) ON DELETE CASCADE,
+    hour TIMESTAMPTZ NOT NULL,
+    checks INTEGER NOT NULL,
+    online_checks INTEGER NOT NULL,
+    PRIMARY KEY (pubkey, hour)
+);
+
+CREATE INDEX IF NOT EXISTS node_uptime_hourly_hour_idx ON public.node_uptime_hourly (hour);
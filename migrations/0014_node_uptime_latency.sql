@@ -0,0 +1,3 @@
+-- Response latency per node and hour, averaged by the leaderboard
+ALTER TABLE public.node_uptime_hourly ADD COLUMN IF NOT EXISTS latency_ms_total BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE public.node_uptime_hourly ADD COLUMN IF NOT EXISTS latency_checks INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- Webhook endpoints notified of node changes, each with its own delivery cursor into node_changes
+CREATE TABLE IF NOT EXISTS public.webhooks (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    -- Last node_changes id delivered, or given up on after too many failed attempts
+    cursor BIGINT NOT NULL,
+    failures INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    last_delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
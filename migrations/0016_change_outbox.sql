@@ -0,0 +1,12 @@
+-- Node changes waiting to be published to Kafka. Rows are written in the same transaction as
+-- their node_changes row and deleted once the broker has acknowledged them.
+CREATE TABLE IF NOT EXISTS public.change_outbox (
+    change_id BIGINT PRIMARY KEY,
+    pubkey TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    authority TEXT,
+    uri TEXT,
+    program_id TEXT,
+    slot BIGINT,
+    changed_at TIMESTAMPTZ NOT NULL
+);
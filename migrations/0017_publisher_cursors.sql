@@ -0,0 +1,7 @@
+-- Delivery cursor into node_changes for each message-bus publisher (e.g. NATS JetStream)
+CREATE TABLE IF NOT EXISTS public.publisher_cursors (
+    name TEXT PRIMARY KEY,
+    -- Last node_changes id acknowledged by the bus
+    cursor BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,4 @@
+-- Trigram index so GET /nodes/search can match URI fragments without scanning every node
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS nodes_uri_trgm_idx ON public.nodes USING gin (uri gin_trgm_ops);
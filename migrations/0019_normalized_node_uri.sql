@@ -0,0 +1,18 @@
+-- Canonical form of a node URI for GET /nodes/by-uri: trimmed, scheme and host lowercased,
+-- default port, trailing slashes and fragment dropped. Values that aren't URLs are only
+-- trimmed and lowercased.
+CREATE OR REPLACE FUNCTION public.normalize_uri(uri TEXT) RETURNS TEXT
+LANGUAGE sql IMMUTABLE PARALLEL SAFE AS $$
+    SELECT CASE
+        WHEN parts IS NULL THEN lower(btrim(uri))
+        ELSE lower(parts[1]) || '://' || lower(parts[2])
+            || CASE
+                WHEN (lower(parts[1]), parts[3]) IN (('http', ':80'), ('https', ':443')) THEN ''
+                ELSE coalesce(parts[3], '')
+            END
+            || regexp_replace(parts[4], '/+$', '')
+    END
+    FROM regexp_match(btrim(uri), '^([A-Za-z][A-Za-z0-9+.-]*)://([^/?#:]*)(:[0-9]+)?([^#]*)') AS parts
+$$;
+
+CREATE INDEX IF NOT EXISTS nodes_normalized_uri_idx ON public.nodes (public.normalize_uri(uri));
@@ -0,0 +1,7 @@
+-- Indexes backing GET /nodes?sort=updated_at|authority; pubkey breaks ties so pages are stable
+CREATE INDEX IF NOT EXISTS nodes_updated_at_pubkey_idx ON public.nodes (updated_at, pubkey);
+CREATE INDEX IF NOT EXISTS nodes_authority_pubkey_idx ON public.nodes (authority, pubkey);
+
+-- Superseded by the indexes above, which serve the same lookups
+DROP INDEX IF EXISTS public.nodes_updated_at_idx;
+DROP INDEX IF EXISTS public.nodes_authority_idx;
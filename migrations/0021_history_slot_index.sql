@@ -0,0 +1,2 @@
+-- Index backing GET /nodes?as_of_slot=, which picks each node's latest change at or before a slot
+CREATE INDEX IF NOT EXISTS nodes_history_pubkey_slot_idx ON public.nodes_history (pubkey, slot DESC, id DESC);
@@ -0,0 +1,3 @@
+-- Indexes backing GET /diff: changes up to a slot, and each pubkey's latest change before a point
+CREATE INDEX IF NOT EXISTS node_changes_slot_idx ON public.node_changes (slot);
+CREATE INDEX IF NOT EXISTS node_changes_pubkey_idx ON public.node_changes (pubkey, id);
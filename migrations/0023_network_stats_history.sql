@@ -0,0 +1,11 @@
+-- Network totals sampled after every reconciliation cycle, backing GET /stats/history
+CREATE TABLE IF NOT EXISTS public.network_stats_history (
+    id BIGSERIAL PRIMARY KEY,
+    total_nodes BIGINT NOT NULL,
+    -- Live nodes whose current URI answered its latest probe; NULL while nothing has been probed
+    online_nodes BIGINT,
+    slot BIGINT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS network_stats_history_recorded_at_idx ON public.network_stats_history (recorded_at);
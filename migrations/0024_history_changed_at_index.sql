@@ -0,0 +1,2 @@
+-- Index backing GET /stats/growth, which counts registrations and deregistrations per period
+CREATE INDEX IF NOT EXISTS nodes_history_changed_at_idx ON public.nodes_history (changed_at);
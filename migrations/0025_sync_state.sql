@@ -0,0 +1,18 @@
+-- Sync checkpoint per program: the last successful reconciliation, and failures since, so a
+-- restarted indexer, API replicas and external monitors can tell how far behind the index is
+CREATE TABLE IF NOT EXISTS public.sync_state (
+    program_id TEXT PRIMARY KEY,
+    -- Snapshot slot and completion time of the last successful cycle; NULL until one succeeds
+    last_synced_slot BIGINT,
+    last_synced_at TIMESTAMPTZ,
+    -- Failed cycles since the last successful one
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    last_failed_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Start from the snapshots already reconciled
+INSERT INTO public.sync_state (program_id, last_synced_slot, last_synced_at)
+SELECT program_id, snapshot_slot, synced_at FROM public.program_snapshots
+ON CONFLICT (program_id) DO NOTHING;
@@ -0,0 +1,9 @@
+-- Fields the V2 NodeDevice layout added. Accounts still in the V1 layout leave them NULL;
+-- layout_version records which layout each row was decoded from
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS name TEXT;
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS registered_at TIMESTAMPTZ;
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS layout_version SMALLINT NOT NULL DEFAULT 1;
+
+-- Rows stored before this release skipped V2's fields; forgetting their hashes makes the next
+-- cycle decode every account again instead of leaving unchanged ones alone
+UPDATE public.nodes SET data_hash = NULL WHERE data_hash IS NOT NULL;
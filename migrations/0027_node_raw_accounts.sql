@@ -0,0 +1,7 @@
+-- The account each row was decoded from, as of its last write, so rows can be decoded again
+-- after a decoder fix without refetching them. NULL for rows not written since this migration
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS raw_data BYTEA;
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS lamports BIGINT;
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS owner TEXT;
+-- NUMERIC because rent-exempt accounts report the largest u64
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS rent_epoch NUMERIC(20, 0);
@@ -0,0 +1,5 @@
+-- Whether each row's last_seen_slot has been finalized. Rows written at processed or confirmed
+-- commitment stay FALSE until then, so readers can skip data a fork could still roll back
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS finalized BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE INDEX IF NOT EXISTS nodes_unfinalized_idx ON public.nodes (last_seen_slot) WHERE NOT finalized;
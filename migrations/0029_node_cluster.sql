@@ -0,0 +1,5 @@
+-- Cluster each node was indexed from (CLUSTER, or the CLUSTERS entry its program belongs to).
+-- NULL for an unnamed default cluster
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS cluster TEXT;
+
+CREATE INDEX IF NOT EXISTS nodes_cluster_idx ON public.nodes (cluster);
@@ -0,0 +1,10 @@
+-- Nodes quarantined through POST /admin/nodes/:pubkey/quarantine, hidden from public reads and
+-- the change feeds while the sync loop keeps indexing them. Kept apart from `nodes` so a
+-- node that is closed and registered again stays quarantined
+CREATE TABLE IF NOT EXISTS public.node_moderation (
+    pubkey TEXT PRIMARY KEY,
+    reason TEXT,
+    -- Name of the admin API key that quarantined the node
+    quarantined_by TEXT,
+    quarantined_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,3 @@
+-- Whether the node's URI passed the checks made on ingest (see src/uri.rs). Rows written
+-- before this migration are checked at the next startup
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS uri_valid BOOLEAN NOT NULL DEFAULT TRUE;
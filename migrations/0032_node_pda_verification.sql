@@ -0,0 +1,3 @@
+-- Whether the account sits at the PDA derived from NODE_DEVICE_SEED and its authority (see
+-- src/pda.rs). NULL while no seed is configured, and for rows predating program_id
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS pda_verified BOOLEAN;
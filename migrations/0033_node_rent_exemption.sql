@@ -0,0 +1,3 @@
+-- Whether the account's lamports cover the rent-exempt minimum for its size (see src/rent.rs).
+-- NULL for rows without a stored balance; the rest are checked at the next startup
+ALTER TABLE public.nodes ADD COLUMN IF NOT EXISTS rent_exempt BOOLEAN;
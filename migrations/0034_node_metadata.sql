@@ -0,0 +1,15 @@
+-- Metadata document each node serves under its URI, written by the enrichment worker
+CREATE TABLE IF NOT EXISTS public.node_metadata (
+    pubkey TEXT PRIMARY KEY REFERENCES public.nodes (pubkey) ON DELETE CASCADE,
+    -- URI the document was fetched under
+    uri TEXT NOT NULL,
+    version TEXT,
+    capabilities TEXT[] NOT NULL DEFAULT '{}',
+    -- The whole document
+    info JSONB,
+    -- Why the latest fetch failed; NULL when it succeeded
+    error TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- When a document was last fetched successfully
+    updated_at TIMESTAMPTZ
+);
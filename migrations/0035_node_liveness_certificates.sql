@@ -0,0 +1,9 @@
+-- Certificate of each https node URI's host, as found by its latest probe; null for http URIs
+-- and hosts that presented none
+ALTER TABLE public.node_liveness ADD COLUMN IF NOT EXISTS cert_valid BOOLEAN;
+ALTER TABLE public.node_liveness ADD COLUMN IF NOT EXISTS cert_hostname_match BOOLEAN;
+ALTER TABLE public.node_liveness ADD COLUMN IF NOT EXISTS cert_expires_at TIMESTAMPTZ;
+
+-- Serves GET /nodes/certificates, which orders by expiry
+CREATE INDEX IF NOT EXISTS node_liveness_cert_expires_at_idx
+    ON public.node_liveness (cert_expires_at) WHERE cert_valid IS NOT NULL;
@@ -0,0 +1,6 @@
+-- How each node URI's host resolved at its latest probe. dns_ok is NULL for rows probed
+-- before resolution was recorded
+ALTER TABLE public.node_liveness ADD COLUMN IF NOT EXISTS dns_ok BOOLEAN;
+-- Addresses the host resolved to, or the URI's own IP address
+ALTER TABLE public.node_liveness ADD COLUMN IF NOT EXISTS resolved_ips TEXT[];
+ALTER TABLE public.node_liveness ADD COLUMN IF NOT EXISTS dns_error TEXT;
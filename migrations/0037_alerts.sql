@@ -0,0 +1,34 @@
+-- Where node operators are notified of their nodes going offline or being pruned, per authority
+CREATE TABLE IF NOT EXISTS public.alert_targets (
+    id BIGSERIAL PRIMARY KEY,
+    authority TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('webhook', 'email')),
+    -- Webhook URL or email address
+    target TEXT NOT NULL,
+    -- Signing key of webhook targets
+    secret TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (authority, kind, target)
+);
+
+-- Notifications for alert targets, queued with the transition that raised them. Delivered and
+-- failed ones are kept for 30 days
+CREATE TABLE IF NOT EXISTS public.alerts (
+    id BIGSERIAL PRIMARY KEY,
+    target_id BIGINT NOT NULL REFERENCES public.alert_targets (id) ON DELETE CASCADE,
+    event TEXT NOT NULL CHECK (event IN ('offline', 'pruned')),
+    -- No foreign key: pruned nodes may be gone
+    pubkey TEXT NOT NULL,
+    authority TEXT NOT NULL,
+    uri TEXT,
+    detail TEXT,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'failed')),
+    attempts INT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    delivered_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS alerts_pending_idx ON public.alerts (next_attempt_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS alerts_target_idx ON public.alerts (target_id, id);
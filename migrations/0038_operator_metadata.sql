@@ -0,0 +1,31 @@
+-- Challenges issued to authorities signing in to the operator API, each usable once
+CREATE TABLE IF NOT EXISTS public.operator_challenges (
+    nonce TEXT PRIMARY KEY,
+    authority TEXT NOT NULL,
+    -- The exact text the authority signs
+    message TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS operator_challenges_expires_idx ON public.operator_challenges (expires_at);
+
+-- Sessions of authorities that signed a challenge. Only the SHA-256 hash of each token is kept
+CREATE TABLE IF NOT EXISTS public.operator_sessions (
+    token_hash BYTEA PRIMARY KEY,
+    authority TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS operator_sessions_expires_idx ON public.operator_sessions (expires_at);
+
+-- Off-chain metadata an authority attached to one of its nodes. It is only shown while the node's
+-- authority is still the one that wrote it
+CREATE TABLE IF NOT EXISTS public.node_operator_metadata (
+    pubkey TEXT PRIMARY KEY REFERENCES public.nodes (pubkey) ON DELETE CASCADE,
+    authority TEXT NOT NULL,
+    display_name TEXT,
+    contact TEXT,
+    description TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,18 @@
+-- Pubkeys and authorities an API key client follows the changes of
+CREATE TABLE IF NOT EXISTS public.watchlists (
+    id BIGSERIAL PRIMARY KEY,
+    api_key_id BIGINT NOT NULL REFERENCES public.api_keys (id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    pubkeys TEXT[] NOT NULL DEFAULT '{}',
+    authorities TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS watchlists_api_key_idx ON public.watchlists (api_key_id, id);
+
+-- A watchlist's webhook is only sent the changes its entries match
+ALTER TABLE public.webhooks
+    ADD COLUMN IF NOT EXISTS watchlist_id BIGINT REFERENCES public.watchlists (id) ON DELETE CASCADE;
+
+CREATE UNIQUE INDEX IF NOT EXISTS webhooks_watchlist_idx ON public.webhooks (watchlist_id) WHERE watchlist_id IS NOT NULL;
@@ -0,0 +1,18 @@
+-- Program and cluster of each history entry, so past snapshots still filter nodes that have
+-- since been hard-deleted
+ALTER TABLE public.nodes_history ADD COLUMN IF NOT EXISTS program_id TEXT;
+ALTER TABLE public.nodes_history ADD COLUMN IF NOT EXISTS cluster TEXT;
+
+UPDATE public.nodes_history h SET program_id = n.program_id, cluster = n.cluster
+FROM public.nodes n
+WHERE n.pubkey = h.pubkey AND h.program_id IS NULL;
+
+-- Nodes unchanged since before the history was kept have no entries, so they get one for the
+-- state they hold, from the slot it was last seen at. Rows from before slots were tracked have
+-- held it since before anything was recorded
+INSERT INTO public.nodes_history (pubkey, change_type, new_authority, new_uri, slot, changed_at, program_id, cluster)
+SELECT n.pubkey, 'added', n.authority, n.uri, COALESCE(n.last_seen_slot, 0),
+       COALESCE(n.updated_at, n.first_seen_at, NOW()), n.program_id, n.cluster
+FROM public.nodes n
+WHERE n.deleted_at IS NULL
+  AND NOT EXISTS (SELECT 1 FROM public.nodes_history h WHERE h.pubkey = n.pubkey);
@@ -0,0 +1,57 @@
+-- Nodes, IDL-decoded accounts and per-program sync state are keyed by cluster as well, so one
+-- program can be indexed from several clusters. The cluster is NULL for an unnamed default
+-- cluster, which the NULLS NOT DISTINCT keys treat as one more cluster
+ALTER TABLE public.node_liveness DROP CONSTRAINT IF EXISTS node_liveness_pubkey_fkey;
+ALTER TABLE public.node_uptime_hourly DROP CONSTRAINT IF EXISTS node_uptime_hourly_pubkey_fkey;
+ALTER TABLE public.node_metadata DROP CONSTRAINT IF EXISTS node_metadata_pubkey_fkey;
+ALTER TABLE public.node_operator_metadata DROP CONSTRAINT IF EXISTS node_operator_metadata_pubkey_fkey;
+
+ALTER TABLE public.nodes DROP CONSTRAINT IF EXISTS nodes_pkey;
+ALTER TABLE public.nodes ADD CONSTRAINT nodes_pubkey_cluster_key UNIQUE NULLS NOT DISTINCT (pubkey, cluster);
+
+-- Liveness, uptime and metadata stay keyed by pubkey, shared by a node's rows on every cluster,
+-- and go once the last of those rows does
+CREATE OR REPLACE FUNCTION public.delete_node_dependents() RETURNS trigger AS $$
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM public.nodes WHERE pubkey = OLD.pubkey) THEN
+        DELETE FROM public.node_liveness WHERE pubkey = OLD.pubkey;
+        DELETE FROM public.node_uptime_hourly WHERE pubkey = OLD.pubkey;
+        DELETE FROM public.node_metadata WHERE pubkey = OLD.pubkey;
+        DELETE FROM public.node_operator_metadata WHERE pubkey = OLD.pubkey;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS nodes_delete_dependents ON public.nodes;
+CREATE TRIGGER nodes_delete_dependents AFTER DELETE ON public.nodes
+    FOR EACH ROW EXECUTE FUNCTION public.delete_node_dependents();
+
+ALTER TABLE public.node_changes ADD COLUMN IF NOT EXISTS cluster TEXT;
+ALTER TABLE public.change_outbox ADD COLUMN IF NOT EXISTS cluster TEXT;
+
+UPDATE public.node_changes c SET cluster = n.cluster
+FROM public.nodes n
+WHERE n.pubkey = c.pubkey AND n.cluster IS NOT NULL AND c.cluster IS NULL;
+
+CREATE INDEX IF NOT EXISTS nodes_history_cluster_pubkey_idx ON public.nodes_history (cluster, pubkey, slot);
+
+ALTER TABLE public.accounts ADD COLUMN IF NOT EXISTS cluster TEXT;
+UPDATE public.accounts a SET cluster = n.cluster
+FROM public.nodes n
+WHERE n.program_id = a.program_id AND n.cluster IS NOT NULL AND a.cluster IS NULL;
+ALTER TABLE public.accounts DROP CONSTRAINT IF EXISTS accounts_pkey;
+ALTER TABLE public.accounts ADD CONSTRAINT accounts_pubkey_cluster_key UNIQUE NULLS NOT DISTINCT (pubkey, cluster);
+
+ALTER TABLE public.sync_state ADD COLUMN IF NOT EXISTS cluster TEXT;
+ALTER TABLE public.program_snapshots ADD COLUMN IF NOT EXISTS cluster TEXT;
+UPDATE public.sync_state s SET cluster = (SELECT n.cluster FROM public.nodes n WHERE n.program_id = s.program_id LIMIT 1)
+WHERE s.cluster IS NULL;
+UPDATE public.program_snapshots s
+SET cluster = (SELECT n.cluster FROM public.nodes n WHERE n.program_id = s.program_id LIMIT 1)
+WHERE s.cluster IS NULL;
+ALTER TABLE public.sync_state DROP CONSTRAINT IF EXISTS sync_state_pkey;
+ALTER TABLE public.sync_state ADD CONSTRAINT sync_state_program_cluster_key UNIQUE NULLS NOT DISTINCT (program_id, cluster);
+ALTER TABLE public.program_snapshots DROP CONSTRAINT IF EXISTS program_snapshots_pkey;
+ALTER TABLE public.program_snapshots
+    ADD CONSTRAINT program_snapshots_program_cluster_key UNIQUE NULLS NOT DISTINCT (program_id, cluster);
@@ -0,0 +1,23 @@
+-- NodeDevice accounts for local development: the columns of the Postgres nodes table, with
+-- timestamps as ISO-8601 text and rent_epoch as text since it can exceed an INTEGER
+CREATE TABLE IF NOT EXISTS nodes (
+    pubkey TEXT PRIMARY KEY,
+    authority TEXT NOT NULL,
+    uri TEXT NOT NULL,
+    program_id TEXT,
+    data_hash BLOB,
+    first_seen_at TEXT,
+    updated_at TEXT,
+    last_seen_slot INTEGER,
+    deleted_at TEXT,
+    name TEXT,
+    registered_at TEXT,
+    layout_version INTEGER NOT NULL DEFAULT 1,
+    raw_data BLOB,
+    lamports INTEGER,
+    owner TEXT,
+    rent_epoch TEXT,
+    finalized INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS nodes_program_id_idx ON nodes (program_id);
@@ -0,0 +1,25 @@
+-- Per-node history and the change feed, as in the Postgres nodes_history and node_changes
+CREATE TABLE IF NOT EXISTS nodes_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pubkey TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    old_authority TEXT,
+    new_authority TEXT,
+    old_uri TEXT,
+    new_uri TEXT,
+    slot INTEGER,
+    changed_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS nodes_history_pubkey_idx ON nodes_history (pubkey, id);
+
+CREATE TABLE IF NOT EXISTS node_changes (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pubkey TEXT NOT NULL,
+    change_type TEXT NOT NULL,
+    authority TEXT,
+    uri TEXT,
+    program_id TEXT,
+    slot INTEGER,
+    changed_at TEXT NOT NULL
+);
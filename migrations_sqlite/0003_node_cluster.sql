@@ -0,0 +1,2 @@
+-- The cluster column of the Postgres nodes table; local development mode leaves it NULL
+ALTER TABLE nodes ADD COLUMN cluster TEXT;
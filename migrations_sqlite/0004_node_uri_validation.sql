@@ -0,0 +1,2 @@
+-- The uri_valid column of the Postgres nodes table
+ALTER TABLE nodes ADD COLUMN uri_valid INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,2 @@
+-- The pda_verified column of the Postgres nodes table
+ALTER TABLE nodes ADD COLUMN pda_verified INTEGER;
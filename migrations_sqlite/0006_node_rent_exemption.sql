@@ -0,0 +1,2 @@
+-- The rent_exempt column of the Postgres nodes table
+ALTER TABLE nodes ADD COLUMN rent_exempt INTEGER;
@@ -0,0 +1,3 @@
+-- Program and cluster of each history entry, as in the Postgres nodes_history
+ALTER TABLE nodes_history ADD COLUMN program_id TEXT;
+ALTER TABLE nodes_history ADD COLUMN cluster TEXT;
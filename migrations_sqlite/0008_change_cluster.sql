@@ -0,0 +1,2 @@
+-- Cluster of each change, as in the Postgres node_changes
+ALTER TABLE node_changes ADD COLUMN cluster TEXT;
@@ -0,0 +1,570 @@
+//! Operator alerts: each alert target registers a webhook URL or email address for one
+//! authority, and is notified when one of that authority's nodes goes offline (its URI was
+//! online at the previous probe and failed this one) or is pruned from the index. Admins
+//! manage every authority's targets under `/admin/alert-targets`; a signed-in operator manages
+//! its own under `/my/alert-targets`, where only webhook targets can be registered, at most
+//! [`MAX_OPERATOR_ALERT_TARGETS`] of them. Signing in proves nothing about an email address,
+//! so only admins add those.
+//!
+//! Alerts are queued in `alerts` by the transaction that records the transition and delivered
+//! by [`run_alert_delivery`], each retried with exponential backoff until it succeeds or is
+//! given up on after [`ALERT_MAX_ATTEMPTS`]. Webhook alerts are a signed JSON `POST` like node
+//! change webhooks (see [`crate::webhooks`]), with `X-Alert-Id`, `X-Alert-Timestamp` and
+//! `X-Alert-Signature`. Email alerts go through the SMTP relay in `ALERT_SMTP_URL`, spoken to
+//! directly over plaintext TCP; the relay handles onward TLS and authentication. Webhook
+//! targets are only reached at public addresses, like node URIs (see [`crate::dns`]).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::postgres::{PgExecutor, PgPool};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time::{sleep, timeout, Duration, Instant};
+use tracing::{debug, info, info_span, warn, Instrument};
+use utoipa::ToSchema;
+
+use crate::config;
+use crate::dns::{self, DnsCache};
+use crate::liveness::describe_request_error;
+use crate::sync::{IngestionPause, SyncContext};
+use crate::webhooks::sign_payload;
+use crate::AppError;
+
+// --- Pause between checks for alerts to deliver ---
+const ALERT_POLL_INTERVAL_MILLIS: u64 = 1000;
+
+// --- Alerts being delivered at once ---
+const ALERT_BATCH_SIZE: i64 = 100;
+
+// --- Time a webhook endpoint or the SMTP relay gets to complete a delivery ---
+const ALERT_TIMEOUT_SECS: u64 = 10;
+
+// --- How long the alert client caches a webhook target's resolved addresses ---
+const ALERT_DNS_TTL_SECS: u64 = 300;
+
+// --- Retry backoff after a failed delivery: doubles per attempt, capped ---
+const ALERT_RETRY_BASE_SECS: i64 = 2;
+const ALERT_RETRY_MAX_SECS: i64 = 3600;
+
+// --- Attempts per alert before it is given up on (about 34 minutes of retries) ---
+pub const ALERT_MAX_ATTEMPTS: i32 = 10;
+
+// --- Days delivered and failed alerts are kept, and how often older ones are deleted ---
+const ALERT_RETENTION_DAYS: i32 = 30;
+const ALERT_CLEANUP_INTERVAL_SECS: u64 = 3600;
+
+// --- Port used when ALERT_SMTP_URL doesn't name one ---
+const DEFAULT_SMTP_PORT: u16 = 25;
+
+// --- Prefix of generated signing secrets ---
+const ALERT_SECRET_PREFIX: &str = "alsec_";
+
+// --- Most alert targets an operator may have when registering one under /my/alert-targets ---
+pub const MAX_OPERATOR_ALERT_TARGETS: i64 = 10;
+
+// --- Longest email address accepted for a target ---
+const MAX_EMAIL_LEN: usize = 254;
+
+/// SMTP relay for email alerts (`ALERT_SMTP_URL`, `ALERT_EMAIL_FROM`).
+#[derive(Clone, Debug)]
+pub struct AlertEmailConfig {
+    /// Relay as `host:port`.
+    pub server: String,
+    pub from: String,
+}
+
+impl AlertEmailConfig {
+    /// `None` when `ALERT_SMTP_URL` is unset; email alerts then fail without being retried.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Ok(url) = config::var("ALERT_SMTP_URL") else { return Ok(None) };
+        let address = url.strip_prefix("smtp://").unwrap_or(&url).trim_end_matches('/');
+        if address.is_empty() || address.contains("://") || address.contains('@') {
+            let source = config::source("ALERT_SMTP_URL");
+            return Err(format!("Invalid {} '{}': expected smtp://host[:port]", source, url).into());
+        }
+        let server = match address.rsplit_once(':') {
+            Some((_, port)) if port.parse::<u16>().is_ok() => address.to_string(),
+            _ => format!("{}:{}", address, DEFAULT_SMTP_PORT),
+        };
+        let Ok(from) = config::var("ALERT_EMAIL_FROM") else {
+            return Err(format!("{} needs ALERT_EMAIL_FROM", config::source("ALERT_SMTP_URL")).into());
+        };
+        if !is_email_address(&from) {
+            let source = config::source("ALERT_EMAIL_FROM");
+            return Err(format!("Invalid {} '{}': expected an email address", source, from).into());
+        }
+        Ok(Some(Self { server, from }))
+    }
+}
+
+/// How an alert target is notified.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertKind {
+    Webhook,
+    Email,
+}
+
+impl AlertKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Webhook => "webhook",
+            Self::Email => "email",
+        }
+    }
+}
+
+/// Node status transitions that raise an alert.
+#[derive(Clone, Copy, Debug)]
+pub enum AlertEvent {
+    /// The node's URI failed a probe after passing the previous one.
+    Offline,
+    /// The node's account was closed and its row pruned.
+    Pruned,
+}
+
+impl AlertEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Offline => "offline",
+            Self::Pruned => "pruned",
+        }
+    }
+}
+
+/// One row of `alert_targets`, as listed by `GET /admin/alert-targets` and
+/// `GET /my/alert-targets`. The secret of a webhook target is only returned when it is created.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Debug)]
+#[schema(as = AlertTarget)]
+pub struct AlertTargetRecord {
+    pub id: i64,
+    pub authority: String,
+    #[schema(value_type = AlertKind)]
+    pub kind: String,
+    /// Webhook URL or email address.
+    pub target: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// --- Columns selected into `AlertTargetRecord` ---
+pub const ALERT_TARGET_COLUMNS: &str = "id, authority, kind, target, created_at";
+
+/// One row of `alerts`: a notification and the state of its delivery.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Debug)]
+#[schema(as = Alert)]
+pub struct AlertRecord {
+    pub id: i64,
+    pub target_id: i64,
+    pub event: String,
+    pub pubkey: String,
+    pub authority: String,
+    /// The node's URI when the alert was raised.
+    pub uri: Option<String>,
+    /// Why the probe failed, for `offline` alerts.
+    pub detail: Option<String>,
+    /// `pending`, `delivered` or `failed` (given up on).
+    pub status: String,
+    pub attempts: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+}
+
+// --- Columns selected into `AlertRecord` ---
+pub const ALERT_COLUMNS: &str = "id, target_id, event, pubkey, authority, uri, detail, status, attempts, \
+     next_attempt_at, last_error, created_at, delivered_at";
+
+/// Registers `target` for `authority`'s nodes and returns its row, with the generated signing
+/// secret for webhook targets; or `None` if the authority already has `limit` targets.
+pub async fn create_alert_target(
+    pool: &PgPool,
+    authority: &str,
+    kind: AlertKind,
+    target: &str,
+    limit: Option<i64>,
+) -> Result<Option<(AlertTargetRecord, Option<String>)>, AppError> {
+    let secret = (kind == AlertKind::Webhook).then(|| {
+        let secret: [u8; 32] = rand::random();
+        format!("{}{}", ALERT_SECRET_PREFIX, secret.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+    });
+    let mut transaction = pool.begin().await?;
+    if let Some(limit) = limit {
+        // Registrations for one authority take turns, so concurrent ones can't all pass the count.
+        sqlx::query("SELECT pg_advisory_xact_lock('public.alert_targets'::regclass::oid::int4, hashtext($1))")
+            .bind(authority)
+            .execute(&mut *transaction)
+            .await?;
+        let registered: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM alert_targets WHERE authority = $1")
+            .bind(authority)
+            .fetch_one(&mut *transaction)
+            .await?;
+        if registered >= limit {
+            return Ok(None);
+        }
+    }
+    let record = sqlx::query_as::<_, AlertTargetRecord>(&format!(
+        "INSERT INTO alert_targets (authority, kind, target, secret) VALUES ($1, $2, $3, $4) RETURNING {}",
+        ALERT_TARGET_COLUMNS
+    ))
+    .bind(authority)
+    .bind(kind.as_str())
+    .bind(target)
+    .bind(&secret)
+    .fetch_one(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(Some((record, secret)))
+}
+
+/// Whether `address` looks deliverable and is safe to put in SMTP commands and headers.
+pub fn is_email_address(address: &str) -> bool {
+    let Some((local, domain)) = address.rsplit_once('@') else { return false };
+    address.len() <= MAX_EMAIL_LEN
+        && !local.is_empty()
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !address.chars().any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | ',' | ';'))
+        && !local.contains('@')
+}
+
+/// Queues `event` for every target registered for the authority of the stored node `pubkey`.
+pub async fn enqueue_alerts(
+    executor: impl PgExecutor<'_>,
+    pubkey: &str,
+    event: AlertEvent,
+    detail: Option<&str>,
+) -> Result<u64, sqlx::Error> {
+    let queued = sqlx::query(
+        "INSERT INTO alerts (target_id, event, pubkey, authority, uri, detail) \
+         SELECT t.id, $2, n.pubkey, n.authority, n.uri, $3 \
+         FROM nodes n JOIN alert_targets t ON t.authority = n.authority WHERE n.pubkey = $1",
+    )
+    .bind(pubkey)
+    .bind(event.as_str())
+    .bind(detail)
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(queued)
+}
+
+/// Body of a webhook alert.
+#[derive(Serialize)]
+struct AlertPayload<'a> {
+    id: i64,
+    event: &'a str,
+    pubkey: &'a str,
+    authority: &'a str,
+    uri: Option<&'a str>,
+    detail: Option<&'a str>,
+    raised_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+struct DueAlert {
+    id: i64,
+    event: String,
+    pubkey: String,
+    authority: String,
+    uri: Option<String>,
+    detail: Option<String>,
+    attempts: i32,
+    created_at: DateTime<Utc>,
+    kind: String,
+    target: String,
+    secret: Option<String>,
+}
+
+/// Why a delivery failed, and whether trying again could help.
+struct Failure {
+    error: String,
+    retry: bool,
+}
+
+impl Failure {
+    fn retry(error: String) -> Self {
+        Self { error, retry: true }
+    }
+}
+
+/// Delivers queued alerts forever.
+pub async fn run_alert_delivery(sync: SyncContext, email: Option<AlertEmailConfig>) {
+    // Connects only to public addresses, wherever a target's hostname resolves by send time.
+    let client = reqwest::Client::builder()
+        .dns_resolver(Arc::new(DnsCache::new(Duration::from_secs(ALERT_DNS_TTL_SECS))))
+        .timeout(Duration::from_secs(ALERT_TIMEOUT_SECS))
+        // A redirect could point the signed payload anywhere; the registered URL must answer.
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!("indexer/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Failed to build the alert HTTP client");
+    async move {
+        let mut cleaned_at: Option<Instant> = None;
+        // Deliveries outlive the poll that started them, so a slow target doesn't hold up
+        // alerts queued after it.
+        let mut deliveries = JoinSet::new();
+        let mut in_flight = HashMap::new();
+        loop {
+            while let Some(finished) = deliveries.try_join_next() {
+                if let Err(e) = finished {
+                    warn!(error = %e, "Alert delivery task failed");
+                }
+            }
+            in_flight.retain(|_, delivery: &mut AbortHandle| !delivery.is_finished());
+            let delivering = deliver_due(&client, email.as_ref(), &sync, &mut deliveries, &mut in_flight);
+            if let Err(e) = delivering.await {
+                warn!(error = %e, "Failed to deliver alerts");
+            }
+            if cleaned_at.is_none_or(|at| at.elapsed() >= Duration::from_secs(ALERT_CLEANUP_INTERVAL_SECS)) {
+                match delete_old_alerts(&sync.pool).await {
+                    Ok(deleted) if deleted > 0 => info!(deleted, "Deleted old alerts"),
+                    Ok(_) => {}
+                    Err(e) => warn!(error = %e, "Failed to delete old alerts"),
+                }
+                cleaned_at = Some(Instant::now());
+            }
+            sleep(Duration::from_millis(ALERT_POLL_INTERVAL_MILLIS)).await;
+        }
+    }
+    .instrument(info_span!("alerts"))
+    .await
+}
+
+async fn delete_old_alerts(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let deleted = sqlx::query(
+        "DELETE FROM alerts WHERE status <> 'pending' AND created_at < NOW() - make_interval(days => $1)",
+    )
+    .bind(ALERT_RETENTION_DAYS)
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(deleted)
+}
+
+/// Starts delivering due alerts that aren't being delivered already, tracking them in
+/// `in_flight` by alert id, up to [`ALERT_BATCH_SIZE`] at a time.
+async fn deliver_due(
+    client: &reqwest::Client,
+    email: Option<&AlertEmailConfig>,
+    sync: &SyncContext,
+    deliveries: &mut JoinSet<()>,
+    in_flight: &mut HashMap<i64, AbortHandle>,
+) -> Result<(), AppError> {
+    let room = ALERT_BATCH_SIZE - in_flight.len() as i64;
+    if room <= 0 {
+        return Ok(());
+    }
+    let busy: Vec<i64> = in_flight.keys().copied().collect();
+    let due = sqlx::query_as::<_, DueAlert>(
+        "SELECT a.id, a.event, a.pubkey, a.authority, a.uri, a.detail, a.attempts, a.created_at, \
+                t.kind, t.target, t.secret \
+         FROM alerts a JOIN alert_targets t ON t.id = a.target_id \
+         WHERE a.status = 'pending' AND a.next_attempt_at <= NOW() AND a.id <> ALL($2) \
+         ORDER BY a.next_attempt_at, a.id LIMIT $1",
+    )
+    .bind(room)
+    .bind(&busy)
+    .fetch_all(&sync.pool)
+    .await?;
+    // Alerts are independent, so they are delivered concurrently.
+    for alert in due {
+        let (client, email, pool, pause) = (client.clone(), email.cloned(), sync.pool.clone(), sync.pause.clone());
+        let id = alert.id;
+        let delivery = deliveries.spawn(async move {
+            if let Err(e) = deliver_alert(&client, email.as_ref(), &pool, &pause, alert).await {
+                warn!(alert = id, error = %e, "Alert delivery failed to record");
+            }
+        });
+        in_flight.insert(id, delivery);
+    }
+    Ok(())
+}
+
+async fn deliver_alert(
+    client: &reqwest::Client,
+    email: Option<&AlertEmailConfig>,
+    pool: &PgPool,
+    pause: &IngestionPause,
+    alert: DueAlert,
+) -> Result<(), AppError> {
+    let Some(_write) = pause.begin_write().await else { return Ok(()) };
+    let result = match alert.kind.as_str() {
+        "webhook" => send_webhook(client, &alert).await,
+        "email" => match email {
+            Some(email) => send_email(email, &alert).await,
+            None => Err(Failure { error: "No SMTP relay is configured (ALERT_SMTP_URL)".to_string(), retry: false }),
+        },
+        kind => Err(Failure { error: format!("Unknown alert target kind '{}'", kind), retry: false }),
+    };
+
+    let Err(failure) = result else {
+        debug!(alert = alert.id, event = %alert.event, pubkey = %alert.pubkey, "Delivered alert");
+        sqlx::query(
+            "UPDATE alerts SET status = 'delivered', attempts = attempts + 1, delivered_at = NOW(), \
+             last_error = NULL WHERE id = $1",
+        )
+        .bind(alert.id)
+        .execute(pool)
+        .await?;
+        return Ok(());
+    };
+
+    let attempts = alert.attempts + 1;
+    if !failure.retry || attempts >= ALERT_MAX_ATTEMPTS {
+        warn!(alert = alert.id, error = %failure.error, attempts, "Giving up on alert delivery");
+        sqlx::query("UPDATE alerts SET status = 'failed', attempts = $2, last_error = $3 WHERE id = $1")
+            .bind(alert.id)
+            .bind(attempts)
+            .bind(&failure.error)
+            .execute(pool)
+            .await?;
+        return Ok(());
+    }
+    let backoff_secs = (ALERT_RETRY_BASE_SECS << (attempts - 1).min(20)).min(ALERT_RETRY_MAX_SECS);
+    warn!(alert = alert.id, error = %failure.error, attempts, backoff_secs, "Alert delivery failed");
+    sqlx::query(
+        "UPDATE alerts SET attempts = $2, last_error = $3, next_attempt_at = NOW() + make_interval(secs => $4) \
+         WHERE id = $1",
+    )
+    .bind(alert.id)
+    .bind(attempts)
+    .bind(&failure.error)
+    .bind(backoff_secs as f64)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn send_webhook(client: &reqwest::Client, alert: &DueAlert) -> Result<(), Failure> {
+    let payload = AlertPayload {
+        id: alert.id,
+        event: &alert.event,
+        pubkey: &alert.pubkey,
+        authority: &alert.authority,
+        uri: alert.uri.as_deref(),
+        detail: alert.detail.as_deref(),
+        raised_at: alert.created_at,
+    };
+    let body = serde_json::to_vec(&payload).map_err(|e| Failure { error: e.to_string(), retry: false })?;
+    let timestamp = Utc::now().timestamp();
+    let secret = alert.secret.as_deref().unwrap_or_default();
+    // Targets registered before URLs were checked could name a private address outright.
+    let url = dns::parse_delivery_url(&alert.target).map_err(|error| Failure { error, retry: false })?;
+    let response = client
+        .post(url)
+        .header("content-type", "application/json")
+        .header("x-alert-id", alert.id)
+        .header("x-alert-timestamp", timestamp)
+        .header("x-alert-signature", sign_payload(secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => Err(Failure::retry(format!("HTTP {}", response.status()))),
+        Err(e) => Err(Failure::retry(describe_request_error(&e))),
+    }
+}
+
+async fn send_email(config: &AlertEmailConfig, alert: &DueAlert) -> Result<(), Failure> {
+    let subject = match alert.event.as_str() {
+        "offline" => format!("Node {} is offline", alert.pubkey),
+        "pruned" => format!("Node {} was pruned", alert.pubkey),
+        event => format!("Node {}: {}", alert.pubkey, event),
+    };
+    let mut body = format!("{}.\r\n\r\nNode: {}\r\nAuthority: {}\r\n", subject, alert.pubkey, alert.authority);
+    if let Some(uri) = &alert.uri {
+        body.push_str(&format!("URI: {}\r\n", one_line(uri)));
+    }
+    if let Some(detail) = &alert.detail {
+        body.push_str(&format!("Detail: {}\r\n", one_line(detail)));
+    }
+    body.push_str(&format!("Raised at: {}\r\n", alert.created_at.to_rfc3339()));
+    let message = format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <alert-{}@indexer>\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\r\n{}",
+        config.from,
+        alert.target,
+        subject,
+        alert.created_at.to_rfc2822(),
+        alert.id,
+        body
+    );
+    let send = smtp_send(&config.server, &config.from, &alert.target, &message);
+    match timeout(Duration::from_secs(ALERT_TIMEOUT_SECS), send).await {
+        Ok(result) => result,
+        Err(_) => Err(Failure::retry(format!("SMTP relay {} timed out", config.server))),
+    }
+}
+
+/// `text` with control characters blanked: on-chain URIs and probe errors can hold anything,
+/// and a stray line break would end the message's header or body line early.
+fn one_line(text: &str) -> String {
+    text.chars().map(|c| if c.is_control() { ' ' } else { c }).collect()
+}
+
+/// Sends one message through the relay at `server`. A 5xx reply is permanent and isn't retried.
+async fn smtp_send(server: &str, from: &str, to: &str, message: &str) -> Result<(), Failure> {
+    let stream = TcpStream::connect(server)
+        .await
+        .map_err(|e| Failure::retry(format!("Failed to connect to SMTP relay {}: {}", server, e)))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    smtp_reply(&mut reader, 220).await?;
+    for (command, expected) in [
+        ("EHLO indexer".to_string(), 250),
+        (format!("MAIL FROM:<{}>", from), 250),
+        (format!("RCPT TO:<{}>", to), 250),
+        ("DATA".to_string(), 354),
+    ] {
+        smtp_write(&mut writer, &format!("{}\r\n", command)).await?;
+        smtp_reply(&mut reader, expected).await?;
+    }
+    // Lines starting with a dot are escaped so none ends the message early.
+    let mut data = String::with_capacity(message.len() + 5);
+    for line in message.split("\r\n") {
+        if line.starts_with('.') {
+            data.push('.');
+        }
+        data.push_str(line);
+        data.push_str("\r\n");
+    }
+    data.push_str(".\r\n");
+    smtp_write(&mut writer, &data).await?;
+    smtp_reply(&mut reader, 250).await?;
+    // The message is accepted; a failed goodbye doesn't undo that.
+    let _ = smtp_write(&mut writer, "QUIT\r\n").await;
+    Ok(())
+}
+
+async fn smtp_write(writer: &mut tokio::net::tcp::OwnedWriteHalf, text: &str) -> Result<(), Failure> {
+    writer.write_all(text.as_bytes()).await.map_err(|e| Failure::retry(format!("SMTP write failed: {}", e)))
+}
+
+/// Reads one possibly multiline reply and checks its code.
+async fn smtp_reply(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>, expected: u16) -> Result<(), Failure> {
+    loop {
+        let mut line = String::new();
+        let read = reader.read_line(&mut line).await.map_err(|e| Failure::retry(format!("SMTP read failed: {}", e)))?;
+        if read == 0 {
+            return Err(Failure::retry("SMTP relay closed the connection".to_string()));
+        }
+        let line = line.trim_end();
+        let code = line.get(..3).and_then(|code| code.parse::<u16>().ok());
+        // `250-` continues a multiline reply; `250 ` ends it.
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        return match code {
+            Some(code) if code == expected => Ok(()),
+            Some(code) => Err(Failure { error: format!("SMTP relay replied '{}'", line), retry: code < 500 }),
+            None => Err(Failure::retry(format!("Malformed SMTP reply '{}'", line))),
+        };
+    }
+}
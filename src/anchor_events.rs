@@ -0,0 +1,127 @@
+//! Anchor event ingestion: `emit!`ted events parsed out of the program's transaction logs.
+//!
+//! Events carry the exact slot a node was registered or deregistered at, rather than the
+//! slot of whichever reconciliation cycle first noticed the change.
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_sdk::pubkey::Pubkey;
+use tokio::time::{sleep, Duration};
+use tokio_stream::StreamExt;
+use tracing::{debug, info, instrument, warn};
+
+use crate::cluster::ProgramKey;
+use crate::decode::DecodeError;
+use crate::idl::IdlDecoder;
+use crate::store::{record_program_events, ProgramEventRecord};
+use crate::sync::{SyncContext, MAX_SUBSCRIBE_BACKOFF_SECS};
+use crate::AppError;
+
+/// Payloads of the `Program data:` lines logged by `program_id` itself, in order. Lines logged
+/// while another program is executing (a CPI target, or the caller around it) are skipped.
+pub fn program_data(logs: &[String], program_id: &Pubkey) -> Vec<Vec<u8>> {
+    let program_id = program_id.to_string();
+    let mut invocations: Vec<&str> = Vec::new();
+    let mut payloads = Vec::new();
+    for line in logs {
+        let Some(rest) = line.strip_prefix("Program ") else { continue };
+        if let Some(data) = rest.strip_prefix("data: ") {
+            if invocations.last() == Some(&program_id.as_str()) {
+                // Anchor logs each event as one base64 blob; anything else isn't an event.
+                if let Ok(payload) = BASE64_STANDARD.decode(data.trim()) {
+                    payloads.push(payload);
+                }
+            }
+            continue;
+        }
+        let mut words = rest.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(program), Some("invoke")) => invocations.push(program),
+            (Some(_), Some(status)) if status == "success" || status.starts_with("failed") => {
+                invocations.pop();
+            }
+            _ => {}
+        }
+    }
+    payloads
+}
+
+/// Decodes the program's events out of a transaction's logs, naming them from the IDL when one
+/// is loaded.
+pub fn parse_events(logs: &[String], program_id: &Pubkey, idl: Option<&IdlDecoder>) -> Vec<ProgramEventRecord> {
+    program_data(logs, program_id)
+        .into_iter()
+        .enumerate()
+        .map(|(index, raw_data)| {
+            let decoded = match idl.map(|idl| idl.decode_event(&raw_data)) {
+                Some(Ok((name, data))) => Some((name.to_string(), data)),
+                Some(Err(DecodeError::UnknownDiscriminator(_))) | None => None,
+                Some(Err(error)) => {
+                    warn!(%error, "Failed to decode event with the IDL");
+                    None
+                }
+            };
+            let (name, data) = match decoded {
+                Some((name, data)) => (name, Some(data)),
+                None => (raw_data.iter().take(8).map(|byte| format!("{:02x}", byte)).collect(), None),
+            };
+            ProgramEventRecord { event_index: index as i32, name, data, raw_data }
+        })
+        .collect()
+}
+
+/// Keeps a `logsSubscribe` stream for `program` open, reconnecting with backoff, and stores
+/// the events of every successful transaction that mentions the program.
+#[instrument(skip_all, fields(program_id = %program.program_id, cluster = sync.clusters.name_of(&program)))]
+pub async fn run_log_subscription(ws_url: String, program: ProgramKey, sync: SyncContext) {
+    let mut backoff_secs = 1;
+    loop {
+        info!(%ws_url, "Connecting to logsSubscribe");
+        match subscribe_program_logs(&ws_url, &program, &sync).await {
+            Ok(()) => {
+                warn!("Log subscription stream ended, reconnecting");
+                backoff_secs = 1;
+            }
+            Err(e) => warn!(error = %e, backoff_secs, "Log subscription failed"),
+        }
+        sleep(Duration::from_secs(backoff_secs)).await;
+        backoff_secs = (backoff_secs * 2).min(MAX_SUBSCRIBE_BACKOFF_SECS);
+    }
+}
+
+async fn subscribe_program_logs(ws_url: &str, program: &ProgramKey, sync: &SyncContext) -> Result<(), AppError> {
+    let program_id = &program.program_id;
+    let client = PubsubClient::new(ws_url).await?;
+    let filter = RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]);
+    let config = RpcTransactionLogsConfig { commitment: Some(sync.config.commitment) };
+    let (mut stream, unsubscribe) = client.logs_subscribe(filter, config).await?;
+    info!("Subscribed to program logs");
+
+    while let Some(response) = stream.next().await {
+        let slot = response.context.slot;
+        let logs = response.value;
+        // A failed transaction's events were rolled back with it.
+        if logs.err.is_some() {
+            continue;
+        }
+        let idl = sync.idls.get(program);
+        let events = parse_events(&logs.logs, program_id, idl.as_deref());
+        if events.is_empty() {
+            continue;
+        }
+        // Unlike account changes, events missed while paused are only recovered by the
+        // transaction history backfill.
+        let Some(_write) = sync.pause.begin_write().await else {
+            debug!(signature = %logs.signature, "Ingestion is paused; dropping program events");
+            continue;
+        };
+        record_program_events(&sync.pool, program_id, &logs.signature, slot, &events).await?;
+        for event in &events {
+            debug!(signature = %logs.signature, slot, event = %event.name, "Stored program event");
+        }
+    }
+
+    unsubscribe().await;
+    Ok(())
+}
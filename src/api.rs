@@ -0,0 +1,4301 @@
+//! HTTP API: node queries, change feeds, stats and probes.
+
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Extension, FromRef, Request, State,
+    },
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, IntoResponseParts, Json, Response, ResponseParts,
+    },
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use sqlx::postgres::PgPool;
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use tracing::{debug, error, info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::alerts::{
+    self, create_alert_target, AlertKind, AlertRecord, AlertTargetRecord, ALERT_COLUMNS, ALERT_TARGET_COLUMNS,
+};
+use crate::auth::{self, create_api_key, ApiKey, ApiKeyRecord, AuthConfig, API_KEY_COLUMNS};
+use crate::cluster::{Clusters, ProgramKey};
+use crate::compression::CompressionConfig;
+use crate::cors::CorsConfig;
+use crate::dns;
+use crate::counts::{NodeCounts, COUNT_TTL};
+use crate::enrichment::{node_metadata, NodeMetadata};
+use crate::error::{self, ApiError, JsonBody, Path, Query};
+use crate::graphql;
+use crate::openapi;
+use crate::operator::{self, operator_metadata, Challenge, Operator, OperatorMetadata, Session};
+use crate::request_id;
+use crate::response_cache::{self, ResponseCache};
+use crate::events::{EventHub, NodeEvent, SequencedEvent};
+use crate::fields::{FieldsParams, SparseJson};
+use crate::liveness::{node_uptime, LivenessStatus, NodeLiveness, NodeUptime, LIVENESS_COLUMNS};
+use crate::moderation::{self, NodeModeration, NOT_QUARANTINED};
+use crate::pool::DatabaseMetrics;
+use crate::rpc::{RpcMetricsSnapshot, SolanaRpc};
+use crate::store::{clear_decode_failures, ApiNode, NODE_COLUMNS};
+use crate::subscriptions::{ClientMessage, ServerMessage, Subscription, MAX_FILTER_PUBKEYS};
+use crate::sync::{self, AccountUpdate, CycleSummary, IngestionPause, ResyncTrigger, SyncContext, SyncHealth};
+use crate::throttle::{self, ClientRateLimiter};
+use crate::uri;
+use crate::watchlists::{self, Watchlist, WatchlistMatcher};
+use crate::webhooks::{create_webhook, WebhookRecord, WEBHOOK_COLUMNS};
+
+// --- Pagination defaults for list endpoints ---
+pub(crate) const DEFAULT_PAGE_LIMIT: i64 = 100;
+pub(crate) const MAX_PAGE_LIMIT: i64 = 1000;
+
+// --- Total number of results, set on paginated list responses ---
+pub const TOTAL_COUNT_HEADER: HeaderName = HeaderName::from_static("x-total-count");
+
+// --- Longest search text accepted by /nodes/search ---
+const MAX_SEARCH_LEN: usize = 200;
+
+// --- Most pubkeys one POST /nodes/batch request may look up ---
+pub(crate) const MAX_BATCH_PUBKEYS: usize = 500;
+
+// --- Default /leaderboard weights for uptime, latency and registration age ---
+pub(crate) const DEFAULT_UPTIME_WEIGHT: f64 = 0.6;
+pub(crate) const DEFAULT_LATENCY_WEIGHT: f64 = 0.3;
+pub(crate) const DEFAULT_AGE_WEIGHT: f64 = 0.1;
+
+// --- Days ahead /nodes/certificates counts an expiry as soon, by default and at most ---
+pub(crate) const DEFAULT_CERT_EXPIRY_DAYS: i64 = 14;
+pub(crate) const MAX_CERT_EXPIRY_DAYS: i64 = 365;
+
+// --- Accounts refetched per POST /admin/decode-failures/retry ---
+pub(crate) const MAX_DECODE_RETRIES: usize = 100;
+
+// --- Longest reason POST /admin/nodes/:pubkey/quarantine records ---
+const MAX_QUARANTINE_REASON_LEN: usize = 1000;
+
+// --- Longest operator metadata fields PUT /my/nodes/:pubkey/metadata accepts, in characters ---
+const MAX_DISPLAY_NAME_LEN: usize = 64;
+const MAX_CONTACT_LEN: usize = 254;
+const MAX_DESCRIPTION_LEN: usize = 1024;
+
+// --- Longest watchlist name, and most pubkeys and authorities one watchlist may list ---
+const MAX_WATCHLIST_NAME_LEN: usize = 100;
+pub(crate) const MAX_WATCHLIST_ENTRIES: usize = 1000;
+
+// --- Upper bound for each dependency check made by /readyz ---
+const READINESS_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// One row of `nodes_history`, as returned by `GET /nodes/:pubkey/history`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+#[schema(as = HistoryEntry)]
+pub struct ApiHistoryEntry {
+    pub id: i64,
+    pub pubkey: String,
+    /// Cluster of the row the entry is for; `null` for an unnamed default cluster.
+    pub cluster: Option<String>,
+    /// `added`, `updated` or `removed`.
+    pub change_type: String,
+    pub old_authority: Option<String>,
+    pub new_authority: Option<String>,
+    pub old_uri: Option<String>,
+    pub new_uri: Option<String>,
+    pub slot: Option<i64>,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct HistoryPage {
+    pub pubkey: String,
+    pub entries: Vec<ApiHistoryEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// One row of `node_transactions`, as returned by `GET /nodes/:pubkey/transactions`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+#[schema(as = NodeTransaction)]
+pub struct ApiNodeTransaction {
+    pub signature: String,
+    pub slot: i64,
+    pub block_time: Option<DateTime<Utc>>,
+    /// First signer, who paid for the transaction.
+    pub fee_payer: String,
+    pub signers: Vec<String>,
+    /// Program instructions that referenced the node, by IDL name or hex discriminator.
+    pub instructions: Vec<String>,
+    pub succeeded: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TransactionsPage {
+    pub pubkey: String,
+    pub transactions: Vec<ApiNodeTransaction>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// One row of `node_changes`, as returned by `GET /changes`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+#[schema(as = Change)]
+pub struct ApiChange {
+    /// Cursor of this change; pass the last one seen as `since` to continue.
+    pub id: i64,
+    pub pubkey: String,
+    /// `upsert` or `delete`.
+    pub change_type: String,
+    /// Node fields after the change; `None` for deletes.
+    pub authority: Option<String>,
+    pub uri: Option<String>,
+    pub program_id: Option<String>,
+    pub slot: Option<i64>,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ChangesParams {
+    /// Only return changes with a cursor greater than this (default 0, i.e. from the start).
+    #[param(minimum = 0)]
+    pub since: Option<i64>,
+    /// Changes to return (default 100, at most 1000).
+    #[param(minimum = 1, maximum = 1000)]
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChangesPage {
+    pub changes: Vec<ApiChange>,
+    /// Cursor to pass as `since` on the next request. Equal to `since` when nothing new arrived.
+    pub next_cursor: i64,
+    /// `true` when more changes are already available after `next_cursor`.
+    pub has_more: bool,
+}
+
+/// A position in `node_changes`: a cursor (`123` or `cursor:123`) or a slot (`slot:123`).
+#[derive(Clone, Copy, Debug)]
+pub enum ChangePoint {
+    Cursor(i64),
+    Slot(i64),
+}
+
+impl ChangePoint {
+    fn parse(name: &str, value: &str) -> Result<Self, ApiError> {
+        let (point, number): (fn(i64) -> Self, &str) = match value.split_once(':') {
+            None => (Self::Cursor, value),
+            Some(("cursor", number)) => (Self::Cursor, number),
+            Some(("slot", number)) => (Self::Slot, number),
+            Some(_) => return Err(Self::invalid(name, value)),
+        };
+        match number.parse::<i64>() {
+            Ok(number) if number >= 0 => Ok(point(number)),
+            _ => Err(Self::invalid(name, value)),
+        }
+    }
+
+    fn invalid(name: &str, value: &str) -> ApiError {
+        let expected = "expected a cursor such as 123 or a slot such as slot:123";
+        ApiError::bad_request("invalid_query", format!("Invalid `{}` '{}': {}", name, value, expected))
+    }
+
+    /// Condition on `node_changes` row `c` selecting the changes made by this point, with the
+    /// cursor or slot bound as `param`.
+    fn reached(self, param: &str) -> String {
+        match self {
+            Self::Cursor(_) => format!("c.id <= {}", param),
+            Self::Slot(_) => format!("c.slot <= {}", param),
+        }
+    }
+
+    fn value(self) -> i64 {
+        match self {
+            Self::Cursor(cursor) | Self::Slot(cursor) => cursor,
+        }
+    }
+}
+
+impl std::fmt::Display for ChangePoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cursor(cursor) => write!(f, "cursor:{}", cursor),
+            Self::Slot(slot) => write!(f, "slot:{}", slot),
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DiffParams {
+    /// Start point, exclusive: a cursor (`123` or `cursor:123`) or a slot (`slot:123`).
+    pub from: String,
+    /// End point, inclusive; defaults to the latest change.
+    pub to: Option<String>,
+}
+
+/// A node's fields at one end of a diff.
+#[derive(Serialize, ToSchema, PartialEq)]
+pub struct DiffNode {
+    pub pubkey: String,
+    /// `null` for an unnamed default cluster.
+    pub cluster: Option<String>,
+    pub authority: String,
+    pub uri: String,
+    pub program_id: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ModifiedNode {
+    pub pubkey: String,
+    pub cluster: Option<String>,
+    pub before: DiffNode,
+    pub after: DiffNode,
+}
+
+/// Changes between two points of `node_changes`, as returned by `GET /diff`. Pages run over
+/// the nodes changed in between, by pubkey and cluster; a node changed and then restored is in none of
+/// the lists, so a page can hold fewer than `limit` nodes.
+#[derive(Serialize, ToSchema)]
+pub struct NodeDiffPage {
+    pub from: String,
+    pub to: String,
+    pub added: Vec<DiffNode>,
+    pub removed: Vec<DiffNode>,
+    pub modified: Vec<ModifiedNode>,
+    /// Nodes changed between `from` and `to`, each pubkey counted once per cluster.
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// A pubkey's latest change at or before each end of a diff; the `before_` columns are `None`
+/// when it had no change yet at `from`.
+#[derive(sqlx::FromRow)]
+struct DiffRow {
+    pubkey: String,
+    cluster: Option<String>,
+    before_type: Option<String>,
+    before_authority: Option<String>,
+    before_uri: Option<String>,
+    before_program_id: Option<String>,
+    after_type: Option<String>,
+    after_authority: Option<String>,
+    after_uri: Option<String>,
+    after_program_id: Option<String>,
+}
+
+/// One row of `program_events`, as returned by `GET /program-events`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+#[schema(as = ProgramEvent)]
+pub struct ApiProgramEvent {
+    /// Cursor of this event; pass the last one seen as `since` to continue.
+    pub id: i64,
+    pub program_id: String,
+    pub signature: String,
+    /// Position of the event among the program's events in its transaction.
+    pub event_index: i32,
+    pub slot: i64,
+    /// IDL event name, or the hex event discriminator when no IDL describes it.
+    pub name: String,
+    /// Decoded event fields; `None` when no IDL describes the event.
+    pub data: Option<serde_json::Value>,
+    /// Hex-encoded event bytes, discriminator included.
+    pub raw_data: String,
+    pub observed_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ProgramEventsParams {
+    /// Only return events with a cursor greater than this (default 0, i.e. from the start).
+    #[param(minimum = 0)]
+    pub since: Option<i64>,
+    /// Events to return (default 100, at most 1000).
+    #[param(minimum = 1, maximum = 1000)]
+    pub limit: Option<i64>,
+    /// Only return events of this program ID.
+    pub program: Option<String>,
+    /// Only return events with this name, e.g. `NodeDeregistered`.
+    pub name: Option<String>,
+    /// Only return events with a top-level field equal to this pubkey.
+    pub pubkey: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ProgramEventsPage {
+    pub events: Vec<ApiProgramEvent>,
+    /// Cursor to pass as `since` on the next request. Equal to `since` when nothing new arrived.
+    pub next_cursor: i64,
+    /// `true` when more events are already available after `next_cursor`.
+    pub has_more: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedWebhook {
+    /// Key for verifying `X-Webhook-Signature`. Shown only once.
+    pub secret: String,
+    #[serde(flatten)]
+    pub record: WebhookRecord,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateAlertTargetRequest {
+    /// Authority whose nodes are watched.
+    pub authority: String,
+    pub kind: AlertKind,
+    /// Webhook URL or email address.
+    pub target: String,
+}
+
+/// Body of `POST /my/alert-targets`, for the session's authority.
+#[derive(Deserialize, ToSchema)]
+pub struct OperatorAlertTargetRequest {
+    /// Webhook URL alerts are posted to.
+    pub url: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedAlertTarget {
+    /// Key for verifying `X-Alert-Signature`, for webhook targets. Shown only once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(flatten)]
+    pub record: AlertTargetRecord,
+}
+
+/// Body of `PUT /watchlists/:id`, and with a webhook URL of `POST /watchlists`.
+#[derive(Deserialize, ToSchema)]
+pub struct WatchlistRequest {
+    pub name: String,
+    /// Nodes watched by pubkey.
+    #[serde(default)]
+    pub pubkeys: Vec<String>,
+    /// Authorities every node of which is watched.
+    #[serde(default)]
+    pub authorities: Vec<String>,
+}
+
+/// Body of `POST /watchlists`.
+#[derive(Deserialize, ToSchema)]
+pub struct CreateWatchlistRequest {
+    #[serde(flatten)]
+    pub watchlist: WatchlistRequest,
+    /// Also deliver matching changes to this URL, like an admin webhook.
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedWatchlist {
+    /// Key for verifying the webhook's `X-Webhook-Signature`, with a `webhook_url`. Shown only once.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_secret: Option<String>,
+    #[serde(flatten)]
+    pub watchlist: Watchlist,
+}
+
+/// Filter accepted by `GET /admin/alert-targets`.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AlertTargetsParams {
+    /// Only list targets of this authority.
+    pub authority: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AlertsPage {
+    pub alerts: Vec<AlertRecord>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// Ranking weights accepted by `GET /leaderboard`. Each must be non-negative; they are
+/// normalised by their sum, so only their ratios matter.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LeaderboardParams {
+    /// Only rank nodes owned by this program ID.
+    pub program: Option<String>,
+    /// Only rank nodes indexed from this cluster, e.g. `mainnet`.
+    pub cluster: Option<String>,
+    /// Weight of 30-day uptime in the score (default 0.6).
+    #[param(minimum = 0)]
+    pub uptime_weight: Option<f64>,
+    /// Weight of average probe latency in the score (default 0.3).
+    #[param(minimum = 0)]
+    pub latency_weight: Option<f64>,
+    /// Weight of registration age in the score (default 0.1).
+    #[param(minimum = 0)]
+    pub age_weight: Option<f64>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Copy)]
+pub struct LeaderboardWeights {
+    pub uptime: f64,
+    pub latency: f64,
+    pub age: f64,
+}
+
+/// One ranked node. `score` runs from 0 to 1: 30-day uptime, plus where the node's average
+/// latency and registration age fall among the ranked nodes (fastest and oldest score 1).
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct LeaderboardEntry {
+    #[sqlx(skip)]
+    pub rank: i64,
+    pub pubkey: String,
+    pub uri: String,
+    pub score: f64,
+    pub uptime_30d: Option<f64>,
+    pub avg_latency_ms: Option<f64>,
+    pub first_seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LeaderboardPage {
+    pub weights: LeaderboardWeights,
+    pub nodes: Vec<LeaderboardEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// One authority and the nodes it controls, as listed by `GET /authorities`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+#[schema(as = Authority)]
+pub struct AuthoritySummary {
+    pub authority: String,
+    pub node_count: i64,
+    /// Earliest and latest `first_seen_at` among its nodes.
+    pub first_registered_at: DateTime<Utc>,
+    pub last_registered_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AuthoritiesPage {
+    pub authorities: Vec<AuthoritySummary>,
+    /// Distinct authorities matching the filter.
+    pub total: i64,
+    /// Nodes matching the filter, to work out each authority's share from.
+    pub total_nodes: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// Window accepted by `GET /nodes/certificates`.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CertificateParams {
+    /// Also list certificates expiring within this many days (default 14, at most 365).
+    #[param(minimum = 0, maximum = 365)]
+    pub within_days: Option<i64>,
+}
+
+/// The certificate one node's URI presented to its latest probe, as listed by
+/// `GET /nodes/certificates`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct NodeCertificate {
+    pub pubkey: String,
+    pub uri: String,
+    pub cert_valid: bool,
+    pub cert_hostname_match: bool,
+    pub cert_expires_at: Option<DateTime<Utc>>,
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CertificatesPage {
+    pub nodes: Vec<NodeCertificate>,
+    pub within_days: i64,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    /// Grants access to the `/admin` routes (default `false`).
+    pub admin: Option<bool>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CreatedApiKey {
+    /// The plaintext key. Only its hash is stored, so it can't be shown again.
+    pub key: String,
+    #[serde(flatten)]
+    pub record: ApiKeyRecord,
+}
+
+/// The pool read-only handlers query: a read replica when `DATABASE_READ_URL` is set, the
+/// primary otherwise. Replicas may lag the primary, so anything that writes, or must see its
+/// own writes (API keys, webhooks), stays on [`AppState::pool`].
+#[derive(Clone)]
+pub struct ReadPool(pub PgPool);
+
+/// Shared state handed to every route.
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub pool: PgPool,
+    pub read_pool: ReadPool,
+    pub events: EventHub,
+    pub program_ids: Vec<Pubkey>,
+    pub rpc: Arc<SolanaRpc>,
+    pub health: SyncHealth,
+    pub auth: AuthConfig,
+    pub compression: CompressionConfig,
+    pub cors: CorsConfig,
+    /// Per-client request limits; `None` leaves clients unthrottled.
+    pub rate_limiter: Option<Arc<ClientRateLimiter>>,
+    /// Reaches this process's reconciliation loops, if it runs any.
+    pub resync: ResyncTrigger,
+    /// Pause switch shared with this process's ingestion tasks.
+    pub pause: IngestionPause,
+    pub counts: Arc<NodeCounts>,
+    /// Write path shared with ingestion, for admin routes that re-ingest accounts.
+    pub sync: SyncContext,
+    /// Cached responses of hot routes; `None` when caching is off.
+    pub response_cache: Option<Arc<ResponseCache>>,
+}
+
+/// Result of the cycle `POST /admin/resync` ran for one program.
+#[derive(Serialize, ToSchema)]
+pub struct ProgramResync {
+    pub program_id: String,
+    /// `null` for an unnamed default cluster.
+    pub cluster: Option<String>,
+    #[serde(flatten)]
+    pub summary: CycleSummary,
+}
+
+/// One row of `decode_failures`: an account whose data didn't decode, so nothing was stored
+/// for it.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct DecodeFailure {
+    pub pubkey: String,
+    pub program_id: String,
+    /// Hex-encoded account data as last fetched.
+    pub raw_data: String,
+    pub error: String,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+/// `GET /admin/decode-failures?program=`: only failures of that program.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DecodeFailuresParams {
+    /// Only list failures of this program ID.
+    pub program: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DecodeFailuresPage {
+    pub failures: Vec<DecodeFailure>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// Body of `POST /admin/decode-failures/retry`. Without `pubkeys`, the failures seen longest
+/// ago are retried, up to [`MAX_DECODE_RETRIES`].
+#[derive(Deserialize, ToSchema)]
+pub struct DecodeRetryRequest {
+    pub pubkeys: Option<Vec<String>>,
+}
+
+/// Body of `POST /admin/nodes/:pubkey/quarantine`.
+#[derive(Deserialize, ToSchema)]
+pub struct QuarantineRequest {
+    /// Why the node is hidden, e.g. the URI it was registered with.
+    pub reason: Option<String>,
+}
+
+/// Body of `POST /my/challenge`.
+#[derive(Deserialize, ToSchema)]
+pub struct ChallengeRequest {
+    pub authority: String,
+}
+
+/// Body of `POST /my/session`.
+#[derive(Deserialize, ToSchema)]
+pub struct SessionRequest {
+    pub authority: String,
+    /// Nonce of the challenge signed.
+    pub nonce: String,
+    /// Base58 ed25519 signature of the challenge message by the authority's keypair.
+    pub signature: String,
+}
+
+/// Body of `PUT /my/nodes/:pubkey/metadata`, replacing all three fields; blank ones are cleared.
+#[derive(Deserialize, ToSchema)]
+pub struct OperatorMetadataRequest {
+    pub display_name: Option<String>,
+    pub contact: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Outcome of `POST /admin/decode-failures/retry`.
+#[derive(Serialize, ToSchema)]
+pub struct DecodeRetry {
+    pub retried: usize,
+    /// Accounts that now decode, or have been closed, and left the table.
+    pub resolved: Vec<String>,
+    /// Accounts that still fail, with the error of this attempt.
+    pub failing: Vec<DecodeFailure>,
+}
+
+/// Ingestion state returned by `POST /admin/pause` and `POST /admin/resume`.
+#[derive(Serialize, ToSchema)]
+pub struct PauseStatus {
+    pub paused: bool,
+}
+
+/// Per-program sync status reported by `/readyz`.
+#[derive(Serialize, ToSchema)]
+pub struct ProgramReadiness {
+    pub program_id: String,
+    /// `null` for an unnamed default cluster.
+    pub cluster: Option<String>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    /// Snapshot slot of the last successful cycle, from this process or its checkpoint.
+    pub last_synced_slot: Option<u64>,
+    /// Seconds since the last successful cycle.
+    pub lag_secs: Option<i64>,
+    pub fresh: bool,
+}
+
+/// A background task restarted after a panic that hasn't stayed up long enough yet.
+#[derive(Serialize, ToSchema)]
+pub struct RecoveringTask {
+    pub task: String,
+    pub panicked_at: DateTime<Utc>,
+    pub panic: String,
+    /// Panics in a row, each followed by a restart.
+    pub panics: u32,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(as = Readiness)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub database: bool,
+    pub rpc: bool,
+    pub programs: Vec<ProgramReadiness>,
+    pub recovering: Vec<RecoveringTask>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(as = Stats)]
+pub struct StatsResponse {
+    pub program_ids: Vec<String>,
+    #[serde(flatten)]
+    pub stats: ApiStats,
+    /// Latest reconciled snapshot per program.
+    pub snapshots: Vec<ApiSnapshot>,
+    /// The first cluster's RPC client; `clusters` has every cluster's.
+    pub rpc: RpcMetricsSnapshot,
+    pub clusters: Vec<ClusterStats>,
+    /// How far each program's index trails its cluster.
+    pub lag: Vec<ProgramLag>,
+    /// Connection pool utilization of this process.
+    pub database: DatabaseMetrics,
+    /// `true` while ingestion in this process is paused by `POST /admin/pause`.
+    pub paused: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ClusterStats {
+    /// `null` for an unnamed default cluster.
+    pub name: Option<String>,
+    pub program_ids: Vec<String>,
+    pub rpc: RpcMetricsSnapshot,
+}
+
+/// Slots a program's last synced snapshot trails its cluster, as of the lag monitor's last read.
+#[derive(Serialize, ToSchema)]
+pub struct ProgramLag {
+    pub program_id: String,
+    /// `null` for an unnamed default cluster.
+    pub cluster: Option<String>,
+    /// `null` until the cluster's slot has been read.
+    pub cluster_slot: Option<u64>,
+    /// `null` until the program has synced.
+    pub synced_slot: Option<u64>,
+    pub synced_at: Option<DateTime<Utc>>,
+    pub lag_slots: Option<u64>,
+}
+
+impl ProgramLag {
+    fn of(program_id: &ProgramKey, health: &SyncHealth, clusters: &Clusters) -> Self {
+        Self {
+            program_id: program_id.program_id.to_string(),
+            cluster: clusters.name_of(program_id).map(str::to_string),
+            cluster_slot: health.cluster_slot(program_id),
+            synced_slot: health.last_synced_slot(program_id),
+            synced_at: health.last_success(program_id),
+            lag_slots: health.lag_slots(program_id),
+        }
+    }
+}
+
+/// `GET /stats/history?resolution=hour|day&limit=`: the latest `limit` buckets.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsHistoryParams {
+    /// Bucket size (default `hour`).
+    pub resolution: Option<StatsResolution>,
+    /// Most recent buckets to return (default 100, at most 1000).
+    #[param(minimum = 1, maximum = 1000)]
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsResolution {
+    Hour,
+    Day,
+}
+
+impl StatsResolution {
+    /// The `date_trunc` field buckets are cut at.
+    fn unit(self) -> &'static str {
+        match self {
+            Self::Hour => "hour",
+            Self::Day => "day",
+        }
+    }
+}
+
+/// Network totals at the end of one bucket of `network_stats_history`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct StatsPoint {
+    /// Start of the bucket, in UTC.
+    pub bucket: DateTime<Utc>,
+    /// From the bucket's last sample.
+    pub total_nodes: i64,
+    /// From the bucket's last sample; `None` until the liveness prober has run.
+    pub online_nodes: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct StatsHistory {
+    pub resolution: StatsResolution,
+    /// Oldest first; buckets without samples are left out.
+    pub points: Vec<StatsPoint>,
+}
+
+/// `GET /stats/growth?period=day|week&limit=`: the latest `limit` periods.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct GrowthParams {
+    /// Period length (default `day`); weeks start on Monday.
+    pub period: Option<GrowthPeriod>,
+    /// Most recent periods to return (default 100, at most 1000).
+    #[param(minimum = 1, maximum = 1000)]
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize, ToSchema, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum GrowthPeriod {
+    Day,
+    Week,
+}
+
+impl GrowthPeriod {
+    /// The `date_trunc` field periods are cut at; weeks start on Monday.
+    fn unit(self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+        }
+    }
+}
+
+/// Registrations and deregistrations recorded in `nodes_history` during one period.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct GrowthPoint {
+    /// Start of the period, in UTC.
+    pub period_start: DateTime<Utc>,
+    pub registrations: i64,
+    pub deregistrations: i64,
+    /// `registrations - deregistrations`.
+    pub net_growth: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct GrowthReport {
+    pub period: GrowthPeriod,
+    /// Oldest first, ending with the current period; periods without changes count zero.
+    pub periods: Vec<GrowthPoint>,
+    pub registrations: i64,
+    pub deregistrations: i64,
+    pub net_growth: i64,
+}
+
+/// One row of `program_snapshots`.
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+#[schema(as = Snapshot)]
+pub struct ApiSnapshot {
+    pub program_id: String,
+    /// Slot the getProgramAccounts response was served at.
+    pub snapshot_slot: i64,
+    pub account_count: i64,
+    pub synced_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct ApiStats {
+    pub total_nodes: i64,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub last_indexed_slot: Option<i64>,
+}
+
+/// Filters accepted by node list endpoints.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NodeFilter {
+    /// Only return nodes owned by this program ID.
+    pub program: Option<String>,
+    /// Only return nodes indexed from this cluster, e.g. `mainnet`.
+    pub cluster: Option<String>,
+    /// Also return soft-deleted nodes (default `false`).
+    pub include_deleted: Option<bool>,
+    /// Only return nodes whose URI is `online`, `offline` or `unknown` to the URI prober.
+    pub status: Option<LivenessStatus>,
+    /// Only return nodes whose stored data comes from a finalized slot (default `false`).
+    pub finalized_only: Option<bool>,
+    /// Also return nodes whose URI failed validation on ingest (default `false`).
+    pub include_invalid: Option<bool>,
+    /// Also return nodes whose address isn't their expected PDA (default `false`).
+    pub include_unverified: Option<bool>,
+    /// Only return nodes whose account is (`true`) or isn't (`false`) rent-exempt; nodes whose
+    /// balance isn't known yet match neither.
+    pub rent_exempt: Option<bool>,
+    /// Only return nodes whose URI's host did (`true`) or didn't (`false`) resolve at its
+    /// latest probe; never-probed nodes match neither.
+    pub dns_ok: Option<bool>,
+}
+
+impl NodeFilter {
+    /// SQL condition for `finalized_only` on a row with a `finalized` column.
+    pub(crate) fn finality(&self) -> &'static str {
+        if self.finalized_only.unwrap_or(false) { "finalized" } else { "TRUE" }
+    }
+
+    /// SQL condition on `nodes` for `status`, `finalized_only`, `include_invalid`,
+    /// `include_unverified`, `rent_exempt` and `dns_ok`, which also leaves out quarantined nodes.
+    pub(crate) fn condition(&self) -> String {
+        let status = self.status.map_or("TRUE", LivenessStatus::condition);
+        let validity = if self.include_invalid.unwrap_or(false) { "TRUE" } else { "uri_valid" };
+        // Unchecked addresses (null) are listed; only failed checks are left out.
+        let verification = if self.include_unverified.unwrap_or(false) { "TRUE" } else { "pda_verified IS NOT FALSE" };
+        let rent = match self.rent_exempt {
+            Some(true) => "rent_exempt",
+            Some(false) => "NOT rent_exempt",
+            None => "TRUE",
+        };
+        let resolution = self.dns_ok.map_or("TRUE", dns::condition);
+        format!(
+            "{} AND {} AND {} AND {} AND {} AND {} AND {}",
+            status,
+            self.finality(),
+            validity,
+            verification,
+            rent,
+            resolution,
+            NOT_QUARANTINED
+        )
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// Page size (default 100, at most 1000).
+    #[param(minimum = 1, maximum = 1000)]
+    pub limit: Option<i64>,
+    /// Rows to skip (default 0).
+    #[param(minimum = 0)]
+    pub offset: Option<i64>,
+}
+
+impl PageParams {
+    /// Clamps the requested window to sane bounds.
+    pub(crate) fn resolve(&self) -> (i64, i64) {
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+        let offset = self.offset.unwrap_or(0).max(0);
+        (limit, offset)
+    }
+}
+
+/// Order of `GET /nodes`: `?sort=updated_at|pubkey|authority&order=asc|desc`, by pubkey
+/// ascending unless given.
+#[derive(Deserialize, IntoParams, Default)]
+#[into_params(parameter_in = Query)]
+pub struct SortParams {
+    /// Column to order by (default `pubkey`); ties are ordered by pubkey.
+    pub sort: Option<NodeSortKey>,
+    /// Sort direction (default `asc`).
+    pub order: Option<SortOrder>,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeSortKey {
+    UpdatedAt,
+    Pubkey,
+    Authority,
+}
+
+#[derive(Deserialize, ToSchema, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortParams {
+    /// `ORDER BY` clause on `nodes`. Ties are broken by pubkey in the same direction, which
+    /// the `(column, pubkey)` indexes serve, so pages stay stable between requests.
+    fn order_by(&self) -> String {
+        let direction = match self.order.unwrap_or(SortOrder::Asc) {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        };
+        match self.sort.unwrap_or(NodeSortKey::Pubkey) {
+            NodeSortKey::Pubkey => format!("pubkey {}", direction),
+            NodeSortKey::UpdatedAt => format!("updated_at {0}, pubkey {0}", direction),
+            NodeSortKey::Authority => format!("authority {0}, pubkey {0}", direction),
+        }
+    }
+}
+
+/// `GET /nodes?as_of_slot=`: list the nodes as they were at that slot instead of now.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AsOfParams {
+    /// List the nodes as they were at this slot, rebuilt from the change history. Nodes already
+    /// stored when history began count from the slot they were last seen at. Can't be combined
+    /// with `status`, `rent_exempt` or `dns_ok`, and `include_invalid` and `include_unverified`
+    /// are implied.
+    #[param(minimum = 0)]
+    pub as_of_slot: Option<i64>,
+}
+
+/// Text matched against node URIs by `GET /nodes/search`.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    /// Text the URI must contain, case-insensitively, e.g. a hostname fragment.
+    #[param(min_length = 1, max_length = 200)]
+    pub q: String,
+}
+
+/// URI looked up by `GET /nodes/by-uri`.
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UriParams {
+    /// URI to match exactly, or after normalizing both sides.
+    pub uri: String,
+}
+
+/// Nodes registered with the same endpoint, as returned by `GET /nodes/by-uri`; more than one
+/// means duplicate registrations.
+#[derive(Serialize, ToSchema)]
+pub struct UriMatches {
+    pub uri: String,
+    /// The form URIs are compared in: scheme and host lowercased, default port and trailing
+    /// slashes dropped.
+    pub normalized_uri: String,
+    /// Earliest registration first.
+    pub nodes: Vec<UriMatch>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UriMatch {
+    /// `false` when the node's URI only matches once both are normalized.
+    pub exact: bool,
+    #[serde(flatten)]
+    pub node: NodeWithUptime,
+}
+
+#[derive(sqlx::FromRow)]
+struct UriMatchRow {
+    exact: bool,
+    #[sqlx(flatten)]
+    node: ApiNode,
+}
+
+/// Body of `POST /nodes/batch`.
+#[derive(Deserialize, ToSchema)]
+pub struct BatchLookupRequest {
+    pub pubkeys: Vec<String>,
+    /// Only look on this cluster. A pubkey indexed from several clusters otherwise resolves as
+    /// `GET /nodes/:pubkey` does.
+    pub cluster: Option<String>,
+    /// Also return soft-deleted nodes (default `false`).
+    pub include_deleted: Option<bool>,
+}
+
+/// Answer to `POST /nodes/batch`: one result per distinct requested pubkey, in request order.
+#[derive(Serialize, ToSchema)]
+pub struct BatchLookup {
+    pub results: Vec<BatchResult>,
+    pub found: usize,
+    pub not_found: usize,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchResult {
+    pub pubkey: String,
+    pub found: bool,
+    /// `None` when no node is indexed under `pubkey`.
+    pub node: Option<NodeWithUptime>,
+}
+
+/// A node with its URI uptime and operator metadata, as returned by `GET /nodes`.
+#[derive(Serialize, ToSchema)]
+pub struct NodeWithUptime {
+    #[serde(flatten)]
+    pub node: ApiNode,
+    /// Rolling URI uptime from the liveness prober; all `null` until the URI has been probed.
+    pub uptime: NodeUptime,
+    /// What the node's authority attached to it (see [`crate::operator`]); `null` when nothing.
+    pub operator_metadata: Option<OperatorMetadata>,
+}
+
+/// A node as returned by `GET /nodes/:pubkey`.
+#[derive(Serialize, ToSchema)]
+pub struct NodeDetail {
+    #[serde(flatten)]
+    pub node: NodeWithUptime,
+    /// The document the node serves under its URI (see [`crate::enrichment`]); `null` until fetched.
+    pub metadata: Option<NodeMetadata>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct NodesPage {
+    pub nodes: Vec<NodeWithUptime>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    /// Offset to request the following page with, or `None` on the last page.
+    pub next_offset: Option<i64>,
+}
+
+/// Nodes as of a past slot, as returned by `GET /nodes?as_of_slot=`. Each node is its latest
+/// `nodes_history` entry at or before the slot: `updated_at` and `last_seen_slot` are that
+/// change's, and `deleted_at` is set when it was a removal (only with `include_deleted`).
+#[derive(Serialize, ToSchema)]
+pub struct NodeSnapshotPage {
+    pub as_of_slot: i64,
+    pub nodes: Vec<ApiNode>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    pub next_offset: Option<i64>,
+}
+
+/// Body of `GET /nodes`: the current nodes, or with `as_of_slot` the nodes as they were then.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum NodeListing {
+    Current(NodesPage),
+    AsOf(NodeSnapshotPage),
+}
+
+/// Sets `X-Total-Count` on a paginated list response to the `total` of its body.
+pub struct TotalCount(pub i64);
+
+impl IntoResponseParts for TotalCount {
+    type Error = Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.headers_mut().insert(TOTAL_COUNT_HEADER, HeaderValue::from(self.0));
+        Ok(res)
+    }
+}
+
+/// Answer to `GET /nodes/count`.
+#[derive(Serialize, ToSchema)]
+pub struct NodeCount {
+    pub count: i64,
+}
+
+/// The `limit`/`offset` page of the nodes `filter` selects in `sort` order, with their uptime,
+/// and the total number selected, as cached by `counts`. Shared with the gRPC service.
+pub(crate) async fn load_nodes(
+    pool: &PgPool,
+    counts: &NodeCounts,
+    filter: &NodeFilter,
+    sort: &SortParams,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<NodeWithUptime>, i64), sqlx::Error> {
+    let total = counts.count(pool, filter).await?;
+    let include_deleted = filter.include_deleted.unwrap_or(false);
+    let condition = filter.condition();
+    let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE ($3::text IS NULL OR program_id = $3) AND ($4 OR deleted_at IS NULL) \
+         AND ($5::text IS NULL OR cluster = $5) AND {} ORDER BY {} LIMIT $1 OFFSET $2",
+        NODE_COLUMNS,
+        condition,
+        sort.order_by()
+    ))
+    .bind(limit)
+    .bind(offset)
+    .bind(&filter.program)
+    .bind(include_deleted)
+    .bind(&filter.cluster)
+    .fetch_all(pool)
+    .await?;
+
+    Ok((with_uptime(pool, nodes).await?, total))
+}
+
+/// The `limit`/`offset` page of the nodes `filter` selects as they were at `slot`, rebuilt from
+/// `nodes_history`, and the total number selected. `filter.status` is ignored.
+async fn load_nodes_as_of(
+    pool: &PgPool,
+    slot: i64,
+    filter: &NodeFilter,
+    sort: &SortParams,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<ApiNode>, i64), sqlx::Error> {
+    // Each cluster's row is rebuilt separately. Removals keep the last authority and URI in the
+    // `old_` columns. `first_seen_at` never changes, so it comes from the row in `nodes` when it
+    // still exists, as do the V2 fields, which the history doesn't keep.
+    let snapshot = r#"
+        WITH latest AS (
+            SELECT DISTINCT ON (h.pubkey, h.cluster) h.pubkey, h.change_type, h.slot, h.changed_at,
+                   COALESCE(h.new_authority, h.old_authority) AS authority,
+                   COALESCE(h.new_uri, h.old_uri) AS uri, h.program_id, h.cluster
+            FROM nodes_history h
+            WHERE h.slot <= $1
+            ORDER BY h.pubkey, h.cluster, h.slot DESC, h.id DESC
+        ),
+        snapshot AS (
+            SELECT l.pubkey, l.authority, l.uri, l.program_id, l.cluster, n.first_seen_at, l.changed_at AS updated_at,
+                   l.slot AS last_seen_slot,
+                   CASE WHEN l.change_type = 'removed' THEN l.changed_at END AS deleted_at,
+                   n.name, n.registered_at, COALESCE(n.layout_version, 1::smallint) AS layout_version,
+                   -- Finalized slots only ever grow, so anything up to the row's is too.
+                   COALESCE(n.finalized AND l.slot <= n.last_seen_slot, FALSE) AS finalized,
+                   -- The past URI may not be the one the row's flag is for; checked below instead.
+                   TRUE AS uri_valid, n.pda_verified,
+                   -- Balances aren't kept over time.
+                   NULL::bigint AS lamports, NULL::boolean AS rent_exempt
+            FROM latest l
+            LEFT JOIN nodes n ON n.pubkey = l.pubkey AND n.cluster IS NOT DISTINCT FROM l.cluster
+        )
+    "#;
+    let include_deleted = filter.include_deleted.unwrap_or(false);
+
+    let total: i64 = sqlx::query_scalar(&format!(
+        "{} SELECT COUNT(*) FROM snapshot \
+         WHERE ($2::text IS NULL OR program_id = $2) AND ($3 OR deleted_at IS NULL) \
+           AND ($4::text IS NULL OR cluster = $4) AND {} AND {}",
+        snapshot,
+        filter.finality(),
+        NOT_QUARANTINED
+    ))
+    .bind(slot)
+    .bind(&filter.program)
+    .bind(include_deleted)
+    .bind(&filter.cluster)
+    .fetch_one(pool)
+    .await?;
+
+    let mut nodes = sqlx::query_as::<_, ApiNode>(&format!(
+        "{} SELECT {} FROM snapshot WHERE ($2::text IS NULL OR program_id = $2) AND ($3 OR deleted_at IS NULL) \
+         AND ($6::text IS NULL OR cluster = $6) AND {} AND {} ORDER BY {} LIMIT $4 OFFSET $5",
+        snapshot,
+        NODE_COLUMNS,
+        filter.finality(),
+        NOT_QUARANTINED,
+        sort.order_by()
+    ))
+    .bind(slot)
+    .bind(&filter.program)
+    .bind(include_deleted)
+    .bind(limit)
+    .bind(offset)
+    .bind(&filter.cluster)
+    .fetch_all(pool)
+    .await?;
+    for node in &mut nodes {
+        node.uri_valid = uri::is_valid(&node.uri);
+    }
+
+    Ok((nodes, total))
+}
+
+/// Pairs each node with its URI uptime and operator metadata.
+async fn with_uptime(pool: &PgPool, nodes: Vec<ApiNode>) -> Result<Vec<NodeWithUptime>, sqlx::Error> {
+    let pubkeys: Vec<String> = nodes.iter().map(|node| node.pubkey.clone()).collect();
+    let uptime = node_uptime(pool, &pubkeys).await?;
+    let mut metadata = operator_metadata(pool, &pubkeys).await?;
+    Ok(nodes
+        .into_iter()
+        .map(|node| {
+            let uptime = uptime.get(&node.pubkey).copied().unwrap_or_default();
+            let operator_metadata = metadata.remove(&node.pubkey);
+            NodeWithUptime { node, uptime, operator_metadata }
+        })
+        .collect())
+}
+
+/// The node indexed under `pubkey`, with its uptime and operator metadata. Soft-deleted nodes
+/// only with `include_deleted`. A pubkey indexed from several clusters resolves to the unnamed
+/// default cluster's row, then by cluster name, unless `cluster` picks one.
+pub(crate) async fn load_node(
+    pool: &PgPool,
+    pubkey: &str,
+    cluster: Option<&str>,
+    include_deleted: bool,
+) -> Result<Option<NodeWithUptime>, sqlx::Error> {
+    let node = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE pubkey = $1 AND ($2 OR deleted_at IS NULL) AND ($3::text IS NULL OR cluster = $3) \
+         AND {} ORDER BY cluster NULLS FIRST LIMIT 1",
+        NODE_COLUMNS, NOT_QUARANTINED
+    ))
+    .bind(pubkey)
+    .bind(include_deleted)
+    .bind(cluster)
+    .fetch_optional(pool)
+    .await?;
+    let Some(node) = node else { return Ok(None) };
+    Ok(with_uptime(pool, vec![node]).await?.pop())
+}
+
+/// Network totals and the latest snapshot of each of `program_ids`. Shared with GraphQL and gRPC.
+pub(crate) async fn load_stats(
+    pool: &PgPool,
+    program_ids: &[String],
+) -> Result<(ApiStats, Vec<ApiSnapshot>), sqlx::Error> {
+    let stats = sqlx::query_as::<_, ApiStats>(
+        "SELECT total_nodes, last_synced_at, last_indexed_slot FROM network_stats WHERE id = 1",
+    )
+    .fetch_optional(pool)
+    .await?
+    // Before the first sync cycle completes there is no stats row yet.
+    .unwrap_or(ApiStats { total_nodes: 0, last_synced_at: None, last_indexed_slot: None });
+
+    let snapshots = sqlx::query_as::<_, ApiSnapshot>(
+        "SELECT program_id, snapshot_slot, account_count, synced_at FROM program_snapshots \
+         WHERE program_id = ANY($1) ORDER BY program_id",
+    )
+    .bind(program_ids)
+    .fetch_all(pool)
+    .await?;
+    Ok((stats, snapshots))
+}
+
+#[utoipa::path(
+    get,
+    path = "/nodes",
+    tag = "nodes",
+    summary = "List indexed nodes",
+    params(PageParams, NodeFilter, SortParams, FieldsParams, AsOfParams),
+    responses(
+        (
+            status = 200,
+            description = "A page of nodes, trimmed to `fields` if given",
+            body = NodeListing,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, description = "A query parameter is malformed or `fields` names an unknown field"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_nodes(
+    State(ReadPool(pool)): State<ReadPool>,
+    State(counts): State<Arc<NodeCounts>>,
+    Query(params): Query<PageParams>,
+    Query(filter): Query<NodeFilter>,
+    Query(sort): Query<SortParams>,
+    Query(fields): Query<FieldsParams>,
+    Query(as_of): Query<AsOfParams>,
+) -> Result<(TotalCount, SparseJson<NodeListing>), ApiError> {
+    let (limit, offset) = params.resolve();
+    let fields = fields.resolve()?;
+    debug!(limit, offset, sort = ?sort.sort, order = ?sort.order, "=> GET /nodes - Fetching nodes from database");
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch nodes from database");
+
+    if let Some(as_of_slot) = as_of.as_of_slot {
+        if as_of_slot < 0 {
+            return Err(ApiError::bad_request("invalid_query", "`as_of_slot` must not be negative"));
+        }
+        // Probe results are only kept for the current URI.
+        if filter.status.is_some() {
+            return Err(ApiError::bad_request("invalid_query", "`status` can't be combined with `as_of_slot`"));
+        }
+        // Nor are past balances.
+        if filter.rent_exempt.is_some() {
+            return Err(ApiError::bad_request("invalid_query", "`rent_exempt` can't be combined with `as_of_slot`"));
+        }
+        if filter.dns_ok.is_some() {
+            return Err(ApiError::bad_request("invalid_query", "`dns_ok` can't be combined with `as_of_slot`"));
+        }
+        let (nodes, total) =
+            load_nodes_as_of(&pool, as_of_slot, &filter, &sort, limit, offset).await.map_err(db_error)?;
+        let next_offset = (offset + (nodes.len() as i64) < total).then(|| offset + nodes.len() as i64);
+
+        debug!(as_of_slot, returned = nodes.len(), total, "<= GET /nodes - Responding with past nodes");
+        let body = NodeListing::AsOf(NodeSnapshotPage { as_of_slot, nodes, total, limit, offset, next_offset });
+        return Ok((TotalCount(total), SparseJson { body, list: "nodes", fields }));
+    }
+
+    let (nodes, total) = load_nodes(&pool, &counts, &filter, &sort, limit, offset).await.map_err(db_error)?;
+    let next_offset = (offset + (nodes.len() as i64) < total).then(|| offset + nodes.len() as i64);
+
+    debug!(returned = nodes.len(), total, "<= GET /nodes - Responding with nodes");
+    let body = NodeListing::Current(NodesPage { nodes, total, limit, offset, next_offset });
+    Ok((TotalCount(total), SparseJson { body, list: "nodes", fields }))
+}
+
+/// How many nodes the filters select; the `total` `GET /nodes` would report, without the page.
+#[utoipa::path(
+    get,
+    path = "/nodes/count",
+    tag = "nodes",
+    summary = "Count the nodes the filters select",
+    description = format!(
+        "Counts are cached for {} seconds, so they can trail the latest changes that long.",
+        COUNT_TTL.as_secs()
+    ),
+    params(NodeFilter),
+    responses(
+        (status = 200, description = "The number of nodes", body = NodeCount),
+        (status = 400, description = "A query parameter is malformed"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_node_count(
+    State(ReadPool(pool)): State<ReadPool>,
+    State(counts): State<Arc<NodeCounts>>,
+    Query(filter): Query<NodeFilter>,
+) -> Result<Json<NodeCount>, ApiError> {
+    debug!(program = ?filter.program, "=> GET /nodes/count - Counting nodes");
+
+    let count = counts.count(&pool, &filter).await.map_err(|e| ApiError::database(e, "Failed to count nodes"))?;
+
+    debug!(count, "<= GET /nodes/count - Responding with count");
+    Ok(Json(NodeCount { count }))
+}
+
+/// Nodes whose URI contains `q`, case-insensitively, closest matches first. The trigram index
+/// on `uri` keeps this fast for fragments of three or more characters.
+#[utoipa::path(
+    get,
+    path = "/nodes/search",
+    tag = "nodes",
+    summary = "Find nodes by a fragment of their URI, closest matches first",
+    params(SearchParams, PageParams, NodeFilter, FieldsParams),
+    responses(
+        (
+            status = 200,
+            description = "A page of matching nodes",
+            body = NodesPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, description = "`q` is missing, empty or too long, or `fields` names an unknown field"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn search_nodes(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(search): Query<SearchParams>,
+    Query(params): Query<PageParams>,
+    Query(filter): Query<NodeFilter>,
+    Query(fields): Query<FieldsParams>,
+) -> Result<(TotalCount, SparseJson<NodesPage>), ApiError> {
+    let (limit, offset) = params.resolve();
+    let fields = fields.resolve()?;
+    let q = search.q.trim();
+    debug!(q, limit, offset, "=> GET /nodes/search - Searching node URIs");
+
+    if q.is_empty() {
+        return Err(ApiError::bad_request("invalid_query", "Search text `q` must not be empty"));
+    }
+    if q.chars().count() > MAX_SEARCH_LEN {
+        let message = format!("Search text `q` must be at most {} characters", MAX_SEARCH_LEN);
+        return Err(ApiError::bad_request("invalid_query", message));
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to search nodes");
+    let pattern = format!("%{}%", q.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+    let include_deleted = filter.include_deleted.unwrap_or(false);
+    let condition = filter.condition();
+
+    let total: i64 = sqlx::query_scalar(&format!(
+        "SELECT COUNT(*) FROM nodes \
+         WHERE uri ILIKE $1 AND ($2::text IS NULL OR program_id = $2) AND ($3 OR deleted_at IS NULL) \
+           AND ($4::text IS NULL OR cluster = $4) AND {}",
+        condition
+    ))
+    .bind(&pattern)
+    .bind(&filter.program)
+    .bind(include_deleted)
+    .bind(&filter.cluster)
+    .fetch_one(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes \
+         WHERE uri ILIKE $1 AND ($2::text IS NULL OR program_id = $2) AND ($3 OR deleted_at IS NULL) \
+           AND ($7::text IS NULL OR cluster = $7) AND {} \
+         ORDER BY similarity(uri, $4) DESC, pubkey LIMIT $5 OFFSET $6",
+        NODE_COLUMNS, condition
+    ))
+    .bind(&pattern)
+    .bind(&filter.program)
+    .bind(include_deleted)
+    .bind(q)
+    .bind(limit)
+    .bind(offset)
+    .bind(&filter.cluster)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+    let nodes = with_uptime(&pool, nodes).await.map_err(db_error)?;
+    let next_offset = (offset + (nodes.len() as i64) < total).then(|| offset + nodes.len() as i64);
+
+    debug!(returned = nodes.len(), total, "<= GET /nodes/search - Responding with matches");
+    let body = NodesPage { nodes, total, limit, offset, next_offset };
+    Ok((TotalCount(total), SparseJson { body, list: "nodes", fields }))
+}
+
+/// Nodes whose URI is `uri`, exactly or once both are normalized (see `normalize_uri` in the
+/// migrations).
+#[utoipa::path(
+    get,
+    path = "/nodes/by-uri",
+    tag = "nodes",
+    summary = "Nodes registered with a URI, to spot duplicate registrations",
+    params(UriParams, NodeFilter),
+    responses(
+        (status = 200, description = "Matching nodes, earliest registration first", body = UriMatches),
+        (status = 400, description = "`uri` is missing or empty"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_nodes_by_uri(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<UriParams>,
+    Query(filter): Query<NodeFilter>,
+) -> Result<Json<UriMatches>, ApiError> {
+    let uri = params.uri;
+    debug!(%uri, "=> GET /nodes/by-uri - Looking up nodes by URI");
+
+    if uri.trim().is_empty() {
+        return Err(ApiError::bad_request("invalid_query", "`uri` must not be empty"));
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to look up nodes by URI");
+    let normalized_uri: String = sqlx::query_scalar("SELECT normalize_uri($1)")
+        .bind(&uri)
+        .fetch_one(&pool)
+        .await
+        .map_err(db_error)?;
+
+    let condition = filter.condition();
+    let rows = sqlx::query_as::<_, UriMatchRow>(&format!(
+        "SELECT {}, uri = $1 AS exact FROM nodes \
+         WHERE normalize_uri(uri) = $2 AND ($3::text IS NULL OR program_id = $3) AND ($4 OR deleted_at IS NULL) \
+           AND ($6::text IS NULL OR cluster = $6) AND {} \
+         ORDER BY first_seen_at, pubkey LIMIT $5",
+        NODE_COLUMNS, condition
+    ))
+    .bind(&uri)
+    .bind(&normalized_uri)
+    .bind(&filter.program)
+    .bind(filter.include_deleted.unwrap_or(false))
+    .bind(MAX_PAGE_LIMIT)
+    .bind(&filter.cluster)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let exact: Vec<bool> = rows.iter().map(|row| row.exact).collect();
+    let nodes = with_uptime(&pool, rows.into_iter().map(|row| row.node).collect()).await.map_err(db_error)?;
+    let nodes: Vec<UriMatch> = exact.into_iter().zip(nodes).map(|(exact, node)| UriMatch { exact, node }).collect();
+
+    debug!(%uri, returned = nodes.len(), "<= GET /nodes/by-uri - Responding with matches");
+    Ok(Json(UriMatches { uri, normalized_uri, nodes }))
+}
+
+/// The nodes indexed under each of the requested pubkeys, in one query. Every key must be
+/// valid base58; repeated keys are answered once.
+#[utoipa::path(
+    post,
+    path = "/nodes/batch",
+    tag = "nodes",
+    summary = "Look up many nodes at once",
+    description = "Answers each distinct pubkey once, in request order, whether or not it is indexed.",
+    request_body = BatchLookupRequest,
+    responses(
+        (status = 200, description = "One result per requested pubkey", body = BatchLookup),
+        (status = 400, description = "The list is empty or too long, or holds an invalid pubkey"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_nodes_batch(
+    State(ReadPool(pool)): State<ReadPool>,
+    JsonBody(request): JsonBody<BatchLookupRequest>,
+) -> Result<Json<BatchLookup>, ApiError> {
+    debug!(requested = request.pubkeys.len(), "=> POST /nodes/batch - Looking up nodes");
+
+    if request.pubkeys.is_empty() {
+        return Err(ApiError::bad_request("invalid_body", "`pubkeys` must not be empty"));
+    }
+    if request.pubkeys.len() > MAX_BATCH_PUBKEYS {
+        let message = format!("`pubkeys` may hold at most {} keys", MAX_BATCH_PUBKEYS);
+        return Err(ApiError::bad_request("invalid_body", message));
+    }
+    let mut seen = HashSet::with_capacity(request.pubkeys.len());
+    let mut pubkeys: Vec<String> = Vec::with_capacity(request.pubkeys.len());
+    for pubkey in request.pubkeys {
+        let pubkey = pubkey.trim().to_string();
+        if Pubkey::from_str(&pubkey).is_err() {
+            return Err(ApiError::invalid_pubkey(&pubkey));
+        }
+        if seen.insert(pubkey.clone()) {
+            pubkeys.push(pubkey);
+        }
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch nodes from database");
+    let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT DISTINCT ON (pubkey) {} FROM nodes WHERE pubkey = ANY($1) AND ($2 OR deleted_at IS NULL) \
+         AND ($3::text IS NULL OR cluster = $3) AND {} ORDER BY pubkey, cluster NULLS FIRST",
+        NODE_COLUMNS, NOT_QUARANTINED
+    ))
+    .bind(&pubkeys)
+    .bind(request.include_deleted.unwrap_or(false))
+    .bind(&request.cluster)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+    let mut nodes: HashMap<String, NodeWithUptime> = with_uptime(&pool, nodes)
+        .await
+        .map_err(db_error)?
+        .into_iter()
+        .map(|node| (node.node.pubkey.clone(), node))
+        .collect();
+
+    let found = nodes.len();
+    let not_found = pubkeys.len() - found;
+    let results = pubkeys
+        .into_iter()
+        .map(|pubkey| {
+            let node = nodes.remove(&pubkey);
+            BatchResult { found: node.is_some(), pubkey, node }
+        })
+        .collect();
+
+    debug!(found, not_found, "<= POST /nodes/batch - Responding with results");
+    Ok(Json(BatchLookup { results, found, not_found }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/nodes/{pubkey}",
+    tag = "nodes",
+    summary = "Look up one node",
+    description = "A pubkey indexed from several clusters resolves to the unnamed default cluster's node, then by \
+        cluster name, unless `cluster` picks one.",
+    params(
+        ("pubkey" = String, Path, description = "Base58 account address of the node"),
+        ("cluster" = Option<String>, Query, description = "Only look on this cluster, e.g. `mainnet`"),
+        ("include_deleted" = Option<bool>, Query, description = "Also return a soft-deleted node (default false)"),
+    ),
+    responses(
+        (status = 200, description = "The node", body = NodeDetail),
+        (status = 400, response = openapi::InvalidPubkey),
+        (status = 404, description = "No node is indexed under the pubkey"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_node(
+    State(ReadPool(pool)): State<ReadPool>,
+    Path(pubkey): Path<String>,
+    Query(filter): Query<NodeFilter>,
+) -> Result<Json<NodeDetail>, ApiError> {
+    debug!(%pubkey, "=> GET /nodes/:pubkey - Looking up node");
+
+    if Pubkey::from_str(&pubkey).is_err() {
+        return Err(ApiError::invalid_pubkey(&pubkey));
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch node from database");
+
+    let include_deleted = filter.include_deleted.unwrap_or(false);
+    match load_node(&pool, &pubkey, filter.cluster.as_deref(), include_deleted).await.map_err(db_error)? {
+        Some(node) => {
+            let metadata = node_metadata(&pool, &pubkey).await.map_err(db_error)?;
+            debug!(%pubkey, enriched = metadata.is_some(), "<= GET /nodes/:pubkey - Found");
+            Ok(Json(NodeDetail { node, metadata }))
+        }
+        None => {
+            debug!(%pubkey, "<= GET /nodes/:pubkey - Not indexed");
+            Err(ApiError::not_found("node_not_found", format!("No node indexed with pubkey {}", pubkey)))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/nodes/{pubkey}/history",
+    tag = "nodes",
+    summary = "A node's change history, newest first",
+    params(("pubkey" = String, Path, description = "Base58 account address of the node"), PageParams),
+    responses(
+        (
+            status = 200,
+            description = "A page of history entries",
+            body = HistoryPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, response = openapi::InvalidPubkey),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_node_history(
+    State(ReadPool(pool)): State<ReadPool>,
+    Path(pubkey): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<(TotalCount, Json<HistoryPage>), ApiError> {
+    let (limit, offset) = params.resolve();
+    debug!(%pubkey, limit, offset, "=> GET /nodes/:pubkey/history - Fetching change history");
+
+    if Pubkey::from_str(&pubkey).is_err() {
+        return Err(ApiError::invalid_pubkey(&pubkey));
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch node history from database");
+
+    let total: i64 =
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM nodes_history WHERE pubkey = $1 AND {}", NOT_QUARANTINED))
+            .bind(&pubkey)
+            .fetch_one(&pool)
+            .await
+            .map_err(db_error)?;
+
+    // Newest first; ids are assigned in commit order within a transaction.
+    let entries = sqlx::query_as::<_, ApiHistoryEntry>(&format!(
+        r#"
+        SELECT id, pubkey, cluster, change_type, old_authority, new_authority, old_uri, new_uri, slot, changed_at
+        FROM nodes_history
+        WHERE pubkey = $1 AND {}
+        ORDER BY id DESC
+        LIMIT $2 OFFSET $3
+        "#,
+        NOT_QUARANTINED
+    ))
+    .bind(&pubkey)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let next_offset = (offset + (entries.len() as i64) < total).then(|| offset + entries.len() as i64);
+
+    debug!(%pubkey, returned = entries.len(), total, "<= GET /nodes/:pubkey/history - Responding with entries");
+    Ok((TotalCount(total), Json(HistoryPage { pubkey, entries, total, limit, offset, next_offset })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/nodes/{pubkey}/transactions",
+    tag = "nodes",
+    summary = "Transactions that touched a node, newest first",
+    params(("pubkey" = String, Path, description = "Base58 account address of the node"), PageParams),
+    responses(
+        (
+            status = 200,
+            description = "A page of transactions",
+            body = TransactionsPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, response = openapi::InvalidPubkey),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_node_transactions(
+    State(ReadPool(pool)): State<ReadPool>,
+    Path(pubkey): Path<String>,
+    Query(params): Query<PageParams>,
+) -> Result<(TotalCount, Json<TransactionsPage>), ApiError> {
+    let (limit, offset) = params.resolve();
+    debug!(%pubkey, limit, offset, "=> GET /nodes/:pubkey/transactions - Fetching transactions");
+
+    if Pubkey::from_str(&pubkey).is_err() {
+        return Err(ApiError::invalid_pubkey(&pubkey));
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch node transactions from database");
+
+    let total: i64 =
+        sqlx::query_scalar(&format!("SELECT COUNT(*) FROM node_transactions WHERE pubkey = $1 AND {}", NOT_QUARANTINED))
+            .bind(&pubkey)
+            .fetch_one(&pool)
+            .await
+            .map_err(db_error)?;
+
+    let transactions = sqlx::query_as::<_, ApiNodeTransaction>(&format!(
+        r#"
+        SELECT signature, slot, block_time, fee_payer, signers, instructions, succeeded, error
+        FROM node_transactions
+        WHERE pubkey = $1 AND {}
+        ORDER BY slot DESC, signature
+        LIMIT $2 OFFSET $3
+        "#,
+        NOT_QUARANTINED
+    ))
+    .bind(&pubkey)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let next_offset =
+        (offset + (transactions.len() as i64) < total).then(|| offset + transactions.len() as i64);
+
+    debug!(%pubkey, returned = transactions.len(), total, "<= GET /nodes/:pubkey/transactions - Responding");
+    Ok((TotalCount(total), Json(TransactionsPage { pubkey, transactions, total, limit, offset, next_offset })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/leaderboard",
+    tag = "nodes",
+    summary = "Nodes ranked by uptime, latency and age",
+    description = "Weights must not be negative, and only their ratios matter.",
+    params(PageParams, LeaderboardParams),
+    responses(
+        (
+            status = 200,
+            description = "A page of ranked nodes",
+            body = LeaderboardPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, description = "A weight is negative, or all of them are zero"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_leaderboard(
+    State(ReadPool(pool)): State<ReadPool>,
+    State(counts): State<Arc<NodeCounts>>,
+    Query(params): Query<PageParams>,
+    Query(ranking): Query<LeaderboardParams>,
+) -> Result<(TotalCount, Json<LeaderboardPage>), ApiError> {
+    let (limit, offset) = params.resolve();
+    let weights = LeaderboardWeights {
+        uptime: ranking.uptime_weight.unwrap_or(DEFAULT_UPTIME_WEIGHT),
+        latency: ranking.latency_weight.unwrap_or(DEFAULT_LATENCY_WEIGHT),
+        age: ranking.age_weight.unwrap_or(DEFAULT_AGE_WEIGHT),
+    };
+    debug!(limit, offset, program = ?ranking.program, "=> GET /leaderboard - Ranking nodes");
+
+    let weight_values = [weights.uptime, weights.latency, weights.age];
+    let invalid = weight_values.iter().any(|weight| !weight.is_finite() || *weight < 0.0);
+    if invalid || weight_values.iter().sum::<f64>() <= 0.0 {
+        return Err(ApiError::bad_request("invalid_weights", "Weights must be non-negative numbers and not all zero"));
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to rank nodes");
+
+    let live = NodeFilter {
+        program: ranking.program.clone(),
+        cluster: ranking.cluster.clone(),
+        include_deleted: None,
+        status: None,
+        finalized_only: None,
+        include_invalid: None,
+        include_unverified: None,
+        rent_exempt: None,
+        dns_ok: None,
+    };
+    let total = counts.count(&pool, &live).await.map_err(db_error)?;
+
+    // Never-probed nodes score zero for uptime and latency but still rank by age.
+    let mut nodes = sqlx::query_as::<_, LeaderboardEntry>(&format!(
+        r#"
+        WITH probes AS (
+            SELECT pubkey,
+                   (100.0 * SUM(online_checks) / NULLIF(SUM(checks), 0))::float8 AS uptime_30d,
+                   SUM(latency_ms_total)::float8 / NULLIF(SUM(latency_checks), 0) AS avg_latency_ms
+            FROM node_uptime_hourly
+            WHERE hour >= NOW() - INTERVAL '30 days'
+            GROUP BY pubkey
+        ),
+        scored AS (
+            SELECT n.pubkey, n.uri, n.first_seen_at, p.uptime_30d, p.avg_latency_ms,
+                   COALESCE(p.uptime_30d, 0) / 100 AS uptime_score,
+                   CASE WHEN p.avg_latency_ms IS NULL THEN 0
+                        ELSE CUME_DIST() OVER (PARTITION BY p.avg_latency_ms IS NULL ORDER BY p.avg_latency_ms DESC)
+                   END AS latency_score,
+                   -- Rows without first_seen_at predate that column, so they are the oldest.
+                   CUME_DIST() OVER (ORDER BY n.first_seen_at DESC NULLS LAST) AS age_score
+            FROM nodes n
+            LEFT JOIN probes p USING (pubkey)
+            WHERE ($1::text IS NULL OR n.program_id = $1) AND ($7::text IS NULL OR n.cluster = $7)
+              AND n.deleted_at IS NULL AND n.uri_valid AND n.pda_verified IS NOT FALSE AND {}
+        )
+        SELECT pubkey, uri, first_seen_at, uptime_30d, avg_latency_ms,
+               (($2 * uptime_score + $3 * latency_score + $4 * age_score) / ($2 + $3 + $4))::float8 AS score
+        FROM scored
+        ORDER BY score DESC, pubkey
+        LIMIT $5 OFFSET $6
+        "#,
+        NOT_QUARANTINED
+    ))
+    .bind(&ranking.program)
+    .bind(weights.uptime)
+    .bind(weights.latency)
+    .bind(weights.age)
+    .bind(limit)
+    .bind(offset)
+    .bind(&ranking.cluster)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+    for (index, node) in nodes.iter_mut().enumerate() {
+        node.rank = offset + index as i64 + 1;
+    }
+
+    let next_offset = (offset + (nodes.len() as i64) < total).then(|| offset + nodes.len() as i64);
+
+    debug!(returned = nodes.len(), total, "<= GET /leaderboard - Responding with ranked nodes");
+    Ok((TotalCount(total), Json(LeaderboardPage { weights, nodes, total, limit, offset, next_offset })))
+}
+
+/// Authorities by how many nodes they control, most first, so concentration of ownership
+/// shows at the top.
+#[utoipa::path(
+    get,
+    path = "/authorities",
+    tag = "nodes",
+    summary = "Authorities by number of nodes controlled, most first",
+    params(PageParams, NodeFilter),
+    responses(
+        (
+            status = 200,
+            description = "A page of authorities",
+            body = AuthoritiesPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, description = "A query parameter is malformed"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_authorities(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<PageParams>,
+    Query(filter): Query<NodeFilter>,
+) -> Result<(TotalCount, Json<AuthoritiesPage>), ApiError> {
+    let (limit, offset) = params.resolve();
+    debug!(limit, offset, program = ?filter.program, "=> GET /authorities - Grouping nodes by authority");
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch authorities from database");
+    let include_deleted = filter.include_deleted.unwrap_or(false);
+    let condition = filter.condition();
+
+    let (total, total_nodes): (i64, i64) = sqlx::query_as(&format!(
+        "SELECT COUNT(DISTINCT authority), COUNT(*) FROM nodes \
+         WHERE ($1::text IS NULL OR program_id = $1) AND ($2 OR deleted_at IS NULL) \
+           AND ($3::text IS NULL OR cluster = $3) AND {}",
+        condition
+    ))
+    .bind(&filter.program)
+    .bind(include_deleted)
+    .bind(&filter.cluster)
+    .fetch_one(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let authorities = sqlx::query_as::<_, AuthoritySummary>(&format!(
+        r#"
+        SELECT authority, COUNT(*) AS node_count,
+               MIN(first_seen_at) AS first_registered_at, MAX(first_seen_at) AS last_registered_at
+        FROM nodes
+        WHERE ($3::text IS NULL OR program_id = $3) AND ($4 OR deleted_at IS NULL)
+          AND ($5::text IS NULL OR cluster = $5) AND {}
+        GROUP BY authority
+        ORDER BY node_count DESC, authority
+        LIMIT $1 OFFSET $2
+        "#,
+        condition
+    ))
+    .bind(limit)
+    .bind(offset)
+    .bind(&filter.program)
+    .bind(include_deleted)
+    .bind(&filter.cluster)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let next_offset = (offset + (authorities.len() as i64) < total).then(|| offset + authorities.len() as i64);
+
+    debug!(returned = authorities.len(), total, "<= GET /authorities - Responding with authorities");
+    Ok((TotalCount(total), Json(AuthoritiesPage { authorities, total, total_nodes, limit, offset, next_offset })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/nodes/{pubkey}/liveness",
+    tag = "nodes",
+    summary = "Latest probe of a node's URI",
+    params(("pubkey" = String, Path, description = "Base58 account address of the node")),
+    responses(
+        (status = 200, description = "The latest probe", body = NodeLiveness),
+        (status = 400, response = openapi::InvalidPubkey),
+        (status = 404, description = "The node's URI hasn't been probed"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_node_liveness(
+    State(ReadPool(pool)): State<ReadPool>,
+    Path(pubkey): Path<String>,
+) -> Result<Json<NodeLiveness>, ApiError> {
+    debug!(%pubkey, "=> GET /nodes/:pubkey/liveness - Fetching latest URI probe");
+
+    if Pubkey::from_str(&pubkey).is_err() {
+        return Err(ApiError::invalid_pubkey(&pubkey));
+    }
+
+    let liveness = sqlx::query_as::<_, NodeLiveness>(&format!(
+        "SELECT {} FROM node_liveness WHERE pubkey = $1 AND {}",
+        LIVENESS_COLUMNS, NOT_QUARANTINED
+    ))
+    .bind(&pubkey)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to fetch node liveness"))?;
+
+    match liveness {
+        Some(liveness) => {
+            debug!(%pubkey, online = liveness.online, "<= GET /nodes/:pubkey/liveness - Found");
+            Ok(Json(liveness))
+        }
+        None => {
+            debug!(%pubkey, "<= GET /nodes/:pubkey/liveness - Not probed");
+            Err(ApiError::not_found("not_probed", format!("The URI of node {} hasn't been probed", pubkey)))
+        }
+    }
+}
+
+/// Nodes whose URI's certificate failed verification, doesn't name the host or expires within
+/// `within_days`, as of each node's latest probe of its current URI. Soonest expiry first, so
+/// operators can act on it before clients start failing.
+#[utoipa::path(
+    get,
+    path = "/nodes/certificates",
+    tag = "nodes",
+    summary = "Nodes whose URI's TLS certificate is invalid or expiring",
+    description = "As of each node's latest probe of its current https URI: certificates that failed verification or \
+        don't name the host, and those expiring within `within_days`. Unreadable expiry dates first, then the \
+        soonest. Needs the liveness prober (`PROBE_URIS`).",
+    params(PageParams, CertificateParams, NodeFilter),
+    responses(
+        (
+            status = 200,
+            description = "A page of failing certificates",
+            body = CertificatesPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, description = "`within_days` is out of range, or a query parameter is malformed"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_node_certificates(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<PageParams>,
+    Query(window): Query<CertificateParams>,
+    Query(filter): Query<NodeFilter>,
+) -> Result<(TotalCount, Json<CertificatesPage>), ApiError> {
+    let (limit, offset) = params.resolve();
+    let within_days = window.within_days.unwrap_or(DEFAULT_CERT_EXPIRY_DAYS);
+    debug!(limit, offset, within_days, "=> GET /nodes/certificates - Fetching failing certificates");
+
+    if !(0..=MAX_CERT_EXPIRY_DAYS).contains(&within_days) {
+        return Err(ApiError::bad_request(
+            "invalid_within_days",
+            format!("within_days must be between 0 and {}", MAX_CERT_EXPIRY_DAYS),
+        ));
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch node certificates");
+    let include_deleted = filter.include_deleted.unwrap_or(false);
+    let failing = format!(
+        r#"
+        WITH matched AS (
+            SELECT pubkey, uri FROM nodes
+            WHERE ($1::text IS NULL OR program_id = $1) AND ($2 OR deleted_at IS NULL)
+              AND ($3::text IS NULL OR cluster = $3) AND {}
+        )
+        SELECT l.pubkey, l.uri, l.cert_valid, l.cert_hostname_match, l.cert_expires_at, l.checked_at
+        FROM node_liveness l
+        JOIN matched m ON m.pubkey = l.pubkey AND m.uri = l.uri
+        WHERE l.cert_valid IS NOT NULL
+          AND (NOT l.cert_valid OR NOT l.cert_hostname_match
+               OR l.cert_expires_at < NOW() + make_interval(days => $4::int))
+        "#,
+        filter.condition()
+    );
+
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM ({}) failing", failing))
+        .bind(&filter.program)
+        .bind(include_deleted)
+        .bind(&filter.cluster)
+        .bind(within_days as i32)
+        .fetch_one(&pool)
+        .await
+        .map_err(db_error)?;
+
+    // Certificates that can't be read for an expiry come first, then the soonest to expire.
+    let nodes = sqlx::query_as::<_, NodeCertificate>(&format!(
+        "{} ORDER BY l.cert_expires_at NULLS FIRST, l.pubkey LIMIT $5 OFFSET $6",
+        failing
+    ))
+    .bind(&filter.program)
+    .bind(include_deleted)
+    .bind(&filter.cluster)
+    .bind(within_days as i32)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let next_offset = (offset + (nodes.len() as i64) < total).then(|| offset + nodes.len() as i64);
+
+    debug!(returned = nodes.len(), total, "<= GET /nodes/certificates - Responding with certificates");
+    Ok((TotalCount(total), Json(CertificatesPage { nodes, within_days, total, limit, offset, next_offset })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/changes",
+    tag = "feeds",
+    summary = "Poll node changes after a cursor",
+    params(ChangesParams),
+    responses(
+        (status = 200, description = "Changes after `since`, oldest first", body = ChangesPage),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_changes(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<ChangesParams>,
+) -> Result<Json<ChangesPage>, ApiError> {
+    let since = params.since.unwrap_or(0).max(0);
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    debug!(since, limit, "=> GET /changes - Fetching changes");
+
+    // Fetch one extra row to know whether another page is waiting.
+    // A quarantined node's removal still shows, so consumers drop it; its other changes don't.
+    let mut changes = sqlx::query_as::<_, ApiChange>(&format!(
+        r#"
+        SELECT id, pubkey, change_type, authority, uri, program_id, slot, changed_at
+        FROM node_changes
+        WHERE id > $1 AND (change_type = 'delete' OR {})
+        ORDER BY id
+        LIMIT $2
+        "#,
+        NOT_QUARANTINED
+    ))
+    .bind(since)
+    .bind(limit + 1)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to fetch changes from database"))?;
+
+    let has_more = changes.len() as i64 > limit;
+    changes.truncate(limit as usize);
+    let next_cursor = changes.last().map_or(since, |change| change.id);
+
+    debug!(returned = changes.len(), next_cursor, has_more, "<= GET /changes - Responding with changes");
+    Ok(Json(ChangesPage { changes, next_cursor, has_more }))
+}
+
+/// What changed between `from` and `to`, replayed from `node_changes`. Nodes whose last change
+/// predates the change log are unknown to it, so they only show up once they change again.
+#[utoipa::path(
+    get,
+    path = "/diff",
+    tag = "feeds",
+    summary = "Nodes added, removed and modified between two points of the change log",
+    description = "Pages run over the changed pubkeys; nodes changed and then restored are left out, so pages can \
+        be short.",
+    params(DiffParams, PageParams),
+    responses(
+        (
+            status = 200,
+            description = "A page of the diff",
+            body = NodeDiffPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, description = "A point is malformed, or `from` is after `to`"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_diff(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<DiffParams>,
+    Query(page): Query<PageParams>,
+) -> Result<(TotalCount, Json<NodeDiffPage>), ApiError> {
+    let (limit, offset) = page.resolve();
+    debug!(from = %params.from, to = ?params.to, limit, offset, "=> GET /diff - Diffing node changes");
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to diff node changes");
+    let from = ChangePoint::parse("from", &params.from)?;
+    let to = match &params.to {
+        Some(to) => ChangePoint::parse("to", to)?,
+        None => ChangePoint::Cursor(
+            sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM node_changes")
+                .fetch_one(&pool)
+                .await
+                .map_err(db_error)?,
+        ),
+    };
+    let reversed = match (from, to) {
+        (ChangePoint::Cursor(from), ChangePoint::Cursor(to)) | (ChangePoint::Slot(from), ChangePoint::Slot(to)) => {
+            from > to
+        }
+        _ => false,
+    };
+    if reversed {
+        return Err(ApiError::bad_request("invalid_query", "`from` must not be after `to`"));
+    }
+
+    let touched = format!(
+        "touched AS (SELECT DISTINCT c.pubkey, c.cluster FROM node_changes c WHERE {} AND NOT ({}) AND {})",
+        to.reached("$2"),
+        from.reached("$1"),
+        NOT_QUARANTINED
+    );
+    let total: i64 = sqlx::query_scalar(&format!("WITH {} SELECT COUNT(*) FROM touched", touched))
+        .bind(from.value())
+        .bind(to.value())
+        .fetch_one(&pool)
+        .await
+        .map_err(db_error)?;
+
+    let latest = |reached: String| {
+        format!(
+            "SELECT DISTINCT ON (c.pubkey, c.cluster) c.pubkey, c.cluster, c.change_type, c.authority, c.uri, \
+             c.program_id FROM node_changes c \
+             JOIN page p ON p.pubkey = c.pubkey AND p.cluster IS NOT DISTINCT FROM c.cluster \
+             WHERE {} ORDER BY c.pubkey, c.cluster, c.id DESC",
+            reached
+        )
+    };
+    let rows = sqlx::query_as::<_, DiffRow>(&format!(
+        r#"
+        WITH {},
+        page AS (SELECT pubkey, cluster FROM touched ORDER BY pubkey, cluster NULLS FIRST LIMIT $3 OFFSET $4),
+        before AS ({}),
+        after AS ({})
+        SELECT p.pubkey, p.cluster,
+               b.change_type AS before_type, b.authority AS before_authority, b.uri AS before_uri,
+               b.program_id AS before_program_id,
+               a.change_type AS after_type, a.authority AS after_authority, a.uri AS after_uri,
+               a.program_id AS after_program_id
+        FROM page p
+        LEFT JOIN before b ON b.pubkey = p.pubkey AND b.cluster IS NOT DISTINCT FROM p.cluster
+        LEFT JOIN after a ON a.pubkey = p.pubkey AND a.cluster IS NOT DISTINCT FROM p.cluster
+        ORDER BY p.pubkey, p.cluster NULLS FIRST
+        "#,
+        touched,
+        latest(from.reached("$1")),
+        latest(to.reached("$2"))
+    ))
+    .bind(from.value())
+    .bind(to.value())
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+    let next_offset = (offset + (rows.len() as i64) < total).then(|| offset + rows.len() as i64);
+
+    let (mut added, mut removed, mut modified) = (Vec::new(), Vec::new(), Vec::new());
+    for row in rows {
+        let before = (row.before_type.as_deref() == Some("upsert")).then(|| DiffNode {
+            pubkey: row.pubkey.clone(),
+            cluster: row.cluster.clone(),
+            authority: row.before_authority.unwrap_or_default(),
+            uri: row.before_uri.unwrap_or_default(),
+            program_id: row.before_program_id,
+        });
+        let after = (row.after_type.as_deref() == Some("upsert")).then(|| DiffNode {
+            pubkey: row.pubkey.clone(),
+            cluster: row.cluster.clone(),
+            authority: row.after_authority.unwrap_or_default(),
+            uri: row.after_uri.unwrap_or_default(),
+            program_id: row.after_program_id,
+        });
+        match (before, after) {
+            (None, Some(after)) => added.push(after),
+            (Some(before), None) => removed.push(before),
+            (Some(before), Some(after)) if before != after => {
+                modified.push(ModifiedNode { pubkey: row.pubkey, cluster: row.cluster, before, after })
+            }
+            _ => {}
+        }
+    }
+
+    debug!(
+        added = added.len(),
+        removed = removed.len(),
+        modified = modified.len(),
+        total,
+        "<= GET /diff - Responding with diff"
+    );
+    let body = NodeDiffPage {
+        from: from.to_string(),
+        to: to.to_string(),
+        added,
+        removed,
+        modified,
+        total,
+        limit,
+        offset,
+        next_offset,
+    };
+    Ok((TotalCount(total), Json(body)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/program-events",
+    tag = "feeds",
+    summary = "Poll decoded Anchor events after a cursor",
+    params(ProgramEventsParams),
+    responses(
+        (status = 200, description = "Events after `since`, oldest first", body = ProgramEventsPage),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_program_events(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<ProgramEventsParams>,
+) -> Result<Json<ProgramEventsPage>, ApiError> {
+    let since = params.since.unwrap_or(0).max(0);
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    debug!(since, limit, name = ?params.name, pubkey = ?params.pubkey, "=> GET /program-events - Fetching events");
+
+    // Fetch one extra row to know whether another page is waiting.
+    let mut events = sqlx::query_as::<_, ApiProgramEvent>(
+        r#"
+        SELECT id, program_id, signature, event_index, slot, name, data, encode(raw_data, 'hex') AS raw_data,
+               observed_at
+        FROM program_events
+        WHERE id > $1
+          AND ($3::text IS NULL OR program_id = $3)
+          AND ($4::text IS NULL OR name = $4)
+          AND ($5::text IS NULL OR EXISTS (SELECT 1 FROM jsonb_each_text(data) AS field WHERE field.value = $5))
+        ORDER BY id
+        LIMIT $2
+        "#,
+    )
+    .bind(since)
+    .bind(limit + 1)
+    .bind(&params.program)
+    .bind(&params.name)
+    .bind(&params.pubkey)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to fetch program events from database"))?;
+
+    let has_more = events.len() as i64 > limit;
+    events.truncate(limit as usize);
+    let next_cursor = events.last().map_or(since, |event| event.id);
+
+    debug!(returned = events.len(), next_cursor, has_more, "<= GET /program-events - Responding with events");
+    Ok(Json(ProgramEventsPage { events, next_cursor, has_more }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/keys",
+    tag = "admin",
+    summary = "List API keys",
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "Every key, revoked ones included", body = Vec<ApiKeyRecord>),
+        (status = 403, response = openapi::Forbidden),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn list_api_keys(
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<ApiKeyRecord>>, ApiError> {
+    debug!("=> GET /admin/keys - Fetching API keys");
+    let keys = sqlx::query_as::<_, ApiKeyRecord>(&format!("SELECT {} FROM api_keys ORDER BY id", API_KEY_COLUMNS))
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to fetch API keys"))?;
+    debug!(returned = keys.len(), "<= GET /admin/keys - Responding with API keys");
+    Ok(Json(keys))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/keys",
+    tag = "admin",
+    summary = "Create an API key",
+    request_body = CreateApiKeyRequest,
+    security(("apiKey" = [])),
+    responses(
+        (status = 201, description = "The key, shown only this once", body = CreatedApiKey),
+        (status = 400, description = "The name is empty"),
+        (status = 403, response = openapi::Forbidden),
+        (status = 409, description = "A key with that name exists"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_api_key(
+    State(pool): State<PgPool>,
+    JsonBody(request): JsonBody<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), ApiError> {
+    let name = request.name.trim();
+    debug!(name, "=> POST /admin/keys - Creating API key");
+    if name.is_empty() {
+        return Err(ApiError::bad_request("invalid_name", "API key name must not be empty"));
+    }
+    let (record, key) = create_api_key(&pool, name, request.admin.unwrap_or(false)).await.map_err(|e| {
+        let duplicate = e
+            .downcast_ref::<sqlx::Error>()
+            .and_then(|e| e.as_database_error())
+            .is_some_and(|e| e.is_unique_violation());
+        if duplicate {
+            ApiError::new(StatusCode::CONFLICT, "duplicate_name", format!("An API key named '{}' exists", name))
+        } else {
+            ApiError::database(e, "Failed to create API key")
+        }
+    })?;
+    info!(id = record.id, name, admin = record.admin, "Created API key");
+    Ok((StatusCode::CREATED, Json(CreatedApiKey { key, record })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/keys/{id}",
+    tag = "admin",
+    summary = "Revoke an API key",
+    params(("id" = i64, Path)),
+    security(("apiKey" = [])),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 403, response = openapi::Forbidden),
+        (status = 404, description = "No live key has that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn revoke_api_key(
+    State(pool): State<PgPool>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    debug!(id, "=> DELETE /admin/keys/:id - Revoking API key");
+    let revoked = sqlx::query("UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to revoke API key"))?
+        .rows_affected();
+    if revoked == 0 {
+        return Err(ApiError::not_found("not_found", format!("No active API key with id {}", id)));
+    }
+    info!(id, "Revoked API key");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/webhooks",
+    tag = "admin",
+    summary = "List webhooks",
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "Every webhook with its delivery state", body = Vec<WebhookRecord>),
+        (status = 403, response = openapi::Forbidden),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn list_webhooks(
+    State(pool): State<PgPool>,
+) -> Result<Json<Vec<WebhookRecord>>, ApiError> {
+    debug!("=> GET /admin/webhooks - Fetching webhooks");
+    let webhooks = sqlx::query_as::<_, WebhookRecord>(&format!("SELECT {} FROM webhooks ORDER BY id", WEBHOOK_COLUMNS))
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to fetch webhooks"))?;
+    debug!(returned = webhooks.len(), "<= GET /admin/webhooks - Responding with webhooks");
+    Ok(Json(webhooks))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/webhooks",
+    tag = "admin",
+    summary = "Register a webhook for node changes",
+    request_body = CreateWebhookRequest,
+    security(("apiKey" = [])),
+    responses(
+        (status = 201, description = "The webhook and its signing secret, shown only this once", body = CreatedWebhook),
+        (status = 400, description = "The URL is not http(s), or its host is not a public address"),
+        (status = 403, response = openapi::Forbidden),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_webhook(
+    State(pool): State<PgPool>,
+    JsonBody(request): JsonBody<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<CreatedWebhook>), ApiError> {
+    let url = request.url.trim();
+    debug!(url, "=> POST /admin/webhooks - Registering webhook");
+    dns::parse_delivery_url(url).map_err(|e| ApiError::bad_request("invalid_url", e))?;
+    let (record, secret) =
+        create_webhook(&pool, url, None).await.map_err(|e| ApiError::database(e, "Failed to register webhook"))?;
+    info!(id = record.id, url, "Registered webhook");
+    Ok((StatusCode::CREATED, Json(CreatedWebhook { secret, record })))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/webhooks/{id}",
+    tag = "admin",
+    summary = "Remove a webhook",
+    params(("id" = i64, Path)),
+    security(("apiKey" = [])),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = 403, response = openapi::Forbidden),
+        (status = 404, description = "No webhook has that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn delete_webhook(
+    State(pool): State<PgPool>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    debug!(id, "=> DELETE /admin/webhooks/:id - Removing webhook");
+    let deleted = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to remove webhook"))?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(ApiError::not_found("not_found", format!("No webhook with id {}", id)));
+    }
+    info!(id, "Removed webhook");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/alert-targets",
+    tag = "admin",
+    summary = "List alert targets",
+    params(AlertTargetsParams),
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "Every matching alert target", body = Vec<AlertTargetRecord>),
+        (status = 403, response = openapi::Forbidden),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn list_alert_targets(
+    State(pool): State<PgPool>,
+    Query(params): Query<AlertTargetsParams>,
+) -> Result<Json<Vec<AlertTargetRecord>>, ApiError> {
+    debug!(authority = ?params.authority, "=> GET /admin/alert-targets - Fetching alert targets");
+    let targets = sqlx::query_as::<_, AlertTargetRecord>(&format!(
+        "SELECT {} FROM alert_targets WHERE $1::text IS NULL OR authority = $1 ORDER BY id",
+        ALERT_TARGET_COLUMNS
+    ))
+    .bind(&params.authority)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to fetch alert targets"))?;
+    debug!(returned = targets.len(), "<= GET /admin/alert-targets - Responding with alert targets");
+    Ok(Json(targets))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/alert-targets",
+    tag = "admin",
+    summary = "Register an alert target for an authority's nodes",
+    description = "The target is notified when one of the authority's nodes goes offline (its URI failed a probe \
+        after passing the previous one) or is pruned. Webhook alerts are signed like node change webhooks, in \
+        `X-Alert-Signature`; email alerts need `ALERT_SMTP_URL`.",
+    request_body = CreateAlertTargetRequest,
+    security(("apiKey" = [])),
+    responses(
+        (
+            status = 201,
+            description = "The target, with a webhook's signing secret shown only this once",
+            body = CreatedAlertTarget
+        ),
+        (
+            status = 400,
+            description = "The authority is not a pubkey, or the target doesn't suit its kind or has a non-public host"
+        ),
+        (status = 403, response = openapi::Forbidden),
+        (status = 409, description = "The authority already has this target"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_alert_target(
+    State(pool): State<PgPool>,
+    JsonBody(request): JsonBody<CreateAlertTargetRequest>,
+) -> Result<(StatusCode, Json<CreatedAlertTarget>), ApiError> {
+    let (authority, target) = (request.authority.trim(), request.target.trim());
+    debug!(authority, kind = request.kind.as_str(), target, "=> POST /admin/alert-targets - Registering alert target");
+    if Pubkey::from_str(authority).is_err() {
+        return Err(ApiError::invalid_pubkey(authority));
+    }
+    let created = register_alert_target(&pool, authority, request.kind, target, None).await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// Checks `target` suits `kind` and registers it for `authority`'s nodes, unless the authority
+/// already has `limit` targets.
+async fn register_alert_target(
+    pool: &PgPool,
+    authority: &str,
+    kind: AlertKind,
+    target: &str,
+    limit: Option<i64>,
+) -> Result<CreatedAlertTarget, ApiError> {
+    match kind {
+        AlertKind::Webhook => {
+            dns::parse_delivery_url(target).map_err(|e| ApiError::bad_request("invalid_target", e))?;
+        }
+        AlertKind::Email if !alerts::is_email_address(target) => {
+            return Err(ApiError::bad_request("invalid_target", format!("'{}' is not an email address", target)));
+        }
+        AlertKind::Email => {}
+    }
+    let created = create_alert_target(pool, authority, kind, target, limit).await.map_err(|e| {
+        let duplicate = e
+            .downcast_ref::<sqlx::Error>()
+            .and_then(|e| e.as_database_error())
+            .is_some_and(|e| e.is_unique_violation());
+        if duplicate {
+            let message = format!("'{}' is already an alert target of {}", target, authority);
+            ApiError::new(StatusCode::CONFLICT, "duplicate_target", message)
+        } else {
+            ApiError::database(e, "Failed to register alert target")
+        }
+    })?;
+    let Some((record, secret)) = created else {
+        let message = format!("{} already has as many alert targets as allowed", authority);
+        return Err(ApiError::new(StatusCode::CONFLICT, "too_many_targets", message));
+    };
+    info!(id = record.id, authority, kind = %record.kind, "Registered alert target");
+    Ok(CreatedAlertTarget { secret, record })
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/alert-targets/{id}",
+    tag = "admin",
+    summary = "Get an alert target",
+    params(("id" = i64, Path)),
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "The alert target", body = AlertTargetRecord),
+        (status = 403, response = openapi::Forbidden),
+        (status = 404, description = "No alert target has that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_alert_target(
+    State(pool): State<PgPool>,
+    Path(id): Path<i64>,
+) -> Result<Json<AlertTargetRecord>, ApiError> {
+    debug!(id, "=> GET /admin/alert-targets/:id - Fetching alert target");
+    sqlx::query_as::<_, AlertTargetRecord>(&format!("SELECT {} FROM alert_targets WHERE id = $1", ALERT_TARGET_COLUMNS))
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to fetch alert target"))?
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("not_found", format!("No alert target with id {}", id)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/alert-targets/{id}",
+    tag = "admin",
+    summary = "Remove an alert target and its queued alerts",
+    params(("id" = i64, Path)),
+    security(("apiKey" = [])),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = 403, response = openapi::Forbidden),
+        (status = 404, description = "No alert target has that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn delete_alert_target(
+    State(pool): State<PgPool>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    debug!(id, "=> DELETE /admin/alert-targets/:id - Removing alert target");
+    // Its queued alerts go with it.
+    let deleted = sqlx::query("DELETE FROM alert_targets WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to remove alert target"))?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(ApiError::not_found("not_found", format!("No alert target with id {}", id)));
+    }
+    info!(id, "Removed alert target");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Alerts raised for one target, newest first, with their delivery state.
+#[utoipa::path(
+    get,
+    path = "/admin/alert-targets/{id}/alerts",
+    tag = "admin",
+    summary = "List a target's alerts with their delivery state",
+    params(("id" = i64, Path), PageParams),
+    security(("apiKey" = [])),
+    responses(
+        (
+            status = 200,
+            description = "A page of alerts, newest first",
+            body = AlertsPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 403, response = openapi::Forbidden),
+        (status = 404, description = "No alert target has that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn list_alerts(
+    State(pool): State<PgPool>,
+    Path(id): Path<i64>,
+    Query(params): Query<PageParams>,
+) -> Result<(TotalCount, Json<AlertsPage>), ApiError> {
+    let (limit, offset) = params.resolve();
+    debug!(id, limit, offset, "=> GET /admin/alert-targets/:id/alerts - Fetching alerts");
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch alerts");
+    let exists: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM alert_targets WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .map_err(db_error)?;
+    if !exists {
+        return Err(ApiError::not_found("not_found", format!("No alert target with id {}", id)));
+    }
+    let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM alerts WHERE target_id = $1")
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .map_err(db_error)?;
+    let alerts = sqlx::query_as::<_, AlertRecord>(&format!(
+        "SELECT {} FROM alerts WHERE target_id = $1 ORDER BY id DESC LIMIT $2 OFFSET $3",
+        ALERT_COLUMNS
+    ))
+    .bind(id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let next_offset = (offset + (alerts.len() as i64) < total).then(|| offset + alerts.len() as i64);
+    debug!(id, returned = alerts.len(), total, "<= GET /admin/alert-targets/:id/alerts - Responding with alerts");
+    Ok((TotalCount(total), Json(AlertsPage { alerts, total, limit, offset, next_offset })))
+}
+
+/// Runs a full reconciliation cycle for every program now, rather than at the next poll, and
+/// returns what each one changed. Only the instance running ingestion can serve this.
+#[utoipa::path(
+    post,
+    path = "/admin/resync",
+    tag = "admin",
+    summary = "Run a reconciliation cycle for every program now",
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "What each program's cycle did", body = Vec<ProgramResync>),
+        (status = 403, response = openapi::Forbidden),
+        (status = 409, description = "Ingestion is paused"),
+        (status = 502, description = "A cycle failed"),
+        (status = 503, description = "This process runs no reconciliation loops"),
+    )
+)]
+async fn post_resync(
+    State(resync): State<ResyncTrigger>,
+    State(pause): State<IngestionPause>,
+    State(sync): State<SyncContext>,
+) -> Result<Json<Vec<ProgramResync>>, ApiError> {
+    debug!("=> POST /admin/resync - Running reconciliation");
+    if pause.is_paused() {
+        let message = "Ingestion is paused; resume it before resyncing";
+        return Err(ApiError::new(StatusCode::CONFLICT, "paused", message));
+    }
+    let programs = sync.clusters.programs();
+    let mut results = Vec::with_capacity(programs.len());
+    for program in &programs {
+        let (program_id, cluster) = (program.program_id, sync.clusters.name_of(program));
+        let summary = match resync.resync(program).await {
+            Some(Ok(summary)) => summary,
+            Some(Err(e)) => {
+                error!(%program_id, cluster, error = %e, "Requested reconciliation failed");
+                let message = format!("Reconciliation failed for program {}", program_id);
+                return Err(ApiError::new(StatusCode::BAD_GATEWAY, "resync_failed", message));
+            }
+            None => {
+                let message = "This instance isn't running ingestion; send the request to the sync leader";
+                return Err(ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "not_ingesting", message));
+            }
+        };
+        info!(%program_id, cluster, upserted = summary.upserted, pruned = summary.pruned, "Resynced on request");
+        let cluster = cluster.map(str::to_string);
+        results.push(ProgramResync { program_id: program_id.to_string(), cluster, summary });
+    }
+    debug!(programs = results.len(), "<= POST /admin/resync - Responding with cycle summaries");
+    Ok(Json(results))
+}
+
+const DECODE_FAILURE_COLUMNS: &str =
+    "pubkey, program_id, encode(raw_data, 'hex') AS raw_data, error, first_seen_at, last_seen_at";
+
+/// Accounts the decoders rejected, most recently seen first.
+#[utoipa::path(
+    get,
+    path = "/admin/decode-failures",
+    tag = "admin",
+    summary = "List accounts whose data failed to decode",
+    params(PageParams, DecodeFailuresParams),
+    security(("apiKey" = [])),
+    responses(
+        (
+            status = 200,
+            description = "A page of decode failures, most recently seen first",
+            body = DecodeFailuresPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 400, description = "A query parameter is malformed"),
+        (status = 403, response = openapi::Forbidden),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn list_decode_failures(
+    State(pool): State<PgPool>,
+    Query(params): Query<PageParams>,
+    Query(filter): Query<DecodeFailuresParams>,
+) -> Result<(TotalCount, Json<DecodeFailuresPage>), ApiError> {
+    let (limit, offset) = params.resolve();
+    debug!(limit, offset, program = ?filter.program, "=> GET /admin/decode-failures - Fetching decode failures");
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch decode failures");
+    let total: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM decode_failures WHERE $1::text IS NULL OR program_id = $1")
+            .bind(&filter.program)
+            .fetch_one(&pool)
+            .await
+            .map_err(db_error)?;
+    let failures = sqlx::query_as::<_, DecodeFailure>(&format!(
+        "SELECT {} FROM decode_failures WHERE $3::text IS NULL OR program_id = $3 \
+         ORDER BY last_seen_at DESC, pubkey LIMIT $1 OFFSET $2",
+        DECODE_FAILURE_COLUMNS
+    ))
+    .bind(limit)
+    .bind(offset)
+    .bind(&filter.program)
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    let next_offset = (offset + (failures.len() as i64) < total).then(|| offset + failures.len() as i64);
+    debug!(returned = failures.len(), total, "<= GET /admin/decode-failures - Responding with decode failures");
+    Ok((TotalCount(total), Json(DecodeFailuresPage { failures, total, limit, offset, next_offset })))
+}
+
+/// Refetches dead-lettered accounts and runs them through the current decoders, e.g. after a
+/// decoder fix has been deployed. Each account is applied like a streamed update: decoded ones
+/// are stored and leave the table, closed ones are pruned.
+#[utoipa::path(
+    post,
+    path = "/admin/decode-failures/retry",
+    tag = "admin",
+    summary = "Refetch undecodable accounts and decode them again",
+    request_body = DecodeRetryRequest,
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "Which accounts now decode", body = DecodeRetry),
+        (status = 400, description = "More pubkeys than one request may retry"),
+        (status = 403, response = openapi::Forbidden),
+        (status = 409, description = "Ingestion is paused"),
+        (status = 500, response = openapi::DatabaseError),
+        (status = 502, description = "The RPC node failed"),
+    )
+)]
+async fn post_decode_retry(
+    State(sync): State<SyncContext>,
+    JsonBody(request): JsonBody<DecodeRetryRequest>,
+) -> Result<Json<DecodeRetry>, ApiError> {
+    let requested = request.pubkeys.as_ref().map(Vec::len);
+    debug!(?requested, "=> POST /admin/decode-failures/retry - Retrying undecodable accounts");
+    if sync.pause.is_paused() {
+        let message = "Ingestion is paused; resume it before retrying";
+        return Err(ApiError::new(StatusCode::CONFLICT, "paused", message));
+    }
+    if requested.is_some_and(|requested| requested > MAX_DECODE_RETRIES) {
+        let message = format!("At most {} accounts can be retried at once", MAX_DECODE_RETRIES);
+        return Err(ApiError::bad_request("too_many_pubkeys", message));
+    }
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to load decode failures");
+    let targets: Vec<(String, String)> = sqlx::query_as(
+        "SELECT pubkey, program_id FROM decode_failures WHERE $1::text[] IS NULL OR pubkey = ANY($1) \
+         ORDER BY last_seen_at, pubkey LIMIT $2",
+    )
+    .bind(&request.pubkeys)
+    .bind(MAX_DECODE_RETRIES as i64)
+    .fetch_all(&sync.pool)
+    .await
+    .map_err(db_error)?;
+
+    let rpc_error = |e: solana_client::client_error::ClientError| {
+        error!(error = %e, "Failed to refetch an undecodable account");
+        ApiError::new(StatusCode::BAD_GATEWAY, "rpc_failed", "Failed to fetch the accounts from the RPC node")
+    };
+    let programs = sync.clusters.programs();
+    for (pubkey, program_id) in &targets {
+        let (Ok(address), Ok(program_id)) = (Pubkey::from_str(pubkey), Pubkey::from_str(program_id)) else {
+            continue;
+        };
+        // Retried on every cluster the program is indexed from.
+        for program in programs.iter().filter(|program| program.program_id == program_id) {
+            // The account is fetched at or after this slot of the cluster.
+            let rpc = sync.clusters.rpc_for(program);
+            let slot = rpc.get_slot().await.map_err(rpc_error)?;
+            // An account the program no longer owns is as good as closed for the index.
+            let (lamports, data, rent_epoch) = match rpc.get_account(&address).await.map_err(rpc_error)? {
+                Some(account) if account.owner == program_id => (account.lamports, account.data, account.rent_epoch),
+                _ => (0, Vec::new(), 0),
+            };
+            let closed = lamports == 0 || data.is_empty();
+            let update = AccountUpdate {
+                program: *program,
+                pubkey: pubkey.clone(),
+                lamports,
+                data,
+                owner: program_id,
+                rent_epoch,
+                slot,
+            };
+            let applied = async {
+                sync::apply_account_update(&sync, update, "Retry").await?;
+                if closed {
+                    clear_decode_failures(&sync.pool, std::slice::from_ref(pubkey)).await?;
+                }
+                Ok::<_, crate::AppError>(())
+            };
+            if let Err(e) = applied.await {
+                error!(%pubkey, error = %e, "Failed to store a retried account");
+                let message = "Failed to store the account";
+                return Err(ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "retry_failed", message));
+            }
+        }
+    }
+
+    let pubkeys: Vec<&str> = targets.iter().map(|(pubkey, _)| pubkey.as_str()).collect();
+    let failing = sqlx::query_as::<_, DecodeFailure>(&format!(
+        "SELECT {} FROM decode_failures WHERE pubkey = ANY($1) ORDER BY pubkey",
+        DECODE_FAILURE_COLUMNS
+    ))
+    .bind(&pubkeys)
+    .fetch_all(&sync.pool)
+    .await
+    .map_err(db_error)?;
+    let resolved: Vec<String> = pubkeys
+        .iter()
+        .filter(|pubkey| !failing.iter().any(|failure| failure.pubkey == **pubkey))
+        .map(|pubkey| pubkey.to_string())
+        .collect();
+    info!(retried = targets.len(), resolved = resolved.len(), "Retried undecodable accounts");
+    debug!(failing = failing.len(), "<= POST /admin/decode-failures/retry - Responding with retry outcome");
+    Ok(Json(DecodeRetry { retried: targets.len(), resolved, failing }))
+}
+
+/// Stops all ingestion writes in this process, leaving the API up. Responds once writes that
+/// were already running have finished, so the database can be worked on right away.
+#[utoipa::path(
+    post,
+    path = "/admin/pause",
+    tag = "admin",
+    summary = "Pause ingestion in this process",
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "Paused", body = PauseStatus),
+        (status = 403, response = openapi::Forbidden),
+    )
+)]
+async fn post_pause(State(pause): State<IngestionPause>) -> Json<PauseStatus> {
+    debug!("=> POST /admin/pause - Pausing ingestion");
+    pause.pause().await;
+    info!("Ingestion paused");
+    Json(PauseStatus { paused: true })
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/resume",
+    tag = "admin",
+    summary = "Resume ingestion in this process",
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "Resumed", body = PauseStatus),
+        (status = 403, response = openapi::Forbidden),
+    )
+)]
+async fn post_resume(State(pause): State<IngestionPause>) -> Json<PauseStatus> {
+    debug!("=> POST /admin/resume - Resuming ingestion");
+    pause.resume();
+    info!("Ingestion resumed");
+    Json(PauseStatus { paused: false })
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/quarantine",
+    tag = "admin",
+    summary = "List quarantined nodes",
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "Quarantined nodes, most recent first", body = Vec<NodeModeration>),
+        (status = 403, response = openapi::Forbidden),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn list_quarantined(State(pool): State<PgPool>) -> Result<Json<Vec<NodeModeration>>, ApiError> {
+    debug!("=> GET /admin/quarantine - Fetching quarantined nodes");
+    let quarantined = moderation::list_quarantined(&pool)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to fetch quarantined nodes"))?;
+    debug!(returned = quarantined.len(), "<= GET /admin/quarantine - Responding with quarantined nodes");
+    Ok(Json(quarantined))
+}
+
+/// Hides a node from public reads and the change feeds, whether or not it is indexed yet. The
+/// sync loop keeps indexing it, so unquarantining shows it as it is on chain by then.
+#[utoipa::path(
+    post,
+    path = "/admin/nodes/{pubkey}/quarantine",
+    tag = "admin",
+    summary = "Hide a node from public reads and the change feeds",
+    description = "The node keeps being indexed. Feeds see it removed; quarantining it again only updates the reason.",
+    params(("pubkey" = String, Path, description = "Base58 account address of the node")),
+    request_body = QuarantineRequest,
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "The quarantine", body = NodeModeration),
+        (status = 400, description = "The pubkey or the reason is invalid"),
+        (status = 403, response = openapi::Forbidden),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_quarantine(
+    State(sync): State<SyncContext>,
+    Extension(key): Extension<ApiKey>,
+    Path(pubkey): Path<String>,
+    JsonBody(request): JsonBody<QuarantineRequest>,
+) -> Result<Json<NodeModeration>, ApiError> {
+    debug!(%pubkey, "=> POST /admin/nodes/:pubkey/quarantine - Quarantining node");
+    if Pubkey::from_str(&pubkey).is_err() {
+        return Err(ApiError::invalid_pubkey(&pubkey));
+    }
+    let reason = request.reason.as_deref().map(str::trim).filter(|reason| !reason.is_empty());
+    if reason.is_some_and(|reason| reason.chars().count() > MAX_QUARANTINE_REASON_LEN) {
+        let message = format!("`reason` must be at most {} characters", MAX_QUARANTINE_REASON_LEN);
+        return Err(ApiError::bad_request("invalid_body", message));
+    }
+    let record = moderation::quarantine(&sync, &pubkey, reason, Some(&key.name))
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to quarantine node"))?;
+    info!(%pubkey, reason, by = key.name, "Quarantined node");
+    Ok(Json(record))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/nodes/{pubkey}/unquarantine",
+    tag = "admin",
+    summary = "Lift a node's quarantine",
+    description = "Feeds see the node added again if it is still indexed.",
+    params(("pubkey" = String, Path, description = "Base58 account address of the node")),
+    security(("apiKey" = [])),
+    responses(
+        (status = 200, description = "The quarantine that was lifted", body = NodeModeration),
+        (status = 400, response = openapi::InvalidPubkey),
+        (status = 403, response = openapi::Forbidden),
+        (status = 404, description = "The node isn't quarantined"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_unquarantine(
+    State(sync): State<SyncContext>,
+    Path(pubkey): Path<String>,
+) -> Result<Json<NodeModeration>, ApiError> {
+    debug!(%pubkey, "=> POST /admin/nodes/:pubkey/unquarantine - Lifting node quarantine");
+    if Pubkey::from_str(&pubkey).is_err() {
+        return Err(ApiError::invalid_pubkey(&pubkey));
+    }
+    let record = moderation::unquarantine(&sync, &pubkey)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to unquarantine node"))?
+        .ok_or_else(|| ApiError::not_found("not_quarantined", format!("Node {} isn't quarantined", pubkey)))?;
+    info!(%pubkey, "Lifted node quarantine");
+    Ok(Json(record))
+}
+
+#[utoipa::path(
+    post,
+    path = "/my/challenge",
+    tag = "operators",
+    summary = "Get a challenge to sign in with an authority's keypair",
+    description = "Sign the returned `message` as is, its UTF-8 bytes with the authority's ed25519 keypair as wallets' \
+        `signMessage` does, and send the signature to POST /my/session within five minutes. Each challenge can be \
+        used once.",
+    request_body = ChallengeRequest,
+    responses(
+        (status = 201, description = "The challenge", body = Challenge),
+        (status = 400, response = openapi::InvalidPubkey),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_challenge(
+    State(pool): State<PgPool>,
+    JsonBody(request): JsonBody<ChallengeRequest>,
+) -> Result<(StatusCode, Json<Challenge>), ApiError> {
+    let authority = request.authority.trim();
+    debug!(authority, "=> POST /my/challenge - Issuing sign-in challenge");
+    if Pubkey::from_str(authority).is_err() {
+        return Err(ApiError::invalid_pubkey(authority));
+    }
+    let challenge = operator::create_challenge(&pool, authority)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to issue challenge"))?;
+    Ok((StatusCode::CREATED, Json(challenge)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/my/session",
+    tag = "operators",
+    summary = "Exchange a signed challenge for a 24-hour session token",
+    request_body = SessionRequest,
+    responses(
+        (status = 201, description = "The session; the token is shown only this once", body = Session),
+        (status = 400, description = "The authority is not a pubkey, or the signature isn't base58"),
+        (
+            status = 401,
+            description = "Missing, unknown or revoked API key, or the challenge is unknown, expired or already used, \
+                or the signature doesn't match it"
+        ),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_session(
+    State(pool): State<PgPool>,
+    JsonBody(request): JsonBody<SessionRequest>,
+) -> Result<(StatusCode, Json<Session>), ApiError> {
+    let authority = request.authority.trim();
+    debug!(authority, "=> POST /my/session - Checking signed challenge");
+    let Ok(authority) = Pubkey::from_str(authority) else {
+        return Err(ApiError::invalid_pubkey(authority));
+    };
+    let Ok(signature) = Signature::from_str(request.signature.trim()) else {
+        return Err(ApiError::bad_request("invalid_signature", "`signature` must be a base58 ed25519 signature"));
+    };
+    let session = operator::sign_in(&pool, &authority, request.nonce.trim(), &signature)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to sign in"))?;
+    let Some(session) = session else {
+        let message = "The challenge is unknown, expired or already used, or the signature doesn't match it";
+        return Err(ApiError::new(StatusCode::UNAUTHORIZED, "invalid_signature", message));
+    };
+    info!(%authority, "Operator signed in");
+    Ok((StatusCode::CREATED, Json(session)))
+}
+
+/// Trims an operator metadata field, checking it is at most `max_len` characters with no control
+/// characters other than newlines in `multiline` fields. Blank values become `None`.
+fn operator_text(
+    name: &str,
+    value: Option<&str>,
+    max_len: usize,
+    multiline: bool,
+) -> Result<Option<String>, ApiError> {
+    let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else { return Ok(None) };
+    if value.chars().count() > max_len {
+        let message = format!("`{}` must be at most {} characters", name, max_len);
+        return Err(ApiError::bad_request("invalid_body", message));
+    }
+    if value.chars().any(|c| c.is_control() && !(multiline && c == '\n')) {
+        return Err(ApiError::bad_request("invalid_body", format!("`{}` contains control characters", name)));
+    }
+    Ok(Some(value.to_string()))
+}
+
+/// Checks that `operator` is the authority of the visible node `pubkey` on one of the clusters
+/// it's indexed from, as operator metadata is shared by all of them.
+async fn check_node_authority(pool: &PgPool, operator: &Operator, pubkey: &str) -> Result<(), ApiError> {
+    if Pubkey::from_str(pubkey).is_err() {
+        return Err(ApiError::invalid_pubkey(pubkey));
+    }
+    let authorities: Vec<String> = sqlx::query_scalar(&format!(
+        "SELECT authority FROM nodes WHERE pubkey = $1 AND deleted_at IS NULL AND {}",
+        NOT_QUARANTINED
+    ))
+    .bind(pubkey)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to fetch node from database"))?;
+    match authorities.first() {
+        Some(_) if authorities.contains(&operator.authority) => Ok(()),
+        Some(_) => Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "not_node_authority",
+            format!("{} is not the authority of node {}", operator.authority, pubkey),
+        )),
+        None => Err(ApiError::not_found("node_not_found", format!("No node indexed with pubkey {}", pubkey))),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/my/nodes/{pubkey}/metadata",
+    tag = "operators",
+    summary = "Attach metadata to one of the session authority's nodes",
+    description = "Replaces the node's display name, contact and description, returned with the node as \
+        `operator_metadata` for as long as its authority stays the same.",
+    params(("pubkey" = String, Path, description = "Base58 account address of the node")),
+    request_body = OperatorMetadataRequest,
+    security(("apiKey" = [], "operatorSession" = []), ("operatorSession" = [])),
+    responses(
+        (status = 200, description = "The stored metadata", body = OperatorMetadata),
+        (status = 400, description = "The pubkey is malformed, or a field is too long or has control characters"),
+        (status = 401, description = "Missing, unknown or revoked API key, or no live session token"),
+        (status = 403, description = "The session's authority is not the node's"),
+        (status = 404, description = "No node is indexed under the pubkey"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn put_operator_metadata(
+    State(pool): State<PgPool>,
+    Extension(operator): Extension<Operator>,
+    Path(pubkey): Path<String>,
+    JsonBody(request): JsonBody<OperatorMetadataRequest>,
+) -> Result<Json<OperatorMetadata>, ApiError> {
+    debug!(%pubkey, authority = operator.authority, "=> PUT /my/nodes/:pubkey/metadata - Setting metadata");
+    let display_name = operator_text("display_name", request.display_name.as_deref(), MAX_DISPLAY_NAME_LEN, false)?;
+    let contact = operator_text("contact", request.contact.as_deref(), MAX_CONTACT_LEN, false)?;
+    let description = operator_text("description", request.description.as_deref(), MAX_DESCRIPTION_LEN, true)?;
+    check_node_authority(&pool, &operator, &pubkey).await?;
+    let metadata = operator::put_metadata(
+        &pool,
+        &pubkey,
+        &operator.authority,
+        display_name.as_deref(),
+        contact.as_deref(),
+        description.as_deref(),
+    )
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to store operator metadata"))?;
+    info!(%pubkey, authority = operator.authority, "Stored operator metadata");
+    Ok(Json(metadata))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/my/nodes/{pubkey}/metadata",
+    tag = "operators",
+    summary = "Remove the metadata of one of the session authority's nodes",
+    params(("pubkey" = String, Path, description = "Base58 account address of the node")),
+    security(("apiKey" = [], "operatorSession" = []), ("operatorSession" = [])),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = 400, response = openapi::InvalidPubkey),
+        (status = 401, description = "Missing, unknown or revoked API key, or no live session token"),
+        (status = 403, description = "The session's authority is not the node's"),
+        (status = 404, description = "No node is indexed under the pubkey, or it has no metadata"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn delete_operator_metadata(
+    State(pool): State<PgPool>,
+    Extension(operator): Extension<Operator>,
+    Path(pubkey): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    debug!(%pubkey, authority = operator.authority, "=> DELETE /my/nodes/:pubkey/metadata - Clearing metadata");
+    check_node_authority(&pool, &operator, &pubkey).await?;
+    let deleted = operator::delete_metadata(&pool, &pubkey)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to delete operator metadata"))?;
+    if !deleted {
+        return Err(ApiError::not_found("not_found", format!("Node {} has no operator metadata", pubkey)));
+    }
+    info!(%pubkey, authority = operator.authority, "Deleted operator metadata");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/my/alert-targets",
+    tag = "operators",
+    summary = "List the session authority's alert targets",
+    security(("apiKey" = [], "operatorSession" = []), ("operatorSession" = [])),
+    responses(
+        (status = 200, description = "Every alert target of the authority", body = Vec<AlertTargetRecord>),
+        (status = 401, description = "Missing, unknown or revoked API key, or no live session token"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_operator_alert_targets(
+    State(pool): State<PgPool>,
+    Extension(operator): Extension<Operator>,
+) -> Result<Json<Vec<AlertTargetRecord>>, ApiError> {
+    debug!(authority = operator.authority, "=> GET /my/alert-targets - Fetching alert targets");
+    let targets = sqlx::query_as::<_, AlertTargetRecord>(&format!(
+        "SELECT {} FROM alert_targets WHERE authority = $1 ORDER BY id",
+        ALERT_TARGET_COLUMNS
+    ))
+    .bind(&operator.authority)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to fetch alert targets"))?;
+    debug!(returned = targets.len(), "<= GET /my/alert-targets - Responding with alert targets");
+    Ok(Json(targets))
+}
+
+#[utoipa::path(
+    post,
+    path = "/my/alert-targets",
+    tag = "operators",
+    summary = "Register an alert target for the session authority's nodes",
+    description = "Notified like a webhook target registered through POST /admin/alert-targets. Email targets \
+        can only be registered by an admin.",
+    request_body = OperatorAlertTargetRequest,
+    security(("apiKey" = [], "operatorSession" = []), ("operatorSession" = [])),
+    responses(
+        (
+            status = 201,
+            description = "The target, with a webhook's signing secret shown only this once",
+            body = CreatedAlertTarget
+        ),
+        (status = 400, description = "The URL is not http(s), or its host is not a public address"),
+        (status = 401, description = "Missing, unknown or revoked API key, or no live session token"),
+        (status = 409, description = "The authority already has this target, or has as many as allowed"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_operator_alert_target(
+    State(pool): State<PgPool>,
+    Extension(operator): Extension<Operator>,
+    JsonBody(request): JsonBody<OperatorAlertTargetRequest>,
+) -> Result<(StatusCode, Json<CreatedAlertTarget>), ApiError> {
+    let (authority, url) = (operator.authority.as_str(), request.url.trim());
+    debug!(authority, url, "=> POST /my/alert-targets - Registering alert target");
+    let limit = Some(alerts::MAX_OPERATOR_ALERT_TARGETS);
+    let created = register_alert_target(&pool, authority, AlertKind::Webhook, url, limit).await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/my/alert-targets/{id}",
+    tag = "operators",
+    summary = "Remove one of the session authority's alert targets and its queued alerts",
+    params(("id" = i64, Path)),
+    security(("apiKey" = [], "operatorSession" = []), ("operatorSession" = [])),
+    responses(
+        (status = 204, description = "Removed"),
+        (status = 401, description = "Missing, unknown or revoked API key, or no live session token"),
+        (status = 404, description = "The authority has no alert target with that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn delete_operator_alert_target(
+    State(pool): State<PgPool>,
+    Extension(operator): Extension<Operator>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    debug!(id, authority = operator.authority, "=> DELETE /my/alert-targets/:id - Removing alert target");
+    // Other authorities' targets look the same as missing ones.
+    let deleted = sqlx::query("DELETE FROM alert_targets WHERE id = $1 AND authority = $2")
+        .bind(id)
+        .bind(&operator.authority)
+        .execute(&pool)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to remove alert target"))?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(ApiError::not_found("not_found", format!("No alert target with id {}", id)));
+    }
+    info!(id, authority = operator.authority, "Removed alert target");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats",
+    tag = "status",
+    summary = "Network totals, snapshots and RPC health",
+    responses(
+        (status = 200, description = "Current stats", body = StatsResponse),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_stats(
+    State(primary): State<PgPool>,
+    State(ReadPool(pool)): State<ReadPool>,
+    State(program_ids): State<Vec<Pubkey>>,
+    State(rpc): State<Arc<SolanaRpc>>,
+    State(pause): State<IngestionPause>,
+    State(sync): State<SyncContext>,
+    State(health): State<SyncHealth>,
+) -> Result<Json<StatsResponse>, ApiError> {
+    debug!("=> GET /stats - Fetching network stats");
+
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch network stats from database");
+
+    let lag =
+        sync.clusters.programs().iter().map(|program| ProgramLag::of(program, &health, &sync.clusters)).collect();
+    let program_ids: Vec<String> = program_ids.iter().map(Pubkey::to_string).collect();
+    let (stats, snapshots) = load_stats(&pool, &program_ids).await.map_err(db_error)?;
+    let clusters = sync
+        .clusters
+        .iter()
+        .map(|cluster| ClusterStats {
+            name: cluster.name.clone(),
+            program_ids: cluster.program_ids.iter().map(Pubkey::to_string).collect(),
+            rpc: cluster.rpc.metrics(),
+        })
+        .collect();
+
+    debug!(total_nodes = stats.total_nodes, "<= GET /stats - Responding with stats");
+    Ok(Json(StatsResponse {
+        program_ids,
+        stats,
+        snapshots,
+        rpc: rpc.metrics(),
+        clusters,
+        lag,
+        database: DatabaseMetrics::of(&primary, &pool),
+        paused: pause.is_paused(),
+    }))
+}
+
+/// Network totals over time for growth charts, one point per hour or day that had a cycle.
+#[utoipa::path(
+    get,
+    path = "/stats/history",
+    tag = "status",
+    summary = "Network totals over time, one point per hour or day",
+    params(StatsHistoryParams),
+    responses(
+        (status = 200, description = "Points oldest first; buckets without a cycle are left out", body = StatsHistory),
+        (status = 400, description = "A query parameter is malformed"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_stats_history(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<StatsHistoryParams>,
+) -> Result<Json<StatsHistory>, ApiError> {
+    let resolution = params.resolution.unwrap_or(StatsResolution::Hour);
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    debug!(?resolution, limit, "=> GET /stats/history - Fetching network stats history");
+
+    let mut points = sqlx::query_as::<_, StatsPoint>(
+        r#"
+        SELECT DISTINCT ON (bucket) date_trunc($1, recorded_at, 'UTC') AS bucket, total_nodes, online_nodes
+        FROM network_stats_history
+        WHERE recorded_at >= date_trunc($1, NOW(), 'UTC') - ($2 - 1) * ('1 ' || $1)::interval
+        ORDER BY bucket DESC, recorded_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(resolution.unit())
+    .bind(limit)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to fetch network stats history"))?;
+    points.reverse();
+
+    debug!(returned = points.len(), "<= GET /stats/history - Responding with stats history");
+    Ok(Json(StatsHistory { resolution, points }))
+}
+
+/// New registrations, deregistrations and net growth per day or week, for community reports.
+#[utoipa::path(
+    get,
+    path = "/stats/growth",
+    tag = "status",
+    summary = "Registrations, deregistrations and net growth per day or week",
+    params(GrowthParams),
+    responses(
+        (status = 200, description = "Periods oldest first, ending with the current one", body = GrowthReport),
+        (status = 400, description = "A query parameter is malformed"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_stats_growth(
+    State(ReadPool(pool)): State<ReadPool>,
+    Query(params): Query<GrowthParams>,
+) -> Result<Json<GrowthReport>, ApiError> {
+    let period = params.period.unwrap_or(GrowthPeriod::Day);
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    debug!(?period, limit, "=> GET /stats/growth - Counting registrations per period");
+
+    let periods = sqlx::query_as::<_, GrowthPoint>(
+        r#"
+        WITH periods AS (
+            SELECT generate_series(
+                date_trunc($1, NOW(), 'UTC') - ($2 - 1) * ('1 ' || $1)::interval,
+                date_trunc($1, NOW(), 'UTC'),
+                ('1 ' || $1)::interval
+            ) AS period_start
+        ),
+        changes AS (
+            SELECT date_trunc($1, changed_at, 'UTC') AS period_start,
+                   COUNT(*) FILTER (WHERE change_type = 'added') AS registrations,
+                   COUNT(*) FILTER (WHERE change_type = 'removed') AS deregistrations
+            FROM nodes_history
+            WHERE change_type IN ('added', 'removed')
+              AND changed_at >= date_trunc($1, NOW(), 'UTC') - ($2 - 1) * ('1 ' || $1)::interval
+            GROUP BY 1
+        )
+        SELECT p.period_start,
+               COALESCE(c.registrations, 0) AS registrations,
+               COALESCE(c.deregistrations, 0) AS deregistrations,
+               COALESCE(c.registrations - c.deregistrations, 0) AS net_growth
+        FROM periods p
+        LEFT JOIN changes c USING (period_start)
+        ORDER BY p.period_start
+        "#,
+    )
+    .bind(period.unit())
+    .bind(limit)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| ApiError::database(e, "Failed to compute node growth"))?;
+
+    let registrations = periods.iter().map(|point| point.registrations).sum();
+    let deregistrations = periods.iter().map(|point| point.deregistrations).sum();
+    let net_growth = registrations - deregistrations;
+
+    debug!(registrations, deregistrations, "<= GET /stats/growth - Responding with growth");
+    Ok(Json(GrowthReport { period, periods, registrations, deregistrations, net_growth }))
+}
+
+/// Tags every response with `X-Indexed-Slot` once a snapshot has been reconciled, so clients
+/// can tell how fresh the data they just read is.
+async fn indexed_slot_header(State(health): State<SyncHealth>, request: Request, next: Next) -> Response {
+    let mut response = next.run(request).await;
+    if let Some(slot) = health.indexed_slot() {
+        response.headers_mut().insert("x-indexed-slot", HeaderValue::from(slot));
+    }
+    response
+}
+
+/// Liveness probe: answers as long as the process is serving requests.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "status",
+    summary = "Liveness probe",
+    security(),
+    responses(
+        (status = 200, description = "The process is serving requests", body = Object),
+    )
+)]
+async fn healthz() -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// What [`crate::while_starting`] serves before the indexer has connected: `/healthz` reports
+/// `"starting"` and every other route, `/readyz` included, is unavailable.
+pub(crate) fn starting_router() -> Router {
+    let starting = || async {
+        let detail = "The indexer is still connecting to its database and RPC node";
+        ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "starting", detail)
+    };
+    Router::new()
+        .route("/healthz", get(|| async { Json(serde_json::json!({ "status": "starting" })) }))
+        .fallback(starting)
+        .layer(middleware::from_fn(request_id::assign))
+}
+
+/// Readiness probe: the database (and its read replica, if any) and every cluster's RPC must
+/// answer and every program must have synced recently.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "status",
+    summary = "Readiness probe",
+    security(),
+    responses(
+        (
+            status = 200,
+            description = "The database and RPC answer, every program synced recently and no task is recovering \
+                from a panic",
+            body = ReadinessResponse
+        ),
+        (status = 503, description = "Not ready", body = ReadinessResponse),
+    )
+)]
+async fn readyz(
+    State(pool): State<PgPool>,
+    State(ReadPool(read_pool)): State<ReadPool>,
+    State(sync): State<SyncContext>,
+    State(health): State<SyncHealth>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    // Both pools are the same one unless a read replica is configured.
+    let database = async {
+        let (primary, replica) =
+            tokio::join!(sqlx::query("SELECT 1").execute(&pool), sqlx::query("SELECT 1").execute(&read_pool));
+        primary.is_ok() && replica.is_ok()
+    };
+    // A single attempt: readiness should reflect the RPC right now, not after retries.
+    let rpc = async {
+        for cluster in sync.clusters.iter() {
+            if cluster.rpc.client().get_slot().await.is_err() {
+                return false;
+            }
+        }
+        true
+    };
+    let (database, rpc) = tokio::join!(
+        async { tokio::time::timeout(READINESS_CHECK_TIMEOUT, database).await.unwrap_or(false) },
+        async { tokio::time::timeout(READINESS_CHECK_TIMEOUT, rpc).await.unwrap_or(false) },
+    );
+
+    let programs: Vec<ProgramReadiness> = sync
+        .clusters
+        .programs()
+        .iter()
+        .map(|program| {
+            let last_synced_at = health.last_success(program);
+            ProgramReadiness {
+                program_id: program.program_id.to_string(),
+                cluster: sync.clusters.name_of(program).map(str::to_string),
+                last_synced_at,
+                last_synced_slot: health.last_synced_slot(program),
+                lag_secs: last_synced_at.map(|at| (Utc::now() - at).num_seconds().max(0)),
+                fresh: health.is_fresh(program),
+            }
+        })
+        .collect();
+
+    let recovering: Vec<RecoveringTask> = health
+        .panicked_tasks()
+        .into_iter()
+        .map(|(task, panic)| RecoveringTask { task, panicked_at: panic.at, panic: panic.message, panics: panic.panics })
+        .collect();
+
+    let ready = database && rpc && programs.iter().all(|program| program.fresh) && recovering.is_empty();
+    if !ready {
+        warn!(database, rpc, recovering = recovering.len(), "Readiness check failed");
+    }
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(ReadinessResponse { ready, database, rpc, programs, recovering }))
+}
+
+/// Each program's sync position and lag in the Prometheus text format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "status",
+    summary = "Prometheus metrics",
+    security(),
+    responses(
+        (
+            status = 200,
+            description = "Gauges of each program's cluster slot, synced slot, slot lag and last sync time",
+            body = String,
+            content_type = "text/plain"
+        ),
+    )
+)]
+async fn metrics(
+    State(health): State<SyncHealth>,
+    State(sync): State<SyncContext>,
+) -> impl IntoResponse {
+    let lags: Vec<ProgramLag> =
+        sync.clusters.programs().iter().map(|program| ProgramLag::of(program, &health, &sync.clusters)).collect();
+    let mut body = String::new();
+    let mut gauge = |name: &str, help: &str, value: &dyn Fn(&ProgramLag) -> Option<u64>| {
+        body.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name));
+        for lag in &lags {
+            let Some(value) = value(lag) else { continue };
+            let cluster = lag.cluster.as_deref().map(|cluster| format!(",cluster=\"{}\"", cluster)).unwrap_or_default();
+            body.push_str(&format!("{}{{program_id=\"{}\"{}}} {}\n", name, lag.program_id, cluster, value));
+        }
+    };
+    gauge("indexer_cluster_slot", "Current slot of the program's cluster.", &|lag| lag.cluster_slot);
+    gauge("indexer_synced_slot", "Snapshot slot of the program's last successful sync.", &|lag| lag.synced_slot);
+    gauge("indexer_slot_lag", "Slots the last synced snapshot trails the cluster.", &|lag| lag.lag_slots);
+    let last_sync = |lag: &ProgramLag| lag.synced_at.map(|at| at.timestamp().max(0) as u64);
+    gauge("indexer_last_sync_timestamp_seconds", "When the program last synced successfully.", &last_sync);
+    ([(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws",
+    tag = "feeds",
+    summary = "WebSocket feed of node changes",
+    description = format!(
+        "Upgrades to a WebSocket that receives one JSON SequencedEvent text message per change, or \
+        `{{\"type\":\"lagged\",\"skipped\":N}}` when the client fell behind. Send a SubscriptionMessage to \
+        only receive the changes of nodes matching a SubscriptionFilter, including those that stop matching, or \
+        to receive every change again. It is answered with `{{\"type\":\"subscribed\",\"filter\":...}}`, \
+        `{{\"type\":\"unsubscribed\"}}` or `{{\"type\":\"error\",\"message\":...}}`, the last leaving the \
+        filter as it was. A filter lists at most {} pubkeys.",
+        MAX_FILTER_PUBKEYS
+    ),
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+    )
+)]
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(pool): State<PgPool>,
+    State(events): State<EventHub>,
+) -> impl IntoResponse {
+    debug!("=> GET /ws - Upgrading connection");
+    // Subscribe before the upgrade completes so no event is missed in between.
+    let rx = events.subscribe();
+    ws.on_upgrade(move |socket| stream_subscribed_events(socket, rx, pool))
+}
+
+/// Sends the client each event `keep` lets through.
+async fn stream_events(
+    mut socket: WebSocket,
+    mut rx: broadcast::Receiver<SequencedEvent>,
+    mut keep: impl FnMut(&NodeEvent) -> bool,
+) {
+    info!("WebSocket client subscribed");
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let payload = match event {
+                    Ok(event) if !keep(&event.event) => continue,
+                    Ok(event) => match serde_json::to_string(&event) {
+                        Ok(json) => json,
+                        Err(e) => {
+                            warn!(error = %e, "Failed to serialize event for WebSocket client");
+                            continue;
+                        }
+                    },
+                    // A slow client missed some events; tell it so it can refetch /nodes.
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        format!(r#"{{"type":"lagged","skipped":{}}}"#, skipped)
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if socket.send(Message::Text(payload)).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+    info!("WebSocket client disconnected");
+}
+
+/// Sends the client every event, or once it subscribes only those its filter matches (see
+/// [`crate::subscriptions`]).
+async fn stream_subscribed_events(mut socket: WebSocket, mut rx: broadcast::Receiver<SequencedEvent>, pool: PgPool) {
+    info!("WebSocket client subscribed");
+    let mut subscription: Option<Subscription> = None;
+    loop {
+        let payload = tokio::select! {
+            event = rx.recv() => match event {
+                Ok(event) => {
+                    if let Some(subscription) = subscription.as_mut() {
+                        match subscription.matches(&pool, &event.event).await {
+                            Ok(true) => {}
+                            Ok(false) => continue,
+                            Err(e) => {
+                                warn!(error = %e, "Failed to check event against WebSocket subscription");
+                                continue;
+                            }
+                        }
+                    }
+                    match serde_json::to_string(&event) {
+                        Ok(json) => json,
+                        Err(e) => {
+                            warn!(error = %e, "Failed to serialize event for WebSocket client");
+                            continue;
+                        }
+                    }
+                }
+                // A slow client missed some events; tell it so it can refetch /nodes.
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    format!(r#"{{"type":"lagged","skipped":{}}}"#, skipped)
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(Message::Text(text))) => {
+                    let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                        Ok(ClientMessage::Subscribe { filter }) => match Subscription::start(&pool, filter).await {
+                            Ok(started) => {
+                                debug!(filter = ?started.filter(), "WebSocket client set a filter");
+                                let filter = started.filter().clone();
+                                subscription = Some(started);
+                                ServerMessage::Subscribed { filter }
+                            }
+                            Err(message) => ServerMessage::Error { message },
+                        },
+                        Ok(ClientMessage::Unsubscribe) => {
+                            subscription = None;
+                            ServerMessage::Unsubscribed
+                        }
+                        Err(e) => ServerMessage::Error { message: format!("Invalid message: {}", e) },
+                    };
+                    serde_json::to_string(&reply).expect("server messages serialize")
+                }
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket.send(Message::Text(payload)).await.is_err() {
+            break;
+        }
+    }
+    info!("WebSocket client disconnected");
+}
+
+/// The key a watchlist request authenticated with; watchlists belong to one.
+fn watchlist_owner(key: Option<Extension<ApiKey>>) -> Result<ApiKey, ApiError> {
+    match key {
+        Some(Extension(key)) => Ok(key),
+        None => Err(ApiError::new(StatusCode::UNAUTHORIZED, "missing_api_key", "Watchlists need an API key")),
+    }
+}
+
+fn watchlist_not_found(id: i64) -> ApiError {
+    ApiError::not_found("not_found", format!("No watchlist with id {}", id))
+}
+
+/// Checks a watchlist's name and entries, dropping duplicate entries.
+fn watchlist_entries(request: WatchlistRequest) -> Result<(String, Vec<String>, Vec<String>), ApiError> {
+    let name = request.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_WATCHLIST_NAME_LEN {
+        let message = format!("`name` must be 1 to {} characters", MAX_WATCHLIST_NAME_LEN);
+        return Err(ApiError::bad_request("invalid_body", message));
+    }
+    if request.pubkeys.len() + request.authorities.len() > MAX_WATCHLIST_ENTRIES {
+        let message = format!("A watchlist lists at most {} pubkeys and authorities", MAX_WATCHLIST_ENTRIES);
+        return Err(ApiError::bad_request("invalid_body", message));
+    }
+    let dedup = |entries: Vec<String>| -> Result<Vec<String>, ApiError> {
+        let mut unique: Vec<String> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let entry = entry.trim().to_string();
+            if Pubkey::from_str(&entry).is_err() {
+                return Err(ApiError::invalid_pubkey(&entry));
+            }
+            if !unique.contains(&entry) {
+                unique.push(entry);
+            }
+        }
+        Ok(unique)
+    };
+    Ok((name, dedup(request.pubkeys)?, dedup(request.authorities)?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/watchlists",
+    tag = "watchlists",
+    summary = "List the API key's watchlists",
+    responses(
+        (status = 200, description = "Every watchlist of the key", body = Vec<Watchlist>),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_watchlists(
+    State(pool): State<PgPool>,
+    key: Option<Extension<ApiKey>>,
+) -> Result<Json<Vec<Watchlist>>, ApiError> {
+    let key = watchlist_owner(key)?;
+    debug!(key = key.name, "=> GET /watchlists - Fetching watchlists");
+    let watchlists = watchlists::list_watchlists(&pool, key.id)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to fetch watchlists"))?;
+    debug!(returned = watchlists.len(), "<= GET /watchlists - Responding with watchlists");
+    Ok(Json(watchlists))
+}
+
+#[utoipa::path(
+    post,
+    path = "/watchlists",
+    tag = "watchlists",
+    summary = "Create a watchlist of pubkeys and authorities",
+    description = format!(
+        "A change matches when its node is listed by pubkey, or its authority before or after the change is \
+        listed. At most {} entries. With a `webhook_url`, matching changes are also delivered like an admin \
+        webhook's.",
+        MAX_WATCHLIST_ENTRIES
+    ),
+    request_body = CreateWatchlistRequest,
+    responses(
+        (
+            status = 201,
+            description = "The watchlist, with its webhook's signing secret shown only this once",
+            body = CreatedWatchlist
+        ),
+        (
+            status = 400,
+            description = "An entry is not a pubkey, the name or entries are out of bounds, or the webhook URL is \
+                not http or https or has a non-public host"
+        ),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn post_watchlist(
+    State(pool): State<PgPool>,
+    key: Option<Extension<ApiKey>>,
+    JsonBody(request): JsonBody<CreateWatchlistRequest>,
+) -> Result<(StatusCode, Json<CreatedWatchlist>), ApiError> {
+    let key = watchlist_owner(key)?;
+    debug!(key = key.name, name = request.watchlist.name, "=> POST /watchlists - Creating watchlist");
+    let (name, pubkeys, authorities) = watchlist_entries(request.watchlist)?;
+    let webhook_url = request.webhook_url.as_deref().map(str::trim);
+    if let Some(url) = webhook_url {
+        dns::parse_delivery_url(url).map_err(|e| ApiError::bad_request("invalid_url", e))?;
+    }
+    let (watchlist, webhook_secret) =
+        watchlists::create_watchlist(&pool, key.id, &name, &pubkeys, &authorities, webhook_url)
+            .await
+            .map_err(|e| ApiError::database(e, "Failed to create watchlist"))?;
+    info!(id = watchlist.id, key = key.name, webhook = ?watchlist.webhook_id, "Created watchlist");
+    Ok((StatusCode::CREATED, Json(CreatedWatchlist { webhook_secret, watchlist })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/watchlists/{id}",
+    tag = "watchlists",
+    summary = "Get a watchlist",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 200, description = "The watchlist", body = Watchlist),
+        (status = 404, description = "The key has no watchlist with that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_watchlist(
+    State(pool): State<PgPool>,
+    key: Option<Extension<ApiKey>>,
+    Path(id): Path<i64>,
+) -> Result<Json<Watchlist>, ApiError> {
+    let key = watchlist_owner(key)?;
+    debug!(id, "=> GET /watchlists/:id - Fetching watchlist");
+    watchlists::get_watchlist(&pool, key.id, id)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to fetch watchlist"))?
+        .map(Json)
+        .ok_or_else(|| watchlist_not_found(id))
+}
+
+#[utoipa::path(
+    put,
+    path = "/watchlists/{id}",
+    tag = "watchlists",
+    summary = "Replace a watchlist's name and entries",
+    params(("id" = i64, Path)),
+    request_body = WatchlistRequest,
+    responses(
+        (status = 200, description = "The watchlist", body = Watchlist),
+        (status = 400, description = "An entry is not a pubkey, or the name or entries are out of bounds"),
+        (status = 404, description = "The key has no watchlist with that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn put_watchlist(
+    State(pool): State<PgPool>,
+    key: Option<Extension<ApiKey>>,
+    Path(id): Path<i64>,
+    JsonBody(request): JsonBody<WatchlistRequest>,
+) -> Result<Json<Watchlist>, ApiError> {
+    let key = watchlist_owner(key)?;
+    debug!(id, "=> PUT /watchlists/:id - Replacing watchlist");
+    let (name, pubkeys, authorities) = watchlist_entries(request)?;
+    let watchlist = watchlists::update_watchlist(&pool, key.id, id, &name, &pubkeys, &authorities)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to update watchlist"))?
+        .ok_or_else(|| watchlist_not_found(id))?;
+    info!(id, pubkeys = pubkeys.len(), authorities = authorities.len(), "Updated watchlist");
+    Ok(Json(watchlist))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/watchlists/{id}",
+    tag = "watchlists",
+    summary = "Delete a watchlist and its webhook",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, description = "The key has no watchlist with that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn delete_watchlist(
+    State(pool): State<PgPool>,
+    key: Option<Extension<ApiKey>>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let key = watchlist_owner(key)?;
+    debug!(id, "=> DELETE /watchlists/:id - Deleting watchlist");
+    let deleted = watchlists::delete_watchlist(&pool, key.id, id)
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to delete watchlist"))?;
+    if !deleted {
+        return Err(watchlist_not_found(id));
+    }
+    info!(id, "Deleted watchlist");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/watchlists/{id}/nodes",
+    tag = "watchlists",
+    summary = "List the nodes a watchlist covers",
+    params(("id" = i64, Path), PageParams),
+    responses(
+        (
+            status = 200,
+            description = "A page of nodes, by pubkey",
+            body = NodesPage,
+            headers(("X-Total-Count" = i64, description = "Same as `total`"))
+        ),
+        (status = 404, description = "The key has no watchlist with that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_watchlist_nodes(
+    State(pool): State<PgPool>,
+    State(ReadPool(read_pool)): State<ReadPool>,
+    key: Option<Extension<ApiKey>>,
+    Path(id): Path<i64>,
+    Query(page): Query<PageParams>,
+) -> Result<(TotalCount, Json<NodesPage>), ApiError> {
+    let key = watchlist_owner(key)?;
+    let (limit, offset) = page.resolve();
+    debug!(id, limit, offset, "=> GET /watchlists/:id/nodes - Fetching watched nodes");
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch watched nodes");
+    let watchlist =
+        watchlists::get_watchlist(&pool, key.id, id).await.map_err(db_error)?.ok_or_else(|| watchlist_not_found(id))?;
+    let (nodes, total) = watchlists::watched_nodes(&read_pool, &watchlist, limit, offset).await.map_err(db_error)?;
+    let nodes = with_uptime(&read_pool, nodes).await.map_err(db_error)?;
+    let next_offset = (offset + (nodes.len() as i64) < total).then(|| offset + nodes.len() as i64);
+    debug!(id, returned = nodes.len(), total, "<= GET /watchlists/:id/nodes - Responding with nodes");
+    Ok((TotalCount(total), Json(NodesPage { nodes, total, limit, offset, next_offset })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/watchlists/{id}/changes",
+    tag = "watchlists",
+    summary = "Poll the changes a watchlist matches after a cursor",
+    description = "Same cursors as /changes; `next_cursor` also moves past changes that didn't match.",
+    params(("id" = i64, Path), ChangesParams),
+    responses(
+        (status = 200, description = "Matching changes after `since`, oldest first", body = ChangesPage),
+        (status = 404, description = "The key has no watchlist with that id"),
+        (status = 500, response = openapi::DatabaseError),
+    )
+)]
+async fn get_watchlist_changes(
+    State(pool): State<PgPool>,
+    State(ReadPool(read_pool)): State<ReadPool>,
+    key: Option<Extension<ApiKey>>,
+    Path(id): Path<i64>,
+    Query(params): Query<ChangesParams>,
+) -> Result<Json<ChangesPage>, ApiError> {
+    let key = watchlist_owner(key)?;
+    let since = params.since.unwrap_or(0).max(0);
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_LIMIT).clamp(1, MAX_PAGE_LIMIT);
+    debug!(id, since, limit, "=> GET /watchlists/:id/changes - Fetching watched changes");
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to fetch watched changes");
+    if watchlists::get_watchlist(&pool, key.id, id).await.map_err(db_error)?.is_none() {
+        return Err(watchlist_not_found(id));
+    }
+
+    // Read first: changes become visible in id order, so none at or below it can still appear.
+    let latest: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM node_changes")
+        .fetch_one(&read_pool)
+        .await
+        .map_err(db_error)?;
+    // Fetch one extra row to know whether another page is waiting.
+    let mut changes = sqlx::query_as::<_, ApiChange>(&format!(
+        r#"
+        SELECT c.id, c.pubkey, c.change_type, c.authority, c.uri, c.program_id, c.slot, c.changed_at
+        FROM node_changes c
+        JOIN watchlists w ON w.id = $3
+        WHERE c.id > $1 AND c.id <= $4 AND (c.change_type = 'delete' OR {}) AND {}
+        ORDER BY c.id
+        LIMIT $2
+        "#,
+        NOT_QUARANTINED,
+        watchlists::MATCHES_CHANGE
+    ))
+    .bind(since)
+    .bind(limit + 1)
+    .bind(id)
+    .bind(latest)
+    .fetch_all(&read_pool)
+    .await
+    .map_err(db_error)?;
+
+    let has_more = changes.len() as i64 > limit;
+    changes.truncate(limit as usize);
+    // Without another page, the changes up to `latest` that didn't match need no second look.
+    let next_cursor = match changes.last() {
+        Some(change) if has_more => change.id,
+        _ => latest.max(since),
+    };
+
+    debug!(id, returned = changes.len(), next_cursor, has_more, "<= GET /watchlists/:id/changes - Responding");
+    Ok(Json(ChangesPage { changes, next_cursor, has_more }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/watchlists/{id}/ws",
+    tag = "watchlists",
+    summary = "WebSocket feed of the changes a watchlist matches",
+    description = "Like /ws, with the watchlist's entries as of connecting.",
+    params(("id" = i64, Path)),
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 404, description = "The key has no watchlist with that id"),
+    )
+)]
+async fn watchlist_ws_handler(
+    ws: WebSocketUpgrade,
+    State(pool): State<PgPool>,
+    State(events): State<EventHub>,
+    key: Option<Extension<ApiKey>>,
+    Path(id): Path<i64>,
+) -> Result<Response, ApiError> {
+    let key = watchlist_owner(key)?;
+    debug!(id, "=> GET /watchlists/:id/ws - Upgrading connection");
+    let db_error = |e: sqlx::Error| ApiError::database(e, "Failed to load watchlist");
+    let watchlist =
+        watchlists::get_watchlist(&pool, key.id, id).await.map_err(db_error)?.ok_or_else(|| watchlist_not_found(id))?;
+    // Subscribe before loading the matcher so no event is missed in between. The entries are
+    // those of the watchlist as it is now; a client reconnects to follow later edits.
+    let rx = events.subscribe();
+    let mut matcher = WatchlistMatcher::load(&pool, &watchlist).await.map_err(db_error)?;
+    Ok(ws.on_upgrade(move |socket| stream_events(socket, rx, move |event| matcher.matches(event))))
+}
+
+#[utoipa::path(
+    get,
+    path = "/events",
+    tag = "feeds",
+    summary = "Server-sent events feed of node changes",
+    description = "`upsert` and `delete` events carry a SequencedEvent and use its id as the event id. Reconnect \
+        with Last-Event-ID to replay missed events; a `resync` event means they are no longer buffered and the \
+        client should refetch.",
+    params(
+        ("Last-Event-ID" = Option<i64>, Header, description = "Id of the last event received before reconnecting"),
+    ),
+    responses(
+        (status = 200, description = "An event stream", body = SequencedEvent, content_type = "text/event-stream"),
+    )
+)]
+async fn sse_handler(
+    State(events): State<EventHub>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    info!(?last_event_id, "=> GET /events - New SSE subscriber");
+
+    let (backlog, complete, rx) = match last_event_id {
+        Some(last_id) => events.resume_after(last_id),
+        None => (Vec::new(), true, events.subscribe()),
+    };
+
+    // If the replay buffer no longer reaches back far enough, tell the client to refetch.
+    let resync = (!complete).then(|| Event::default().event("resync").data("{}"));
+    let replay = tokio_stream::iter(backlog.into_iter().map(sse_event));
+    let live = BroadcastStream::new(rx).map(|event| match event {
+        Ok(event) => sse_event(event),
+        Err(_) => Event::default().event("resync").data("{}"),
+    });
+
+    let stream = tokio_stream::iter(resync).chain(replay).chain(live).map(Ok);
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn sse_event(event: SequencedEvent) -> Event {
+    let kind = match &event.event {
+        NodeEvent::Added { .. } | NodeEvent::Updated { .. } => "upsert",
+        NodeEvent::Removed { .. } => "delete",
+    };
+    Event::default()
+        .id(event.id.to_string())
+        .event(kind)
+        .json_data(&event)
+        .unwrap_or_else(|_| Event::default().event("resync").data("{}"))
+}
+
+/// Builds the API router over `state`, including the API key, rate limit, `X-Indexed-Slot`,
+/// CORS and compression layers. The probes stay reachable without a key and are never throttled.
+pub fn router(state: AppState) -> Router {
+    let cors = state.cors.layer();
+    let admin = Router::new()
+        .route("/admin/keys", get(list_api_keys).post(post_api_key))
+        .route("/admin/keys/:id", delete(revoke_api_key))
+        .route("/admin/webhooks", get(list_webhooks).post(post_webhook))
+        .route("/admin/webhooks/:id", delete(delete_webhook))
+        .route("/admin/alert-targets", get(list_alert_targets).post(post_alert_target))
+        .route("/admin/alert-targets/:id", get(get_alert_target).delete(delete_alert_target))
+        .route("/admin/alert-targets/:id/alerts", get(list_alerts))
+        .route("/admin/decode-failures", get(list_decode_failures))
+        .route("/admin/decode-failures/retry", post(post_decode_retry))
+        .route("/admin/resync", post(post_resync))
+        .route("/admin/pause", post(post_pause))
+        .route("/admin/resume", post(post_resume))
+        .route("/admin/quarantine", get(list_quarantined))
+        .route("/admin/nodes/:pubkey/quarantine", post(post_quarantine))
+        .route("/admin/nodes/:pubkey/unquarantine", post(post_unquarantine))
+        .route_layer(middleware::from_fn(auth::require_admin));
+    // Each API key sees its own watchlists, so these are never cached either.
+    let watchlists = Router::new()
+        .route("/watchlists", get(get_watchlists).post(post_watchlist))
+        .route("/watchlists/:id", get(get_watchlist).put(put_watchlist).delete(delete_watchlist))
+        .route("/watchlists/:id/nodes", get(get_watchlist_nodes))
+        .route("/watchlists/:id/changes", get(get_watchlist_changes))
+        .route("/watchlists/:id/ws", get(watchlist_ws_handler));
+    let operator = Router::new()
+        .route("/my/nodes/:pubkey/metadata", put(put_operator_metadata).delete(delete_operator_metadata))
+        .route("/my/alert-targets", get(get_operator_alert_targets).post(post_operator_alert_target))
+        .route("/my/alert-targets/:id", delete(delete_operator_alert_target))
+        .route_layer(middleware::from_fn_with_state(state.clone(), operator::require_session))
+        .route("/my/challenge", post(post_challenge))
+        .route("/my/session", post(post_session));
+
+    let mut authenticated = Router::new()
+        .route("/nodes", get(get_nodes))
+        .route("/nodes/count", get(get_node_count))
+        .route("/nodes/search", get(search_nodes))
+        .route("/nodes/by-uri", get(get_nodes_by_uri))
+        .route("/nodes/batch", post(post_nodes_batch))
+        .route("/nodes/certificates", get(get_node_certificates))
+        .route("/nodes/:pubkey", get(get_node))
+        .route("/nodes/:pubkey/history", get(get_node_history))
+        .route("/nodes/:pubkey/transactions", get(get_node_transactions))
+        .route("/nodes/:pubkey/liveness", get(get_node_liveness))
+        .route("/changes", get(get_changes))
+        .route("/diff", get(get_diff))
+        .route("/leaderboard", get(get_leaderboard))
+        .route("/authorities", get(get_authorities))
+        .route("/program-events", get(get_program_events))
+        .route("/stats", get(get_stats))
+        .route("/stats/history", get(get_stats_history))
+        .route("/stats/growth", get(get_stats_growth))
+        .route("/ws", get(ws_handler))
+        .route("/events", get(sse_handler))
+        .merge(graphql::routes(state.clone()));
+    // Before the admin, watchlist and operator routes are merged, so their responses are never cached.
+    if let Some(cache) = state.response_cache.clone() {
+        authenticated = authenticated.route_layer(middleware::from_fn_with_state(cache, response_cache::cache));
+    }
+    let mut authenticated = authenticated.merge(admin).merge(watchlists).merge(operator);
+    if let Some(limiter) = state.rate_limiter.clone() {
+        authenticated = authenticated.route_layer(middleware::from_fn_with_state(limiter, throttle::throttle));
+    }
+    let mut authenticated =
+        authenticated.route_layer(middleware::from_fn_with_state(state.clone(), auth::authenticate));
+    if let Some(limiter) = state.rate_limiter.clone() {
+        authenticated =
+            authenticated.route_layer(middleware::from_fn_with_state(limiter, throttle::throttle_by_address));
+    }
+
+    let compression = state.compression.layer();
+    let router = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .merge(openapi::routes())
+        .merge(authenticated)
+        .fallback(error::route_not_found)
+        .method_not_allowed_fallback(error::method_not_allowed)
+        .layer(middleware::from_fn_with_state(state.health.clone(), indexed_slot_header))
+        .with_state(state)
+        .layer(cors)
+        .layer(middleware::from_fn(request_id::assign));
+    match compression {
+        Some(compression) => router.layer(compression),
+        None => router,
+    }
+}
@@ -0,0 +1,159 @@
+//! API key authentication. Clients send a key in the `X-API-Key` header (or the `api_key`
+//! query parameter, for browser WebSocket and EventSource clients that can't set headers).
+//! Keys are stored as SHA-256 hashes in `api_keys`; every authenticated request bumps the
+//! key's usage counters so heavy consumers can be spotted, and only keys with the admin flag
+//! reach the `/admin` routes.
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::postgres::{PgExecutor, PgPool};
+use utoipa::ToSchema;
+
+use crate::config;
+use crate::error::ApiError;
+use crate::AppError;
+
+// --- Header carrying the API key ---
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// --- Prefix of generated keys, so leaked ones are easy to recognise ---
+const API_KEY_PREFIX: &str = "idx_";
+
+/// Whether API routes require a key (`API_KEYS_REQUIRED`). Without it, requests with no key
+/// are served anonymously and only the admin routes need one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AuthConfig {
+    pub require_key: bool,
+}
+
+impl AuthConfig {
+    pub fn from_env() -> Self {
+        let require_key = config::var("API_KEYS_REQUIRED").is_ok_and(|value| value == "true" || value == "1");
+        Self { require_key }
+    }
+}
+
+/// The key a request authenticated with, stored in the request extensions.
+#[derive(Clone, Debug)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub admin: bool,
+}
+
+/// One row of `api_keys`, as listed by `GET /admin/keys`. The key itself is never stored.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Debug)]
+#[schema(as = ApiKey)]
+pub struct ApiKeyRecord {
+    pub id: i64,
+    pub name: String,
+    pub admin: bool,
+    pub request_count: i64,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+// --- Columns selected into `ApiKeyRecord` ---
+pub const API_KEY_COLUMNS: &str = "id, name, admin, request_count, last_used_at, created_at, revoked_at";
+
+pub fn hash_api_key(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+/// Creates a key named `name` and returns its row with the plaintext key, which is shown
+/// exactly once. Keys are random 256-bit values, so a fast unsalted hash is enough to store them.
+pub async fn create_api_key(
+    executor: impl PgExecutor<'_>,
+    name: &str,
+    admin: bool,
+) -> Result<(ApiKeyRecord, String), AppError> {
+    let secret: [u8; 32] = rand::random();
+    let key = format!("{}{}", API_KEY_PREFIX, secret.iter().map(|byte| format!("{:02x}", byte)).collect::<String>());
+    let record = sqlx::query_as::<_, ApiKeyRecord>(&format!(
+        "INSERT INTO api_keys (name, key_hash, admin) VALUES ($1, $2, $3) RETURNING {}",
+        API_KEY_COLUMNS
+    ))
+    .bind(name)
+    .bind(hash_api_key(&key))
+    .bind(admin)
+    .fetch_one(executor)
+    .await?;
+    Ok((record, key))
+}
+
+/// Whether the request carries an API key, valid or not.
+pub(crate) fn presents_api_key(request: &Request) -> bool {
+    request_api_key(request).is_some()
+}
+
+fn request_api_key(request: &Request) -> Option<String> {
+    if let Some(value) = request.headers().get(API_KEY_HEADER) {
+        return value.to_str().ok().map(str::to_string);
+    }
+    // Generated keys are hex, so they never need percent-decoding.
+    let query = request.uri().query()?;
+    query.split('&').find_map(|pair| pair.strip_prefix("api_key=")).map(str::to_string)
+}
+
+fn rejection(status: StatusCode, code: &'static str, detail: &str) -> Response {
+    ApiError::new(status, code, detail).into_response()
+}
+
+/// Resolves the request's API key, if any, and meters it. Unknown or revoked keys are always
+/// rejected; a missing key only when keys are required.
+pub async fn authenticate(
+    State(pool): State<PgPool>,
+    State(auth): State<AuthConfig>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = request_api_key(&request) else {
+        if auth.require_key {
+            return rejection(StatusCode::UNAUTHORIZED, "missing_api_key", "An API key is required");
+        }
+        return next.run(request).await;
+    };
+
+    match lookup_api_key(&pool, &key).await {
+        Ok(Some(key)) => {
+            request.extensions_mut().insert(key);
+            next.run(request).await
+        }
+        Ok(None) => rejection(StatusCode::UNAUTHORIZED, "invalid_api_key", "Unknown or revoked API key"),
+        Err(e) => ApiError::database(e, "Failed to check API key").into_response(),
+    }
+}
+
+/// Finds the live key `key` hashes to and meters the request against it; `None` for unknown or
+/// revoked keys. Authenticating and metering share one round trip.
+pub async fn lookup_api_key(executor: impl PgExecutor<'_>, key: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+    let found = sqlx::query_as::<_, (i64, String, bool)>(
+        r#"
+        UPDATE api_keys
+        SET request_count = request_count + 1, last_used_at = NOW()
+        WHERE key_hash = $1 AND revoked_at IS NULL
+        RETURNING id, name, admin
+        "#,
+    )
+    .bind(hash_api_key(key))
+    .fetch_optional(executor)
+    .await?;
+    Ok(found.map(|(id, name, admin)| ApiKey { id, name, admin }))
+}
+
+/// Lets only admin keys through; layered inside [`authenticate`].
+pub async fn require_admin(request: Request, next: Next) -> Response {
+    match request.extensions().get::<ApiKey>() {
+        Some(key) if key.admin => next.run(request).await,
+        Some(_) => rejection(StatusCode::FORBIDDEN, "forbidden", "This API key has no admin access"),
+        None => rejection(StatusCode::UNAUTHORIZED, "missing_api_key", "An admin API key is required"),
+    }
+}
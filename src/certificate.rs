@@ -0,0 +1,59 @@
+//! TLS certificates of https node URIs, inspected by the liveness prober. Each probe of an https
+//! URI records in `node_liveness` whether the certificate its host presented passed
+//! verification, whether it names the host, and when it expires. `GET /nodes/certificates`
+//! lists the nodes whose certificate is invalid or expires soon.
+
+use chrono::{DateTime, Utc};
+use x509_parser::extensions::GeneralName;
+
+/// What an https URI's host presented, as stored with its latest probe.
+#[derive(Clone, Copy, Debug)]
+pub struct CertificateCheck {
+    /// Whether the certificate passed verification: a trusted chain, in its validity period
+    /// and naming the host.
+    pub valid: bool,
+    /// Whether one of the certificate's subject alternative names matches the host.
+    pub hostname_match: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl CertificateCheck {
+    /// Reads the DER-encoded leaf certificate `host` presented; `valid` is the verifier's
+    /// verdict. `None` when the certificate can't be parsed.
+    pub fn inspect(der: &[u8], host: &str, valid: bool) -> Option<Self> {
+        let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+        let expires_at = DateTime::from_timestamp(certificate.validity().not_after.timestamp(), 0);
+        let names = certificate.subject_alternative_name().ok().flatten();
+        // Like the verifier, only subject alternative names count; the common name doesn't.
+        let hostname_match = valid
+            || names.is_some_and(|names| names.value.general_names.iter().any(|name| names_host(name, host)));
+        Some(Self { valid, hostname_match, expires_at })
+    }
+}
+
+fn names_host(name: &GeneralName, host: &str) -> bool {
+    match name {
+        GeneralName::DNSName(pattern) => matches_dns_name(pattern, host),
+        GeneralName::IPAddress(octets) => {
+            // IPv6 hosts come bracketed in URLs.
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            match host.parse::<std::net::IpAddr>() {
+                Ok(std::net::IpAddr::V4(ip)) => ip.octets().as_slice() == *octets,
+                Ok(std::net::IpAddr::V6(ip)) => ip.octets().as_slice() == *octets,
+                Err(_) => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Whether the DNS name `pattern` covers `host`; a leading `*` label stands for exactly one label.
+fn matches_dns_name(pattern: &str, host: &str) -> bool {
+    let (pattern, host) = (pattern.trim_end_matches('.'), host.trim_end_matches('.'));
+    match pattern.strip_prefix("*.") {
+        Some(parent) => host
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest.eq_ignore_ascii_case(parent)),
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
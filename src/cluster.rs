@@ -0,0 +1,205 @@
+//! Several clusters indexed by one process. `RPC_URL` points at the default cluster, which
+//! `CLUSTER` may name; `CLUSTERS` adds more, each reached through its own RPC endpoints. A
+//! `PROGRAM_ID` entry written as `<cluster>:<program id>` is indexed from that cluster instead
+//! of the default one. Nodes carry their cluster's name in `nodes.cluster` (null for an unnamed
+//! default cluster), which the node routes filter on with `?cluster=`.
+//!
+//! The same program can be indexed from several clusters: nodes, their history and changes,
+//! IDL-decoded accounts and sync checkpoints are keyed by cluster as well, and per-program state
+//! in the process by [`ProgramKey`]. A node's liveness, uptime, metadata and quarantine are
+//! keyed by pubkey alone and shared by its rows on every cluster, as is its transaction history.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::config;
+use crate::rpc::{self, SolanaRpc};
+use crate::{AppError, IngestionBackend};
+
+// --- Longest cluster name, which must also be lowercase letters, digits, `-` or `_` ---
+const MAX_CLUSTER_NAME_LEN: usize = 32;
+
+/// A cluster other than the default one, from `CLUSTERS`.
+#[derive(Clone, Debug)]
+pub struct ClusterConfig {
+    pub name: String,
+    /// The first endpoint is preferred; the rest are failovers.
+    pub rpc_urls: Vec<String>,
+    pub program_ids: Vec<Pubkey>,
+}
+
+impl ClusterConfig {
+    /// Reads `CLUSTERS`: comma-separated `name=url` entries, where repeating a name adds a
+    /// failover endpoint. Programs are assigned from `PROGRAM_ID` afterwards.
+    pub fn from_env() -> Result<Vec<Self>, AppError> {
+        let Ok(value) = config::var("CLUSTERS") else { return Ok(Vec::new()) };
+        let mut clusters: Vec<Self> = Vec::new();
+        for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let parsed = entry
+                .split_once('=')
+                .map(|(name, url)| (name.trim(), url.trim()))
+                .filter(|(name, url)| is_valid_name(name) && url.contains("://"));
+            let (name, url) = parsed.ok_or_else(|| {
+                let source = config::source("CLUSTERS");
+                let example = "mainnet=https://api.mainnet-beta.solana.com";
+                format!("Invalid {} entry '{}': expected a cluster name and RPC URL such as {}", source, entry, example)
+            })?;
+            match clusters.iter_mut().find(|cluster| cluster.name == name) {
+                Some(cluster) => cluster.rpc_urls.push(url.to_string()),
+                None => {
+                    let rpc_urls = vec![url.to_string()];
+                    clusters.push(Self { name: name.to_string(), rpc_urls, program_ids: Vec::new() });
+                }
+            }
+        }
+        Ok(clusters)
+    }
+}
+
+/// Reads `CLUSTER`, the name of the cluster `RPC_URL` points at.
+pub fn default_cluster_from_env() -> Result<Option<String>, AppError> {
+    match config::var("CLUSTER") {
+        Ok(name) if is_valid_name(&name) => Ok(Some(name)),
+        Ok(name) => Err(invalid_name(&config::source("CLUSTER"), &name).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Splits a `PROGRAM_ID` entry into its cluster, if it names one, and program.
+pub fn parse_program(entry: &str) -> Result<(Option<String>, Pubkey), AppError> {
+    let (cluster, program_id) = match entry.split_once(':') {
+        Some((cluster, program_id)) => (Some(cluster.trim()), program_id.trim()),
+        None => (None, entry),
+    };
+    if let Some(cluster) = cluster
+        && !is_valid_name(cluster)
+    {
+        return Err(invalid_name("program ID cluster", cluster).into());
+    }
+    let pubkey = Pubkey::from_str(program_id).map_err(|e| format!("Invalid program ID '{}': {}", program_id, e))?;
+    Ok((cluster.map(str::to_string), pubkey))
+}
+
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_CLUSTER_NAME_LEN
+        && name.bytes().all(|byte| byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' || byte == b'_')
+}
+
+fn invalid_name(source: &str, name: &str) -> String {
+    format!(
+        "Invalid {} '{}': expected at most {} lowercase letters, digits, '-' or '_'",
+        source, name, MAX_CLUSTER_NAME_LEN
+    )
+}
+
+/// A program on the cluster it's indexed from, which keys per-program state such as sync
+/// health and resync loops. `cluster` is the cluster's position in [`Clusters`].
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ProgramKey {
+    pub(crate) cluster: usize,
+    pub program_id: Pubkey,
+}
+
+/// One cluster's programs and the clients reading them.
+pub struct Cluster {
+    /// `None` for an unnamed default cluster.
+    pub name: Option<String>,
+    pub program_ids: Vec<Pubkey>,
+    pub rpc: Arc<SolanaRpc>,
+    /// How account changes stream in between reconciliation passes.
+    pub(crate) backend: IngestionBackend,
+    /// Pubsub endpoint for Anchor event logs, when they are indexed.
+    pub(crate) logs_ws_url: Option<String>,
+}
+
+/// Every indexed cluster, the default one first when it has programs.
+#[derive(Clone)]
+pub struct Clusters(Arc<Vec<Cluster>>);
+
+impl Clusters {
+    pub(crate) fn new(clusters: Vec<Cluster>) -> Self {
+        Self(Arc::new(clusters))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Cluster> {
+        self.0.iter()
+    }
+
+    /// Every indexed program, cluster by cluster.
+    pub fn programs(&self) -> Vec<ProgramKey> {
+        let programs = self.0.iter().enumerate().flat_map(|(cluster, config)| {
+            config.program_ids.iter().map(move |program_id| ProgramKey { cluster, program_id: *program_id })
+        });
+        programs.collect()
+    }
+
+    /// The cluster `program` is indexed from.
+    pub fn get(&self, program: &ProgramKey) -> &Cluster {
+        &self.0[program.cluster]
+    }
+
+    /// Name stored in `nodes.cluster` for `program`'s nodes.
+    pub fn name_of(&self, program: &ProgramKey) -> Option<&str> {
+        self.get(program).name.as_deref()
+    }
+
+    /// The RPC client for `program`'s cluster.
+    pub fn rpc_for(&self, program: &ProgramKey) -> &Arc<SolanaRpc> {
+        &self.get(program).rpc
+    }
+
+    /// `program_id` as indexed from the cluster named `cluster` (`None` for an unnamed default).
+    pub fn find(&self, cluster: Option<&str>, program_id: &Pubkey) -> Option<ProgramKey> {
+        self.programs()
+            .into_iter()
+            .find(|program| program.program_id == *program_id && self.name_of(program) == cluster)
+    }
+
+    /// How `program` appears in logs and task names: `<cluster>:<program id>`, as `PROGRAM_ID`
+    /// writes it, or the program ID alone on an unnamed default cluster.
+    pub fn label(&self, program: &ProgramKey) -> String {
+        match self.name_of(program) {
+            Some(name) => format!("{}:{}", name, program.program_id),
+            None => program.program_id.to_string(),
+        }
+    }
+
+    /// The first cluster's RPC client.
+    pub fn primary_rpc(&self) -> &Arc<SolanaRpc> {
+        &self.0[0].rpc
+    }
+}
+
+/// Checks that cluster names are unique and that every cluster lists programs to index.
+pub(crate) fn validate(default_name: Option<&str>, clusters: &[ClusterConfig]) -> Result<(), AppError> {
+    let mut names: Vec<&str> = default_name.into_iter().collect();
+    for cluster in clusters {
+        if !is_valid_name(&cluster.name) {
+            return Err(invalid_name("cluster name", &cluster.name).into());
+        }
+        if names.contains(&cluster.name.as_str()) {
+            return Err(format!("Cluster '{}' is configured twice", cluster.name).into());
+        }
+        names.push(&cluster.name);
+        if cluster.rpc_urls.is_empty() {
+            return Err(format!("Cluster '{}' has no RPC URL", cluster.name).into());
+        }
+        if cluster.program_ids.is_empty() {
+            return Err(format!("Cluster '{}' has no programs to index", cluster.name).into());
+        }
+    }
+    Ok(())
+}
+
+/// The streaming backend of a cluster other than the default: programSubscribe at its own
+/// endpoint, unless the default cluster only polls. Geyser endpoints serve one cluster, so
+/// the others fall back to programSubscribe.
+pub(crate) fn secondary_backend(default: &IngestionBackend, rpc_url: &str) -> IngestionBackend {
+    match default {
+        IngestionBackend::PollOnly => IngestionBackend::PollOnly,
+        _ => IngestionBackend::ProgramSubscribe { ws_url: Some(rpc::ws_url_from_rpc(rpc_url)) },
+    }
+}
@@ -0,0 +1,81 @@
+//! Response compression. Bodies are gzip- or brotli-encoded when the client's
+//! `Accept-Encoding` allows it, except for small bodies, event streams, images and any
+//! content type listed in `API_COMPRESSION_SKIP_TYPES` (formats that are already compressed).
+
+use std::sync::Arc;
+
+use axum::http::{header, Extensions, HeaderMap, StatusCode, Version};
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+
+use crate::config;
+use crate::AppError;
+
+// --- Responses smaller than this are sent as they are ---
+const DEFAULT_MIN_BYTES: u16 = 1024;
+
+// --- Content types skipped unless API_COMPRESSION_SKIP_TYPES says otherwise ---
+const DEFAULT_SKIP_TYPES: &[&str] = &["application/gzip", "application/zip", "application/zstd"];
+
+/// Whether and what the API compresses (`API_COMPRESSION`, `API_COMPRESSION_MIN_BYTES`,
+/// `API_COMPRESSION_SKIP_TYPES`).
+#[derive(Clone, Debug)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    /// Bodies of known size below this many bytes are never compressed.
+    pub min_bytes: u16,
+    /// Content type prefixes that are never compressed, e.g. `application/gzip`.
+    pub skip_types: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            min_bytes: DEFAULT_MIN_BYTES,
+            skip_types: DEFAULT_SKIP_TYPES.iter().map(|content_type| content_type.to_string()).collect(),
+        }
+    }
+}
+
+impl CompressionConfig {
+    pub fn from_env() -> Result<Self, AppError> {
+        let mut compression = Self::default();
+        if let Ok(value) = config::var("API_COMPRESSION") {
+            compression.enabled = value != "false" && value != "0";
+        }
+        if let Ok(value) = config::var("API_COMPRESSION_MIN_BYTES") {
+            compression.min_bytes = value.parse::<u16>().map_err(|_| {
+                let source = config::source("API_COMPRESSION_MIN_BYTES");
+                format!("Invalid {} '{}': expected a number of bytes up to {}", source, value, u16::MAX)
+            })?;
+        }
+        if let Ok(value) = config::var("API_COMPRESSION_SKIP_TYPES") {
+            compression.skip_types = value
+                .split(',')
+                .map(|content_type| content_type.trim().to_ascii_lowercase())
+                .filter(|content_type| !content_type.is_empty())
+                .collect();
+        }
+        Ok(compression)
+    }
+
+    /// The compression layer, or `None` when compression is off.
+    pub(crate) fn layer(&self) -> Option<CompressionLayer<impl Predicate + use<>>> {
+        if !self.enabled {
+            return None;
+        }
+        let skip_types: Arc<[String]> = self.skip_types.clone().into();
+        let not_skipped = move |_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions| {
+            let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or("");
+            let content_type = content_type.to_ascii_lowercase();
+            !skip_types.iter().any(|skipped| content_type.starts_with(skipped.as_str()))
+        };
+        let predicate = SizeAbove::new(self.min_bytes)
+            .and(NotForContentType::GRPC)
+            .and(NotForContentType::IMAGES)
+            .and(NotForContentType::SSE)
+            .and(not_skipped);
+        Some(CompressionLayer::new().compress_when(predicate))
+    }
+}
@@ -0,0 +1,385 @@
+//! Optional `config.toml` settings, layered under the environment.
+//!
+//! Every setting keeps its environment variable; the file only supplies values for variables
+//! that are unset, so a deployment can check in a config file and still override single
+//! settings per environment. The `from_env` constructors read through [`var`], which checks
+//! the environment first and the loaded file second.
+//!
+//! ```toml
+//! [database]
+//! url = "postgres://indexer@localhost/indexer"
+//!
+//! [rpc]
+//! urls = ["https://api.devnet.solana.com"]
+//!
+//! [programs]
+//! ids = ["5LzZhK83HbsJPTC877hRcfCZLg1cZvqDUQgLL3BxLYb4"]
+//!
+//! [sync]
+//! poll_interval_secs = 10
+//! ```
+//!
+//! `config.example.toml` lists every key alongside the variable it stands in for.
+
+use std::collections::{BTreeMap, HashMap};
+use std::env::VarError;
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+use crate::AppError;
+
+// --- File read when neither --config nor CONFIG_FILE names one (skipped if missing) ---
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Values from the loaded file by environment variable name, with the `section.key` they came
+/// from for error messages.
+static FILE_VALUES: OnceLock<HashMap<&'static str, FileValue>> = OnceLock::new();
+
+struct FileValue {
+    field: &'static str,
+    value: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    mode: Option<String>,
+    startup_timeout_secs: Option<u64>,
+    #[serde(default)]
+    database: DatabaseSection,
+    #[serde(default)]
+    rpc: RpcSection,
+    /// Other clusters' RPC endpoints by cluster name.
+    clusters: Option<BTreeMap<String, Vec<String>>>,
+    #[serde(default)]
+    programs: ProgramsSection,
+    #[serde(default)]
+    sync: SyncSection,
+    #[serde(default)]
+    geyser: GeyserSection,
+    #[serde(default)]
+    api: ApiSection,
+    #[serde(default)]
+    probe: ProbeSection,
+    #[serde(default)]
+    enrichment: EnrichmentSection,
+    #[serde(default)]
+    kafka: KafkaSection,
+    #[serde(default)]
+    nats: NatsSection,
+    #[serde(default)]
+    alerts: AlertsSection,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct DatabaseSection {
+    url: Option<String>,
+    read_url: Option<String>,
+    max_connections: Option<u32>,
+    min_connections: Option<u32>,
+    acquire_timeout_secs: Option<u64>,
+    idle_timeout_secs: Option<u64>,
+    statement_timeout_ms: Option<u64>,
+    run_migrations: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct RpcSection {
+    urls: Option<Vec<String>>,
+    cluster: Option<String>,
+    ws_url: Option<String>,
+    commitment: Option<String>,
+    max_retries: Option<u32>,
+    retry_base_ms: Option<u64>,
+    retry_max_ms: Option<u64>,
+    rate_limit_rps: Option<f64>,
+    rate_limit_burst: Option<f64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ProgramsSection {
+    ids: Option<Vec<String>>,
+    idl_source: Option<String>,
+    node_device_data_size: Option<u64>,
+    node_device_seed: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct SyncSection {
+    backend: Option<String>,
+    program_subscribe: Option<bool>,
+    poll_interval_secs: Option<u64>,
+    prune_max_percent: Option<f64>,
+    decode_workers: Option<usize>,
+    gpa_shard_bytes: Option<u8>,
+    soft_delete: Option<bool>,
+    index_transactions: Option<bool>,
+    tx_history_poll_secs: Option<u64>,
+    refresh_interval_secs: Option<u64>,
+    refresh_recent_secs: Option<u64>,
+    refresh_max_pubkeys: Option<usize>,
+    refresh_watch: Option<Vec<String>>,
+    lag_check_secs: Option<u64>,
+    lag_alert_slots: Option<u64>,
+    lag_alert_webhook_url: Option<String>,
+    index_events: Option<bool>,
+    leader_election: Option<bool>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct GeyserSection {
+    endpoint: Option<String>,
+    x_token: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ApiSection {
+    port: Option<u16>,
+    grpc_port: Option<u16>,
+    ready_max_sync_age_secs: Option<u64>,
+    require_api_key: Option<bool>,
+    rate_limit_rpm: Option<f64>,
+    rate_limit_burst: Option<f64>,
+    trust_forwarded_for: Option<bool>,
+    compression: Option<bool>,
+    compression_min_bytes: Option<u16>,
+    compression_skip_types: Option<Vec<String>>,
+    cors_origins: Option<Vec<String>>,
+    cors_methods: Option<Vec<String>>,
+    cors_headers: Option<Vec<String>>,
+    cors_allow_credentials: Option<bool>,
+    cache_ttls: Option<Vec<String>>,
+    cache_max_entries: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ProbeSection {
+    enabled: Option<bool>,
+    interval_secs: Option<u64>,
+    timeout_secs: Option<u64>,
+    concurrency: Option<u64>,
+    dns_ttl_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct EnrichmentSection {
+    enabled: Option<bool>,
+    interval_secs: Option<u64>,
+    timeout_secs: Option<u64>,
+    concurrency: Option<u64>,
+    path: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct KafkaSection {
+    brokers: Option<Vec<String>>,
+    topic: Option<String>,
+    client_id: Option<String>,
+    compression: Option<String>,
+    tls: Option<bool>,
+    sasl_mechanism: Option<String>,
+    sasl_username: Option<String>,
+    sasl_password: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct NatsSection {
+    urls: Option<Vec<String>>,
+    subject_prefix: Option<String>,
+    token: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct AlertsSection {
+    smtp_url: Option<String>,
+    email_from: Option<String>,
+}
+
+impl ConfigFile {
+    /// Flattens the file into the environment variables each key stands in for.
+    fn into_values(self) -> HashMap<&'static str, FileValue> {
+        let mut values = HashMap::new();
+        let mut set = |name: &'static str, field: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                values.insert(name, FileValue { field, value });
+            }
+        };
+        fn text(value: Option<impl Display>) -> Option<String> {
+            value.map(|value| value.to_string())
+        }
+
+        let ConfigFile {
+            mode,
+            startup_timeout_secs,
+            database,
+            rpc,
+            clusters,
+            programs,
+            sync,
+            geyser,
+            api,
+            probe,
+            enrichment,
+            kafka,
+            nats,
+            alerts,
+        } = self;
+        set("RUN_MODE", "mode", mode);
+        set("STARTUP_TIMEOUT_SECS", "startup_timeout_secs", text(startup_timeout_secs));
+
+        set("DATABASE_URL", "database.url", database.url);
+        set("DATABASE_READ_URL", "database.read_url", database.read_url);
+        set("DATABASE_MAX_CONNECTIONS", "database.max_connections", text(database.max_connections));
+        set("DATABASE_MIN_CONNECTIONS", "database.min_connections", text(database.min_connections));
+        set("DATABASE_ACQUIRE_TIMEOUT_SECS", "database.acquire_timeout_secs", text(database.acquire_timeout_secs));
+        set("DATABASE_IDLE_TIMEOUT_SECS", "database.idle_timeout_secs", text(database.idle_timeout_secs));
+        set("DATABASE_STATEMENT_TIMEOUT_MS", "database.statement_timeout_ms", text(database.statement_timeout_ms));
+        set("RUN_MIGRATIONS", "database.run_migrations", text(database.run_migrations));
+
+        set("RPC_URL", "rpc.urls", rpc.urls.map(|urls| urls.join(",")));
+        set("CLUSTER", "rpc.cluster", rpc.cluster);
+        set("WS_URL", "rpc.ws_url", rpc.ws_url);
+        set("COMMITMENT", "rpc.commitment", rpc.commitment);
+        let clusters = clusters.map(|clusters| {
+            let entries = clusters.iter().flat_map(|(name, urls)| urls.iter().map(move |url| (name, url)));
+            entries.map(|(name, url)| format!("{}={}", name, url)).collect::<Vec<_>>().join(",")
+        });
+        set("CLUSTERS", "clusters", clusters);
+        set("RPC_MAX_RETRIES", "rpc.max_retries", text(rpc.max_retries));
+        set("RPC_RETRY_BASE_MS", "rpc.retry_base_ms", text(rpc.retry_base_ms));
+        set("RPC_RETRY_MAX_MS", "rpc.retry_max_ms", text(rpc.retry_max_ms));
+        set("RPC_RATE_LIMIT_RPS", "rpc.rate_limit_rps", text(rpc.rate_limit_rps));
+        set("RPC_RATE_LIMIT_BURST", "rpc.rate_limit_burst", text(rpc.rate_limit_burst));
+
+        set("PROGRAM_ID", "programs.ids", programs.ids.map(|ids| ids.join(",")));
+        set("IDL_SOURCE", "programs.idl_source", programs.idl_source);
+        set("NODE_DEVICE_DATA_SIZE", "programs.node_device_data_size", text(programs.node_device_data_size));
+        set("NODE_DEVICE_SEED", "programs.node_device_seed", programs.node_device_seed);
+
+        set("INGESTION_BACKEND", "sync.backend", sync.backend);
+        set("ENABLE_PROGRAM_SUBSCRIBE", "sync.program_subscribe", text(sync.program_subscribe));
+        set("POLL_INTERVAL_SECS", "sync.poll_interval_secs", text(sync.poll_interval_secs));
+        set("PRUNE_MAX_PERCENT", "sync.prune_max_percent", text(sync.prune_max_percent));
+        set("DECODE_WORKERS", "sync.decode_workers", text(sync.decode_workers));
+        set("GPA_SHARD_BYTES", "sync.gpa_shard_bytes", text(sync.gpa_shard_bytes));
+        set("SOFT_DELETE", "sync.soft_delete", text(sync.soft_delete));
+        set("INDEX_TRANSACTIONS", "sync.index_transactions", text(sync.index_transactions));
+        set("TX_HISTORY_POLL_SECS", "sync.tx_history_poll_secs", text(sync.tx_history_poll_secs));
+        set("REFRESH_INTERVAL_SECS", "sync.refresh_interval_secs", text(sync.refresh_interval_secs));
+        set("REFRESH_RECENT_SECS", "sync.refresh_recent_secs", text(sync.refresh_recent_secs));
+        set("REFRESH_MAX_PUBKEYS", "sync.refresh_max_pubkeys", text(sync.refresh_max_pubkeys));
+        set("REFRESH_WATCH", "sync.refresh_watch", sync.refresh_watch.map(|pubkeys| pubkeys.join(",")));
+        set("LAG_CHECK_SECS", "sync.lag_check_secs", text(sync.lag_check_secs));
+        set("LAG_ALERT_SLOTS", "sync.lag_alert_slots", text(sync.lag_alert_slots));
+        set("LAG_ALERT_WEBHOOK_URL", "sync.lag_alert_webhook_url", sync.lag_alert_webhook_url);
+        set("INDEX_EVENTS", "sync.index_events", text(sync.index_events));
+        set("LEADER_ELECTION", "sync.leader_election", text(sync.leader_election));
+
+        set("GEYSER_ENDPOINT", "geyser.endpoint", geyser.endpoint);
+        set("GEYSER_X_TOKEN", "geyser.x_token", geyser.x_token);
+
+        set("PORT", "api.port", text(api.port));
+        set("GRPC_PORT", "api.grpc_port", text(api.grpc_port));
+        set("READY_MAX_SYNC_AGE_SECS", "api.ready_max_sync_age_secs", text(api.ready_max_sync_age_secs));
+        set("API_KEYS_REQUIRED", "api.require_api_key", text(api.require_api_key));
+        set("API_RATE_LIMIT_RPM", "api.rate_limit_rpm", text(api.rate_limit_rpm));
+        set("API_RATE_LIMIT_BURST", "api.rate_limit_burst", text(api.rate_limit_burst));
+        set("API_TRUST_FORWARDED_FOR", "api.trust_forwarded_for", text(api.trust_forwarded_for));
+        set("API_COMPRESSION", "api.compression", text(api.compression));
+        set("API_COMPRESSION_MIN_BYTES", "api.compression_min_bytes", text(api.compression_min_bytes));
+        let skip_types = api.compression_skip_types.map(|types| types.join(","));
+        set("API_COMPRESSION_SKIP_TYPES", "api.compression_skip_types", skip_types);
+        set("API_CORS_ORIGINS", "api.cors_origins", api.cors_origins.map(|origins| origins.join(",")));
+        set("API_CORS_METHODS", "api.cors_methods", api.cors_methods.map(|methods| methods.join(",")));
+        set("API_CORS_HEADERS", "api.cors_headers", api.cors_headers.map(|headers| headers.join(",")));
+        set("API_CORS_ALLOW_CREDENTIALS", "api.cors_allow_credentials", text(api.cors_allow_credentials));
+        set("API_CACHE_TTLS", "api.cache_ttls", api.cache_ttls.map(|ttls| ttls.join(",")));
+        set("API_CACHE_MAX_ENTRIES", "api.cache_max_entries", text(api.cache_max_entries));
+
+        set("PROBE_URIS", "probe.enabled", text(probe.enabled));
+        set("PROBE_INTERVAL_SECS", "probe.interval_secs", text(probe.interval_secs));
+        set("PROBE_TIMEOUT_SECS", "probe.timeout_secs", text(probe.timeout_secs));
+        set("PROBE_CONCURRENCY", "probe.concurrency", text(probe.concurrency));
+        set("PROBE_DNS_TTL_SECS", "probe.dns_ttl_secs", text(probe.dns_ttl_secs));
+
+        set("ENRICH_NODES", "enrichment.enabled", text(enrichment.enabled));
+        set("ENRICH_INTERVAL_SECS", "enrichment.interval_secs", text(enrichment.interval_secs));
+        set("ENRICH_TIMEOUT_SECS", "enrichment.timeout_secs", text(enrichment.timeout_secs));
+        set("ENRICH_CONCURRENCY", "enrichment.concurrency", text(enrichment.concurrency));
+        set("ENRICH_PATH", "enrichment.path", enrichment.path);
+
+        set("KAFKA_BROKERS", "kafka.brokers", kafka.brokers.map(|brokers| brokers.join(",")));
+        set("KAFKA_TOPIC", "kafka.topic", kafka.topic);
+        set("KAFKA_CLIENT_ID", "kafka.client_id", kafka.client_id);
+        set("KAFKA_COMPRESSION", "kafka.compression", kafka.compression);
+        set("KAFKA_TLS", "kafka.tls", text(kafka.tls));
+        set("KAFKA_SASL_MECHANISM", "kafka.sasl_mechanism", kafka.sasl_mechanism);
+        set("KAFKA_SASL_USERNAME", "kafka.sasl_username", kafka.sasl_username);
+        set("KAFKA_SASL_PASSWORD", "kafka.sasl_password", kafka.sasl_password);
+
+        set("NATS_URL", "nats.urls", nats.urls.map(|urls| urls.join(",")));
+        set("NATS_SUBJECT_PREFIX", "nats.subject_prefix", nats.subject_prefix);
+        set("NATS_TOKEN", "nats.token", nats.token);
+
+        set("ALERT_SMTP_URL", "alerts.smtp_url", alerts.smtp_url);
+        set("ALERT_EMAIL_FROM", "alerts.email_from", alerts.email_from);
+        values
+    }
+}
+
+/// Loads `path` as the process-wide config file. `required` makes a missing file an error;
+/// otherwise it is skipped and `Ok(false)` returned. Unknown keys and values of the wrong type
+/// are rejected with the offending `section.key`. Can only be called once.
+pub fn load(path: &Path, required: bool) -> Result<bool, AppError> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => return Ok(false),
+        Err(e) => return Err(format!("Failed to read config file {}: {}", path.display(), e).into()),
+    };
+    let file: ConfigFile =
+        serde_path_to_error::deserialize(toml::Deserializer::new(&contents)).map_err(|e| match e.path().iter().next() {
+            // Prefix the key; a syntax error has none and keeps toml's line/column report instead.
+            Some(_) => format!("Invalid config file {}: {}: {}", path.display(), e.path(), e.inner().message()),
+            None => format!("Invalid config file {}: {}", path.display(), e.into_inner()),
+        })?;
+    FILE_VALUES.set(file.into_values()).map_err(|_| "Config file already loaded")?;
+    Ok(true)
+}
+
+/// Reads setting `name` from the environment, falling back to the loaded config file. Same
+/// contract as [`std::env::var`].
+pub fn var(name: &str) -> Result<String, VarError> {
+    match std::env::var(name) {
+        Err(VarError::NotPresent) => FILE_VALUES
+            .get()
+            .and_then(|values| values.get(name))
+            .map(|file_value| file_value.value.clone())
+            .ok_or(VarError::NotPresent),
+        result => result,
+    }
+}
+
+/// Where setting `name` was read from, for validation errors: the environment variable, or
+/// the config file key when the file supplied it.
+pub fn source(name: &str) -> String {
+    if std::env::var_os(name).is_none()
+        && let Some(file_value) = FILE_VALUES.get().and_then(|values| values.get(name))
+    {
+        return format!("{} (config file)", file_value.field);
+    }
+    name.to_string()
+}
@@ -0,0 +1,112 @@
+//! Cross-origin policy for browser clients (`API_CORS_ORIGINS`, `API_CORS_METHODS`,
+//! `API_CORS_HEADERS`, `API_CORS_ALLOW_CREDENTIALS`). Any origin may call the API unless
+//! origins are listed.
+
+use std::str::FromStr;
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+use crate::config;
+use crate::api::TOTAL_COUNT_HEADER;
+use crate::request_id::REQUEST_ID_HEADER;
+use crate::response_cache::CACHE_STATUS_HEADER;
+use crate::AppError;
+
+/// A list setting that is either `*` or explicit values.
+#[derive(Clone, Debug)]
+pub enum Allowed<T> {
+    Any,
+    List(Vec<T>),
+}
+
+/// Which cross-origin requests browsers may make.
+#[derive(Clone, Debug)]
+pub struct CorsConfig {
+    pub origins: Allowed<HeaderValue>,
+    pub methods: Allowed<Method>,
+    pub headers: Allowed<HeaderName>,
+    /// Let pages send cookies and `Authorization` headers. Needs explicit origins; with `*`
+    /// methods or headers, those of each preflight request are allowed back.
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self { origins: Allowed::Any, methods: Allowed::Any, headers: Allowed::Any, allow_credentials: false }
+    }
+}
+
+impl CorsConfig {
+    pub fn from_env() -> Result<Self, AppError> {
+        let origins = allowed("API_CORS_ORIGINS", "an http(s) origin such as https://app.example", |origin| {
+            // Browsers send the origin without a path, so one with a path would never match.
+            let origin = origin.trim_end_matches('/');
+            let host = origin.strip_prefix("https://").or_else(|| origin.strip_prefix("http://"))?;
+            if host.is_empty() || host.contains(['/', '?', '#']) {
+                return None;
+            }
+            HeaderValue::from_str(origin).ok()
+        })?;
+        let methods = allowed("API_CORS_METHODS", "an HTTP method such as GET", |method| {
+            Method::from_str(&method.to_ascii_uppercase()).ok()
+        })?;
+        let headers = allowed("API_CORS_HEADERS", "a header name such as X-API-Key", |header| {
+            HeaderName::from_str(header).ok()
+        })?;
+        let allow_credentials =
+            config::var("API_CORS_ALLOW_CREDENTIALS").is_ok_and(|value| value == "true" || value == "1");
+        if allow_credentials && matches!(origins, Allowed::Any) {
+            let message = format!(
+                "{} needs {} to list origins; browsers reject credentials with `*`",
+                config::source("API_CORS_ALLOW_CREDENTIALS"),
+                config::source("API_CORS_ORIGINS")
+            );
+            return Err(message.into());
+        }
+        Ok(Self { origins, methods, headers, allow_credentials })
+    }
+
+    pub(crate) fn layer(&self) -> CorsLayer {
+        let origins = match &self.origins {
+            Allowed::Any => AllowOrigin::from(Any),
+            Allowed::List(origins) => AllowOrigin::list(origins.iter().cloned()),
+        };
+        // `*` isn't honoured for credentialed requests, so echo what the preflight asks for.
+        let methods = match &self.methods {
+            Allowed::Any if self.allow_credentials => AllowMethods::mirror_request(),
+            Allowed::Any => AllowMethods::from(Any),
+            Allowed::List(methods) => AllowMethods::list(methods.iter().cloned()),
+        };
+        let headers = match &self.headers {
+            Allowed::Any if self.allow_credentials => AllowHeaders::mirror_request(),
+            Allowed::Any => AllowHeaders::from(Any),
+            Allowed::List(headers) => AllowHeaders::list(headers.iter().cloned()),
+        };
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .allow_credentials(self.allow_credentials)
+            // Lets pages read the ID to quote in bug reports, list totals and cache hits.
+            .expose_headers([REQUEST_ID_HEADER, TOTAL_COUNT_HEADER, CACHE_STATUS_HEADER])
+    }
+}
+
+/// Reads a comma-separated list from `name`; unset or `*` allows anything.
+fn allowed<T>(name: &str, expected: &str, parse: impl Fn(&str) -> Option<T>) -> Result<Allowed<T>, AppError> {
+    let Ok(value) = config::var(name) else { return Ok(Allowed::Any) };
+    let items: Vec<&str> = value.split(',').map(str::trim).filter(|item| !item.is_empty()).collect();
+    if items.is_empty() || items == ["*"] {
+        return Ok(Allowed::Any);
+    }
+    let mut list = Vec::with_capacity(items.len());
+    for item in items {
+        let parsed = parse(item).ok_or_else(|| {
+            let hint = if item == "*" { "; `*` can't be combined with other values" } else { "" };
+            format!("Invalid {} entry '{}': expected {}{}", config::source(name), item, expected, hint)
+        })?;
+        list.push(parsed);
+    }
+    Ok(Allowed::List(list))
+}
@@ -0,0 +1,96 @@
+//! Cached node counts behind `GET /nodes/count`, the `total` of `GET /nodes` and the
+//! `X-Total-Count` header. A count is reused until it is [`COUNT_TTL`] old, whatever changed in
+//! between: under churn, dropping it on every change event would leave almost every request
+//! counting again, and liveness probes and finalization change `?status=` and
+//! `?finalized_only=` counts without publishing anything anyway.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use sqlx::PgPool;
+use tokio::time::Instant;
+
+use crate::api::NodeFilter;
+use crate::liveness::LivenessStatus;
+
+// --- Longest a cached count is served for ---
+pub(crate) const COUNT_TTL: Duration = Duration::from_secs(10);
+
+// --- Most distinct filters whose counts are kept ---
+const MAX_CACHED_COUNTS: usize = 1024;
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct CountKey {
+    program: Option<String>,
+    cluster: Option<String>,
+    include_deleted: bool,
+    status: Option<LivenessStatus>,
+    finalized_only: bool,
+    include_invalid: bool,
+    include_unverified: bool,
+    rent_exempt: Option<bool>,
+    dns_ok: Option<bool>,
+}
+
+struct CachedCount {
+    count: i64,
+    counted_at: Instant,
+}
+
+/// Node counts per filter, each kept for [`COUNT_TTL`].
+#[derive(Default)]
+pub struct NodeCounts {
+    cached: Mutex<HashMap<CountKey, CachedCount>>,
+}
+
+impl NodeCounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of nodes `filter` selects, counted in the database only when no fresh count
+    /// is cached.
+    pub async fn count(&self, pool: &PgPool, filter: &NodeFilter) -> Result<i64, sqlx::Error> {
+        let key = CountKey {
+            program: filter.program.clone(),
+            cluster: filter.cluster.clone(),
+            include_deleted: filter.include_deleted.unwrap_or(false),
+            status: filter.status,
+            finalized_only: filter.finalized_only.unwrap_or(false),
+            include_invalid: filter.include_invalid.unwrap_or(false),
+            include_unverified: filter.include_unverified.unwrap_or(false),
+            rent_exempt: filter.rent_exempt,
+            dns_ok: filter.dns_ok,
+        };
+        if let Some(cached) = self.cached.lock().unwrap().get(&key)
+            && cached.counted_at.elapsed() < COUNT_TTL
+        {
+            return Ok(cached.count);
+        }
+        let counted_at = Instant::now();
+
+        let condition = filter.condition();
+        let count: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM nodes \
+             WHERE ($1::text IS NULL OR program_id = $1) AND ($2 OR deleted_at IS NULL) \
+               AND ($3::text IS NULL OR cluster = $3) AND {}",
+            condition
+        ))
+        .bind(&key.program)
+        .bind(key.include_deleted)
+        .bind(&key.cluster)
+        .fetch_one(pool)
+        .await?;
+
+        // Aged from before the query ran, so the count is never served older than the TTL.
+        let mut cached = self.cached.lock().unwrap();
+        if cached.len() >= MAX_CACHED_COUNTS && !cached.contains_key(&key) {
+            cached.retain(|_, entry| entry.counted_at.elapsed() < COUNT_TTL);
+        }
+        if cached.len() < MAX_CACHED_COUNTS || cached.contains_key(&key) {
+            cached.insert(key, CachedCount { count, counted_at });
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,330 @@
+//! Bounds-checked decoding of the program's Anchor accounts.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::OnceLock;
+
+use borsh::BorshDeserialize;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::PgConnection;
+use tracing::debug;
+
+use crate::config;
+use crate::AppError;
+
+#[derive(BorshDeserialize, Debug)]
+pub struct NetworkStats {
+    pub total_nodes: u64,
+}
+
+/// A decoded `NodeDevice` account. Accounts written before the program's upgrade keep the
+/// [`NodeDeviceLayout::V1`] layout, so the fields it added are `None` for them.
+#[derive(Debug, Serialize)]
+pub struct NodeDevice {
+    pub authority: Pubkey,
+    pub uri: String,
+    /// Operator-chosen display name; added in V2.
+    pub name: Option<String>,
+    /// Unix time the program registered the device; added in V2.
+    pub registered_at: Option<i64>,
+    pub layout: NodeDeviceLayout,
+}
+
+/// On-chain layouts of `NodeDevice`. Both share the discriminator; V2 appends its fields after
+/// `uri` and is told apart by the data that follows it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeDeviceLayout {
+    /// `authority`, `uri`.
+    V1,
+    /// V1 followed by `name` and `registered_at`.
+    V2,
+}
+
+impl NodeDeviceLayout {
+    /// The number stored in `nodes.layout_version`.
+    pub fn version(self) -> i16 {
+        match self {
+            NodeDeviceLayout::V1 => 1,
+            NodeDeviceLayout::V2 => 2,
+        }
+    }
+}
+
+/// Anchor's account discriminator: the first 8 bytes of `sha256("account:<Name>")`.
+pub fn account_discriminator(name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("account:{}", name).as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+/// getProgramAccounts filter matching accounts that start with `discriminator`.
+pub fn discriminator_filter(discriminator: &[u8; 8]) -> RpcFilterType {
+    RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, discriminator))
+}
+
+/// getProgramAccounts filters selecting only NodeDevice accounts. The data size is only
+/// known when the program allocates fixed space, so it is opt-in via `NODE_DEVICE_DATA_SIZE`;
+/// it matches a single layout, so leave it unset while V1 and V2 accounts coexist.
+pub fn node_device_filters() -> Result<Vec<RpcFilterType>, AppError> {
+    let mut filters = vec![discriminator_filter(&node_device_discriminator())];
+    if let Ok(size) = config::var("NODE_DEVICE_DATA_SIZE") {
+        let size: u64 = size
+            .parse()
+            .map_err(|e| format!("Invalid {} '{}': {}", config::source("NODE_DEVICE_DATA_SIZE"), size, e))?;
+        filters.push(RpcFilterType::DataSize(size));
+    }
+    Ok(filters)
+}
+
+/// Why an account's bytes could not be decoded. Decoders never panic on bad input.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The data ended before a field could be read.
+    Truncated { field: &'static str, needed: usize, available: usize },
+    UnknownDiscriminator([u8; 8]),
+    WrongDiscriminator { expected: &'static str, actual: [u8; 8] },
+    InvalidUtf8 { field: &'static str },
+    Borsh(String),
+    /// The account's IDL type definition can't be decoded (unsupported or missing type).
+    Idl(String),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Truncated { field, needed, available } => {
+                write!(f, "truncated data reading {}: needed {} bytes, {} available", field, needed, available)
+            }
+            DecodeError::UnknownDiscriminator(actual) => write!(f, "unknown account discriminator {:02x?}", actual),
+            DecodeError::WrongDiscriminator { expected, actual } => {
+                write!(f, "discriminator {:02x?} is not a {} account", actual, expected)
+            }
+            DecodeError::InvalidUtf8 { field } => write!(f, "{} is not valid UTF-8", field),
+            DecodeError::Borsh(e) => write!(f, "borsh decode failed: {}", e),
+            DecodeError::Idl(e) => write!(f, "IDL decode failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Cursor over account bytes whose reads fail with `DecodeError::Truncated` instead of panicking.
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    pub(crate) fn take(&mut self, field: &'static str, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::Truncated { field, needed: len, available: self.data.len() });
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N], DecodeError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(field, N)?);
+        Ok(out)
+    }
+
+    pub(crate) fn u32_le(&mut self, field: &'static str) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.array(field)?))
+    }
+
+    pub(crate) fn i64_le(&mut self, field: &'static str) -> Result<i64, DecodeError> {
+        Ok(i64::from_le_bytes(self.array(field)?))
+    }
+
+    pub(crate) fn string(&mut self, field: &'static str) -> Result<String, DecodeError> {
+        let len = self.u32_le(field)? as usize;
+        let bytes = self.take(field, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8 { field })
+    }
+}
+
+/// The first 8 bytes of `data`, which identify its Anchor account type.
+pub fn read_discriminator(data: &[u8]) -> Result<[u8; 8], DecodeError> {
+    ByteReader::new(data).array::<8>("discriminator")
+}
+
+/// Discriminator of the `NodeDevice` accounts mirrored into `nodes`.
+pub fn node_device_discriminator() -> [u8; 8] {
+    static DISCRIMINATOR: OnceLock<[u8; 8]> = OnceLock::new();
+    *DISCRIMINATOR.get_or_init(|| account_discriminator("NodeDevice"))
+}
+
+/// Checks that `data` starts with the discriminator of account type `name` and returns
+/// a reader positioned just after it.
+pub fn expect_discriminator<'a>(data: &'a [u8], name: &'static str) -> Result<ByteReader<'a>, DecodeError> {
+    let mut reader = ByteReader::new(data);
+    let actual = reader.array::<8>("discriminator")?;
+    if actual != account_discriminator(name) {
+        return Err(DecodeError::WrongDiscriminator { expected: name, actual });
+    }
+    Ok(reader)
+}
+
+/// Decodes either `NodeDevice` layout. A V1 account ends at `uri`, or is zero-padded past it
+/// when the program allocated fixed space; anything else after `uri` is read as V2's fields.
+pub fn deserialize_node_device(data: &[u8]) -> Result<NodeDevice, DecodeError> {
+    let mut reader = expect_discriminator(data, "NodeDevice")?;
+    let authority = Pubkey::new_from_array(reader.array("authority")?);
+    let uri = reader.string("uri")?;
+    if reader.data.iter().all(|byte| *byte == 0) {
+        return Ok(NodeDevice { authority, uri, name: None, registered_at: None, layout: NodeDeviceLayout::V1 });
+    }
+    let name = reader.string("name")?;
+    let registered_at = reader.i64_le("registered_at")?;
+    Ok(NodeDevice {
+        authority,
+        uri,
+        name: Some(name),
+        registered_at: Some(registered_at),
+        layout: NodeDeviceLayout::V2,
+    })
+}
+
+pub fn deserialize_network_stats(data: &[u8]) -> Result<NetworkStats, DecodeError> {
+    let mut reader = expect_discriminator(data, "NetworkStats")?;
+    // Anchor may pad the account past the struct, so only the leading bytes are decoded.
+    NetworkStats::deserialize_reader(&mut reader.data).map_err(|e| DecodeError::Borsh(e.to_string()))
+}
+
+/// Where a decoded account came from, handed to [`AccountDecoder::store`].
+#[derive(Clone, Copy, Debug)]
+pub struct AccountContext<'a> {
+    pub program_id: &'a Pubkey,
+    pub pubkey: &'a str,
+    pub slot: u64,
+}
+
+/// Future returned by [`AccountDecoder::store`].
+pub type StoreFuture<'a> = Pin<Box<dyn Future<Output = Result<(), AppError>> + Send + 'a>>;
+
+/// Decodes one Anchor account type and persists it. `NodeDevice` accounts are handled by the
+/// sync loop itself; every other account type the indexer understands is an `AccountDecoder`
+/// registered in a [`DecoderRegistry`].
+pub trait AccountDecoder: Send + Sync + 'static {
+    type Account: Send + 'static;
+
+    /// Anchor account name; the discriminator is derived from it.
+    const NAME: &'static str;
+
+    fn decode(&self, data: &[u8]) -> Result<Self::Account, DecodeError>;
+
+    /// Writes `account` inside the sync transaction that observed it.
+    fn store<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        context: AccountContext<'a>,
+        account: Self::Account,
+    ) -> StoreFuture<'a>;
+}
+
+/// Object-safe view of an [`AccountDecoder`], so decoders of different types share a registry.
+pub trait ErasedDecoder: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Decodes `data` and, on success, returns the future that stores it.
+    fn decode_and_store<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        context: AccountContext<'a>,
+        data: &[u8],
+    ) -> Result<StoreFuture<'a>, DecodeError>;
+}
+
+impl<D: AccountDecoder> ErasedDecoder for D {
+    fn name(&self) -> &'static str {
+        D::NAME
+    }
+
+    fn decode_and_store<'a>(
+        &'a self,
+        conn: &'a mut PgConnection,
+        context: AccountContext<'a>,
+        data: &[u8],
+    ) -> Result<StoreFuture<'a>, DecodeError> {
+        let account = self.decode(data)?;
+        Ok(self.store(conn, context, account))
+    }
+}
+
+/// The non-`NodeDevice` account types the indexer decodes, keyed by discriminator.
+#[derive(Default)]
+pub struct DecoderRegistry {
+    decoders: Vec<([u8; 8], Box<dyn ErasedDecoder>)>,
+}
+
+impl DecoderRegistry {
+    /// An empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The decoders for the program's built-in account types.
+    pub fn with_defaults() -> Self {
+        Self::new().register(NetworkStatsDecoder)
+    }
+
+    /// Adds `decoder`, replacing any decoder registered for the same account name.
+    pub fn register<D: AccountDecoder>(mut self, decoder: D) -> Self {
+        let discriminator = account_discriminator(D::NAME);
+        self.decoders.retain(|(known, _)| *known != discriminator);
+        self.decoders.push((discriminator, Box::new(decoder)));
+        self
+    }
+
+    pub fn find(&self, discriminator: &[u8; 8]) -> Option<&dyn ErasedDecoder> {
+        self.decoders
+            .iter()
+            .find(|(known, _)| known == discriminator)
+            .map(|(_, decoder)| decoder.as_ref())
+    }
+
+    /// Every registered discriminator with its decoder.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8; 8], &dyn ErasedDecoder)> {
+        self.decoders.iter().map(|(discriminator, decoder)| (discriminator, decoder.as_ref()))
+    }
+}
+
+/// The program's singleton `NetworkStats` account. Its count is only logged; the indexed
+/// total in `network_stats` is computed from `nodes` instead.
+pub struct NetworkStatsDecoder;
+
+impl AccountDecoder for NetworkStatsDecoder {
+    type Account = NetworkStats;
+
+    const NAME: &'static str = "NetworkStats";
+
+    fn decode(&self, data: &[u8]) -> Result<NetworkStats, DecodeError> {
+        deserialize_network_stats(data)
+    }
+
+    fn store<'a>(
+        &'a self,
+        _conn: &'a mut PgConnection,
+        context: AccountContext<'a>,
+        stats: NetworkStats,
+    ) -> StoreFuture<'a> {
+        Box::pin(async move {
+            debug!(pubkey = context.pubkey, total_nodes = stats.total_nodes, "On-chain NetworkStats account");
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,202 @@
+//! DNS resolution of node URI hosts, for the liveness prober. Each probe resolves its URI's
+//! hostname first and records in `node_liveness` the addresses found, or why resolution failed;
+//! `?dns_ok=` filters on the result. Resolutions are cached for `PROBE_DNS_TTL_SECS` and the
+//! probe clients connect through the same cache, so a hostname shared by many nodes is looked
+//! up once per TTL rather than once per request.
+//!
+//! Anyone can register a URI, so the clients that fetch them (the prober and node enrichment)
+//! only connect to publicly routable addresses: the cache drops any other address a hostname
+//! resolves to when it connects, [`check_url`] rejects IP-address hosts outside public ranges,
+//! and [`redirect_policy`] checks every redirect hop the same way. Webhook and alert
+//! target URLs come from API clients and operators too, so they pass [`parse_delivery_url`]
+//! and the clients delivering to them connect through the cache.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use tokio::time::{Duration, Instant};
+
+/// Resolutions by hostname, failures included, each kept for `ttl`.
+#[derive(Clone)]
+pub struct DnsCache {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, Resolution>>>,
+}
+
+struct Resolution {
+    resolved_at: Instant,
+    result: Result<Vec<IpAddr>, String>,
+}
+
+impl DnsCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, entries: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Addresses `host` resolves to, or why it doesn't resolve; cached while fresh.
+    pub async fn lookup(&self, host: &str) -> Result<Vec<IpAddr>, String> {
+        let host = host.to_ascii_lowercase();
+        if let Some(resolution) = self.entries.lock().unwrap().get(&host)
+            && resolution.resolved_at.elapsed() < self.ttl
+        {
+            return resolution.result.clone();
+        }
+
+        let result = match tokio::net::lookup_host((host.as_str(), 0)).await {
+            Ok(addrs) => {
+                let mut ips: Vec<IpAddr> = Vec::new();
+                for addr in addrs {
+                    if !ips.contains(&addr.ip()) {
+                        ips.push(addr.ip());
+                    }
+                }
+                if ips.is_empty() { Err("No addresses found".to_string()) } else { Ok(ips) }
+            }
+            Err(e) => Err(e.to_string()),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        // Hosts no longer registered would otherwise stay cached forever.
+        entries.retain(|_, resolution| resolution.resolved_at.elapsed() < self.ttl);
+        entries.insert(host, Resolution { resolved_at: Instant::now(), result: result.clone() });
+        result
+    }
+}
+
+impl Resolve for DnsCache {
+    fn resolve(&self, name: Name) -> Resolving {
+        let cache = self.clone();
+        Box::pin(async move {
+            let ips: Vec<IpAddr> = cache.lookup(name.as_str()).await?.into_iter().filter(|ip| is_public(*ip)).collect();
+            if ips.is_empty() {
+                return Err(format!("{} resolves to no public address", name.as_str()).into());
+            }
+            // Port 0 is replaced with the URL's.
+            let addrs: Addrs = Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok(addrs)
+        })
+    }
+}
+
+/// Whether `ip` is publicly routable: not loopback, private, link-local, shared (CGNAT),
+/// unspecified, broadcast, multicast or documentation space. IPv4-mapped IPv6 addresses are
+/// judged by the IPv4 address they carry.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    let shared = a == 100 && (64..128).contains(&b);
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        || shared
+        || a == 0)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let documentation = ip.segments()[0] == 0x2001 && ip.segments()[1] == 0x0db8;
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        || ip.is_unique_local()
+        || ip.is_unicast_link_local()
+        || documentation)
+}
+
+/// Rejects `url` when its host is an IP address that isn't public, which connects without
+/// going through the resolver. Hostnames are checked as they resolve.
+pub fn check_url(url: &Url) -> Result<(), String> {
+    let host = url.host_str().ok_or("URI has no host")?;
+    // IPv6 hosts come bracketed in URLs; IPv4 ones are already in dotted decimal.
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) if !is_public(ip) => Err(format!("{} is not a public address", ip)),
+        _ => Ok(()),
+    }
+}
+
+/// Parses a URL the indexer will `POST` to: http(s), and not an IP-address host outside public
+/// ranges. Hostnames are checked as the delivering client, built on a [`DnsCache`], resolves them.
+pub fn parse_delivery_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url)
+        .ok()
+        .filter(|parsed| matches!(parsed.scheme(), "http" | "https"))
+        .ok_or_else(|| format!("'{}' is not an http or https URL", url))?;
+    check_url(&parsed).map_err(|e| format!("'{}' is not deliverable: {}", url, e))?;
+    Ok(parsed)
+}
+
+/// Follows at most `max` redirects, each to a URL that passes [`check_url`].
+pub fn redirect_policy(max: usize) -> Policy {
+    Policy::custom(move |attempt| {
+        if attempt.previous().len() > max {
+            return attempt.error(format!("Stopped after {} redirects", max));
+        }
+        match check_url(attempt.url()) {
+            Ok(()) => attempt.follow(),
+            Err(e) => attempt.error(format!("Redirect refused: {}", e)),
+        }
+    })
+}
+
+/// SQL condition on `nodes` for `?dns_ok=`: whether the latest probe of the node's current
+/// URI resolved its host. Never-probed nodes match neither.
+pub fn condition(dns_ok: bool) -> &'static str {
+    if dns_ok {
+        "EXISTS (SELECT 1 FROM node_liveness l WHERE l.pubkey = nodes.pubkey AND l.uri = nodes.uri AND l.dns_ok)"
+    } else {
+        "EXISTS (SELECT 1 FROM node_liveness l WHERE l.pubkey = nodes.pubkey AND l.uri = nodes.uri AND NOT l.dns_ok)"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_public_addresses_pass() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["::1", "::", "fe80::1", "fd00::1", "::ffff:127.0.0.1", "::ffff:169.254.169.254"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["1.1.1.1", "8.8.8.8", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn ip_hosts_are_checked_before_connecting() {
+        let check = |url: &str| check_url(&Url::parse(url).unwrap());
+        assert!(check("http://169.254.169.254/latest/meta-data").is_err());
+        assert!(check("http://[::1]:8080/info").is_err());
+        // Shorthand IPv4 forms are normalized by the URL parser.
+        assert!(check("http://0x7f.1/").is_err());
+        assert!(check("https://1.1.1.1/").is_ok());
+        assert!(check("https://example.com/").is_ok());
+    }
+
+    #[test]
+    fn delivery_urls_are_public_http() {
+        assert!(parse_delivery_url("https://hooks.example.com/alerts").is_ok());
+        assert!(parse_delivery_url("http://1.1.1.1:8080/").is_ok());
+        for url in ["ftp://example.com/", "example.com", "http://127.0.0.1/", "http://[fd00::1]/", "http://10.0.0.1/"] {
+            assert!(parse_delivery_url(url).is_err(), "{}", url);
+        }
+    }
+}
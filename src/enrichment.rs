@@ -0,0 +1,304 @@
+//! Node enrichment: a background worker that fetches the metadata document many devices serve
+//! at `/info` under their URI and keeps it in `node_metadata`, returned by `GET /nodes/:pubkey`.
+//! The document is a JSON object; its `version` string and `capabilities` list of strings are
+//! stored as columns and the whole object alongside them.
+//!
+//! A failed fetch is recorded in `error` but keeps the metadata last fetched from the same URI,
+//! so a node that is briefly down doesn't lose it.
+//!
+//! Fetches only reach public addresses, redirects included (see [`crate::dns`]), so a node
+//! can't point the worker at internal services and read their answers back through the API.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::PgExecutor;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{debug, info, info_span, warn, Instrument};
+use utoipa::ToSchema;
+
+use crate::config;
+use crate::dns::{self, DnsCache};
+use crate::liveness::describe_request_error;
+use crate::moderation::NOT_QUARANTINED;
+use crate::sync::SyncContext;
+use crate::uri;
+use crate::AppError;
+
+// --- Default ENRICH_INTERVAL_SECS between enrichment passes ---
+const DEFAULT_ENRICH_INTERVAL_SECS: u64 = 3600;
+
+// --- Default ENRICH_TIMEOUT_SECS for a whole fetch ---
+const DEFAULT_ENRICH_TIMEOUT_SECS: u64 = 5;
+
+// --- Default ENRICH_CONCURRENCY: nodes fetched from at once ---
+const DEFAULT_ENRICH_CONCURRENCY: usize = 8;
+
+// --- Default ENRICH_PATH appended to each node's URI ---
+const DEFAULT_ENRICH_PATH: &str = "/info";
+
+// --- Largest metadata document read, in bytes ---
+const MAX_METADATA_BYTES: usize = 64 * 1024;
+
+// --- Redirects followed before a fetch gives up ---
+const MAX_ENRICH_REDIRECTS: usize = 5;
+
+// --- Seconds a hostname's resolution is reused for ---
+const ENRICH_DNS_TTL_SECS: u64 = 300;
+
+/// Settings for node enrichment (`ENRICH_NODES`, `ENRICH_INTERVAL_SECS`, `ENRICH_TIMEOUT_SECS`,
+/// `ENRICH_CONCURRENCY`, `ENRICH_PATH`).
+#[derive(Clone, Debug)]
+pub struct EnrichmentConfig {
+    /// Pause between passes over every indexed node.
+    pub interval: Duration,
+    /// Time a node gets to serve the whole document.
+    pub timeout: Duration,
+    pub concurrency: usize,
+    /// Path of the document under the node's URI, starting with `/`.
+    pub path: String,
+}
+
+impl EnrichmentConfig {
+    /// `None` unless `ENRICH_NODES=true`: like URI probing, enrichment sends requests to
+    /// whatever URIs were registered on chain, so it is opt-in.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let enabled = config::var("ENRICH_NODES").is_ok_and(|value| value == "true" || value == "1");
+        if !enabled {
+            return Ok(None);
+        }
+        let positive = |name: &str, default: u64| match config::var(name) {
+            Ok(value) => value.parse::<u64>().ok().filter(|number| *number > 0).ok_or_else(|| {
+                format!("Invalid {} '{}': expected a positive number", config::source(name), value)
+            }),
+            Err(_) => Ok(default),
+        };
+        let path = config::var("ENRICH_PATH").unwrap_or_else(|_| DEFAULT_ENRICH_PATH.to_string());
+        if !path.starts_with('/') || path.contains(['?', '#']) {
+            let source = config::source("ENRICH_PATH");
+            return Err(format!("Invalid {} '{}': expected a path starting with /", source, path).into());
+        }
+        Ok(Some(Self {
+            interval: Duration::from_secs(positive("ENRICH_INTERVAL_SECS", DEFAULT_ENRICH_INTERVAL_SECS)?),
+            timeout: Duration::from_secs(positive("ENRICH_TIMEOUT_SECS", DEFAULT_ENRICH_TIMEOUT_SECS)?),
+            concurrency: positive("ENRICH_CONCURRENCY", DEFAULT_ENRICH_CONCURRENCY as u64)? as usize,
+            path,
+        }))
+    }
+}
+
+/// One row of `node_metadata`, as returned with `GET /nodes/:pubkey`.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Debug)]
+pub struct NodeMetadata {
+    /// Node URI the document was fetched under; older than the node's current URI if that
+    /// changed since.
+    pub uri: String,
+    pub version: Option<String>,
+    pub capabilities: Vec<String>,
+    /// The whole document.
+    pub info: Option<Value>,
+    /// Why the latest fetch failed; `None` when it succeeded.
+    pub error: Option<String>,
+    /// When the latest fetch was made.
+    pub fetched_at: DateTime<Utc>,
+    /// When a document was last fetched successfully.
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+// --- Columns selected into `NodeMetadata` ---
+pub const METADATA_COLUMNS: &str = "uri, version, capabilities, info, error, fetched_at, updated_at";
+
+/// The stored metadata of `pubkey`, if it has been fetched.
+pub async fn node_metadata(executor: impl PgExecutor<'_>, pubkey: &str) -> Result<Option<NodeMetadata>, sqlx::Error> {
+    sqlx::query_as::<_, NodeMetadata>(&format!(
+        "SELECT {} FROM node_metadata WHERE pubkey = $1",
+        METADATA_COLUMNS
+    ))
+    .bind(pubkey)
+    .fetch_optional(executor)
+    .await
+}
+
+/// Outcome of fetching one node's document.
+struct Fetch {
+    version: Option<String>,
+    capabilities: Vec<String>,
+    info: Option<Value>,
+    error: Option<String>,
+}
+
+impl Fetch {
+    fn failed(error: String) -> Self {
+        Self { version: None, capabilities: Vec::new(), info: None, error: Some(error) }
+    }
+}
+
+/// Fetches the metadata of every live node of `program_ids` once per interval, forever.
+pub async fn run_enrichment(program_ids: Vec<Pubkey>, sync: SyncContext, config: EnrichmentConfig) {
+    let client = reqwest::Client::builder()
+        .dns_resolver(Arc::new(DnsCache::new(Duration::from_secs(ENRICH_DNS_TTL_SECS))))
+        .timeout(config.timeout)
+        .redirect(dns::redirect_policy(MAX_ENRICH_REDIRECTS))
+        .user_agent(concat!("indexer/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Failed to build the enrichment HTTP client");
+    async move {
+        loop {
+            let started = Instant::now();
+            match enrich_nodes(&client, &program_ids, &sync, &config).await {
+                Ok((fetched, failed)) => {
+                    let duration_ms = started.elapsed().as_millis() as u64;
+                    info!(fetched, failed, duration_ms, "Node enrichment pass complete");
+                }
+                Err(e) => warn!(error = %e, "Node enrichment pass failed"),
+            }
+            sleep(config.interval).await;
+        }
+    }
+    .instrument(info_span!("enrichment"))
+    .await
+}
+
+/// Fetches every node's document and records the results. Returns how many fetches succeeded
+/// and failed.
+async fn enrich_nodes(
+    client: &reqwest::Client,
+    program_ids: &[Pubkey],
+    sync: &SyncContext,
+    config: &EnrichmentConfig,
+) -> Result<(usize, usize), AppError> {
+    let program_ids: Vec<String> = program_ids.iter().map(Pubkey::to_string).collect();
+    // Quarantined nodes are left alone: their URIs are the ones most likely to be hostile.
+    let nodes: Vec<(String, String)> = sqlx::query_as(&format!(
+        "SELECT pubkey, uri FROM nodes \
+         WHERE (program_id = ANY($1) OR program_id IS NULL) AND deleted_at IS NULL AND {}",
+        NOT_QUARANTINED
+    ))
+    .bind(&program_ids)
+    .fetch_all(&sync.pool)
+    .await?;
+    let mut pending = nodes.into_iter().filter(|(_, uri)| uri::is_valid(uri));
+
+    let (mut fetched, mut failed) = (0, 0);
+    let mut fetches = JoinSet::new();
+    loop {
+        while fetches.len() < config.concurrency
+            && let Some((pubkey, uri)) = pending.next()
+        {
+            let (client, path) = (client.clone(), config.path.clone());
+            fetches.spawn(async move {
+                let fetch = fetch_metadata(&client, &uri, &path).await;
+                (pubkey, uri, fetch)
+            });
+        }
+        let Some(joined) = fetches.join_next().await else { break };
+        let (pubkey, uri, fetch) = joined?;
+        // Dropping `fetches` on the way out cancels the fetches still running.
+        let Some(_write) = sync.pause.begin_write().await else {
+            info!("Ingestion is paused; ending enrichment pass early");
+            break;
+        };
+        record_metadata(&sync.pool, &pubkey, &uri, &fetch).await?;
+        debug!(%pubkey, %uri, version = fetch.version, error = fetch.error, "Fetched node metadata");
+        match fetch.error {
+            None => fetched += 1,
+            Some(_) => failed += 1,
+        }
+    }
+    Ok((fetched, failed))
+}
+
+/// `path` under `uri`, which keeps its own path as a prefix but not its query or fragment.
+fn metadata_url(uri: &str, path: &str) -> Option<reqwest::Url> {
+    let mut url = reqwest::Url::parse(uri).ok()?;
+    let joined = format!("{}{}", url.path().trim_end_matches('/'), path);
+    url.set_path(&joined);
+    url.set_query(None);
+    url.set_fragment(None);
+    Some(url)
+}
+
+async fn fetch_metadata(client: &reqwest::Client, uri: &str, path: &str) -> Fetch {
+    let Some(url) = metadata_url(uri, path) else { return Fetch::failed("Invalid URI".to_string()) };
+    if let Err(e) = dns::check_url(&url) {
+        return Fetch::failed(e);
+    }
+    let mut response = match client.get(url).header(reqwest::header::ACCEPT, "application/json").send().await {
+        Ok(response) => response,
+        Err(e) => return Fetch::failed(describe_request_error(&e)),
+    };
+    let status = response.status();
+    if !status.is_success() {
+        return Fetch::failed(format!("HTTP {}", status));
+    }
+    // Read in chunks so an endless body can't grow past the limit.
+    let mut body = Vec::new();
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) if body.len() + chunk.len() > MAX_METADATA_BYTES => {
+                return Fetch::failed(format!("Document exceeds {} bytes", MAX_METADATA_BYTES));
+            }
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            Ok(None) => break,
+            Err(e) => return Fetch::failed(describe_request_error(&e)),
+        }
+    }
+    match serde_json::from_slice::<Value>(&body) {
+        // Postgres can store neither in TEXT nor in JSONB a string holding NUL.
+        Ok(info) if info.to_string().contains("\\u0000") => {
+            Fetch::failed("Document contains NUL characters".to_string())
+        }
+        Ok(Value::Object(fields)) => {
+            let version = fields.get("version").and_then(Value::as_str).map(str::to_string);
+            let capabilities = match fields.get("capabilities") {
+                Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+                _ => Vec::new(),
+            };
+            Fetch { version, capabilities, info: Some(Value::Object(fields)), error: None }
+        }
+        Ok(_) => Fetch::failed("Document is not a JSON object".to_string()),
+        Err(e) => Fetch::failed(format!("Document is not valid JSON: {}", e)),
+    }
+}
+
+async fn record_metadata(
+    executor: impl PgExecutor<'_>,
+    pubkey: &str,
+    uri: &str,
+    fetch: &Fetch,
+) -> Result<(), AppError> {
+    // The node may have been pruned while it was fetched from, which would violate the foreign key.
+    sqlx::query(
+        r#"
+        INSERT INTO node_metadata (pubkey, uri, version, capabilities, info, error, fetched_at, updated_at)
+        SELECT $1, $2, $3, $4, $5, $6, NOW(), CASE WHEN $6 IS NULL THEN NOW() END
+        WHERE EXISTS (SELECT 1 FROM nodes WHERE pubkey = $1)
+        ON CONFLICT (pubkey) DO UPDATE
+        SET uri = EXCLUDED.uri,
+            error = EXCLUDED.error,
+            fetched_at = EXCLUDED.fetched_at,
+            -- A failed fetch keeps what the same URI served before; a new URI starts without it.
+            version = CASE WHEN EXCLUDED.error IS NULL OR node_metadata.uri <> EXCLUDED.uri
+                           THEN EXCLUDED.version ELSE node_metadata.version END,
+            capabilities = CASE WHEN EXCLUDED.error IS NULL OR node_metadata.uri <> EXCLUDED.uri
+                                THEN EXCLUDED.capabilities ELSE node_metadata.capabilities END,
+            info = CASE WHEN EXCLUDED.error IS NULL OR node_metadata.uri <> EXCLUDED.uri
+                        THEN EXCLUDED.info ELSE node_metadata.info END,
+            updated_at = CASE WHEN EXCLUDED.error IS NULL OR node_metadata.uri <> EXCLUDED.uri
+                              THEN EXCLUDED.updated_at ELSE node_metadata.updated_at END
+        "#,
+    )
+    .bind(pubkey)
+    .bind(uri)
+    .bind(&fetch.version)
+    .bind(&fetch.capabilities)
+    .bind(&fetch.info)
+    .bind(&fetch.error)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
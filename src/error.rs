@@ -0,0 +1,150 @@
+//! API errors. Every failed request gets an RFC 7807 `application/problem+json` body with a
+//! stable `code` and the request's ID; internal failures are logged in full but only
+//! described in general terms to the client.
+
+use std::fmt::Display;
+
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts,
+    },
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::request_id;
+
+// --- Media type of error bodies ---
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// A failed API request.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    detail: String,
+    /// What actually went wrong, when `detail` doesn't say; only reported, never sent.
+    cause: Option<String>,
+}
+
+/// Set on the response of a `5xx` [`ApiError`] for [`request_id::assign`] to report.
+#[derive(Clone, Debug)]
+pub(crate) struct ServerError {
+    pub code: &'static str,
+    /// The cause if there is one, else the detail sent to the client.
+    pub error: String,
+}
+
+/// RFC 7807 problem details. `type` is always `about:blank`, so `title` is the status
+/// phrase and `code` tells errors with the same status apart.
+#[derive(Serialize, ToSchema)]
+pub struct Problem<'a> {
+    #[serde(rename = "type")]
+    #[schema(example = "about:blank")]
+    kind: &'static str,
+    /// HTTP status phrase.
+    title: &'static str,
+    status: u16,
+    detail: &'a str,
+    /// Machine-readable code, e.g. `node_not_found`.
+    code: &'a str,
+    /// Also sent as `X-Request-Id`; quote it when reporting problems.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    /// An error whose `detail` is safe to show the client.
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Self { status, code, detail: detail.into(), cause: None }
+    }
+
+    pub fn bad_request(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, code, detail)
+    }
+
+    pub fn not_found(code: &'static str, detail: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, code, detail)
+    }
+
+    pub fn invalid_pubkey(pubkey: &str) -> Self {
+        Self::bad_request("invalid_pubkey", format!("'{}' is not a valid base58 pubkey", pubkey))
+    }
+
+    /// Logs a failed query and answers `500` with `detail`, keeping the database error itself
+    /// out of the response.
+    pub fn database(e: impl Display, detail: impl Into<String>) -> Self {
+        error!(error = %e, "Database query failed");
+        let error = Self::new(StatusCode::INTERNAL_SERVER_ERROR, "database_error", detail);
+        Self { cause: Some(e.to_string()), ..error }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let problem = Problem {
+            kind: "about:blank",
+            title: self.status.canonical_reason().unwrap_or("Error"),
+            status: self.status.as_u16(),
+            detail: &self.detail,
+            code: self.code,
+            request_id: request_id::current(),
+        };
+        let body = serde_json::to_vec(&problem).expect("problem details serialize");
+        let mut response = (self.status, body).into_response();
+        response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE));
+        if self.status.is_server_error() {
+            let error = self.cause.unwrap_or(self.detail);
+            response.extensions_mut().insert(ServerError { code: self.code, error });
+        }
+        response
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), "invalid_body", rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::new(rejection.status(), "invalid_query", rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::new(rejection.status(), "invalid_path", rejection.body_text())
+    }
+}
+
+/// [`axum::Json`] body extractor that rejects with an [`ApiError`].
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct JsonBody<T>(pub T);
+
+/// [`axum::extract::Query`] that rejects with an [`ApiError`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
+
+/// [`axum::extract::Path`] that rejects with an [`ApiError`].
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
+
+/// Answers requests for routes that don't exist.
+pub(crate) async fn route_not_found() -> ApiError {
+    ApiError::not_found("route_not_found", "No such route")
+}
+
+
+/// Answers requests with a method the route doesn't serve.
+pub(crate) async fn method_not_allowed() -> ApiError {
+    ApiError::new(StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", "The route doesn't serve this method")
+}
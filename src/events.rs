@@ -0,0 +1,119 @@
+//! In-process fan-out of node change events to WebSocket and SSE subscribers.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use serde::Serialize;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use crate::store::ApiNode;
+
+// --- Number of change events buffered per subscriber before it starts lagging ---
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+// --- Number of recent change events kept for SSE Last-Event-ID replay ---
+const EVENT_REPLAY_CAPACITY: usize = 4096;
+
+/// A change detected by the background sync, fanned out to every live subscriber.
+#[derive(Serialize, ToSchema, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NodeEvent {
+    Added { node: ApiNode },
+    Updated { node: ApiNode },
+    /// `cluster` is the removed row's, as on [`ApiNode::cluster`].
+    Removed { pubkey: String, cluster: Option<String> },
+}
+
+impl NodeEvent {
+    pub fn pubkey(&self) -> &str {
+        match self {
+            Self::Added { node } | Self::Updated { node } => &node.pubkey,
+            Self::Removed { pubkey, .. } => pubkey,
+        }
+    }
+
+    /// Cluster of the node the change is about.
+    pub fn cluster(&self) -> Option<&str> {
+        match self {
+            Self::Added { node } | Self::Updated { node } => node.cluster.as_deref(),
+            Self::Removed { cluster, .. } => cluster.as_deref(),
+        }
+    }
+}
+
+/// A `NodeEvent` tagged with a monotonically increasing id, used as the SSE event id.
+#[derive(Serialize, ToSchema, Clone, Debug)]
+pub struct SequencedEvent {
+    pub id: u64,
+    #[serde(flatten)]
+    pub event: NodeEvent,
+}
+
+/// Fan-out hub for change events. Keeps a short replay buffer so SSE clients can
+/// resume from `Last-Event-ID` after a reconnect.
+#[derive(Clone)]
+pub struct EventHub {
+    tx: broadcast::Sender<SequencedEvent>,
+    inner: Arc<Mutex<HubInner>>,
+}
+
+struct HubInner {
+    next_id: u64,
+    recent: VecDeque<SequencedEvent>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        // Seed ids from the wall clock so they keep increasing across restarts.
+        let next_id = Utc::now().timestamp_millis().max(0) as u64 * 1000;
+        Self {
+            tx,
+            inner: Arc::new(Mutex::new(HubInner { next_id, recent: VecDeque::new() })),
+        }
+    }
+
+    pub fn publish(&self, event: NodeEvent) {
+        let mut inner = self.inner.lock().unwrap();
+        let sequenced = SequencedEvent { id: inner.next_id, event };
+        inner.next_id += 1;
+        if inner.recent.len() == EVENT_REPLAY_CAPACITY {
+            inner.recent.pop_front();
+        }
+        inner.recent.push_back(sequenced.clone());
+        // An error here only means nobody is subscribed right now.
+        let _ = self.tx.send(sequenced);
+    }
+
+    /// The id the next published event will get, so a change in it means something was published.
+    pub fn next_id(&self) -> u64 {
+        self.inner.lock().unwrap().next_id
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Returns the buffered events after `last_id` together with a receiver for
+    /// everything published afterwards. The second value is `false` when events
+    /// after `last_id` have already been evicted, so the client must refetch.
+    pub fn resume_after(
+        &self,
+        last_id: u64,
+    ) -> (Vec<SequencedEvent>, bool, broadcast::Receiver<SequencedEvent>) {
+        // Holding the lock while subscribing guarantees no gap and no duplicates.
+        let inner = self.inner.lock().unwrap();
+        let rx = self.tx.subscribe();
+        let complete = inner.recent.front().is_none_or(|oldest| oldest.id <= last_id + 1);
+        let backlog = inner.recent.iter().filter(|e| e.id > last_id).cloned().collect();
+        (backlog, complete, rx)
+    }
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,76 @@
+//! Sparse node lists: `?fields=pubkey,uri` trims each listed node to the named fields, so
+//! clients that only need a few columns don't download the rest.
+
+use axum::response::{IntoResponse, Json, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::IntoParams;
+
+use crate::error::ApiError;
+
+// --- Fields of a listed node that ?fields= may name ---
+pub(crate) const NODE_FIELDS: &[&str] = &[
+    "pubkey",
+    "authority",
+    "uri",
+    "program_id",
+    "cluster",
+    "first_seen_at",
+    "updated_at",
+    "last_seen_slot",
+    "deleted_at",
+    "name",
+    "registered_at",
+    "layout_version",
+    "finalized",
+    "uri_valid",
+    "pda_verified",
+    "lamports",
+    "rent_exempt",
+    "uptime",
+    "operator_metadata",
+];
+
+/// `?fields=` on node list endpoints: a comma-separated subset of [`NODE_FIELDS`].
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FieldsParams {
+    /// Comma-separated node fields to return, all by default, e.g. `pubkey,uri,uptime`.
+    pub fields: Option<String>,
+}
+
+impl FieldsParams {
+    /// The requested fields in [`NODE_FIELDS`] order, or `None` when every field is wanted.
+    pub(crate) fn resolve(&self) -> Result<Option<Vec<&'static str>>, ApiError> {
+        let Some(fields) = &self.fields else { return Ok(None) };
+        let names: Vec<&str> = fields.split(',').map(str::trim).filter(|name| !name.is_empty()).collect();
+        if names.is_empty() {
+            return Err(ApiError::bad_request("invalid_fields", "`fields` must name at least one field"));
+        }
+        if let Some(unknown) = names.iter().find(|name| !NODE_FIELDS.contains(name)) {
+            let message = format!("Unknown field '{}'; expected any of {}", unknown, NODE_FIELDS.join(", "));
+            return Err(ApiError::bad_request("invalid_fields", message));
+        }
+        Ok(Some(NODE_FIELDS.iter().copied().filter(|field| names.contains(field)).collect()))
+    }
+}
+
+/// A JSON body whose `list` array of nodes is trimmed to `fields`, or sent whole when `None`.
+pub struct SparseJson<T> {
+    pub body: T,
+    pub list: &'static str,
+    pub fields: Option<Vec<&'static str>>,
+}
+
+impl<T: Serialize> IntoResponse for SparseJson<T> {
+    fn into_response(self) -> Response {
+        let Some(fields) = self.fields else { return Json(self.body).into_response() };
+        let mut body = serde_json::to_value(&self.body).expect("node lists serialize");
+        if let Some(Value::Array(items)) = body.get_mut(self.list) {
+            for item in items.iter_mut().filter_map(Value::as_object_mut) {
+                item.retain(|name, _| fields.contains(&name.as_str()));
+            }
+        }
+        Json(body).into_response()
+    }
+}
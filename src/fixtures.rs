@@ -0,0 +1,253 @@
+//! Replayed RPC for offline development and deterministic runs (`--mock-rpc <FIXTURES>`): the
+//! sync loop's RPC calls are answered from account snapshots in a JSON file instead of a
+//! cluster. The file lists snapshots in the order they should be served:
+//!
+//! ```json
+//! {
+//!   "snapshots": [
+//!     {
+//!       "slot": 100,
+//!       "accounts": [
+//!         { "pubkey": "…", "owner": "<program id>", "data": "<base64>", "lamports": 1461600 }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! A program moves to the next snapshot when a getProgramAccounts query it has already been
+//! served from the current one comes again, as it does each sync cycle, and stays on the last
+//! once they run out; so consecutive cycles see accounts appear, change and close.
+//! getSlot, getAccountInfo, getMultipleAccounts and getBlocks answer from the newest snapshot
+//! served so far; transaction history is empty, and subscriptions aren't available.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use solana_client::client_error::{ClientError, Result as ClientResult};
+use solana_client::rpc_config::RpcProgramAccountsConfig;
+use solana_client::rpc_filter::RpcFilterType;
+use solana_client::rpc_request::{RpcError, RpcRequest};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::AppError;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FixtureFile {
+    snapshots: Vec<RawSnapshot>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSnapshot {
+    slot: u64,
+    #[serde(default)]
+    accounts: Vec<RawAccount>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAccount {
+    pubkey: String,
+    owner: String,
+    /// Base64 account data.
+    data: String,
+    #[serde(default)]
+    lamports: u64,
+    #[serde(default)]
+    rent_epoch: u64,
+}
+
+/// One account as the fixture RPC serves it.
+struct FixtureAccount {
+    pubkey: Pubkey,
+    owner: Pubkey,
+    data: Vec<u8>,
+    lamports: u64,
+    rent_epoch: u64,
+}
+
+impl FixtureAccount {
+    /// The `UiAccount` JSON of a base64-encoded account.
+    fn to_json(&self) -> Value {
+        json!({
+            "lamports": self.lamports,
+            "data": [BASE64_STANDARD.encode(&self.data), "base64"],
+            "owner": self.owner.to_string(),
+            "executable": false,
+            "rentEpoch": self.rent_epoch,
+            "space": self.data.len(),
+        })
+    }
+
+    fn matches(&self, filters: &[RpcFilterType]) -> bool {
+        filters.iter().all(|filter| match filter {
+            RpcFilterType::DataSize(size) => self.data.len() as u64 == *size,
+            RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(&self.data),
+            RpcFilterType::TokenAccountState => false,
+        })
+    }
+}
+
+struct Snapshot {
+    slot: u64,
+    accounts: Vec<FixtureAccount>,
+}
+
+/// Account snapshots loaded from a fixture file, to serve with [`crate::rpc::SolanaRpc::from_fixtures`].
+pub struct RpcFixtures {
+    /// The file they came from, reported as the endpoint URL.
+    source: String,
+    snapshots: Vec<Snapshot>,
+}
+
+impl RpcFixtures {
+    /// Reads and validates the fixture file at `path`.
+    pub fn load(path: &Path) -> Result<Self, AppError> {
+        let context = |e: &dyn std::fmt::Display| format!("Invalid RPC fixtures {}: {}", path.display(), e);
+        let text = std::fs::read_to_string(path).map_err(|e| context(&e))?;
+        let file: FixtureFile = serde_json::from_str(&text).map_err(|e| context(&e))?;
+        if file.snapshots.is_empty() {
+            return Err(context(&"at least one snapshot is required").into());
+        }
+        let mut snapshots = Vec::with_capacity(file.snapshots.len());
+        for (index, snapshot) in file.snapshots.into_iter().enumerate() {
+            let mut accounts = Vec::with_capacity(snapshot.accounts.len());
+            for account in snapshot.accounts {
+                let at = |what: &str| context(&format!("snapshot {} account '{}': {}", index, account.pubkey, what));
+                accounts.push(FixtureAccount {
+                    pubkey: Pubkey::from_str(&account.pubkey).map_err(|_| at("pubkey isn't base58"))?,
+                    owner: Pubkey::from_str(&account.owner).map_err(|_| at("owner isn't base58"))?,
+                    data: BASE64_STANDARD.decode(&account.data).map_err(|_| at("data isn't base64"))?,
+                    lamports: account.lamports,
+                    rent_epoch: account.rent_epoch,
+                });
+            }
+            snapshots.push(Snapshot { slot: snapshot.slot, accounts });
+        }
+        Ok(Self { source: format!("fixtures:{}", path.display()), snapshots })
+    }
+}
+
+/// The [`RpcSender`] behind a fixture-backed client.
+pub(crate) struct FixtureSender {
+    fixtures: RpcFixtures,
+    state: Mutex<ReplayState>,
+}
+
+#[derive(Default)]
+struct ReplayState {
+    /// Snapshot each program is on, and the filter sets it has been queried with there.
+    programs: HashMap<Pubkey, (usize, HashSet<String>)>,
+    /// Newest snapshot served to any program.
+    current: usize,
+}
+
+impl FixtureSender {
+    pub(crate) fn new(fixtures: RpcFixtures) -> Self {
+        Self { fixtures, state: Mutex::new(ReplayState::default()) }
+    }
+
+    pub(crate) fn source(&self) -> &str {
+        &self.fixtures.source
+    }
+
+    fn current(&self) -> &Snapshot {
+        &self.fixtures.snapshots[self.state.lock().unwrap().current]
+    }
+
+    fn program_accounts(&self, params: &Value) -> Result<Value, String> {
+        let program_id = params[0].as_str().and_then(|id| Pubkey::from_str(id).ok()).ok_or("Invalid program id")?;
+        let config: RpcProgramAccountsConfig = match params.get(1) {
+            Some(config) => serde_json::from_value(config.clone()).map_err(|e| e.to_string())?,
+            None => RpcProgramAccountsConfig::default(),
+        };
+        let filters = config.filters.unwrap_or_default();
+        let index = {
+            let mut state = self.state.lock().unwrap();
+            let (index, queried) = state.programs.entry(program_id).or_default();
+            let query = serde_json::to_string(&filters).map_err(|e| e.to_string())?;
+            if queried.contains(&query) && *index + 1 < self.fixtures.snapshots.len() {
+                *index += 1;
+                queried.clear();
+            }
+            queried.insert(query);
+            let index = *index;
+            state.current = state.current.max(index);
+            index
+        };
+        let snapshot = &self.fixtures.snapshots[index];
+        let accounts: Vec<Value> = snapshot
+            .accounts
+            .iter()
+            .filter(|account| account.owner == program_id && account.matches(&filters))
+            .map(|account| json!({ "pubkey": account.pubkey.to_string(), "account": account.to_json() }))
+            .collect();
+        Ok(if config.with_context == Some(true) {
+            json!({ "context": { "slot": snapshot.slot }, "value": accounts })
+        } else {
+            Value::from(accounts)
+        })
+    }
+
+    fn account_info(&self, params: &Value) -> Result<Value, String> {
+        let pubkey = params[0].as_str().and_then(|key| Pubkey::from_str(key).ok()).ok_or("Invalid pubkey")?;
+        let snapshot = self.current();
+        let account = snapshot.accounts.iter().find(|account| account.pubkey == pubkey);
+        Ok(json!({ "context": { "slot": snapshot.slot }, "value": account.map(FixtureAccount::to_json) }))
+    }
+
+    fn multiple_accounts(&self, params: &Value) -> Result<Value, String> {
+        let pubkeys = params[0].as_array().ok_or("Invalid pubkeys")?;
+        let snapshot = self.current();
+        let mut accounts = Vec::with_capacity(pubkeys.len());
+        for pubkey in pubkeys {
+            let pubkey = pubkey.as_str().and_then(|key| Pubkey::from_str(key).ok()).ok_or("Invalid pubkey")?;
+            let account = snapshot.accounts.iter().find(|account| account.pubkey == pubkey);
+            accounts.push(account.map(FixtureAccount::to_json));
+        }
+        Ok(json!({ "context": { "slot": snapshot.slot }, "value": accounts }))
+    }
+
+    /// Every slot in the range up to the current one holds a block.
+    fn blocks(&self, params: &Value) -> Result<Value, String> {
+        let start = params[0].as_u64().ok_or("Invalid start slot")?;
+        let slot = self.current().slot;
+        let end = params.get(1).and_then(Value::as_u64).unwrap_or(slot).min(slot);
+        Ok(Value::from((start..=end).collect::<Vec<u64>>()))
+    }
+}
+
+#[async_trait]
+impl RpcSender for FixtureSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let response = match request {
+            RpcRequest::GetProgramAccounts => self.program_accounts(&params),
+            RpcRequest::GetAccountInfo => self.account_info(&params),
+            RpcRequest::GetMultipleAccounts => self.multiple_accounts(&params),
+            RpcRequest::GetSlot => Ok(Value::from(self.current().slot)),
+            RpcRequest::GetBlocks => self.blocks(&params),
+            RpcRequest::GetSignaturesForAddress => Ok(json!([])),
+            RpcRequest::GetHealth => Ok(json!("ok")),
+            RpcRequest::GetVersion => Ok(json!({ "solana-core": "fixtures", "feature-set": 0 })),
+            other => Err(format!("{} isn't served from fixtures", other)),
+        };
+        response.map_err(|message| ClientError::new_with_request(RpcError::RpcRequestError(message).into(), request))
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        RpcTransportStats::default()
+    }
+
+    fn url(&self) -> String {
+        self.fixtures.source.clone()
+    }
+}
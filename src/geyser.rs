@@ -0,0 +1,107 @@
+//! Yellowstone Geyser gRPC ingestion backend.
+//!
+//! Streams account updates for the program straight from a Geyser plugin and runs
+//! them through the same `apply_account_update` pipeline as the RPC subscription.
+
+use std::collections::HashMap;
+
+use futures::{SinkExt, StreamExt};
+use solana_commitment_config::{CommitmentConfig, CommitmentLevel as SolanaCommitmentLevel};
+use solana_sdk::pubkey::Pubkey;
+use tokio::time::{sleep, Duration};
+use tracing::{info, instrument, warn};
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::prelude::{
+    subscribe_update::UpdateOneof, CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts,
+    SubscribeRequestPing,
+};
+
+use crate::cluster::ProgramKey;
+use crate::sync::{apply_account_update, AccountUpdate, SyncContext, MAX_SUBSCRIBE_BACKOFF_SECS};
+use crate::AppError;
+
+/// Keeps a Geyser subscription open, reconnecting with backoff on failure.
+#[instrument(skip_all, fields(program_id = %program.program_id))]
+pub async fn run_geyser_stream(
+    endpoint: String,
+    x_token: Option<String>,
+    program: ProgramKey,
+    sync: SyncContext,
+) {
+    let mut backoff_secs = 1;
+    loop {
+        info!(%endpoint, "Connecting to Geyser");
+        match stream_program_accounts(&endpoint, x_token.clone(), &program, &sync).await {
+            Ok(()) => {
+                warn!("Geyser stream ended, reconnecting");
+                backoff_secs = 1;
+            }
+            Err(e) => warn!(error = %e, backoff_secs, "Geyser stream failed"),
+        }
+        sleep(Duration::from_secs(backoff_secs)).await;
+        backoff_secs = (backoff_secs * 2).min(MAX_SUBSCRIBE_BACKOFF_SECS);
+    }
+}
+
+async fn stream_program_accounts(
+    endpoint: &str,
+    x_token: Option<String>,
+    program: &ProgramKey,
+    sync: &SyncContext,
+) -> Result<(), AppError> {
+    let program_id = &program.program_id;
+    let mut client = GeyserGrpcClient::build_from_shared(endpoint.to_string())?
+        .x_token(x_token)?
+        .connect()
+        .await?;
+
+    let request = SubscribeRequest {
+        accounts: HashMap::from([(
+            "nodes".to_string(),
+            SubscribeRequestFilterAccounts { owner: vec![program_id.to_string()], ..Default::default() },
+        )]),
+        commitment: Some(commitment_level(sync.config.commitment) as i32),
+        ..Default::default()
+    };
+    let (mut sink, mut stream) = client.subscribe_with_request(Some(request)).await?;
+    info!("Subscribed to Geyser account updates");
+
+    while let Some(update) = stream.next().await {
+        match update?.update_oneof {
+            Some(UpdateOneof::Account(update)) => {
+                let Some(account) = update.account else { continue };
+                let pubkey_bytes: [u8; 32] = match account.pubkey.as_slice().try_into() {
+                    Ok(bytes) => bytes,
+                    Err(_) => continue,
+                };
+                let pubkey = Pubkey::new_from_array(pubkey_bytes).to_string();
+                let update = AccountUpdate {
+                    program: *program,
+                    pubkey,
+                    lamports: account.lamports,
+                    data: account.data,
+                    owner: Pubkey::try_from(account.owner.as_slice()).unwrap_or(*program_id),
+                    rent_epoch: account.rent_epoch,
+                    slot: update.slot,
+                };
+                apply_account_update(sync, update, "Geyser").await?;
+            }
+            // Servers drop idle streams unless pings are answered.
+            Some(UpdateOneof::Ping(_)) => {
+                sink.send(SubscribeRequest { ping: Some(SubscribeRequestPing { id: 1 }), ..Default::default() })
+                    .await?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn commitment_level(commitment: CommitmentConfig) -> CommitmentLevel {
+    match commitment.commitment {
+        SolanaCommitmentLevel::Processed => CommitmentLevel::Processed,
+        SolanaCommitmentLevel::Confirmed => CommitmentLevel::Confirmed,
+        SolanaCommitmentLevel::Finalized => CommitmentLevel::Finalized,
+    }
+}
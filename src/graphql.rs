@@ -0,0 +1,958 @@
+//! GraphQL API on async-graphql: `POST`/`GET /graphql` for queries over nodes, stats and history
+//! with field-level filters and relations, and `/graphql/ws` for subscriptions to the change feed
+//! (`graphql-transport-ws`, or the older `graphql-ws`). The schema is served as SDL at
+//! `/graphql/schema` and answers introspection queries.
+
+mod filter;
+
+use std::slice;
+
+use async_graphql::{
+    Context, EmptyMutation, Enum, Error, InputValueError, InputValueResult, Object, Result, Scalar, ScalarType,
+    Schema, Subscription, Value,
+};
+use async_graphql::http::ALL_WEBSOCKET_PROTOCOLS;
+use async_graphql_axum::{GraphQLProtocol, GraphQLRequest, GraphQLResponse, GraphQLWebSocket};
+use axum::extract::ws::WebSocketUpgrade;
+use axum::{http::header, response::IntoResponse, routing::get, Extension, Router};
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, error};
+use utoipa::ToSchema;
+
+use self::filter::{HistoryFilter, NodeFilter, SqlFilter};
+use crate::api::{load_stats, ApiHistoryEntry, ApiNodeTransaction, ApiSnapshot, ApiStats, AppState, PageParams};
+use crate::enrichment::{self, node_metadata};
+use crate::events::{NodeEvent, SequencedEvent};
+use crate::liveness::{node_uptime, NodeLiveness, NodeUptime, LIVENESS_COLUMNS};
+use crate::moderation::NOT_QUARANTINED;
+use crate::operator::{self, operator_metadata};
+use crate::store::{ApiNode, NODE_COLUMNS};
+
+// --- Deepest field nesting a query may select ---
+const MAX_SELECTION_DEPTH: usize = 12;
+
+// --- Columns selected into `ApiHistoryEntry` ---
+const HISTORY_COLUMNS: &str =
+    "id, pubkey, cluster, change_type, old_authority, new_authority, old_uri, new_uri, slot, changed_at";
+
+pub type IndexerSchema = Schema<Query, EmptyMutation, Subscription>;
+
+/// Builds the schema, resolving against `state`.
+pub fn schema(state: AppState) -> IndexerSchema {
+    Schema::build(Query, EmptyMutation, Subscription).data(state).limit_depth(MAX_SELECTION_DEPTH).finish()
+}
+
+/// `/graphql`, `/graphql/ws` and `/graphql/schema`, to be merged behind authentication.
+pub(crate) fn routes(state: AppState) -> Router<AppState> {
+    let schema = schema(state);
+    Router::new()
+        .route("/graphql", get(get_graphql).post(post_graphql))
+        .route("/graphql/ws", get(graphql_ws))
+        .route("/graphql/schema", get(get_graphql_schema))
+        .layer(Extension(schema))
+}
+
+/// Body of `POST /graphql`, described for the OpenAPI document only.
+#[derive(ToSchema)]
+#[schema(as = GraphQLRequest)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestDoc {
+    pub query: String,
+    pub variables: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Operation to run when the query has several.
+    pub operation_name: Option<String>,
+}
+
+/// Body of a `/graphql` answer, described for the OpenAPI document only.
+#[derive(ToSchema)]
+#[schema(as = GraphQLResponse)]
+pub struct ResponseDoc {
+    pub data: Option<serde_json::Map<String, serde_json::Value>>,
+    pub errors: Option<Vec<ErrorDoc>>,
+}
+
+#[derive(ToSchema)]
+#[schema(as = GraphQLError)]
+pub struct ErrorDoc {
+    pub message: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    tag = "graphql",
+    summary = "Run a GraphQL query",
+    request_body = RequestDoc,
+    responses(
+        (
+            status = 200,
+            description = "Data, with an error for each field that failed; only errors when the query is invalid",
+            body = ResponseDoc
+        ),
+        (status = 400, description = "The request isn't a GraphQL request", body = String, content_type = "text/plain"),
+    )
+)]
+pub(crate) async fn post_graphql(
+    Extension(schema): Extension<IndexerSchema>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let request = request.into_inner();
+    debug!(operation = ?request.operation_name, "=> POST /graphql - Executing query");
+    let response = schema.execute(request).await;
+    debug!(errors = response.errors.len(), "<= POST /graphql - Responding");
+    response.into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/graphql",
+    tag = "graphql",
+    summary = "Run a GraphQL query given in the URL",
+    params(
+        ("query" = String, Query),
+        ("variables" = Option<String>, Query, description = "JSON-encoded variables"),
+        ("operationName" = Option<String>, Query, description = "Operation to run when the query has several"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "Data, with an error for each field that failed; only errors when the query is invalid",
+            body = ResponseDoc
+        ),
+        (status = 400, description = "The request isn't a GraphQL request", body = String, content_type = "text/plain"),
+    )
+)]
+pub(crate) async fn get_graphql(
+    Extension(schema): Extension<IndexerSchema>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    let request = request.into_inner();
+    debug!(operation = ?request.operation_name, "=> GET /graphql - Executing query");
+    let response = schema.execute(request).await;
+    debug!(errors = response.errors.len(), "<= GET /graphql - Responding");
+    response.into()
+}
+
+#[utoipa::path(
+    get,
+    path = "/graphql/schema",
+    tag = "graphql",
+    summary = "The GraphQL schema in SDL",
+    responses((status = 200, description = "The schema", body = String, content_type = "text/plain"))
+)]
+pub(crate) async fn get_graphql_schema(Extension(schema): Extension<IndexerSchema>) -> impl IntoResponse {
+    debug!("=> GET /graphql/schema - Serving SDL");
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], schema.sdl())
+}
+
+#[utoipa::path(
+    get,
+    path = "/graphql/ws",
+    tag = "graphql",
+    summary = "GraphQL subscriptions over WebSocket",
+    description = "Speaks the graphql-transport-ws protocol, or the older graphql-ws; subscribe to `nodeChanges`.",
+    responses((status = 101, description = "Switching to the WebSocket protocol"))
+)]
+pub(crate) async fn graphql_ws(
+    Extension(schema): Extension<IndexerSchema>,
+    protocol: GraphQLProtocol,
+    upgrade: WebSocketUpgrade,
+) -> impl IntoResponse {
+    debug!("=> GET /graphql/ws - Upgrading to WebSocket");
+    upgrade
+        .protocols(ALL_WEBSOCKET_PROTOCOLS)
+        .on_upgrade(move |stream| GraphQLWebSocket::new(stream, schema, protocol).serve())
+}
+
+/// 64-bit integer (slots, ids and counts).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct BigInt(pub i64);
+
+/// 64-bit integer (slots, ids and counts).
+#[Scalar(name = "BigInt")]
+impl ScalarType for BigInt {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        match &value {
+            Value::Number(number) => number.as_i64().map(BigInt).ok_or_else(|| InputValueError::expected_type(value)),
+            _ => Err(InputValueError::expected_type(value)),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Number(self.0.into())
+    }
+}
+
+impl From<BigInt> for i64 {
+    fn from(value: BigInt) -> Self {
+        value.0
+    }
+}
+
+fn pool<'c>(ctx: &Context<'c>) -> &'c PgPool {
+    &ctx.data_unchecked::<AppState>().read_pool.0
+}
+
+/// Logs a failed query and turns it into the error reported for `field`.
+fn db_error(field: &'static str) -> impl FnOnce(sqlx::Error) -> Error {
+    move |e| {
+        error!(error = %e, field, "Database query failed");
+        Error::new(format!("Failed to resolve '{}' from the database", field))
+    }
+}
+
+/// Clamps `limit` and `offset` arguments like the REST endpoints do.
+fn page(limit: i32, offset: BigInt) -> (i64, i64) {
+    PageParams { limit: Some(limit.into()), offset: Some(offset.0) }.resolve()
+}
+
+/// Offset of the page after one of `len` items at `offset`, unless that was the last.
+fn next_offset(offset: i64, len: usize, total: i64) -> Option<BigInt> {
+    let next = offset + len as i64;
+    (next < total).then_some(BigInt(next))
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Nodes whose URI failed validation on ingest, or whose address isn't their expected PDA, are
+    /// left out unless includeInvalid or includeUnverified is set.
+    #[allow(clippy::too_many_arguments)]
+    async fn nodes(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<NodeFilter>,
+        #[graphql(default)] include_deleted: bool,
+        #[graphql(default)] include_invalid: bool,
+        #[graphql(default)] include_unverified: bool,
+        #[graphql(default = 100)] limit: i32,
+        #[graphql(default_with = "BigInt(0)")] offset: BigInt,
+    ) -> Result<NodePage> {
+        let pool = pool(ctx);
+        let (limit, offset) = page(limit, offset);
+        let filter = filter.unwrap_or_default();
+        let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM nodes WHERE (");
+        count.push_bind(include_deleted).push(" OR deleted_at IS NULL) AND (");
+        count.push_bind(include_invalid).push(" OR uri_valid) AND (");
+        count.push_bind(include_unverified).push(" OR pda_verified IS NOT FALSE) AND ");
+        filter.push_condition(&mut count);
+        count.push(" AND ").push(NOT_QUARANTINED);
+        let total: i64 = count.build_query_scalar().fetch_one(pool).await.map_err(db_error("nodes"))?;
+
+        let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM nodes WHERE (", NODE_COLUMNS));
+        query.push_bind(include_deleted).push(" OR deleted_at IS NULL) AND (");
+        query.push_bind(include_invalid).push(" OR uri_valid) AND (");
+        query.push_bind(include_unverified).push(" OR pda_verified IS NOT FALSE) AND ");
+        filter.push_condition(&mut query);
+        query.push(" AND ").push(NOT_QUARANTINED);
+        query.push(" ORDER BY pubkey LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+        let nodes: Vec<ApiNode> = query.build_query_as().fetch_all(pool).await.map_err(db_error("nodes"))?;
+
+        // One uptime query for the whole page rather than one per node.
+        let pubkeys: Vec<String> = nodes.iter().map(|node| node.pubkey.clone()).collect();
+        let uptime = node_uptime(pool, &pubkeys).await.map_err(db_error("nodes"))?;
+        let nodes = nodes
+            .into_iter()
+            .map(|node| {
+                let uptime = uptime.get(&node.pubkey).copied().unwrap_or_default();
+                Node { node, uptime: Some(uptime) }
+            })
+            .collect();
+        Ok(NodePage { nodes, total, limit, offset })
+    }
+
+    /// A pubkey indexed from several clusters resolves to the unnamed default cluster's node, then
+    /// by cluster name, unless cluster picks one.
+    async fn node(
+        &self,
+        ctx: &Context<'_>,
+        pubkey: String,
+        cluster: Option<String>,
+        #[graphql(default)] include_deleted: bool,
+    ) -> Result<Option<Node>> {
+        let node = sqlx::query_as::<_, ApiNode>(&format!(
+            "SELECT {} FROM nodes WHERE pubkey = $1 AND ($2 OR deleted_at IS NULL) \
+             AND ($3::text IS NULL OR cluster = $3) AND {} ORDER BY cluster NULLS FIRST LIMIT 1",
+            NODE_COLUMNS, NOT_QUARANTINED
+        ))
+        .bind(&pubkey)
+        .bind(include_deleted)
+        .bind(&cluster)
+        .fetch_optional(pool(ctx))
+        .await
+        .map_err(db_error("node"))?;
+        Ok(node.map(|node| Node { node, uptime: None }))
+    }
+
+    async fn stats(&self, ctx: &Context<'_>) -> Result<Stats> {
+        let state = ctx.data_unchecked::<AppState>();
+        let program_ids: Vec<String> = state.program_ids.iter().map(ToString::to_string).collect();
+        let (stats, snapshots) = load_stats(&state.read_pool.0, &program_ids).await.map_err(db_error("stats"))?;
+        Ok(Stats { stats, snapshots, program_ids, paused: state.pause.is_paused() })
+    }
+
+    /// All nodes' change history, newest first.
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<HistoryFilter>,
+        #[graphql(default = 100)] limit: i32,
+        #[graphql(default_with = "BigInt(0)")] offset: BigInt,
+    ) -> Result<HistoryPage> {
+        let pool = pool(ctx);
+        let (limit, offset) = page(limit, offset);
+        let filter = filter.unwrap_or_default();
+        let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM nodes_history WHERE ");
+        filter.push_condition(&mut count);
+        count.push(" AND ").push(NOT_QUARANTINED);
+        let total: i64 = count.build_query_scalar().fetch_one(pool).await.map_err(db_error("history"))?;
+
+        let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM nodes_history WHERE ", HISTORY_COLUMNS));
+        filter.push_condition(&mut query);
+        query.push(" AND ").push(NOT_QUARANTINED);
+        query.push(" ORDER BY id DESC LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+        let entries: Vec<ApiHistoryEntry> =
+            query.build_query_as().fetch_all(pool).await.map_err(db_error("history"))?;
+        Ok(HistoryPage { entries: entries.into_iter().map(HistoryEntry).collect(), total, limit, offset })
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum NodeChangeType {
+    Added,
+    Updated,
+    Removed,
+}
+
+pub struct Subscription;
+
+#[Subscription]
+impl Subscription {
+    /// Changes as they are indexed. Every argument given must match.
+    async fn node_changes(
+        &self,
+        ctx: &Context<'_>,
+        pubkeys: Option<Vec<String>>,
+        program_id: Option<String>,
+        types: Option<Vec<NodeChangeType>>,
+    ) -> impl Stream<Item = Result<NodeChange>> {
+        let events = ctx.data_unchecked::<AppState>().events.subscribe();
+        BroadcastStream::new(events).filter_map(move |event| match event {
+            Ok(event) => {
+                let change = NodeChange(event);
+                let matches = types.as_ref().is_none_or(|types| types.contains(&change.change_type()))
+                    && pubkeys.as_ref().is_none_or(|pubkeys| pubkeys.contains(change.pubkey()))
+                    // Removals don't say which program the node belonged to, so they always pass.
+                    && (change.change_type() == NodeChangeType::Removed
+                        || program_id.as_deref().is_none_or(|program| change.program_id() == Some(program)));
+                matches.then_some(Ok(change))
+            }
+            // A slow client missed some changes; tell it so it can refetch.
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                Some(Err(Error::new(format!("Missed {} change events; refetch to resync", skipped))))
+            }
+        })
+    }
+}
+
+pub struct Node {
+    node: ApiNode,
+    /// Already loaded with the rest of a page; queried on demand otherwise.
+    uptime: Option<NodeUptime>,
+}
+
+#[Object]
+impl Node {
+    async fn pubkey(&self) -> &str {
+        &self.node.pubkey
+    }
+
+    async fn authority(&self) -> &str {
+        &self.node.authority
+    }
+
+    async fn uri(&self) -> &str {
+        &self.node.uri
+    }
+
+    async fn program_id(&self) -> Option<&str> {
+        self.node.program_id.as_deref()
+    }
+
+    /// Null for an unnamed default cluster.
+    async fn cluster(&self) -> Option<&str> {
+        self.node.cluster.as_deref()
+    }
+
+    async fn first_seen_at(&self) -> Option<DateTime<Utc>> {
+        self.node.first_seen_at
+    }
+
+    async fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.node.updated_at
+    }
+
+    async fn last_seen_slot(&self) -> Option<BigInt> {
+        self.node.last_seen_slot.map(BigInt)
+    }
+
+    async fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        self.node.deleted_at
+    }
+
+    /// Null for accounts still in the V1 layout.
+    async fn name(&self) -> Option<&str> {
+        self.node.name.as_deref()
+    }
+
+    /// Null for accounts still in the V1 layout.
+    async fn registered_at(&self) -> Option<DateTime<Utc>> {
+        self.node.registered_at
+    }
+
+    /// NodeDevice layout the account was decoded from, 1 or 2.
+    async fn layout_version(&self) -> i32 {
+        self.node.layout_version.into()
+    }
+
+    /// Whether lastSeenSlot is finalized, so no fork can undo the data.
+    async fn finalized(&self) -> bool {
+        self.node.finalized
+    }
+
+    /// Whether uri passed the checks made on ingest.
+    async fn uri_valid(&self) -> bool {
+        self.node.uri_valid
+    }
+
+    /// Whether the account sits at its expected PDA; null when that isn't checked.
+    async fn pda_verified(&self) -> Option<bool> {
+        self.node.pda_verified
+    }
+
+    /// Balance of the account as of its last write; null until then.
+    async fn lamports(&self) -> Option<BigInt> {
+        self.node.lamports.map(BigInt)
+    }
+
+    /// Whether lamports cover the rent-exempt minimum for the account's size; null with lamports.
+    async fn rent_exempt(&self) -> Option<bool> {
+        self.node.rent_exempt
+    }
+
+    async fn uptime(&self, ctx: &Context<'_>) -> Result<Uptime> {
+        if let Some(uptime) = self.uptime {
+            return Ok(Uptime(uptime));
+        }
+        let pubkey = &self.node.pubkey;
+        let uptime = node_uptime(pool(ctx), slice::from_ref(pubkey)).await.map_err(db_error("uptime"))?;
+        Ok(Uptime(uptime.get(pubkey).copied().unwrap_or_default()))
+    }
+
+    async fn liveness(&self, ctx: &Context<'_>) -> Result<Option<Liveness>> {
+        let liveness = sqlx::query_as::<_, NodeLiveness>(&format!(
+            "SELECT {} FROM node_liveness WHERE pubkey = $1",
+            LIVENESS_COLUMNS
+        ))
+        .bind(&self.node.pubkey)
+        .fetch_optional(pool(ctx))
+        .await
+        .map_err(db_error("liveness"))?;
+        Ok(liveness.map(Liveness))
+    }
+
+    /// The document the node serves under its URI; null until fetched.
+    async fn metadata(&self, ctx: &Context<'_>) -> Result<Option<Metadata>> {
+        let metadata = node_metadata(pool(ctx), &self.node.pubkey).await.map_err(db_error("metadata"))?;
+        Ok(metadata.map(Metadata))
+    }
+
+    /// What the node's authority attached to it; null when nothing.
+    async fn operator_metadata(&self, ctx: &Context<'_>) -> Result<Option<OperatorMetadata>> {
+        let metadata = operator_metadata(pool(ctx), slice::from_ref(&self.node.pubkey))
+            .await
+            .map_err(db_error("operatorMetadata"))?;
+        Ok(metadata.into_values().next().map(OperatorMetadata))
+    }
+
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 100)] limit: i32,
+        #[graphql(default_with = "BigInt(0)")] offset: BigInt,
+    ) -> Result<HistoryPage> {
+        let pool = pool(ctx);
+        let (limit, offset) = page(limit, offset);
+        let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM nodes_history WHERE pubkey = $1")
+            .bind(&self.node.pubkey)
+            .fetch_one(pool)
+            .await
+            .map_err(db_error("history"))?;
+        let entries = sqlx::query_as::<_, ApiHistoryEntry>(&format!(
+            "SELECT {} FROM nodes_history WHERE pubkey = $1 ORDER BY id DESC LIMIT $2 OFFSET $3",
+            HISTORY_COLUMNS
+        ))
+        .bind(&self.node.pubkey)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+        .map_err(db_error("history"))?;
+        Ok(HistoryPage { entries: entries.into_iter().map(HistoryEntry).collect(), total, limit, offset })
+    }
+
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 100)] limit: i32,
+        #[graphql(default_with = "BigInt(0)")] offset: BigInt,
+    ) -> Result<TransactionPage> {
+        let pool = pool(ctx);
+        let (limit, offset) = page(limit, offset);
+        let total: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM node_transactions WHERE pubkey = $1")
+            .bind(&self.node.pubkey)
+            .fetch_one(pool)
+            .await
+            .map_err(db_error("transactions"))?;
+        let transactions = sqlx::query_as::<_, ApiNodeTransaction>(
+            "SELECT signature, slot, block_time, fee_payer, signers, instructions, succeeded, error \
+             FROM node_transactions WHERE pubkey = $1 ORDER BY slot DESC, signature LIMIT $2 OFFSET $3",
+        )
+        .bind(&self.node.pubkey)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(pool)
+        .await
+        .map_err(db_error("transactions"))?;
+        Ok(TransactionPage { transactions: transactions.into_iter().map(Transaction).collect(), total, limit, offset })
+    }
+}
+
+pub struct NodePage {
+    nodes: Vec<Node>,
+    total: i64,
+    limit: i64,
+    offset: i64,
+}
+
+#[Object]
+impl NodePage {
+    async fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    async fn total(&self) -> BigInt {
+        BigInt(self.total)
+    }
+
+    async fn limit(&self) -> i32 {
+        self.limit as i32
+    }
+
+    async fn offset(&self) -> BigInt {
+        BigInt(self.offset)
+    }
+
+    async fn next_offset(&self) -> Option<BigInt> {
+        next_offset(self.offset, self.nodes.len(), self.total)
+    }
+}
+
+pub struct HistoryPage {
+    entries: Vec<HistoryEntry>,
+    total: i64,
+    limit: i64,
+    offset: i64,
+}
+
+#[Object]
+impl HistoryPage {
+    async fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    async fn total(&self) -> BigInt {
+        BigInt(self.total)
+    }
+
+    async fn limit(&self) -> i32 {
+        self.limit as i32
+    }
+
+    async fn offset(&self) -> BigInt {
+        BigInt(self.offset)
+    }
+
+    async fn next_offset(&self) -> Option<BigInt> {
+        next_offset(self.offset, self.entries.len(), self.total)
+    }
+}
+
+pub struct TransactionPage {
+    transactions: Vec<Transaction>,
+    total: i64,
+    limit: i64,
+    offset: i64,
+}
+
+#[Object]
+impl TransactionPage {
+    async fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    async fn total(&self) -> BigInt {
+        BigInt(self.total)
+    }
+
+    async fn limit(&self) -> i32 {
+        self.limit as i32
+    }
+
+    async fn offset(&self) -> BigInt {
+        BigInt(self.offset)
+    }
+
+    async fn next_offset(&self) -> Option<BigInt> {
+        next_offset(self.offset, self.transactions.len(), self.total)
+    }
+}
+
+pub struct Uptime(NodeUptime);
+
+#[Object(name = "NodeUptime")]
+impl Uptime {
+    #[graphql(name = "uptime24h")]
+    async fn uptime_24h(&self) -> Option<f64> {
+        self.0.uptime_24h
+    }
+
+    #[graphql(name = "uptime7d")]
+    async fn uptime_7d(&self) -> Option<f64> {
+        self.0.uptime_7d
+    }
+
+    #[graphql(name = "uptime30d")]
+    async fn uptime_30d(&self) -> Option<f64> {
+        self.0.uptime_30d
+    }
+}
+
+pub struct Metadata(enrichment::NodeMetadata);
+
+#[Object(name = "NodeMetadata")]
+impl Metadata {
+    async fn uri(&self) -> &str {
+        &self.0.uri
+    }
+
+    async fn version(&self) -> Option<&str> {
+        self.0.version.as_deref()
+    }
+
+    async fn capabilities(&self) -> &[String] {
+        &self.0.capabilities
+    }
+
+    /// Why the latest fetch failed; null when it succeeded.
+    async fn error(&self) -> Option<&str> {
+        self.0.error.as_deref()
+    }
+
+    async fn fetched_at(&self) -> DateTime<Utc> {
+        self.0.fetched_at
+    }
+
+    async fn updated_at(&self) -> Option<DateTime<Utc>> {
+        self.0.updated_at
+    }
+}
+
+pub struct OperatorMetadata(operator::OperatorMetadata);
+
+#[Object(name = "OperatorMetadata")]
+impl OperatorMetadata {
+    async fn display_name(&self) -> Option<&str> {
+        self.0.display_name.as_deref()
+    }
+
+    async fn contact(&self) -> Option<&str> {
+        self.0.contact.as_deref()
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.0.description.as_deref()
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+}
+
+pub struct Liveness(NodeLiveness);
+
+#[Object(name = "NodeLiveness")]
+impl Liveness {
+    async fn uri(&self) -> &str {
+        &self.0.uri
+    }
+
+    async fn online(&self) -> bool {
+        self.0.online
+    }
+
+    async fn status_code(&self) -> Option<i32> {
+        self.0.status_code
+    }
+
+    async fn latency_ms(&self) -> Option<i32> {
+        self.0.latency_ms
+    }
+
+    async fn error(&self) -> Option<&str> {
+        self.0.error.as_deref()
+    }
+
+    async fn checked_at(&self) -> DateTime<Utc> {
+        self.0.checked_at
+    }
+
+    async fn last_seen_online(&self) -> Option<DateTime<Utc>> {
+        self.0.last_seen_online
+    }
+
+    /// Null for http URIs and hosts that presented no certificate.
+    async fn cert_valid(&self) -> Option<bool> {
+        self.0.cert_valid
+    }
+
+    async fn cert_hostname_match(&self) -> Option<bool> {
+        self.0.cert_hostname_match
+    }
+
+    async fn cert_expires_at(&self) -> Option<DateTime<Utc>> {
+        self.0.cert_expires_at
+    }
+
+    /// Null for rows probed before resolution was recorded.
+    async fn dns_ok(&self) -> Option<bool> {
+        self.0.dns_ok
+    }
+
+    /// Addresses the host resolved to, or the URI's own IP address.
+    async fn resolved_ips(&self) -> Option<&[String]> {
+        self.0.resolved_ips.as_deref()
+    }
+
+    async fn dns_error(&self) -> Option<&str> {
+        self.0.dns_error.as_deref()
+    }
+}
+
+pub struct HistoryEntry(ApiHistoryEntry);
+
+#[Object]
+impl HistoryEntry {
+    async fn id(&self) -> BigInt {
+        BigInt(self.0.id)
+    }
+
+    async fn pubkey(&self) -> &str {
+        &self.0.pubkey
+    }
+
+    /// Null for an unnamed default cluster.
+    async fn cluster(&self) -> Option<&str> {
+        self.0.cluster.as_deref()
+    }
+
+    async fn change_type(&self) -> &str {
+        &self.0.change_type
+    }
+
+    async fn old_authority(&self) -> Option<&str> {
+        self.0.old_authority.as_deref()
+    }
+
+    async fn new_authority(&self) -> Option<&str> {
+        self.0.new_authority.as_deref()
+    }
+
+    async fn old_uri(&self) -> Option<&str> {
+        self.0.old_uri.as_deref()
+    }
+
+    async fn new_uri(&self) -> Option<&str> {
+        self.0.new_uri.as_deref()
+    }
+
+    async fn slot(&self) -> Option<BigInt> {
+        self.0.slot.map(BigInt)
+    }
+
+    async fn changed_at(&self) -> DateTime<Utc> {
+        self.0.changed_at
+    }
+
+    /// The node as indexed now, including when it has since been soft-deleted.
+    async fn node(&self, ctx: &Context<'_>) -> Result<Option<Node>> {
+        let node = sqlx::query_as::<_, ApiNode>(&format!(
+            "SELECT {} FROM nodes WHERE pubkey = $1 AND cluster IS NOT DISTINCT FROM $2 AND {}",
+            NODE_COLUMNS, NOT_QUARANTINED
+        ))
+        .bind(&self.0.pubkey)
+        .bind(&self.0.cluster)
+        .fetch_optional(pool(ctx))
+        .await
+        .map_err(db_error("node"))?;
+        Ok(node.map(|node| Node { node, uptime: None }))
+    }
+}
+
+pub struct Transaction(ApiNodeTransaction);
+
+#[Object(name = "NodeTransaction")]
+impl Transaction {
+    async fn signature(&self) -> &str {
+        &self.0.signature
+    }
+
+    async fn slot(&self) -> BigInt {
+        BigInt(self.0.slot)
+    }
+
+    async fn block_time(&self) -> Option<DateTime<Utc>> {
+        self.0.block_time
+    }
+
+    async fn fee_payer(&self) -> &str {
+        &self.0.fee_payer
+    }
+
+    async fn signers(&self) -> &[String] {
+        &self.0.signers
+    }
+
+    async fn instructions(&self) -> &[String] {
+        &self.0.instructions
+    }
+
+    async fn succeeded(&self) -> bool {
+        self.0.succeeded
+    }
+
+    async fn error(&self) -> Option<&str> {
+        self.0.error.as_deref()
+    }
+}
+
+pub struct Stats {
+    stats: ApiStats,
+    snapshots: Vec<ApiSnapshot>,
+    program_ids: Vec<String>,
+    paused: bool,
+}
+
+#[Object]
+impl Stats {
+    async fn program_ids(&self) -> &[String] {
+        &self.program_ids
+    }
+
+    async fn total_nodes(&self) -> BigInt {
+        BigInt(self.stats.total_nodes)
+    }
+
+    async fn last_synced_at(&self) -> Option<DateTime<Utc>> {
+        self.stats.last_synced_at
+    }
+
+    async fn last_indexed_slot(&self) -> Option<BigInt> {
+        self.stats.last_indexed_slot.map(BigInt)
+    }
+
+    async fn snapshots(&self) -> Vec<Snapshot<'_>> {
+        self.snapshots.iter().map(Snapshot).collect()
+    }
+
+    async fn paused(&self) -> bool {
+        self.paused
+    }
+}
+
+pub struct Snapshot<'a>(&'a ApiSnapshot);
+
+#[Object]
+impl Snapshot<'_> {
+    async fn program_id(&self) -> &str {
+        &self.0.program_id
+    }
+
+    async fn snapshot_slot(&self) -> BigInt {
+        BigInt(self.0.snapshot_slot)
+    }
+
+    async fn account_count(&self) -> BigInt {
+        BigInt(self.0.account_count)
+    }
+
+    async fn synced_at(&self) -> DateTime<Utc> {
+        self.0.synced_at
+    }
+}
+
+pub struct NodeChange(SequencedEvent);
+
+impl NodeChange {
+    fn change_type(&self) -> NodeChangeType {
+        match &self.0.event {
+            NodeEvent::Added { .. } => NodeChangeType::Added,
+            NodeEvent::Updated { .. } => NodeChangeType::Updated,
+            NodeEvent::Removed { .. } => NodeChangeType::Removed,
+        }
+    }
+
+    fn pubkey(&self) -> &String {
+        match &self.0.event {
+            NodeEvent::Added { node } | NodeEvent::Updated { node } => &node.pubkey,
+            NodeEvent::Removed { pubkey, .. } => pubkey,
+        }
+    }
+
+    fn program_id(&self) -> Option<&str> {
+        match &self.0.event {
+            NodeEvent::Added { node } | NodeEvent::Updated { node } => node.program_id.as_deref(),
+            NodeEvent::Removed { .. } => None,
+        }
+    }
+}
+
+#[Object(name = "NodeChange")]
+impl NodeChange {
+    /// Event id, as sent on /ws and /events.
+    async fn id(&self) -> BigInt {
+        BigInt(self.0.id as i64)
+    }
+
+    #[graphql(name = "type")]
+    async fn kind(&self) -> NodeChangeType {
+        self.change_type()
+    }
+
+    #[graphql(name = "pubkey")]
+    async fn changed_pubkey(&self) -> &str {
+        self.pubkey()
+    }
+
+    /// `null` for REMOVED.
+    async fn node(&self) -> Option<Node> {
+        match &self.0.event {
+            NodeEvent::Added { node } | NodeEvent::Updated { node } => Some(Node { node: node.clone(), uptime: None }),
+            NodeEvent::Removed { .. } => None,
+        }
+    }
+}
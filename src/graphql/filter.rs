@@ -0,0 +1,345 @@
+//! GraphQL filter inputs and their translation into SQL `WHERE` conditions. Every condition
+//! is `TRUE` or `FALSE`, never `NULL`, so `not` always selects the complement.
+
+use async_graphql::{Enum, InputObject};
+use chrono::{DateTime, Utc};
+use sqlx::{Encode, Postgres, QueryBuilder, Type};
+
+use super::BigInt;
+use crate::dns;
+use crate::liveness::LivenessStatus;
+
+/// Conditions on a text column.
+#[derive(InputObject, Default, Debug)]
+pub struct StringFilter {
+    eq: Option<String>,
+    ne: Option<String>,
+    #[graphql(name = "in")]
+    one_of: Option<Vec<String>>,
+    not_in: Option<Vec<String>>,
+    contains: Option<String>,
+    starts_with: Option<String>,
+    ends_with: Option<String>,
+    is_null: Option<bool>,
+}
+
+/// Conditions on a 64-bit integer column.
+#[derive(InputObject, Default, Debug)]
+pub struct BigIntFilter {
+    eq: Option<BigInt>,
+    ne: Option<BigInt>,
+    gt: Option<BigInt>,
+    gte: Option<BigInt>,
+    lt: Option<BigInt>,
+    lte: Option<BigInt>,
+    #[graphql(name = "in")]
+    one_of: Option<Vec<BigInt>>,
+    is_null: Option<bool>,
+}
+
+/// Conditions on a timestamp column; values are RFC 3339 strings.
+#[derive(InputObject, Default, Debug)]
+pub struct DateTimeFilter {
+    eq: Option<DateTime<Utc>>,
+    gt: Option<DateTime<Utc>>,
+    gte: Option<DateTime<Utc>>,
+    lt: Option<DateTime<Utc>>,
+    lte: Option<DateTime<Utc>>,
+    is_null: Option<bool>,
+}
+
+/// `LivenessStatus` as a GraphQL enum value.
+#[derive(Enum, Clone, Copy, PartialEq, Eq, Debug)]
+#[graphql(name = "LivenessStatus")]
+pub enum StatusInput {
+    Online,
+    Offline,
+    Unknown,
+}
+
+impl From<StatusInput> for LivenessStatus {
+    fn from(status: StatusInput) -> Self {
+        match status {
+            StatusInput::Online => Self::Online,
+            StatusInput::Offline => Self::Offline,
+            StatusInput::Unknown => Self::Unknown,
+        }
+    }
+}
+
+/// `Query.nodes` filter. All given conditions must hold.
+#[derive(InputObject, Default, Debug)]
+pub struct NodeFilter {
+    and: Option<Vec<NodeFilter>>,
+    or: Option<Vec<NodeFilter>>,
+    not: Option<Box<NodeFilter>>,
+    pubkey: Option<StringFilter>,
+    authority: Option<StringFilter>,
+    uri: Option<StringFilter>,
+    program_id: Option<StringFilter>,
+    cluster: Option<StringFilter>,
+    first_seen_at: Option<DateTimeFilter>,
+    updated_at: Option<DateTimeFilter>,
+    last_seen_slot: Option<BigIntFilter>,
+    deleted_at: Option<DateTimeFilter>,
+    status: Option<StatusInput>,
+    finalized: Option<bool>,
+    lamports: Option<BigIntFilter>,
+    rent_exempt: Option<bool>,
+    dns_ok: Option<bool>,
+}
+
+/// `Query.history` filter. All given conditions must hold.
+#[derive(InputObject, Default, Debug)]
+pub struct HistoryFilter {
+    and: Option<Vec<HistoryFilter>>,
+    or: Option<Vec<HistoryFilter>>,
+    not: Option<Box<HistoryFilter>>,
+    pubkey: Option<StringFilter>,
+    change_type: Option<StringFilter>,
+    old_authority: Option<StringFilter>,
+    new_authority: Option<StringFilter>,
+    old_uri: Option<StringFilter>,
+    new_uri: Option<StringFilter>,
+    slot: Option<BigIntFilter>,
+    changed_at: Option<DateTimeFilter>,
+}
+
+/// A filter that can be appended to a query as one parenthesised condition.
+pub trait SqlFilter {
+    fn push_condition(&self, query: &mut QueryBuilder<'_, Postgres>);
+}
+
+impl SqlFilter for NodeFilter {
+    fn push_condition(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        let mut conditions = Conditions::new(query);
+        conditions.strings("pubkey", &self.pubkey);
+        conditions.strings("authority", &self.authority);
+        conditions.strings("uri", &self.uri);
+        conditions.strings("program_id", &self.program_id);
+        conditions.strings("cluster", &self.cluster);
+        conditions.timestamps("first_seen_at", &self.first_seen_at);
+        conditions.timestamps("updated_at", &self.updated_at);
+        conditions.integers("last_seen_slot", &self.last_seen_slot);
+        conditions.timestamps("deleted_at", &self.deleted_at);
+        if let Some(status) = self.status {
+            conditions.next().push(LivenessStatus::from(status).condition());
+        }
+        if let Some(finalized) = self.finalized {
+            conditions.next().push(if finalized { "finalized" } else { "NOT finalized" });
+        }
+        conditions.integers("lamports", &self.lamports);
+        if let Some(rent_exempt) = self.rent_exempt {
+            conditions.next().push(if rent_exempt { "rent_exempt" } else { "NOT rent_exempt" });
+        }
+        if let Some(dns_ok) = self.dns_ok {
+            conditions.next().push(dns::condition(dns_ok));
+        }
+        conditions.combine(&self.and, &self.or, &self.not);
+        conditions.finish();
+    }
+}
+
+impl SqlFilter for HistoryFilter {
+    fn push_condition(&self, query: &mut QueryBuilder<'_, Postgres>) {
+        let mut conditions = Conditions::new(query);
+        conditions.strings("pubkey", &self.pubkey);
+        conditions.strings("change_type", &self.change_type);
+        conditions.strings("old_authority", &self.old_authority);
+        conditions.strings("new_authority", &self.new_authority);
+        conditions.strings("old_uri", &self.old_uri);
+        conditions.strings("new_uri", &self.new_uri);
+        conditions.integers("slot", &self.slot);
+        conditions.timestamps("changed_at", &self.changed_at);
+        conditions.combine(&self.and, &self.or, &self.not);
+        conditions.finish();
+    }
+}
+
+/// Joins conditions with `AND` inside one pair of parentheses.
+struct Conditions<'b, 'q> {
+    query: &'b mut QueryBuilder<'q, Postgres>,
+    empty: bool,
+}
+
+impl<'b, 'q> Conditions<'b, 'q> {
+    fn new(query: &'b mut QueryBuilder<'q, Postgres>) -> Self {
+        query.push("(");
+        Self { query, empty: true }
+    }
+
+    /// Starts another condition.
+    fn next(&mut self) -> &mut QueryBuilder<'q, Postgres> {
+        if !self.empty {
+            self.query.push(" AND ");
+        }
+        self.empty = false;
+        self.query
+    }
+
+    fn finish(self) {
+        if self.empty {
+            self.query.push("TRUE");
+        }
+        self.query.push(")");
+    }
+
+    /// `COALESCE(<column> <operator> $n, FALSE)`, when `value` is given.
+    fn compare<T>(&mut self, column: &str, operator: &str, value: &Option<T>)
+    where
+        T: for<'e> Encode<'e, Postgres> + Type<Postgres> + Send + Clone + 'q,
+    {
+        if let Some(value) = value {
+            let query = self.next();
+            query.push("COALESCE(").push(column).push(operator).push_bind(value.clone()).push(", FALSE)");
+        }
+    }
+
+    fn is_null(&mut self, column: &str, is_null: Option<bool>) {
+        if let Some(is_null) = is_null {
+            let check = if is_null { " IS NULL" } else { " IS NOT NULL" };
+            self.next().push(column).push(check);
+        }
+    }
+
+    fn strings(&mut self, column: &str, filter: &Option<StringFilter>) {
+        let Some(filter) = filter else { return };
+        self.compare(column, " = ", &filter.eq);
+        if let Some(value) = &filter.ne {
+            self.next().push(column).push(" IS DISTINCT FROM ").push_bind(value.clone());
+        }
+        if let Some(values) = &filter.one_of {
+            self.next().push("COALESCE(").push(column).push(" = ANY(").push_bind(values.clone()).push("), FALSE)");
+        }
+        if let Some(values) = &filter.not_in {
+            self.next().push("COALESCE(").push(column).push(" <> ALL(").push_bind(values.clone()).push("), TRUE)");
+        }
+        if let Some(value) = &filter.contains {
+            let query = self.next();
+            query.push("COALESCE(strpos(").push(column).push(", ").push_bind(value.clone()).push(") > 0, FALSE)");
+        }
+        if let Some(value) = &filter.starts_with {
+            let query = self.next();
+            query.push("COALESCE(starts_with(").push(column).push(", ").push_bind(value.clone()).push("), FALSE)");
+        }
+        if let Some(value) = &filter.ends_with {
+            let query = self.next();
+            query.push("COALESCE(starts_with(reverse(").push(column).push("), reverse(");
+            query.push_bind(value.clone()).push(")), FALSE)");
+        }
+        self.is_null(column, filter.is_null);
+    }
+
+    fn integers(&mut self, column: &str, filter: &Option<BigIntFilter>) {
+        let Some(filter) = filter else { return };
+        self.compare(column, " = ", &filter.eq.map(i64::from));
+        if let Some(value) = filter.ne {
+            self.next().push(column).push(" IS DISTINCT FROM ").push_bind(i64::from(value));
+        }
+        self.compare(column, " > ", &filter.gt.map(i64::from));
+        self.compare(column, " >= ", &filter.gte.map(i64::from));
+        self.compare(column, " < ", &filter.lt.map(i64::from));
+        self.compare(column, " <= ", &filter.lte.map(i64::from));
+        if let Some(values) = &filter.one_of {
+            let values: Vec<i64> = values.iter().copied().map(i64::from).collect();
+            self.next().push("COALESCE(").push(column).push(" = ANY(").push_bind(values).push("), FALSE)");
+        }
+        self.is_null(column, filter.is_null);
+    }
+
+    fn timestamps(&mut self, column: &str, filter: &Option<DateTimeFilter>) {
+        let Some(filter) = filter else { return };
+        self.compare(column, " = ", &filter.eq);
+        self.compare(column, " > ", &filter.gt);
+        self.compare(column, " >= ", &filter.gte);
+        self.compare(column, " < ", &filter.lt);
+        self.compare(column, " <= ", &filter.lte);
+        self.is_null(column, filter.is_null);
+    }
+
+    fn combine<F: SqlFilter>(&mut self, and: &Option<Vec<F>>, or: &Option<Vec<F>>, not: &Option<Box<F>>) {
+        for filter in and.iter().flatten() {
+            filter.push_condition(self.next());
+        }
+        if let Some(filters) = or {
+            let query = self.next();
+            query.push("(");
+            for (index, filter) in filters.iter().enumerate() {
+                if index > 0 {
+                    query.push(" OR ");
+                }
+                filter.push_condition(query);
+            }
+            // An empty `or` matches nothing, like an empty `in`.
+            if filters.is_empty() {
+                query.push("FALSE");
+            }
+            query.push(")");
+        }
+        if let Some(filter) = not {
+            let query = self.next();
+            query.push("NOT ");
+            filter.push_condition(query);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_graphql::{value, InputType, Value};
+
+    use super::*;
+
+    /// The SQL `filter`, given as a GraphQL input value, appends to a query.
+    fn sql<F: InputType + SqlFilter>(filter: Value) -> String {
+        let filter = F::parse(Some(filter)).map_err(|e| e.into_server_error(Default::default())).unwrap();
+        let mut query = QueryBuilder::new("");
+        filter.push_condition(&mut query);
+        query.sql().to_string()
+    }
+
+    #[test]
+    fn fields_are_joined_with_and() {
+        assert_eq!(sql::<NodeFilter>(value!({})), "(TRUE)");
+        assert_eq!(
+            sql::<NodeFilter>(value!({
+                "pubkey": { "eq": "a", "ne": "b" },
+                "lastSeenSlot": { "gte": 5, "in": [1, 2] },
+            })),
+            "(COALESCE(pubkey = $1, FALSE) AND pubkey IS DISTINCT FROM $2 AND COALESCE(last_seen_slot >= $3, FALSE) \
+             AND COALESCE(last_seen_slot = ANY($4), FALSE))"
+        );
+        assert_eq!(
+            sql::<HistoryFilter>(value!({
+                "newUri": { "endsWith": ".com", "isNull": false },
+                "changeType": { "notIn": [] },
+            })),
+            "(COALESCE(change_type <> ALL($1), TRUE) AND COALESCE(starts_with(reverse(new_uri), reverse($2)), FALSE) \
+             AND new_uri IS NOT NULL)"
+        );
+    }
+
+    #[test]
+    fn combinators_nest_their_filters() {
+        assert_eq!(
+            sql::<NodeFilter>(value!({
+                "finalized": true,
+                "and": [{ "authority": { "in": ["x", "y"] } }],
+                "or": [{ "uri": { "startsWith": "https" } }, {}],
+                "not": { "rentExempt": false },
+            })),
+            "(finalized AND (COALESCE(authority = ANY($1), FALSE)) \
+             AND ((COALESCE(starts_with(uri, $2), FALSE)) OR (TRUE)) AND NOT (NOT rent_exempt))"
+        );
+        // An empty `or` matches nothing.
+        assert_eq!(sql::<NodeFilter>(value!({ "or": [] })), "((FALSE))");
+    }
+
+    #[test]
+    fn malformed_filters_are_rejected() {
+        assert!(NodeFilter::parse(Some(value!({ "lastSeenSlot": { "eq": "5" } }))).is_err());
+        assert!(NodeFilter::parse(Some(value!({ "lastSeenSlot": { "eq": 1.5 } }))).is_err());
+        assert!(NodeFilter::parse(Some(value!({ "status": "ASLEEP" }))).is_err());
+        assert!(NodeFilter::parse(Some(value!({ "updatedAt": { "gt": "yesterday" } }))).is_err());
+    }
+}
@@ -0,0 +1,308 @@
+//! gRPC API (`indexer.v1.Indexer`, see `proto/indexer.proto`) for machine consumers. It serves
+//! the same data as the REST API through the same queries, and applies the same API key and
+//! per-client rate limit rules; keys go in the `x-api-key` metadata entry.
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use solana_sdk::pubkey::Pubkey;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status};
+use tracing::{debug, error, info};
+
+use crate::api::{self, ApiSnapshot, AppState, NodeFilter, NodeWithUptime, PageParams, SortParams};
+use crate::auth::{self, API_KEY_HEADER};
+use crate::config;
+use crate::events::{NodeEvent, SequencedEvent};
+use crate::liveness::{LivenessStatus, NodeUptime};
+use crate::store::ApiNode;
+use crate::AppError;
+
+use self::proto::indexer_server::{Indexer, IndexerServer};
+
+/// Messages and service traits generated from `proto/indexer.proto`.
+pub mod proto {
+    tonic::include_proto!("indexer.v1");
+}
+
+// --- Changes buffered per WatchChanges stream before the sender waits for the client ---
+const WATCH_BUFFER: usize = 64;
+
+/// Where the gRPC API listens (`GRPC_PORT`); off when unset.
+#[derive(Clone, Copy, Debug)]
+pub struct GrpcConfig {
+    pub port: u16,
+}
+
+impl GrpcConfig {
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Ok(value) = config::var("GRPC_PORT") else {
+            return Ok(None);
+        };
+        let port = value
+            .parse::<u16>()
+            .map_err(|_| format!("Invalid {} '{}': expected a port number", config::source("GRPC_PORT"), value))?;
+        Ok(Some(Self { port }))
+    }
+}
+
+/// Serves the gRPC API over `state` on `listener` until the server stops.
+pub async fn serve(state: AppState, listener: TcpListener) -> Result<(), AppError> {
+    info!(addr = %listener.local_addr()?, "gRPC server listening");
+    tonic::transport::Server::builder()
+        .add_service(IndexerServer::new(GrpcApi { state }))
+        .serve_with_incoming(TcpListenerStream::new(listener))
+        .await?;
+    Ok(())
+}
+
+struct GrpcApi {
+    state: AppState,
+}
+
+impl GrpcApi {
+    /// Resolves and meters the call's API key like the HTTP middleware does, then takes a
+    /// token from the caller's rate limit bucket. As over HTTP, a key isn't looked up while
+    /// the caller's address is over its failed authentication budget.
+    async fn admit<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let metadata = request.metadata();
+        let limiter = self.state.rate_limiter.as_deref();
+        // A proxy appends to the last of several headers.
+        let forwarded_for =
+            metadata.get_all("x-forwarded-for").iter().next_back().and_then(|value| value.to_str().ok());
+        let too_many_requests = |retry_after_secs: u64| {
+            Status::resource_exhausted(format!("Too many requests; retry in {}s", retry_after_secs.max(1)))
+        };
+        let key = match metadata.get(API_KEY_HEADER).map(|value| value.to_str()) {
+            None if self.state.auth.require_key => return Err(Status::unauthenticated("An API key is required")),
+            None => None,
+            Some(key) => {
+                if let Some(limiter) = limiter {
+                    limiter.check_failed_auth(forwarded_for, request.remote_addr()).map_err(too_many_requests)?;
+                }
+                let found = match key {
+                    Ok(key) => auth::lookup_api_key(&self.state.pool, key).await.map_err(|e| {
+                        error!(error = %e, "Database query failed");
+                        Status::internal("Failed to check API key")
+                    })?,
+                    Err(_) => None,
+                };
+                if found.is_none() {
+                    if let Some(limiter) = limiter {
+                        limiter.record_failed_auth(forwarded_for, request.remote_addr());
+                    }
+                    return Err(Status::unauthenticated("Unknown or revoked API key"));
+                }
+                found
+            }
+        };
+        if let Some(limiter) = limiter {
+            limiter.acquire(key.as_ref(), forwarded_for, request.remote_addr()).map_err(too_many_requests)?;
+        }
+        Ok(())
+    }
+}
+
+fn db_error(e: sqlx::Error) -> Status {
+    error!(error = %e, "Database query failed");
+    Status::internal("Failed to query the database")
+}
+
+fn timestamp(at: Option<DateTime<Utc>>) -> Option<prost_types::Timestamp> {
+    at.map(|at| prost_types::Timestamp { seconds: at.timestamp(), nanos: at.timestamp_subsec_nanos() as i32 })
+}
+
+fn node_message(node: ApiNode, uptime: Option<NodeUptime>) -> proto::Node {
+    proto::Node {
+        pubkey: node.pubkey,
+        authority: node.authority,
+        uri: node.uri,
+        program_id: node.program_id,
+        cluster: node.cluster,
+        first_seen_at: timestamp(node.first_seen_at),
+        updated_at: timestamp(node.updated_at),
+        last_seen_slot: node.last_seen_slot,
+        deleted_at: timestamp(node.deleted_at),
+        name: node.name,
+        registered_at: timestamp(node.registered_at),
+        layout_version: node.layout_version.into(),
+        finalized: node.finalized,
+        uri_valid: node.uri_valid,
+        pda_verified: node.pda_verified,
+        lamports: node.lamports,
+        rent_exempt: node.rent_exempt,
+        uptime: uptime.map(|uptime| proto::Uptime {
+            uptime_24h: uptime.uptime_24h,
+            uptime_7d: uptime.uptime_7d,
+            uptime_30d: uptime.uptime_30d,
+        }),
+    }
+}
+
+fn node_with_uptime(NodeWithUptime { node, uptime, .. }: NodeWithUptime) -> proto::Node {
+    node_message(node, Some(uptime))
+}
+
+fn snapshot_message(snapshot: ApiSnapshot) -> proto::Snapshot {
+    proto::Snapshot {
+        program_id: snapshot.program_id,
+        snapshot_slot: snapshot.snapshot_slot,
+        account_count: snapshot.account_count,
+        synced_at: timestamp(Some(snapshot.synced_at)),
+    }
+}
+
+fn change_message(change: SequencedEvent) -> proto::NodeChange {
+    let (change_type, pubkey, node) = match change.event {
+        NodeEvent::Added { node } => (proto::ChangeType::Added, node.pubkey.clone(), Some(node)),
+        NodeEvent::Updated { node } => (proto::ChangeType::Updated, node.pubkey.clone(), Some(node)),
+        NodeEvent::Removed { pubkey, .. } => (proto::ChangeType::Removed, pubkey, None),
+    };
+    proto::NodeChange {
+        id: change.id,
+        r#type: change_type.into(),
+        pubkey,
+        node: node.map(|node| node_message(node, None)),
+    }
+}
+
+/// Whether `event` passes a WatchChanges request's filters.
+fn watched(request: &proto::WatchChangesRequest, event: &NodeEvent) -> bool {
+    match event {
+        NodeEvent::Added { node } | NodeEvent::Updated { node } => {
+            (request.pubkeys.is_empty() || request.pubkeys.contains(&node.pubkey))
+                && request.program_id.as_ref().is_none_or(|program| node.program_id.as_ref() == Some(program))
+        }
+        // Removals don't say which program the node belonged to, so they always pass that filter.
+        NodeEvent::Removed { pubkey, .. } => request.pubkeys.is_empty() || request.pubkeys.contains(pubkey),
+    }
+}
+
+#[tonic::async_trait]
+impl Indexer for GrpcApi {
+    async fn list_nodes(
+        &self,
+        request: Request<proto::ListNodesRequest>,
+    ) -> Result<Response<proto::ListNodesResponse>, Status> {
+        self.admit(&request).await?;
+        let request = request.into_inner();
+        let (limit, offset) = PageParams { limit: request.limit, offset: Some(request.offset) }.resolve();
+        debug!(limit, offset, "=> ListNodes - Fetching nodes from database");
+
+        let status = match request.status() {
+            proto::LivenessStatus::Unspecified => None,
+            proto::LivenessStatus::Online => Some(LivenessStatus::Online),
+            proto::LivenessStatus::Offline => Some(LivenessStatus::Offline),
+            proto::LivenessStatus::Unknown => Some(LivenessStatus::Unknown),
+        };
+        let filter = NodeFilter {
+            program: request.program_id,
+            cluster: request.cluster,
+            include_deleted: Some(request.include_deleted),
+            status,
+            finalized_only: Some(request.finalized_only),
+            include_invalid: Some(request.include_invalid),
+            include_unverified: Some(request.include_unverified),
+            rent_exempt: request.rent_exempt,
+            dns_ok: request.dns_ok,
+        };
+        let sort = SortParams::default();
+        let (nodes, total) = api::load_nodes(&self.state.read_pool.0, &self.state.counts, &filter, &sort, limit, offset)
+            .await
+            .map_err(db_error)?;
+        let next_offset = (offset + (nodes.len() as i64) < total).then(|| offset + nodes.len() as i64);
+
+        debug!(returned = nodes.len(), total, "<= ListNodes - Responding with nodes");
+        let nodes = nodes.into_iter().map(node_with_uptime).collect();
+        Ok(Response::new(proto::ListNodesResponse { nodes, total, limit, offset, next_offset }))
+    }
+
+    async fn get_node(&self, request: Request<proto::GetNodeRequest>) -> Result<Response<proto::Node>, Status> {
+        self.admit(&request).await?;
+        let proto::GetNodeRequest { pubkey, include_deleted } = request.into_inner();
+        debug!(%pubkey, "=> GetNode - Looking up node");
+
+        if Pubkey::from_str(&pubkey).is_err() {
+            return Err(Status::invalid_argument(format!("'{}' is not a valid base58 pubkey", pubkey)));
+        }
+        match api::load_node(&self.state.read_pool.0, &pubkey, None, include_deleted).await.map_err(db_error)? {
+            Some(node) => {
+                debug!(%pubkey, "<= GetNode - Found");
+                Ok(Response::new(node_with_uptime(node)))
+            }
+            None => {
+                debug!(%pubkey, "<= GetNode - Not indexed");
+                Err(Status::not_found(format!("No node indexed with pubkey {}", pubkey)))
+            }
+        }
+    }
+
+    async fn get_stats(&self, request: Request<proto::GetStatsRequest>) -> Result<Response<proto::Stats>, Status> {
+        self.admit(&request).await?;
+        debug!("=> GetStats - Fetching network stats");
+
+        let program_ids: Vec<String> = self.state.program_ids.iter().map(Pubkey::to_string).collect();
+        let (stats, snapshots) = api::load_stats(&self.state.read_pool.0, &program_ids).await.map_err(db_error)?;
+
+        debug!(total_nodes = stats.total_nodes, "<= GetStats - Responding with stats");
+        Ok(Response::new(proto::Stats {
+            program_ids,
+            total_nodes: stats.total_nodes,
+            last_synced_at: timestamp(stats.last_synced_at),
+            last_indexed_slot: stats.last_indexed_slot,
+            snapshots: snapshots.into_iter().map(snapshot_message).collect(),
+            paused: self.state.pause.is_paused(),
+        }))
+    }
+
+    type WatchChangesStream = ReceiverStream<Result<proto::NodeChange, Status>>;
+
+    async fn watch_changes(
+        &self,
+        request: Request<proto::WatchChangesRequest>,
+    ) -> Result<Response<Self::WatchChangesStream>, Status> {
+        self.admit(&request).await?;
+        let request = request.into_inner();
+        debug!(pubkeys = request.pubkeys.len(), after_id = ?request.after_id, "=> WatchChanges - Subscribing");
+
+        let (backlog, mut events) = match request.after_id {
+            Some(after_id) => {
+                let (backlog, complete, events) = self.state.events.resume_after(after_id);
+                if !complete {
+                    let message = "Changes after that id are no longer buffered; refetch and watch again";
+                    return Err(Status::data_loss(message));
+                }
+                (backlog, events)
+            }
+            None => (Vec::new(), self.state.events.subscribe()),
+        };
+
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            for event in backlog {
+                if watched(&request, &event.event) && tx.send(Ok(change_message(event))).await.is_err() {
+                    return;
+                }
+            }
+            loop {
+                let change = match events.recv().await {
+                    Ok(event) if watched(&request, &event.event) => Ok(change_message(event)),
+                    Ok(_) => continue,
+                    // A slow client missed some changes; end the stream so it refetches.
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        let message = format!("Missed {} changes; refetch and watch again", skipped);
+                        Err(Status::data_loss(message))
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                let failed = change.is_err();
+                if tx.send(change).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
@@ -0,0 +1,524 @@
+//! Anchor IDL loading and generic account decoding.
+//!
+//! With an IDL loaded, every account of the program is decoded into JSON by walking the
+//! type definitions in the IDL rather than a hand-written layout, so a program upgrade that
+//! changes an account's fields only needs a new IDL, not a new indexer build.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use flate2::read::ZlibDecoder;
+use serde::Deserialize;
+use serde_json::{Map, Number, Value};
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use tracing::{info, warn};
+
+use crate::cluster::ProgramKey;
+use crate::config;
+use crate::decode::{account_discriminator, read_discriminator, ByteReader, DecodeError};
+use crate::rpc::SolanaRpc;
+use crate::store::{data_hash, AccountRecord};
+use crate::AppError;
+
+// --- Seed Anchor derives a program's on-chain IDL account address from ---
+const IDL_ACCOUNT_SEED: &str = "anchor:idl";
+
+// --- Type nesting limit, so a self-referential IDL type can't recurse forever ---
+const MAX_TYPE_DEPTH: usize = 32;
+
+/// Where each program's Anchor IDL is read from (`IDL_SOURCE`).
+#[derive(Clone, Debug)]
+pub enum IdlSource {
+    /// An IDL JSON file, used for every configured program.
+    File(PathBuf),
+    /// The IDL account `anchor idl init` writes for the program.
+    OnChain,
+}
+
+impl IdlSource {
+    /// `IDL_SOURCE=onchain` or a path to an IDL JSON file. Unset disables generic decoding.
+    pub fn from_env() -> Option<Self> {
+        match config::var("IDL_SOURCE") {
+            Ok(value) if value == "onchain" => Some(IdlSource::OnChain),
+            Ok(value) if !value.is_empty() => Some(IdlSource::File(value.into())),
+            _ => None,
+        }
+    }
+}
+
+/// The parts of an Anchor IDL needed to decode accounts and events and name instructions. Both
+/// the legacy layout, where each account and event carries its type inline, and the Anchor 0.30+
+/// layout with explicit discriminators and a shared `types` section are accepted.
+#[derive(Deserialize)]
+pub struct Idl {
+    #[serde(default)]
+    instructions: Vec<IdlInstruction>,
+    #[serde(default)]
+    accounts: Vec<IdlAccount>,
+    #[serde(default)]
+    events: Vec<IdlEvent>,
+    #[serde(default)]
+    types: Vec<IdlTypeDef>,
+}
+
+#[derive(Deserialize)]
+struct IdlInstruction {
+    name: String,
+    #[serde(default)]
+    discriminator: Option<[u8; 8]>,
+}
+
+#[derive(Deserialize)]
+struct IdlAccount {
+    name: String,
+    #[serde(default)]
+    discriminator: Option<[u8; 8]>,
+    #[serde(rename = "type", default)]
+    ty: Option<IdlTypeDefTy>,
+}
+
+#[derive(Deserialize)]
+struct IdlEvent {
+    name: String,
+    #[serde(default)]
+    discriminator: Option<[u8; 8]>,
+    /// Legacy IDLs list the event's fields here instead of in `types`.
+    #[serde(default)]
+    fields: Option<Vec<IdlField>>,
+}
+
+#[derive(Deserialize)]
+struct IdlTypeDef {
+    name: String,
+    #[serde(rename = "type")]
+    ty: IdlTypeDefTy,
+    /// `borsh` unless the type is zero-copy (`bytemuck`), which isn't supported.
+    #[serde(default)]
+    serialization: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum IdlTypeDefTy {
+    Struct {
+        #[serde(default)]
+        fields: Option<IdlFields>,
+    },
+    Enum {
+        variants: Vec<IdlEnumVariant>,
+    },
+    Type {
+        alias: IdlType,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IdlFields {
+    Named(Vec<IdlField>),
+    Tuple(Vec<IdlType>),
+}
+
+#[derive(Deserialize)]
+struct IdlField {
+    name: String,
+    #[serde(rename = "type")]
+    ty: IdlType,
+}
+
+#[derive(Deserialize)]
+struct IdlEnumVariant {
+    name: String,
+    #[serde(default)]
+    fields: Option<IdlFields>,
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "Value")]
+enum IdlType {
+    Bool,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+    U64,
+    I64,
+    F64,
+    U128,
+    I128,
+    Bytes,
+    String,
+    Pubkey,
+    Vec(Box<IdlType>),
+    Option(Box<IdlType>),
+    COption(Box<IdlType>),
+    Array(Box<IdlType>, usize),
+    Defined(String),
+    /// A type this decoder can't read (generics, u256, ...). Only accounts that actually
+    /// contain one fail to decode; the rest of the IDL stays usable.
+    Unsupported(String),
+}
+
+impl TryFrom<Value> for IdlType {
+    type Error = String;
+
+    fn try_from(value: Value) -> Result<Self, String> {
+        let boxed = |value: Value| IdlType::try_from(value).map(Box::new);
+        let ty = match value {
+            Value::String(name) => match name.as_str() {
+                "bool" => IdlType::Bool,
+                "u8" => IdlType::U8,
+                "i8" => IdlType::I8,
+                "u16" => IdlType::U16,
+                "i16" => IdlType::I16,
+                "u32" => IdlType::U32,
+                "i32" => IdlType::I32,
+                "f32" => IdlType::F32,
+                "u64" => IdlType::U64,
+                "i64" => IdlType::I64,
+                "f64" => IdlType::F64,
+                "u128" => IdlType::U128,
+                "i128" => IdlType::I128,
+                "bytes" => IdlType::Bytes,
+                "string" => IdlType::String,
+                "publicKey" | "pubkey" => IdlType::Pubkey,
+                _ => IdlType::Unsupported(name),
+            },
+            Value::Object(object) if object.len() == 1 => {
+                let Some((key, value)) = object.into_iter().next() else {
+                    return Err("empty IDL type".to_string());
+                };
+                match (key.as_str(), value) {
+                    ("vec", inner) => IdlType::Vec(boxed(inner)?),
+                    ("option", inner) => IdlType::Option(boxed(inner)?),
+                    ("coption", inner) => IdlType::COption(boxed(inner)?),
+                    ("array", Value::Array(mut parts)) if parts.len() == 2 => match parts[1].as_u64() {
+                        Some(len) => IdlType::Array(boxed(parts.swap_remove(0))?, len as usize),
+                        None => IdlType::Unsupported(format!("array of length {}", parts[1])),
+                    },
+                    ("defined", Value::String(name)) => IdlType::Defined(name),
+                    ("defined", Value::Object(defined)) => {
+                        let generic = defined.get("generics").and_then(Value::as_array).is_some_and(|g| !g.is_empty());
+                        match defined.get("name").and_then(Value::as_str) {
+                            Some(name) if !generic => IdlType::Defined(name.to_string()),
+                            _ => IdlType::Unsupported(format!("defined {}", Value::Object(defined))),
+                        }
+                    }
+                    (key, value) => IdlType::Unsupported(format!("{} {}", key, value)),
+                }
+            }
+            other => return Err(format!("invalid IDL type {}", other)),
+        };
+        Ok(ty)
+    }
+}
+
+/// Decodes every account type an [`Idl`] describes into JSON.
+pub struct IdlDecoder {
+    instructions: Vec<([u8; 8], String)>,
+    accounts: Vec<([u8; 8], String)>,
+    events: Vec<([u8; 8], String)>,
+    types: HashMap<String, IdlTypeDef>,
+}
+
+impl IdlDecoder {
+    pub fn new(idl: Idl) -> Self {
+        let mut types: HashMap<String, IdlTypeDef> =
+            idl.types.into_iter().map(|def| (def.name.clone(), def)).collect();
+        let mut accounts = Vec::with_capacity(idl.accounts.len());
+        for account in idl.accounts {
+            // Legacy IDLs only carry the name; the discriminator is derived the usual Anchor way.
+            let discriminator = account.discriminator.unwrap_or_else(|| account_discriminator(&account.name));
+            if let Some(ty) = account.ty {
+                types.insert(account.name.clone(), IdlTypeDef { name: account.name.clone(), ty, serialization: None });
+            }
+            accounts.push((discriminator, account.name));
+        }
+        let mut events = Vec::with_capacity(idl.events.len());
+        for event in idl.events {
+            let discriminator = event.discriminator.unwrap_or_else(|| anchor_discriminator("event", &event.name));
+            if let Some(fields) = event.fields {
+                let ty = IdlTypeDefTy::Struct { fields: Some(IdlFields::Named(fields)) };
+                types.insert(event.name.clone(), IdlTypeDef { name: event.name.clone(), ty, serialization: None });
+            }
+            events.push((discriminator, event.name));
+        }
+        let instructions = idl
+            .instructions
+            .into_iter()
+            .map(|instruction| {
+                let discriminator =
+                    instruction.discriminator.unwrap_or_else(|| instruction_discriminator(&instruction.name));
+                (discriminator, instruction.name)
+            })
+            .collect();
+        Self { instructions, accounts, events, types }
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, AppError> {
+        let idl: Idl = serde_json::from_slice(json).map_err(|e| format!("Invalid Anchor IDL: {}", e))?;
+        Ok(Self::new(idl))
+    }
+
+    /// Discriminators and names of the account types the IDL declares.
+    pub fn account_types(&self) -> impl Iterator<Item = (&[u8; 8], &str)> {
+        self.accounts.iter().map(|(discriminator, name)| (discriminator, name.as_str()))
+    }
+
+    /// Name of the instruction whose discriminator `data` starts with.
+    pub fn instruction_name(&self, data: &[u8]) -> Option<&str> {
+        let discriminator = data.get(..8)?;
+        self.instructions
+            .iter()
+            .find(|(known, _)| known.as_slice() == discriminator)
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// Decodes an account into its IDL account type name and a JSON object of its fields.
+    pub fn decode(&self, data: &[u8]) -> Result<(&str, Value), DecodeError> {
+        // Anchor may pad the account past the struct, so trailing bytes are ignored.
+        self.decode_tagged(&self.accounts, data)
+    }
+
+    /// Decodes an `emit!`ted event payload into its IDL event name and fields.
+    pub fn decode_event(&self, data: &[u8]) -> Result<(&str, Value), DecodeError> {
+        self.decode_tagged(&self.events, data)
+    }
+
+    fn decode_tagged<'a>(
+        &'a self,
+        known: &'a [([u8; 8], String)],
+        data: &[u8],
+    ) -> Result<(&'a str, Value), DecodeError> {
+        let discriminator = read_discriminator(data)?;
+        let (_, name) = known
+            .iter()
+            .find(|(known, _)| *known == discriminator)
+            .ok_or(DecodeError::UnknownDiscriminator(discriminator))?;
+        let mut reader = ByteReader::new(&data[8..]);
+        let value = self.decode_defined(&mut reader, name, 0)?;
+        Ok((name, value))
+    }
+
+    fn decode_defined(&self, reader: &mut ByteReader<'_>, name: &str, depth: usize) -> Result<Value, DecodeError> {
+        let def = self
+            .types
+            .get(name)
+            .ok_or_else(|| DecodeError::Idl(format!("type {} is not defined", name)))?;
+        if let Some(serialization) = def.serialization.as_deref().filter(|serialization| *serialization != "borsh") {
+            return Err(DecodeError::Idl(format!("{} uses unsupported {} serialization", name, serialization)));
+        }
+        match &def.ty {
+            IdlTypeDefTy::Struct { fields } => self.decode_fields(reader, fields.as_ref(), depth),
+            IdlTypeDefTy::Enum { variants } => {
+                let tag = reader.take("enum variant", 1)?[0];
+                let variant = variants
+                    .get(tag as usize)
+                    .ok_or_else(|| DecodeError::Idl(format!("{} has no variant {}", name, tag)))?;
+                match &variant.fields {
+                    None => Ok(Value::String(variant.name.clone())),
+                    Some(fields) => {
+                        let fields = self.decode_fields(reader, Some(fields), depth)?;
+                        Ok(Value::Object(Map::from_iter([(variant.name.clone(), fields)])))
+                    }
+                }
+            }
+            IdlTypeDefTy::Type { alias } => self.decode_type(reader, alias, depth),
+        }
+    }
+
+    fn decode_fields(
+        &self,
+        reader: &mut ByteReader<'_>,
+        fields: Option<&IdlFields>,
+        depth: usize,
+    ) -> Result<Value, DecodeError> {
+        match fields {
+            None => Ok(Value::Object(Map::new())),
+            Some(IdlFields::Named(fields)) => {
+                let mut object = Map::with_capacity(fields.len());
+                for field in fields {
+                    object.insert(field.name.clone(), self.decode_type(reader, &field.ty, depth)?);
+                }
+                Ok(Value::Object(object))
+            }
+            Some(IdlFields::Tuple(types)) => types
+                .iter()
+                .map(|ty| self.decode_type(reader, ty, depth))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+        }
+    }
+
+    fn decode_type(&self, reader: &mut ByteReader<'_>, ty: &IdlType, depth: usize) -> Result<Value, DecodeError> {
+        if depth >= MAX_TYPE_DEPTH {
+            return Err(DecodeError::Idl("types nested too deeply".to_string()));
+        }
+        let depth = depth + 1;
+        let float = |value: f64| Number::from_f64(value).map_or(Value::Null, Value::Number);
+        let value = match ty {
+            IdlType::Bool => Value::Bool(reader.take("bool", 1)?[0] != 0),
+            IdlType::U8 => reader.take("u8", 1)?[0].into(),
+            IdlType::I8 => (reader.take("i8", 1)?[0] as i8).into(),
+            IdlType::U16 => u16::from_le_bytes(reader.array("u16")?).into(),
+            IdlType::I16 => i16::from_le_bytes(reader.array("i16")?).into(),
+            IdlType::U32 => reader.u32_le("u32")?.into(),
+            IdlType::I32 => i32::from_le_bytes(reader.array("i32")?).into(),
+            IdlType::F32 => float(f32::from_le_bytes(reader.array("f32")?) as f64),
+            IdlType::U64 => u64::from_le_bytes(reader.array("u64")?).into(),
+            IdlType::I64 => i64::from_le_bytes(reader.array("i64")?).into(),
+            IdlType::F64 => float(f64::from_le_bytes(reader.array("f64")?)),
+            // JSON numbers can't hold 128-bit integers losslessly.
+            IdlType::U128 => u128::from_le_bytes(reader.array("u128")?).to_string().into(),
+            IdlType::I128 => i128::from_le_bytes(reader.array("i128")?).to_string().into(),
+            IdlType::Bytes => {
+                let len = reader.u32_le("bytes")? as usize;
+                reader.take("bytes", len)?.to_vec().into()
+            }
+            IdlType::String => reader.string("string")?.into(),
+            IdlType::Pubkey => Pubkey::new_from_array(reader.array("pubkey")?).to_string().into(),
+            IdlType::Vec(inner) => {
+                let len = reader.u32_le("vec")? as usize;
+                // Every element takes at least a byte, so a longer length can only be corrupt.
+                if len > reader.remaining() {
+                    return Err(DecodeError::Truncated { field: "vec", needed: len, available: reader.remaining() });
+                }
+                let items = (0..len).map(|_| self.decode_type(reader, inner, depth));
+                Value::Array(items.collect::<Result<_, _>>()?)
+            }
+            IdlType::Option(inner) => match reader.take("option", 1)?[0] {
+                0 => Value::Null,
+                1 => self.decode_type(reader, inner, depth)?,
+                tag => return Err(DecodeError::Idl(format!("invalid option tag {}", tag))),
+            },
+            // COption is fixed-size: the value's bytes are present (zeroed) even when unset.
+            IdlType::COption(inner) => {
+                let tag = reader.u32_le("coption")?;
+                let value = self.decode_type(reader, inner, depth)?;
+                if tag == 0 { Value::Null } else { value }
+            }
+            IdlType::Array(inner, len) => {
+                let items = (0..*len).map(|_| self.decode_type(reader, inner, depth));
+                Value::Array(items.collect::<Result<_, _>>()?)
+            }
+            IdlType::Defined(name) => self.decode_defined(reader, name, depth)?,
+            IdlType::Unsupported(ty) => return Err(DecodeError::Idl(format!("unsupported type {}", ty))),
+        };
+        Ok(value)
+    }
+}
+
+/// The first 8 bytes of `sha256("<namespace>:<name>")`, as Anchor derives discriminators.
+fn anchor_discriminator(namespace: &str, name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("{}:{}", namespace, name).as_bytes());
+    let mut discriminator = [0u8; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+/// Anchor's instruction discriminator: the `global` namespace over the snake_case name.
+/// Legacy IDLs list instructions in camelCase, so the name is converted back first.
+fn instruction_discriminator(name: &str) -> [u8; 8] {
+    let mut snake = String::with_capacity(name.len() + 4);
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    anchor_discriminator("global", &snake)
+}
+
+/// Decodes `data` into an `accounts` row, if the IDL knows its type. Failures are only logged:
+/// the generic table is best-effort and never holds up node indexing.
+pub fn decode_account(idl: &IdlDecoder, pubkey: &str, data: &[u8]) -> Option<AccountRecord> {
+    match idl.decode(data) {
+        Ok((account_type, data)) => {
+            Some(AccountRecord { pubkey: pubkey.to_string(), account_type: account_type.to_string(), data })
+        }
+        Err(DecodeError::UnknownDiscriminator(_)) => None,
+        Err(error) => {
+            warn!(pubkey, %error, "Failed to decode account with the IDL");
+            None
+        }
+    }
+}
+
+/// Address of the IDL account Anchor maintains for `program_id`.
+pub fn idl_address(program_id: &Pubkey) -> Result<Pubkey, AppError> {
+    let (base, _) = Pubkey::find_program_address(&[], program_id);
+    Ok(Pubkey::create_with_seed(&base, IDL_ACCOUNT_SEED, program_id)?)
+}
+
+/// Unpacks the JSON stored in an on-chain IDL account: discriminator, authority, then a
+/// length-prefixed zlib stream.
+fn inflate_idl_account(data: &[u8]) -> Result<Vec<u8>, AppError> {
+    let mut reader = ByteReader::new(data);
+    reader.take("discriminator", 8)?;
+    reader.take("authority", 32)?;
+    let len = reader.u32_le("data_len")? as usize;
+    let mut json = Vec::new();
+    ZlibDecoder::new(reader.take("data", len)?).read_to_end(&mut json)?;
+    Ok(json)
+}
+
+/// Reads the raw IDL JSON for `program_id`, or `None` if the program has no on-chain IDL.
+async fn read_idl(client: &SolanaRpc, program_id: &Pubkey, source: &IdlSource) -> Result<Option<Vec<u8>>, AppError> {
+    match source {
+        IdlSource::File(path) => {
+            let json = tokio::fs::read(path)
+                .await
+                .map_err(|e| format!("Failed to read IDL {}: {}", path.display(), e))?;
+            Ok(Some(json))
+        }
+        IdlSource::OnChain => match client.get_account(&idl_address(program_id)?).await? {
+            Some(account) => Ok(Some(inflate_idl_account(&account.data)?)),
+            None => Ok(None),
+        },
+    }
+}
+
+struct LoadedIdl {
+    hash: Vec<u8>,
+    decoder: Arc<IdlDecoder>,
+}
+
+/// The IDL decoder currently loaded for each program on each cluster, replaced whenever its IDL changes.
+#[derive(Clone, Default)]
+pub struct IdlRegistry {
+    loaded: Arc<Mutex<HashMap<ProgramKey, LoadedIdl>>>,
+}
+
+impl IdlRegistry {
+    pub fn get(&self, program: &ProgramKey) -> Option<Arc<IdlDecoder>> {
+        self.loaded.lock().unwrap().get(program).map(|loaded| loaded.decoder.clone())
+    }
+
+    /// Re-reads `program`'s IDL from its cluster through `client` and swaps in a new decoder if it changed.
+    pub async fn refresh(&self, client: &SolanaRpc, program: &ProgramKey, source: &IdlSource) -> Result<(), AppError> {
+        let Some(json) = read_idl(client, &program.program_id, source).await? else {
+            if self.loaded.lock().unwrap().remove(program).is_some() {
+                warn!("On-chain IDL account is gone; generic account decoding stopped");
+            }
+            return Ok(());
+        };
+        let hash = data_hash(&json);
+        if self.loaded.lock().unwrap().get(program).is_some_and(|loaded| loaded.hash == hash) {
+            return Ok(());
+        }
+        let decoder = IdlDecoder::from_json(&json)?;
+        info!(account_types = decoder.accounts.len(), "Loaded Anchor IDL");
+        self.loaded.lock().unwrap().insert(*program, LoadedIdl { hash, decoder: Arc::new(decoder) });
+        Ok(())
+    }
+}
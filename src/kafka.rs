@@ -0,0 +1,277 @@
+//! Kafka sink: relays `change_outbox` rows to a topic, at least once.
+//!
+//! Changes are queued in the outbox by the transaction that records them, and an outbox row is
+//! only deleted after the partition leader has acknowledged it with `acks=all`, so a crash or a
+//! broker outage delays messages rather than losing them (a crash right after a send can
+//! repeat them). Messages are keyed by node pubkey and partitioned like the Java client's
+//! default partitioner, so each node's changes stay in order on a single partition.
+//!
+//! Batches are produced with rskafka, compressed as `KAFKA_COMPRESSION` says, over TLS when
+//! `KAFKA_TLS` is set and authenticated with SASL PLAIN or SCRAM when `KAFKA_SASL_MECHANISM` is.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use rskafka::client::partition::{Compression, PartitionClient, UnknownTopicHandling};
+use rskafka::client::{Client, ClientBuilder, Credentials, SaslConfig};
+use rskafka::record::Record;
+use rustls::RootCertStore;
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::config;
+use crate::sync::SyncContext;
+use crate::AppError;
+
+// --- Default KAFKA_TOPIC ---
+const DEFAULT_KAFKA_TOPIC: &str = "node-changes";
+
+// --- Pause between outbox polls when nothing is waiting ---
+const RELAY_INTERVAL_MILLIS: u64 = 500;
+
+// --- Outbox rows published per produce round ---
+const RELAY_BATCH_SIZE: i64 = 500;
+
+// --- Upper bound for the relay's retry backoff after a failed round ---
+const MAX_RELAY_BACKOFF_SECS: u64 = 60;
+
+/// Where and how to publish changes (`KAFKA_BROKERS`, `KAFKA_TOPIC`, `KAFKA_CLIENT_ID`,
+/// `KAFKA_COMPRESSION`, `KAFKA_TLS`, `KAFKA_SASL_MECHANISM`, `KAFKA_SASL_USERNAME`,
+/// `KAFKA_SASL_PASSWORD`).
+#[derive(Clone)]
+pub struct KafkaConfig {
+    /// Bootstrap brokers as `host:port`; the others are discovered from their metadata.
+    pub brokers: Vec<String>,
+    pub topic: String,
+    pub client_id: String,
+    pub compression: Compression,
+    /// Connect to brokers over TLS, verified against the webpki root certificates.
+    pub tls: bool,
+    pub sasl: Option<SaslConfig>,
+}
+
+impl KafkaConfig {
+    /// `None` when `KAFKA_BROKERS` is unset, leaving the sink off.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Ok(brokers) = config::var("KAFKA_BROKERS") else { return Ok(None) };
+        let brokers: Vec<String> =
+            brokers.split(',').map(str::trim).filter(|broker| !broker.is_empty()).map(str::to_string).collect();
+        if brokers.is_empty() {
+            return Err(format!("{} must list at least one host:port", config::source("KAFKA_BROKERS")).into());
+        }
+        let compression = match config::var("KAFKA_COMPRESSION").as_deref() {
+            Err(_) | Ok("none") => Compression::NoCompression,
+            Ok("gzip") => Compression::Gzip,
+            Ok("lz4") => Compression::Lz4,
+            Ok("snappy") => Compression::Snappy,
+            Ok("zstd") => Compression::Zstd,
+            Ok(other) => {
+                let source = config::source("KAFKA_COMPRESSION");
+                return Err(format!("Invalid {} '{}': expected none, gzip, lz4, snappy or zstd", source, other).into());
+            }
+        };
+        Ok(Some(Self {
+            brokers,
+            topic: config::var("KAFKA_TOPIC").unwrap_or_else(|_| DEFAULT_KAFKA_TOPIC.to_string()),
+            client_id: config::var("KAFKA_CLIENT_ID").unwrap_or_else(|_| "indexer".to_string()),
+            compression,
+            tls: config::var("KAFKA_TLS").is_ok_and(|value| value == "true" || value == "1"),
+            sasl: sasl_from_env()?,
+        }))
+    }
+}
+
+/// SASL settings, when `KAFKA_SASL_MECHANISM` is set; it needs a username and password.
+fn sasl_from_env() -> Result<Option<SaslConfig>, AppError> {
+    let Ok(mechanism) = config::var("KAFKA_SASL_MECHANISM") else { return Ok(None) };
+    let credential = |name: &str| {
+        config::var(name).map_err(|_| {
+            let mechanism = config::source("KAFKA_SASL_MECHANISM");
+            AppError::from(format!("{} requires {}", mechanism, config::source(name)))
+        })
+    };
+    let credentials = Credentials::new(credential("KAFKA_SASL_USERNAME")?, credential("KAFKA_SASL_PASSWORD")?);
+    match mechanism.as_str() {
+        "PLAIN" => Ok(Some(SaslConfig::Plain(credentials))),
+        "SCRAM-SHA-256" => Ok(Some(SaslConfig::ScramSha256(credentials))),
+        "SCRAM-SHA-512" => Ok(Some(SaslConfig::ScramSha512(credentials))),
+        other => {
+            let source = config::source("KAFKA_SASL_MECHANISM");
+            Err(format!("Invalid {} '{}': expected PLAIN, SCRAM-SHA-256 or SCRAM-SHA-512", source, other).into())
+        }
+    }
+}
+
+/// One row of `change_outbox`, published as the message value.
+#[derive(Serialize, sqlx::FromRow)]
+struct ChangeMessage {
+    /// `node_changes` cursor; consumers can deduplicate redelivered messages on it.
+    change_id: i64,
+    pubkey: String,
+    /// `upsert` or `delete`.
+    change_type: String,
+    authority: Option<String>,
+    uri: Option<String>,
+    program_id: Option<String>,
+    slot: Option<i64>,
+    changed_at: DateTime<Utc>,
+}
+
+/// Publishes the outbox to Kafka forever, reconnecting with backoff when a round fails.
+pub async fn run_kafka_relay(config: KafkaConfig, sync: SyncContext) {
+    async move {
+        let mut producer = None;
+        let mut backoff_secs = 1;
+        loop {
+            match relay_outbox(&mut producer, &config, &sync).await {
+                Ok(published) => {
+                    backoff_secs = 1;
+                    // A full batch means more is waiting; keep going without pausing.
+                    if published < RELAY_BATCH_SIZE as usize {
+                        sleep(Duration::from_millis(RELAY_INTERVAL_MILLIS)).await;
+                    }
+                }
+                Err(e) => {
+                    warn!(error = %e, backoff_secs, "Failed to publish changes to Kafka");
+                    // Start over from the topic metadata, in case partitions were added.
+                    producer = None;
+                    sleep(Duration::from_secs(backoff_secs)).await;
+                    backoff_secs = (backoff_secs * 2).min(MAX_RELAY_BACKOFF_SECS);
+                }
+            }
+        }
+    }
+    .instrument(info_span!("kafka_relay"))
+    .await
+}
+
+/// Publishes the oldest outbox rows and deletes those the brokers acknowledged.
+async fn relay_outbox(
+    producer: &mut Option<Producer>,
+    config: &KafkaConfig,
+    sync: &SyncContext,
+) -> Result<usize, AppError> {
+    let Some(_write) = sync.pause.begin_write().await else { return Ok(0) };
+    let changes = sqlx::query_as::<_, ChangeMessage>(
+        "SELECT change_id, pubkey, change_type, authority, uri, program_id, slot, changed_at \
+         FROM change_outbox ORDER BY change_id LIMIT $1",
+    )
+    .bind(RELAY_BATCH_SIZE)
+    .fetch_all(&sync.pool)
+    .await?;
+    if changes.is_empty() {
+        return Ok(0);
+    }
+    let mut records = Vec::with_capacity(changes.len());
+    for change in &changes {
+        let key = change.pubkey.clone().into_bytes();
+        let record = Record {
+            key: Some(key.clone()),
+            value: Some(serde_json::to_vec(change)?),
+            headers: BTreeMap::new(),
+            timestamp: change.changed_at,
+        };
+        records.push((key, record));
+    }
+    let producer = match producer {
+        Some(producer) => producer,
+        None => producer.insert(Producer::connect(config).await?),
+    };
+    producer.send(records).await?;
+
+    let change_ids: Vec<i64> = changes.iter().map(|change| change.change_id).collect();
+    delete_published(&sync.pool, &change_ids).await?;
+    debug!(published = change_ids.len(), "Published changes to Kafka");
+    Ok(change_ids.len())
+}
+
+async fn delete_published(pool: &PgPool, change_ids: &[i64]) -> Result<(), AppError> {
+    sqlx::query("DELETE FROM change_outbox WHERE change_id = ANY($1)").bind(change_ids).execute(pool).await?;
+    Ok(())
+}
+
+/// The topic's partition count and a client for each partition written to so far.
+struct Producer {
+    client: Client,
+    config: KafkaConfig,
+    partitions: u32,
+    partition_clients: HashMap<i32, PartitionClient>,
+}
+
+impl Producer {
+    async fn connect(config: &KafkaConfig) -> Result<Self, AppError> {
+        let mut builder = ClientBuilder::new(config.brokers.clone()).client_id(config.client_id.as_str());
+        if config.tls {
+            let roots = RootCertStore { roots: webpki_roots::TLS_SERVER_ROOTS.to_vec() };
+            let tls = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            builder = builder.tls_config(Arc::new(tls));
+        }
+        if let Some(sasl) = &config.sasl {
+            builder = builder.sasl_config(sasl.clone());
+        }
+        let client = builder.build().await?;
+        let topics = client.list_topics().await?;
+        let topic = topics
+            .iter()
+            .find(|topic| topic.name == config.topic)
+            .ok_or_else(|| format!("Kafka topic {} does not exist", config.topic))?;
+        let partitions = topic.partitions.len() as u32;
+        // The default partitioner numbers partitions 0..n, so a gap would misroute keys.
+        if partitions == 0 || topic.partitions.iter().enumerate().any(|(index, partition)| *partition != index as i32) {
+            return Err(format!("Kafka topic {} reported an incomplete partition list", config.topic).into());
+        }
+        info!(partitions, topic = %config.topic, "Connected to Kafka");
+        Ok(Self { client, config: config.clone(), partitions, partition_clients: HashMap::new() })
+    }
+
+    /// Sends `records`, one batch per partition, and waits for every partition to acknowledge
+    /// them with `acks=all`.
+    async fn send(&mut self, records: Vec<(Vec<u8>, Record)>) -> Result<(), AppError> {
+        let mut by_partition: HashMap<i32, Vec<Record>> = HashMap::new();
+        for (key, record) in records {
+            let partition = ((murmur2(&key) & 0x7fff_ffff) % self.partitions) as i32;
+            by_partition.entry(partition).or_default().push(record);
+        }
+        for (partition, records) in by_partition {
+            if !self.partition_clients.contains_key(&partition) {
+                let client =
+                    self.client.partition_client(&self.config.topic, partition, UnknownTopicHandling::Error).await?;
+                self.partition_clients.insert(partition, client);
+            }
+            let client = &self.partition_clients[&partition];
+            client.produce(records, self.config.compression).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Kafka's murmur2 variant, which the default partitioner applies to message keys.
+fn murmur2(data: &[u8]) -> u32 {
+    const SEED: u32 = 0x9747_b28c;
+    const M: u32 = 0x5bd1_e995;
+    let mut hash = SEED ^ data.len() as u32;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes(chunk.try_into().expect("chunk of 4"));
+        k = k.wrapping_mul(M);
+        k ^= k >> 24;
+        k = k.wrapping_mul(M);
+        hash = hash.wrapping_mul(M) ^ k;
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (index, byte) in tail.iter().enumerate() {
+            hash ^= (*byte as u32) << (8 * index);
+        }
+        hash = hash.wrapping_mul(M);
+    }
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(M);
+    hash ^ (hash >> 15)
+}
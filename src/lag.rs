@@ -0,0 +1,173 @@
+//! Indexer lag: how many slots each program's last synced snapshot trails its cluster. A monitor
+//! reads every cluster's current slot each `LAG_CHECK_SECS` into [`SyncHealth`], which `/metrics`
+//! and `/stats` report from. The process running ingestion also alerts when a program's lag
+//! rises above `LAG_ALERT_SLOTS` and again once it is back under: a warning in the log, and a
+//! `POST` of a [`LagAlert`] to `LAG_ALERT_WEBHOOK_URL` when one is configured.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::time::{sleep, Duration};
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::cluster::{Clusters, ProgramKey};
+use crate::config;
+use crate::liveness::describe_request_error;
+use crate::sync::SyncHealth;
+use crate::AppError;
+
+// --- Default LAG_CHECK_SECS between reads of each cluster's slot ---
+const DEFAULT_LAG_CHECK_SECS: u64 = 30;
+
+// --- Time the lag alert webhook gets to respond ---
+const LAG_WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
+/// Settings for lag tracking (`LAG_CHECK_SECS`, `LAG_ALERT_SLOTS`, `LAG_ALERT_WEBHOOK_URL`).
+#[derive(Clone, Debug)]
+pub struct LagConfig {
+    /// Pause between reads of each cluster's slot.
+    pub check_interval: Duration,
+    /// Lag in slots above which a program is alerted on; `None` disables alerts.
+    pub alert_slots: Option<u64>,
+    /// Receives a `POST` per alert, besides the log line.
+    pub webhook_url: Option<reqwest::Url>,
+}
+
+impl Default for LagConfig {
+    fn default() -> Self {
+        Self { check_interval: Duration::from_secs(DEFAULT_LAG_CHECK_SECS), alert_slots: None, webhook_url: None }
+    }
+}
+
+impl LagConfig {
+    pub fn from_env() -> Result<Self, AppError> {
+        let check_secs = match config::var("LAG_CHECK_SECS") {
+            Ok(value) => value.parse::<u64>().ok().filter(|secs| *secs > 0).ok_or_else(|| {
+                let source = config::source("LAG_CHECK_SECS");
+                format!("Invalid {} '{}': expected a positive number of seconds", source, value)
+            })?,
+            Err(_) => DEFAULT_LAG_CHECK_SECS,
+        };
+        let alert_slots = match config::var("LAG_ALERT_SLOTS") {
+            Ok(value) => Some(value.parse::<u64>().ok().ok_or_else(|| {
+                let source = config::source("LAG_ALERT_SLOTS");
+                format!("Invalid {} '{}': expected a number of slots", source, value)
+            })?),
+            Err(_) => None,
+        };
+        let webhook_url = match config::var("LAG_ALERT_WEBHOOK_URL") {
+            Ok(value) => {
+                let url = reqwest::Url::parse(&value)
+                    .ok()
+                    .filter(|url| matches!(url.scheme(), "http" | "https"))
+                    .ok_or_else(|| {
+                        let source = config::source("LAG_ALERT_WEBHOOK_URL");
+                        format!("Invalid {} '{}': expected an http(s) URL", source, value)
+                    })?;
+                if alert_slots.is_none() {
+                    let source = config::source("LAG_ALERT_WEBHOOK_URL");
+                    return Err(format!("{} needs {} to be set", source, config::source("LAG_ALERT_SLOTS")).into());
+                }
+                Some(url)
+            }
+            Err(_) => None,
+        };
+        Ok(Self { check_interval: Duration::from_secs(check_secs), alert_slots, webhook_url })
+    }
+}
+
+/// Body of a lag alert webhook request.
+#[derive(Serialize, Debug)]
+pub struct LagAlert {
+    /// `lag_exceeded` or `lag_recovered`.
+    pub event: &'static str,
+    pub program_id: String,
+    /// `null` for an unnamed default cluster.
+    pub cluster: Option<String>,
+    pub cluster_slot: u64,
+    pub synced_slot: u64,
+    pub lag_slots: u64,
+    pub threshold_slots: u64,
+    pub at: DateTime<Utc>,
+}
+
+/// Reads every cluster's slot into `health` once per interval, forever. With `alerts` set, also
+/// alerts on programs whose lag crosses the threshold.
+pub async fn run_lag_monitor(clusters: Clusters, health: SyncHealth, config: LagConfig, alerts: bool) {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(LAG_WEBHOOK_TIMEOUT_SECS))
+        .user_agent(concat!("indexer/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Failed to build the lag alert HTTP client");
+    async move {
+        // Programs currently over the threshold, so each crossing alerts once.
+        let mut lagging = HashSet::new();
+        loop {
+            for (index, cluster) in clusters.iter().enumerate() {
+                let slot = match cluster.rpc.get_slot().await {
+                    Ok(slot) => slot,
+                    Err(e) => {
+                        warn!(cluster = cluster.name.as_deref(), error = %e, "Failed to read the cluster slot");
+                        continue;
+                    }
+                };
+                for program in clusters.programs().into_iter().filter(|program| program.cluster == index) {
+                    health.record_cluster_slot(program, slot);
+                    let Some(threshold) = config.alert_slots.filter(|_| alerts) else { continue };
+                    if let Some(alert) = check(&health, &mut lagging, &program, cluster.name.as_deref(), threshold)
+                        && let Some(url) = &config.webhook_url
+                    {
+                        send_alert(&client, url, &alert).await;
+                    }
+                }
+            }
+            sleep(config.check_interval).await;
+        }
+    }
+    .instrument(info_span!("lag_monitor"))
+    .await
+}
+
+/// Logs and returns an alert if `program`'s lag on `cluster` just crossed `threshold`.
+fn check(
+    health: &SyncHealth,
+    lagging: &mut HashSet<ProgramKey>,
+    program: &ProgramKey,
+    cluster: Option<&str>,
+    threshold: u64,
+) -> Option<LagAlert> {
+    // A program that never synced is readiness's concern, not lag's.
+    let (synced_slot, cluster_slot) = (health.last_synced_slot(program)?, health.cluster_slot(program)?);
+    let lag_slots = cluster_slot.saturating_sub(synced_slot);
+    let program_id = &program.program_id;
+    let event = if lag_slots > threshold && lagging.insert(*program) {
+        warn!(%program_id, cluster, lag_slots, threshold, "Indexer lag exceeds the alert threshold");
+        "lag_exceeded"
+    } else if lag_slots <= threshold && lagging.remove(program) {
+        info!(%program_id, cluster, lag_slots, threshold, "Indexer lag is back under the alert threshold");
+        "lag_recovered"
+    } else {
+        return None;
+    };
+    Some(LagAlert {
+        event,
+        program_id: program_id.to_string(),
+        cluster: cluster.map(str::to_string),
+        cluster_slot,
+        synced_slot,
+        lag_slots,
+        threshold_slots: threshold,
+        at: Utc::now(),
+    })
+}
+
+/// A failed alert is only logged; the next crossing sends another.
+async fn send_alert(client: &reqwest::Client, url: &reqwest::Url, alert: &LagAlert) {
+    let body = serde_json::to_string(alert).expect("LagAlert serializes");
+    match client.post(url.clone()).header("content-type", "application/json").body(body).send().await {
+        Ok(response) if response.status().is_success() => {}
+        Ok(response) => warn!(status = response.status().as_u16(), "Lag alert webhook rejected the alert"),
+        Err(e) => warn!(error = %describe_request_error(&e), "Failed to send the lag alert webhook"),
+    }
+}
@@ -0,0 +1,93 @@
+//! Leader election for replicas sharing one database: each campaigns for a session-level
+//! advisory lock, and only the holder runs ingestion. The lock lives on a dedicated
+//! connection, so a leader that crashes or loses the database releases it and another
+//! replica takes over on its next attempt.
+
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::PgPool;
+use sqlx::{Connection, PgConnection};
+use tokio::time::{sleep, timeout, Duration};
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::{AppError, Indexer};
+
+// --- Pause between attempts to take the sync lock, and between checks that it is still held ---
+const LEADER_CHECK_INTERVAL_SECS: u64 = 5;
+
+/// Advisory lock key for a set of programs: replicas indexing the same programs contend for
+/// it, while indexers of other programs can share the database.
+pub fn leader_lock_key(program_ids: &[Pubkey]) -> i64 {
+    let mut program_ids: Vec<String> = program_ids.iter().map(Pubkey::to_string).collect();
+    program_ids.sort();
+    let hash = Sha256::digest(format!("sync_leader:{}", program_ids.join(",")).as_bytes());
+    let mut key = [0u8; 8];
+    key.copy_from_slice(&hash[..8]);
+    i64::from_le_bytes(key)
+}
+
+/// Takes the lock on a connection of its own if no other instance holds it. The lock is
+/// held for as long as the returned connection stays open.
+pub async fn try_acquire(pool: &PgPool, key: i64) -> Result<Option<PgConnection>, AppError> {
+    let mut connection = pool.acquire().await?.detach();
+    let acquired: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+        .bind(key)
+        .fetch_one(&mut connection)
+        .await?;
+    Ok(acquired.then_some(connection))
+}
+
+/// Campaigns for leadership forever. While leading, runs [`Indexer::spawn_ingestion`]; while
+/// following, runs the database follower instead when `follow` is set, so a replica serving
+/// the API still reports readiness and live changes.
+pub async fn run_leader_election(indexer: Indexer, follow: bool) {
+    let key = leader_lock_key(&indexer.program_ids);
+    async move {
+        loop {
+            let follower = if follow { indexer.spawn_follower() } else { Vec::new() };
+            let mut connection = campaign(&indexer.pool, key).await;
+            for task in follower {
+                task.abort();
+            }
+
+            info!("Acquired sync leadership; starting ingestion");
+            let tasks = indexer.spawn_ingestion();
+            loop {
+                sleep(Duration::from_secs(LEADER_CHECK_INTERVAL_SECS)).await;
+                // Panicked tasks are restarted, so one that ends has given up; a healthy replica takes over.
+                if tasks.iter().any(|task| task.is_finished()) {
+                    warn!("An ingestion task stopped; giving up sync leadership");
+                    break;
+                }
+                let check = timeout(Duration::from_secs(LEADER_CHECK_INTERVAL_SECS), connection.ping()).await;
+                if !matches!(check, Ok(Ok(()))) {
+                    warn!("Lost the sync lock connection; stopping ingestion");
+                    break;
+                }
+            }
+            for task in tasks {
+                task.abort();
+            }
+            // Closing the session releases the lock if the server still holds it for us.
+            let _ = connection.close().await;
+        }
+    }
+    .instrument(info_span!("leader_election", key))
+    .await
+}
+
+async fn campaign(pool: &PgPool, key: i64) -> PgConnection {
+    let mut waiting_logged = false;
+    loop {
+        match try_acquire(pool, key).await {
+            Ok(Some(connection)) => return connection,
+            Ok(None) if !waiting_logged => {
+                info!("Another instance holds the sync lock; standing by");
+                waiting_logged = true;
+            }
+            Ok(None) => {}
+            Err(e) => warn!(error = %e, "Failed to campaign for sync leadership"),
+        }
+        sleep(Duration::from_secs(LEADER_CHECK_INTERVAL_SECS)).await;
+    }
+}
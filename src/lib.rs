@@ -0,0 +1,948 @@
+//! Solana program indexer: mirrors the program's `NodeDevice` accounts into Postgres and
+//! serves them over HTTP, WebSocket and SSE.
+//!
+//! The `indexer` binary is a thin wrapper around [`IndexerBuilder`]; embedders can use the
+//! same builder to run the sync loop inside their own service or mount [`Indexer::router`]
+//! next to their own routes.
+//!
+//! Other account types of the program are indexed by implementing
+//! [`decode::AccountDecoder`] and passing it to [`IndexerBuilder::decoder`], or generically
+//! from the program's Anchor IDL (see [`idl`]).
+
+// The OpenAPI schema map is one `json!` literal, deeper than the default limit allows.
+#![recursion_limit = "256"]
+
+pub mod alerts;
+pub mod anchor_events;
+pub mod api;
+pub mod auth;
+pub mod certificate;
+pub mod cluster;
+pub mod compression;
+pub mod config;
+pub mod cors;
+pub mod counts;
+pub mod decode;
+pub mod dns;
+pub mod enrichment;
+pub mod error;
+pub mod events;
+pub mod fields;
+pub mod fixtures;
+#[cfg(feature = "geyser")]
+pub mod geyser;
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod idl;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod lag;
+pub mod leader;
+pub mod liveness;
+pub mod local;
+pub mod moderation;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod openapi;
+pub mod operator;
+pub mod pda;
+pub mod pool;
+pub mod refresh;
+pub mod rent;
+pub mod reporting;
+pub mod request_id;
+pub mod response_cache;
+pub mod rpc;
+pub mod storage;
+pub mod store;
+pub mod subscriptions;
+pub mod supervisor;
+pub mod sync;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod throttle;
+pub mod transactions;
+pub mod uri;
+pub mod verify;
+pub mod watchlists;
+pub mod webhooks;
+
+use std::fmt::Display;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::Router;
+use chrono::Utc;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::PgPool;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{info, warn};
+
+use crate::alerts::AlertEmailConfig;
+use crate::api::{AppState, ReadPool};
+use crate::auth::AuthConfig;
+use crate::cluster::{Cluster, ClusterConfig, Clusters, ProgramKey};
+use crate::compression::CompressionConfig;
+use crate::cors::CorsConfig;
+use crate::counts::NodeCounts;
+use crate::decode::{AccountDecoder, DecoderRegistry};
+use crate::enrichment::EnrichmentConfig;
+use crate::events::EventHub;
+use crate::fixtures::RpcFixtures;
+use crate::idl::IdlRegistry;
+#[cfg(feature = "kafka")]
+use crate::kafka::KafkaConfig;
+use crate::lag::LagConfig;
+use crate::liveness::LivenessConfig;
+use crate::refresh::RefreshConfig;
+#[cfg(feature = "nats")]
+use crate::nats::NatsConfig;
+use crate::pool::PoolConfig;
+use crate::response_cache::{ResponseCache, ResponseCacheConfig};
+use crate::rpc::{RateLimit, RetryPolicy, SolanaRpc};
+use crate::supervisor::supervise;
+use crate::sync::{IngestionPause, ResyncTrigger, SyncConfig, SyncContext, SyncHealth, DEFAULT_READY_MAX_SYNC_AGE_SECS};
+use crate::throttle::{ClientRateLimit, ClientRateLimiter};
+use crate::transactions::TransactionHistoryConfig;
+use crate::verify::DriftReport;
+
+// --- Type alias for our thread-safe error type ---
+pub type AppError = Box<dyn std::error::Error + Send + Sync>;
+
+// --- RPC endpoint used when none is configured ---
+pub const DEFAULT_RPC_URL: &str = "https://api.devnet.solana.com";
+
+// --- Default STARTUP_TIMEOUT_SECS: how long startup keeps retrying the database and RPC ---
+pub const DEFAULT_STARTUP_TIMEOUT_SECS: u64 = 60;
+
+// --- Longest pause between startup connection attempts; the first is one second ---
+const STARTUP_RETRY_MAX_SECS: u64 = 10;
+
+/// How account changes reach the indexer between reconciliation passes.
+#[derive(Clone, Debug)]
+pub enum IngestionBackend {
+    /// Only the periodic getProgramAccounts reconciliation.
+    PollOnly,
+    /// programSubscribe against a pubsub endpoint; `None` derives it from the first RPC URL.
+    ProgramSubscribe { ws_url: Option<String> },
+    /// Yellowstone Geyser gRPC stream.
+    #[cfg(feature = "geyser")]
+    Geyser { endpoint: String, x_token: Option<String> },
+}
+
+/// Configures and connects an [`Indexer`].
+pub struct IndexerBuilder {
+    database_url: Option<String>,
+    pool: Option<PgPool>,
+    read_database_url: Option<String>,
+    read_pool: Option<PgPool>,
+    pool_config: PoolConfig,
+    rpc_urls: Vec<String>,
+    rpc_fixtures: Option<RpcFixtures>,
+    program_ids: Vec<Pubkey>,
+    cluster_name: Option<String>,
+    clusters: Vec<ClusterConfig>,
+    sync_config: Option<SyncConfig>,
+    retry: RetryPolicy,
+    rate_limit: Option<RateLimit>,
+    backend: IngestionBackend,
+    run_migrations: bool,
+    max_sync_age: Duration,
+    startup_timeout: Duration,
+    decoders: DecoderRegistry,
+    transaction_history: Option<TransactionHistoryConfig>,
+    anchor_events: bool,
+    liveness: Option<LivenessConfig>,
+    enrichment: Option<EnrichmentConfig>,
+    refresh: Option<RefreshConfig>,
+    lag: LagConfig,
+    #[cfg(feature = "kafka")]
+    kafka: Option<KafkaConfig>,
+    #[cfg(feature = "nats")]
+    nats: Option<NatsConfig>,
+    alert_email: Option<AlertEmailConfig>,
+    leader_election: bool,
+    auth: AuthConfig,
+    compression: CompressionConfig,
+    cors: CorsConfig,
+    client_rate_limit: Option<ClientRateLimit>,
+    response_cache: Option<ResponseCacheConfig>,
+}
+
+impl Default for IndexerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IndexerBuilder {
+    pub fn new() -> Self {
+        Self {
+            database_url: None,
+            pool: None,
+            read_database_url: None,
+            read_pool: None,
+            pool_config: PoolConfig::default(),
+            rpc_urls: Vec::new(),
+            rpc_fixtures: None,
+            program_ids: Vec::new(),
+            cluster_name: None,
+            clusters: Vec::new(),
+            sync_config: None,
+            retry: RetryPolicy::default(),
+            rate_limit: None,
+            backend: IngestionBackend::ProgramSubscribe { ws_url: None },
+            run_migrations: true,
+            max_sync_age: Duration::from_secs(DEFAULT_READY_MAX_SYNC_AGE_SECS),
+            startup_timeout: Duration::from_secs(DEFAULT_STARTUP_TIMEOUT_SECS),
+            decoders: DecoderRegistry::with_defaults(),
+            transaction_history: None,
+            anchor_events: false,
+            liveness: None,
+            enrichment: None,
+            refresh: None,
+            lag: LagConfig::default(),
+            #[cfg(feature = "kafka")]
+            kafka: None,
+            #[cfg(feature = "nats")]
+            nats: None,
+            alert_email: None,
+            leader_election: true,
+            auth: AuthConfig::default(),
+            compression: CompressionConfig::default(),
+            cors: CorsConfig::default(),
+            client_rate_limit: None,
+            response_cache: None,
+        }
+    }
+
+    /// Postgres connection string. Ignored when a pool is supplied with [`Self::pool`].
+    pub fn database_url(mut self, database_url: impl Into<String>) -> Self {
+        self.database_url = Some(database_url.into());
+        self
+    }
+
+    /// Reuses an existing connection pool instead of opening one.
+    pub fn pool(mut self, pool: PgPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Connection string of a read replica for the API's read-only queries, which otherwise go
+    /// to the primary. Ignored when a pool is supplied with [`Self::read_pool`].
+    pub fn read_database_url(mut self, read_database_url: impl Into<String>) -> Self {
+        self.read_database_url = Some(read_database_url.into());
+        self
+    }
+
+    /// Reuses an existing pool for the API's read-only queries.
+    pub fn read_pool(mut self, read_pool: PgPool) -> Self {
+        self.read_pool = Some(read_pool);
+        self
+    }
+
+    /// Connections per pool; a read replica gets a pool of the same size.
+    pub fn max_connections(mut self, max_connections: u32) -> Self {
+        self.pool_config.max_connections = max_connections;
+        self
+    }
+
+    /// Sizes and timeouts of the pools opened from [`Self::database_url`] and
+    /// [`Self::read_database_url`].
+    pub fn pool_config(mut self, pool_config: PoolConfig) -> Self {
+        self.pool_config = pool_config;
+        self
+    }
+
+    /// Adds an RPC endpoint. The first one added is preferred; later ones are failover targets.
+    pub fn rpc_url(mut self, rpc_url: impl Into<String>) -> Self {
+        self.rpc_urls.push(rpc_url.into());
+        self
+    }
+
+    /// Answers RPC calls from recorded snapshots instead of the RPC URLs (see [`fixtures`]).
+    /// Needs the [`IngestionBackend::PollOnly`] backend and no Anchor event stream.
+    pub fn rpc_fixtures(mut self, fixtures: RpcFixtures) -> Self {
+        self.rpc_fixtures = Some(fixtures);
+        self
+    }
+
+    pub fn program_id(mut self, program_id: Pubkey) -> Self {
+        if !self.program_ids.contains(&program_id) {
+            self.program_ids.push(program_id);
+        }
+        self
+    }
+
+    /// Names the cluster the RPC URLs point at, stored with its nodes in `nodes.cluster`.
+    pub fn cluster_name(mut self, name: impl Into<String>) -> Self {
+        self.cluster_name = Some(name.into());
+        self
+    }
+
+    /// Also indexes `cluster`'s programs, through its own RPC endpoints (see [`cluster`]).
+    pub fn cluster(mut self, cluster: ClusterConfig) -> Self {
+        self.clusters.push(cluster);
+        self
+    }
+
+    /// Reconciliation settings. Defaults to [`SyncConfig::from_env`].
+    pub fn sync_config(mut self, sync_config: SyncConfig) -> Self {
+        self.sync_config = Some(sync_config);
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: Option<RateLimit>) -> Self {
+        self.rate_limit = rate_limit;
+        self
+    }
+
+    pub fn backend(mut self, backend: IngestionBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Whether [`Self::build`] applies the embedded migrations (default `true`).
+    pub fn run_migrations(mut self, run_migrations: bool) -> Self {
+        self.run_migrations = run_migrations;
+        self
+    }
+
+    /// How long a program may go without a successful sync before `/readyz` fails.
+    pub fn max_sync_age(mut self, max_sync_age: Duration) -> Self {
+        self.max_sync_age = max_sync_age;
+        self
+    }
+
+    /// How long [`Self::build`] keeps retrying an unreachable database or RPC node, with
+    /// backoff, before giving up; zero fails on the first attempt.
+    pub fn startup_timeout(mut self, startup_timeout: Duration) -> Self {
+        self.startup_timeout = startup_timeout;
+        self
+    }
+
+    /// Indexes another account type alongside `NodeDevice`, replacing any decoder already
+    /// registered for the same account name.
+    pub fn decoder<D: AccountDecoder>(mut self, decoder: D) -> Self {
+        self.decoders = self.decoders.register(decoder);
+        self
+    }
+
+    /// Indexes each node's transactions into `node_transactions` (off by default).
+    pub fn transaction_history(mut self, config: Option<TransactionHistoryConfig>) -> Self {
+        self.transaction_history = config;
+        self
+    }
+
+    /// Probes every node's URI into `node_liveness` (off by default).
+    pub fn liveness(mut self, config: Option<LivenessConfig>) -> Self {
+        self.liveness = config;
+        self
+    }
+
+    /// Fetches each node's metadata document into `node_metadata` (off by default).
+    pub fn enrichment(mut self, config: Option<EnrichmentConfig>) -> Self {
+        self.enrichment = config;
+        self
+    }
+
+    /// Re-reads recently changed and watched pubkeys with getMultipleAccounts between full
+    /// scans (off by default).
+    pub fn targeted_refresh(mut self, config: Option<RefreshConfig>) -> Self {
+        self.refresh = config;
+        self
+    }
+
+    /// How often each cluster's slot is read for the lag metric, and when lag is alerted on.
+    pub fn lag(mut self, config: LagConfig) -> Self {
+        self.lag = config;
+        self
+    }
+
+    /// Publishes every node change to Kafka through the `change_outbox` table (off by default).
+    #[cfg(feature = "kafka")]
+    pub fn kafka(mut self, config: Option<KafkaConfig>) -> Self {
+        self.kafka = config;
+        self
+    }
+
+    /// Publishes every node change to NATS JetStream (off by default).
+    #[cfg(feature = "nats")]
+    pub fn nats(mut self, config: Option<NatsConfig>) -> Self {
+        self.nats = config;
+        self
+    }
+
+    /// SMTP relay for email alert targets; without one, only webhook alerts are delivered.
+    pub fn alert_email(mut self, config: Option<AlertEmailConfig>) -> Self {
+        self.alert_email = config;
+        self
+    }
+
+    /// Streams the program's logs and stores its Anchor events in `program_events` (off by
+    /// default). Uses the programSubscribe endpoint when one is configured.
+    pub fn anchor_events(mut self, anchor_events: bool) -> Self {
+        self.anchor_events = anchor_events;
+        self
+    }
+
+    /// Only sync while holding the database's sync lock for these programs, so replicas don't
+    /// race each other (default `true`). See [`leader`].
+    pub fn leader_election(mut self, leader_election: bool) -> Self {
+        self.leader_election = leader_election;
+        self
+    }
+
+    /// API key settings; by default keys are optional except on the `/admin` routes.
+    pub fn auth(mut self, auth: AuthConfig) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// API response compression; on by default, see [`CompressionConfig`].
+    pub fn compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Cross-origin policy; any origin by default.
+    pub fn cors(mut self, cors: CorsConfig) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    /// Per-client API request budget; unthrottled by default. Clients are told apart by API
+    /// key, else by address, which [`Indexer::serve`] provides as connection info.
+    pub fn client_rate_limit(mut self, client_rate_limit: Option<ClientRateLimit>) -> Self {
+        self.client_rate_limit = client_rate_limit;
+        self
+    }
+
+    /// Caches the `200` responses of the configured API routes in memory; off by default.
+    /// Entries are dropped on every node change event, or once their route's TTL passes.
+    pub fn response_cache(mut self, response_cache: Option<ResponseCacheConfig>) -> Self {
+        self.response_cache = response_cache;
+        self
+    }
+
+    /// Connects to Postgres and the RPC node. Nothing is spawned until
+    /// [`Indexer::spawn_ingestion`] or [`Indexer::serve`] is called.
+    pub async fn build(self) -> Result<Indexer, AppError> {
+        if self.program_ids.is_empty() && self.clusters.is_empty() {
+            return Err("At least one program ID must be configured".into());
+        }
+        cluster::validate(self.cluster_name.as_deref(), &self.clusters)?;
+        let rpc_urls = if self.rpc_urls.is_empty() { vec![DEFAULT_RPC_URL.to_string()] } else { self.rpc_urls };
+        if self.rpc_fixtures.is_some() && (!matches!(self.backend, IngestionBackend::PollOnly) || self.anchor_events) {
+            return Err("RPC fixtures only replay snapshots; use the PollOnly backend without Anchor events".into());
+        }
+        if self.rpc_fixtures.is_some() && !self.clusters.is_empty() {
+            return Err("RPC fixtures replay a single cluster; other clusters can't be added".into());
+        }
+        let sync_config = match self.sync_config {
+            Some(sync_config) => sync_config,
+            None => SyncConfig::from_env()?,
+        };
+
+        // Compose may start us before Postgres or a local validator accepts connections.
+        let deadline = Instant::now() + self.startup_timeout;
+        let pool_config = &self.pool_config;
+        let pool = match self.pool {
+            Some(pool) => pool,
+            None => {
+                let database_url = self.database_url.ok_or("A database URL or pool must be configured")?;
+                retry_startup("the database", deadline, || pool_config.connect(&database_url)).await?
+            }
+        };
+        info!("Connected to the database");
+        let read_pool = match (self.read_pool, self.read_database_url) {
+            (Some(read_pool), _) => read_pool,
+            (None, Some(read_database_url)) => {
+                let read_pool =
+                    retry_startup("the read replica", deadline, || pool_config.connect(&read_database_url)).await?;
+                info!("Connected to the read replica");
+                read_pool
+            }
+            (None, None) => pool.clone(),
+        };
+
+        // Schema lives in ./migrations and is embedded at compile time.
+        if self.run_migrations {
+            store::migrate(&pool).await?;
+            info!("Database migrations applied");
+            let rechecked = store::recheck_nodes(&pool, sync_config.node_device_seed.as_deref()).await?;
+            if rechecked > 0 {
+                info!(rechecked, "Corrected the URI, PDA and rent flags of stored nodes");
+            }
+        }
+
+        let commitment = sync_config.commitment.commitment;
+        let backend = match self.backend {
+            IngestionBackend::ProgramSubscribe { ws_url: None } => {
+                IngestionBackend::ProgramSubscribe { ws_url: Some(rpc::ws_url_from_rpc(&rpc_urls[0])) }
+            }
+            backend => backend,
+        };
+        let anchor_events = self.anchor_events;
+        let logs_ws_url = |backend: &IngestionBackend, rpc_url: &str| {
+            anchor_events.then(|| match backend {
+                IngestionBackend::ProgramSubscribe { ws_url: Some(ws_url) } => ws_url.clone(),
+                _ => rpc::ws_url_from_rpc(rpc_url),
+            })
+        };
+
+        // The default cluster is only connected to when it has programs of its own.
+        let mut clusters = Vec::with_capacity(self.clusters.len() + 1);
+        if !self.program_ids.is_empty() {
+            let rpc = match self.rpc_fixtures {
+                Some(fixtures) => {
+                    info!(%commitment, "Replaying RPC fixtures");
+                    Arc::new(SolanaRpc::from_fixtures(fixtures, sync_config.commitment))
+                }
+                None => {
+                    info!(%commitment, endpoints = rpc_urls.len(), "Using RPC commitment level");
+                    Arc::new(SolanaRpc::new(&rpc_urls, sync_config.commitment, self.retry, self.rate_limit))
+                }
+            };
+            let slot = retry_startup("the RPC node", deadline, || rpc.get_slot()).await?;
+            info!(slot, cluster = self.cluster_name.as_deref(), "Connected to Solana");
+            clusters.push(Cluster {
+                name: self.cluster_name,
+                program_ids: self.program_ids.clone(),
+                rpc,
+                logs_ws_url: logs_ws_url(&backend, &rpc_urls[0]),
+                backend: backend.clone(),
+            });
+        }
+        for config in self.clusters {
+            let rpc = Arc::new(SolanaRpc::new(&config.rpc_urls, sync_config.commitment, self.retry, self.rate_limit));
+            let target = format!("the {} RPC node", config.name);
+            let slot = retry_startup(&target, deadline, || rpc.get_slot()).await?;
+            info!(slot, cluster = %config.name, endpoints = config.rpc_urls.len(), "Connected to Solana");
+            let cluster_backend = cluster::secondary_backend(&backend, &config.rpc_urls[0]);
+            clusters.push(Cluster {
+                name: Some(config.name),
+                program_ids: config.program_ids,
+                rpc,
+                logs_ws_url: logs_ws_url(&cluster_backend, &config.rpc_urls[0]),
+                backend: cluster_backend,
+            });
+        }
+        let clusters = Clusters::new(clusters);
+        let mut program_ids: Vec<Pubkey> = Vec::new();
+        for program in clusters.programs() {
+            if !program_ids.contains(&program.program_id) {
+                program_ids.push(program.program_id);
+            }
+        }
+
+        // Readiness and lag start from the last run's checkpoint rather than from nothing.
+        let health = SyncHealth::new(self.max_sync_age);
+        sync::load_checkpoints(&pool, &clusters, &health).await?;
+        for program in clusters.programs() {
+            let (program_id, cluster) = (program.program_id, clusters.name_of(&program));
+            match (health.last_success(&program), health.last_synced_slot(&program)) {
+                (Some(at), Some(slot)) => {
+                    let behind_secs = (Utc::now() - at).num_seconds();
+                    info!(%program_id, cluster, slot, behind_secs, "Resuming from the last sync checkpoint");
+                }
+                _ => info!(%program_id, cluster, "No sync checkpoint yet"),
+            }
+        }
+
+        let events = EventHub::new();
+        let counts = Arc::new(NodeCounts::new());
+        let response_cache = self.response_cache.map(|config| Arc::new(ResponseCache::new(config, events.clone())));
+        let sync = SyncContext {
+            pool: pool.clone(),
+            events: events.clone(),
+            config: Arc::new(sync_config),
+            decoders: Arc::new(self.decoders),
+            idls: IdlRegistry::default(),
+            pause: IngestionPause::default(),
+            clusters,
+            #[cfg(feature = "kafka")]
+            outbox: self.kafka.is_some(),
+            #[cfg(not(feature = "kafka"))]
+            outbox: false,
+        };
+        Ok(Indexer {
+            pool,
+            read_pool,
+            events,
+            health,
+            sync,
+            program_ids,
+            transaction_history: self.transaction_history,
+            liveness: self.liveness,
+            enrichment: self.enrichment,
+            refresh: self.refresh,
+            lag: self.lag,
+            #[cfg(feature = "kafka")]
+            kafka: self.kafka,
+            #[cfg(feature = "nats")]
+            nats: self.nats,
+            alert_email: self.alert_email,
+            leader_election: self.leader_election,
+            auth: self.auth,
+            compression: self.compression,
+            cors: self.cors,
+            rate_limiter: self.client_rate_limit.map(|limit| Arc::new(ClientRateLimiter::new(limit))),
+            resync: ResyncTrigger::default(),
+            response_cache,
+            counts,
+        })
+    }
+}
+
+/// Retries `connect` with doubling pauses until it succeeds or `deadline` passes, then returns
+/// its last error. The last attempt is made right at the deadline.
+async fn retry_startup<T, E, F>(target: &str, deadline: Instant, mut connect: impl FnMut() -> F) -> Result<T, AppError>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<AppError> + Display,
+{
+    let mut pause = Duration::from_secs(1);
+    loop {
+        match connect().await {
+            Ok(connected) => return Ok(connected),
+            Err(e) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Err(e.into());
+                }
+                let wait = pause.min(remaining);
+                warn!(error = %e, retry_in_ms = wait.as_millis() as u64, "Failed to connect to {}; retrying", target);
+                sleep(wait).await;
+                pause = (pause * 2).min(Duration::from_secs(STARTUP_RETRY_MAX_SECS));
+            }
+        }
+    }
+}
+
+/// Runs `startup` while a duplicate of `listener` answers `/healthz` with `"starting"` and
+/// every other route with `503`, so probes see a live process while [`IndexerBuilder::build`]
+/// waits for the database and RPC. `listener` must be non-blocking; it is left unused once
+/// `startup` returns, ready to be converted for [`Indexer::serve`].
+pub async fn while_starting<T>(
+    listener: &std::net::TcpListener,
+    startup: impl Future<Output = T>,
+) -> Result<T, AppError> {
+    let starting = TcpListener::from_std(listener.try_clone()?)?;
+    let (stop, stopped) = oneshot::channel::<()>();
+    let server = tokio::spawn(async move {
+        let shutdown = async {
+            let _ = stopped.await;
+        };
+        axum::serve(starting, api::starting_router()).with_graceful_shutdown(shutdown).await
+    });
+    let output = startup.await;
+    let _ = stop.send(());
+    server.await??;
+    Ok(output)
+}
+
+/// A connected indexer, ready to start syncing and serving.
+#[derive(Clone)]
+pub struct Indexer {
+    pool: PgPool,
+    /// Where the API reads from; the same pool as `pool` without a read replica.
+    read_pool: PgPool,
+    events: EventHub,
+    health: SyncHealth,
+    sync: SyncContext,
+    /// Every cluster's programs, each listed once however many clusters index it.
+    program_ids: Vec<Pubkey>,
+    transaction_history: Option<TransactionHistoryConfig>,
+    liveness: Option<LivenessConfig>,
+    enrichment: Option<EnrichmentConfig>,
+    refresh: Option<RefreshConfig>,
+    lag: LagConfig,
+    #[cfg(feature = "kafka")]
+    kafka: Option<KafkaConfig>,
+    #[cfg(feature = "nats")]
+    nats: Option<NatsConfig>,
+    alert_email: Option<AlertEmailConfig>,
+    leader_election: bool,
+    auth: AuthConfig,
+    compression: CompressionConfig,
+    cors: CorsConfig,
+    rate_limiter: Option<Arc<ClientRateLimiter>>,
+    resync: ResyncTrigger,
+    response_cache: Option<Arc<ResponseCache>>,
+    counts: Arc<NodeCounts>,
+}
+
+impl Indexer {
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// The pool behind the API's read-only queries.
+    pub fn read_pool(&self) -> &PgPool {
+        &self.read_pool
+    }
+
+    /// Change events published after each committed write.
+    pub fn events(&self) -> &EventHub {
+        &self.events
+    }
+
+    pub fn program_ids(&self) -> &[Pubkey] {
+        &self.program_ids
+    }
+
+    /// The public API, ready to be served or nested into another router.
+    pub fn router(&self) -> Router {
+        api::router(self.app_state())
+    }
+
+    fn app_state(&self) -> AppState {
+        AppState {
+            pool: self.pool.clone(),
+            read_pool: ReadPool(self.read_pool.clone()),
+            events: self.events.clone(),
+            program_ids: self.program_ids.clone(),
+            rpc: self.sync.clusters.primary_rpc().clone(),
+            health: self.health.clone(),
+            auth: self.auth,
+            compression: self.compression.clone(),
+            cors: self.cors.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            resync: self.resync.clone(),
+            pause: self.sync.pause.clone(),
+            counts: self.counts.clone(),
+            sync: self.sync.clone(),
+            response_cache: self.response_cache.clone(),
+        }
+    }
+
+    /// Serves the gRPC API on `listener` until the server stops. Runs alongside [`Self::serve`]
+    /// or [`Self::serve_api`], whose ingestion or database follower feeds `WatchChanges`.
+    #[cfg(feature = "grpc")]
+    pub async fn serve_grpc(&self, listener: TcpListener) -> Result<(), AppError> {
+        grpc::serve(self.app_state(), listener).await
+    }
+
+    /// Starts each cluster's streaming backend and one reconciliation loop per program,
+    /// regardless of leader election. Every task is supervised, so a panic restarts it.
+    pub fn spawn_ingestion(&self) -> Vec<JoinHandle<()>> {
+        let mut tasks = Vec::new();
+        let clusters = &self.sync.clusters;
+        for (index, cluster) in clusters.iter().enumerate() {
+            let cluster_name = cluster.name.as_deref().unwrap_or("default");
+            let programs: Vec<ProgramKey> =
+                clusters.programs().into_iter().filter(|program| program.cluster == index).collect();
+            // Real-time ingestion; the poll below remains as a periodic reconciliation pass.
+            match &cluster.backend {
+                IngestionBackend::PollOnly => {}
+                IngestionBackend::ProgramSubscribe { ws_url } => {
+                    let ws_url = ws_url.clone().expect("resolved in IndexerBuilder::build");
+                    for program in programs.clone() {
+                        let (ws_url, sync) = (ws_url.clone(), self.sync.clone());
+                        let task = format!("program_subscription {}", clusters.label(&program));
+                        tasks.push(supervise(&self.health, task, move || {
+                            sync::run_program_subscription(ws_url.clone(), program, sync.clone())
+                        }));
+                    }
+                }
+                #[cfg(feature = "geyser")]
+                IngestionBackend::Geyser { endpoint, x_token } => {
+                    for program in programs.clone() {
+                        let (endpoint, x_token, sync) = (endpoint.clone(), x_token.clone(), self.sync.clone());
+                        let task = format!("geyser_stream {}", clusters.label(&program));
+                        tasks.push(supervise(&self.health, task, move || {
+                            geyser::run_geyser_stream(endpoint.clone(), x_token.clone(), program, sync.clone())
+                        }));
+                    }
+                }
+            }
+
+            // One reconciliation loop per program so a slow or failing program doesn't stall the others.
+            for program in programs.clone() {
+                let (rpc, sync, health, resync) =
+                    (cluster.rpc.clone(), self.sync.clone(), self.health.clone(), self.resync.clone());
+                tasks.push(supervise(&self.health, format!("reconciliation {}", clusters.label(&program)), move || {
+                    sync::run_reconciliation(rpc.clone(), program, sync.clone(), health.clone(), resync.clone())
+                }));
+            }
+            if let Some(ws_url) = &cluster.logs_ws_url {
+                for program in programs.clone() {
+                    let (ws_url, sync) = (ws_url.clone(), self.sync.clone());
+                    tasks.push(supervise(&self.health, format!("log_subscription {}", clusters.label(&program)), move || {
+                        anchor_events::run_log_subscription(ws_url.clone(), program, sync.clone())
+                    }));
+                }
+            }
+            if let Some(config) = self.transaction_history {
+                for program in programs.clone() {
+                    let (rpc, sync) = (cluster.rpc.clone(), self.sync.clone());
+                    let task = format!("transaction_history {}", clusters.label(&program));
+                    tasks.push(supervise(&self.health, task, move || {
+                        transactions::run_transaction_history(rpc.clone(), program, sync.clone(), config)
+                    }));
+                }
+            }
+            if let Some(config) = &self.refresh {
+                let (rpc, programs, sync, config) =
+                    (cluster.rpc.clone(), programs.clone(), self.sync.clone(), config.clone());
+                tasks.push(supervise(&self.health, format!("targeted_refresh {}", cluster_name), move || {
+                    refresh::run_targeted_refresh(rpc.clone(), programs.clone(), sync.clone(), config.clone())
+                }));
+            }
+            // Rows from before program_id was recorded are finalized against the first cluster.
+            let (rpc, program_ids, sync) = (cluster.rpc.clone(), cluster.program_ids.clone(), self.sync.clone());
+            let name = cluster.name.clone();
+            tasks.push(supervise(&self.health, format!("finalization {}", cluster_name), move || {
+                sync::run_finalization(rpc.clone(), program_ids.clone(), name.clone(), index == 0, sync.clone())
+            }));
+        }
+        let sync = self.sync.clone();
+        tasks.push(supervise(&self.health, "webhook_delivery".to_string(), move || {
+            webhooks::run_webhook_delivery(sync.clone())
+        }));
+        let (sync, email) = (self.sync.clone(), self.alert_email.clone());
+        tasks.push(supervise(&self.health, "alert_delivery".to_string(), move || {
+            alerts::run_alert_delivery(sync.clone(), email.clone())
+        }));
+        tasks.push(self.spawn_lag_monitor(true));
+        if let Some(config) = self.liveness {
+            let (program_ids, sync) = (self.program_ids.clone(), self.sync.clone());
+            tasks.push(supervise(&self.health, "liveness_probe".to_string(), move || {
+                liveness::run_liveness_probe(program_ids.clone(), sync.clone(), config)
+            }));
+        }
+        if let Some(config) = &self.enrichment {
+            let (config, program_ids, sync) = (config.clone(), self.program_ids.clone(), self.sync.clone());
+            tasks.push(supervise(&self.health, "enrichment".to_string(), move || {
+                enrichment::run_enrichment(program_ids.clone(), sync.clone(), config.clone())
+            }));
+        }
+        #[cfg(feature = "kafka")]
+        if let Some(config) = &self.kafka {
+            let (config, sync) = (config.clone(), self.sync.clone());
+            tasks.push(supervise(&self.health, "kafka_relay".to_string(), move || {
+                kafka::run_kafka_relay(config.clone(), sync.clone())
+            }));
+        }
+        #[cfg(feature = "nats")]
+        if let Some(config) = &self.nats {
+            let (config, sync) = (config.clone(), self.sync.clone());
+            tasks.push(supervise(&self.health, "nats_publisher".to_string(), move || {
+                nats::run_nats_publisher(config.clone(), sync.clone())
+            }));
+        }
+        tasks
+    }
+
+    /// Runs a single reconciliation cycle per program and returns, for cron-style deployments
+    /// that don't keep the indexer running. Fails if any program's cycle failed. With
+    /// [`SyncConfig::dry_run`] set, each cycle only reads the database and logs what it would write.
+    pub async fn sync_once(&self) -> Result<(), AppError> {
+        // Held until this returns; a running leader already keeps the index in sync. A dry run
+        // only reads, without a write transaction or lock, so it can check a live deployment
+        // alongside its leader.
+        let _lock = if self.leader_election && !self.sync.config.dry_run {
+            match leader::try_acquire(&self.pool, leader::leader_lock_key(&self.program_ids)).await? {
+                Some(connection) => Some(connection),
+                None => {
+                    info!("Another instance holds the sync lock; skipping this run");
+                    return Ok(());
+                }
+            }
+        } else {
+            None
+        };
+        let (mut failed, programs) = (0, self.sync.clusters.programs());
+        for program in &programs {
+            let rpc = self.sync.clusters.rpc_for(program);
+            if sync::reconcile_once(rpc, program, &self.sync, &self.health).await.is_err() {
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            return Err(format!("Reconciliation failed for {} of {} programs", failed, programs.len()).into());
+        }
+        Ok(())
+    }
+
+    /// Compares each program's indexed nodes with the chain without writing anything.
+    pub async fn verify(&self) -> Result<Vec<DriftReport>, AppError> {
+        let programs = self.sync.clusters.programs();
+        let mut reports = Vec::with_capacity(programs.len());
+        for program in &programs {
+            let report = verify::verify_program(self.sync.clusters.rpc_for(program), program, &self.sync)
+                .await
+                .map_err(|e| format!("Failed to verify program {}: {}", self.sync.clusters.label(program), e))?;
+            reports.push(report);
+        }
+        Ok(reports)
+    }
+
+    /// Spawns ingestion and serves the API on `listener` until the server stops.
+    pub async fn serve(self, listener: TcpListener) -> Result<(), AppError> {
+        if self.leader_election {
+            tokio::spawn(leader::run_leader_election(self.clone(), true));
+        } else {
+            self.spawn_ingestion();
+        }
+        let app = self.router();
+        info!(addr = %listener.local_addr()?, "API server listening");
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+        Ok(())
+    }
+
+    /// Serves the API without syncing, for replicas behind a load balancer while a single
+    /// [`Self::run_ingestion`] process writes the shared database. Readiness, the indexed slot
+    /// and live change events are followed from the database instead.
+    pub async fn serve_api(self, listener: TcpListener) -> Result<(), AppError> {
+        self.spawn_follower();
+        let app = self.router();
+        info!(addr = %listener.local_addr()?, "API server listening (API only)");
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+        Ok(())
+    }
+
+    /// Follows another process's ingestion through the database, keeping readiness, the lag
+    /// metric and live change events current without alerting on lag.
+    pub(crate) fn spawn_follower(&self) -> Vec<JoinHandle<()>> {
+        let (pool, clusters, events, health) =
+            (self.pool.clone(), self.sync.clusters.clone(), self.events.clone(), self.health.clone());
+        let follower = supervise(&self.health, "database_follower".to_string(), move || {
+            sync::run_database_follower(pool.clone(), clusters.clone(), events.clone(), health.clone())
+        });
+        vec![follower, self.spawn_lag_monitor(false)]
+    }
+
+    /// The lag monitor, alerting only from the process running ingestion.
+    fn spawn_lag_monitor(&self, alerts: bool) -> JoinHandle<()> {
+        let (clusters, health, config) = (self.sync.clusters.clone(), self.health.clone(), self.lag.clone());
+        supervise(&self.health, "lag_monitor".to_string(), move || {
+            lag::run_lag_monitor(clusters.clone(), health.clone(), config.clone(), alerts)
+        })
+    }
+
+    /// Runs ingestion without the API until a task stops; tasks that panic are restarted instead.
+    /// With leader election on, this instance stands by until it holds the sync lock.
+    pub async fn run_ingestion(self) -> Result<(), AppError> {
+        info!("Running ingestion only; the API is not served");
+        if self.leader_election {
+            leader::run_leader_election(self, false).await;
+            return Ok(());
+        }
+        for task in self.spawn_ingestion() {
+            task.await?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+//! URI liveness: a background prober that sends an HTTP GET to every indexed node's URI and
+//! records in `node_liveness` whether it answered, how fast, and when it was last seen online.
+//! `GET /nodes?status=online` filters on the latest result.
+//!
+//! Every probe is also counted into `node_uptime_hourly`, from which the node API reports
+//! rolling 24h, 7d and 30d uptime percentages. Probes of https URIs also record the host's
+//! certificate (see [`crate::certificate`]), and every probe its host's addresses (see
+//! [`crate::dns`]). Probes only reach public addresses, redirects included, so the prober can't
+//! be used to map the indexer's internal network; other URIs are recorded offline.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::PgExecutor;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{debug, info, info_span, warn, Instrument};
+use utoipa::ToSchema;
+
+use crate::alerts::{enqueue_alerts, AlertEvent};
+use crate::certificate::CertificateCheck;
+use crate::config;
+use crate::dns::{self, DnsCache};
+use crate::sync::SyncContext;
+use crate::uri;
+use crate::AppError;
+
+// --- Default PROBE_INTERVAL_SECS between probe passes ---
+const DEFAULT_PROBE_INTERVAL_SECS: u64 = 300;
+
+// --- Default PROBE_TIMEOUT_SECS before a URI counts as offline ---
+const DEFAULT_PROBE_TIMEOUT_SECS: u64 = 5;
+
+// --- Default PROBE_CONCURRENCY: URIs probed at once ---
+const DEFAULT_PROBE_CONCURRENCY: usize = 16;
+
+// --- Default PROBE_DNS_TTL_SECS a hostname's resolution is reused for ---
+const DEFAULT_PROBE_DNS_TTL_SECS: u64 = 300;
+
+// --- Redirects followed before a probe gives up ---
+const MAX_PROBE_REDIRECTS: usize = 5;
+
+/// Settings for URI probing (`PROBE_URIS`, `PROBE_INTERVAL_SECS`, `PROBE_TIMEOUT_SECS`,
+/// `PROBE_CONCURRENCY`, `PROBE_DNS_TTL_SECS`).
+#[derive(Clone, Copy, Debug)]
+pub struct LivenessConfig {
+    /// Pause between passes over every indexed node.
+    pub interval: Duration,
+    /// Time a URI gets to answer with response headers.
+    pub timeout: Duration,
+    pub concurrency: usize,
+    /// How long a hostname's resolution, successful or not, is reused.
+    pub dns_ttl: Duration,
+}
+
+impl LivenessConfig {
+    /// `None` unless `PROBE_URIS=true`: probing sends requests to whatever URIs were registered
+    /// on chain, so it is opt-in.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let enabled = config::var("PROBE_URIS").is_ok_and(|value| value == "true" || value == "1");
+        if !enabled {
+            return Ok(None);
+        }
+        let positive = |name: &str, default: u64| match config::var(name) {
+            Ok(value) => value.parse::<u64>().ok().filter(|number| *number > 0).ok_or_else(|| {
+                format!("Invalid {} '{}': expected a positive number", config::source(name), value)
+            }),
+            Err(_) => Ok(default),
+        };
+        Ok(Some(Self {
+            interval: Duration::from_secs(positive("PROBE_INTERVAL_SECS", DEFAULT_PROBE_INTERVAL_SECS)?),
+            timeout: Duration::from_secs(positive("PROBE_TIMEOUT_SECS", DEFAULT_PROBE_TIMEOUT_SECS)?),
+            concurrency: positive("PROBE_CONCURRENCY", DEFAULT_PROBE_CONCURRENCY as u64)? as usize,
+            dns_ttl: Duration::from_secs(positive("PROBE_DNS_TTL_SECS", DEFAULT_PROBE_DNS_TTL_SECS)?),
+        }))
+    }
+}
+
+/// Liveness filter accepted by `GET /nodes` as `?status=`.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LivenessStatus {
+    /// The node's current URI answered its latest probe with a 2xx.
+    Online,
+    /// The node's current URI failed its latest probe.
+    Offline,
+    /// The node's current URI hasn't been probed, e.g. because it isn't an HTTP(S) URL.
+    Unknown,
+}
+
+impl LivenessStatus {
+    /// `WHERE` condition on `nodes` selecting the nodes in this state.
+    pub fn condition(self) -> &'static str {
+        match self {
+            Self::Online => {
+                "EXISTS (SELECT 1 FROM node_liveness l \
+                 WHERE l.pubkey = nodes.pubkey AND l.uri = nodes.uri AND l.online)"
+            }
+            Self::Offline => {
+                "EXISTS (SELECT 1 FROM node_liveness l \
+                 WHERE l.pubkey = nodes.pubkey AND l.uri = nodes.uri AND NOT l.online)"
+            }
+            Self::Unknown => {
+                "NOT EXISTS (SELECT 1 FROM node_liveness l WHERE l.pubkey = nodes.pubkey AND l.uri = nodes.uri)"
+            }
+        }
+    }
+}
+
+/// One row of `node_liveness`, as returned by `GET /nodes/:pubkey/liveness`.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Debug)]
+pub struct NodeLiveness {
+    pub pubkey: String,
+    /// URI the probe went to; older than the node's current URI if that changed since.
+    pub uri: String,
+    pub online: bool,
+    /// HTTP status of the response, if the URI answered at all.
+    pub status_code: Option<i32>,
+    /// Time until the response headers arrived.
+    pub latency_ms: Option<i32>,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+    pub last_seen_online: Option<DateTime<Utc>>,
+    /// Whether the host's certificate passed verification; `None` for http URIs and hosts
+    /// that presented none.
+    pub cert_valid: Option<bool>,
+    /// Whether the certificate names the host.
+    pub cert_hostname_match: Option<bool>,
+    pub cert_expires_at: Option<DateTime<Utc>>,
+    /// Whether the URI's host resolved; `None` for rows probed before resolution was recorded.
+    pub dns_ok: Option<bool>,
+    /// Addresses the host resolved to, or the URI's own IP address.
+    pub resolved_ips: Option<Vec<String>>,
+    pub dns_error: Option<String>,
+}
+
+// --- Columns selected into `NodeLiveness` ---
+pub const LIVENESS_COLUMNS: &str = "pubkey, uri, online, status_code, latency_ms, error, checked_at, last_seen_online, \
+     cert_valid, cert_hostname_match, cert_expires_at, dns_ok, resolved_ips, dns_error";
+
+/// Share of a node's URI probes that found it online, in percent. Each window is `None` until
+/// the node has been probed within it.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Clone, Copy, Debug, Default)]
+pub struct NodeUptime {
+    pub uptime_24h: Option<f64>,
+    pub uptime_7d: Option<f64>,
+    pub uptime_30d: Option<f64>,
+}
+
+/// Rolling uptime of each of `pubkeys` that has been probed in the last 30 days.
+pub async fn node_uptime(
+    executor: impl PgExecutor<'_>,
+    pubkeys: &[String],
+) -> Result<HashMap<String, NodeUptime>, sqlx::Error> {
+    let window = |interval: &str| {
+        format!(
+            "(100.0 * SUM(online_checks) FILTER (WHERE hour >= NOW() - INTERVAL '{0}') \
+             / NULLIF(SUM(checks) FILTER (WHERE hour >= NOW() - INTERVAL '{0}'), 0))::float8",
+            interval
+        )
+    };
+    let rows = sqlx::query_as::<_, (String, Option<f64>, Option<f64>, Option<f64>)>(&format!(
+        "SELECT pubkey, {}, {}, {} FROM node_uptime_hourly \
+         WHERE pubkey = ANY($1) AND hour >= NOW() - INTERVAL '30 days' GROUP BY pubkey",
+        window("24 hours"),
+        window("7 days"),
+        window("30 days")
+    ))
+    .bind(pubkeys)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(pubkey, uptime_24h, uptime_7d, uptime_30d)| (pubkey, NodeUptime { uptime_24h, uptime_7d, uptime_30d }))
+        .collect())
+}
+
+/// Outcome of probing one URI.
+struct Probe {
+    online: bool,
+    status_code: Option<i32>,
+    latency_ms: Option<i32>,
+    error: Option<String>,
+    certificate: Option<CertificateCheck>,
+    /// Addresses of the URI's host, or why it didn't resolve.
+    resolution: Result<Vec<IpAddr>, String>,
+}
+
+impl Probe {
+    fn failed(error: String, resolution: Result<Vec<IpAddr>, String>) -> Self {
+        Self { online: false, status_code: None, latency_ms: None, error: Some(error), certificate: None, resolution }
+    }
+}
+
+/// Probes the URI of every live node of `program_ids` once per interval, forever.
+pub async fn run_liveness_probe(program_ids: Vec<Pubkey>, sync: SyncContext, config: LivenessConfig) {
+    let dns = DnsCache::new(config.dns_ttl);
+    let client = reqwest::Client::builder()
+        .dns_resolver(Arc::new(dns.clone()))
+        .timeout(config.timeout)
+        .redirect(dns::redirect_policy(MAX_PROBE_REDIRECTS))
+        .user_agent(concat!("indexer/", env!("CARGO_PKG_VERSION")))
+        .tls_info(true)
+        .build()
+        .expect("Failed to build the URI probe HTTP client");
+    // Reads the certificate of hosts that failed verification, so it can be reported.
+    let inspector = reqwest::Client::builder()
+        .dns_resolver(Arc::new(dns.clone()))
+        .timeout(config.timeout)
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!("indexer/", env!("CARGO_PKG_VERSION")))
+        .danger_accept_invalid_certs(true)
+        .tls_info(true)
+        .build()
+        .expect("Failed to build the certificate inspection HTTP client");
+    async move {
+        loop {
+            let started = Instant::now();
+            match probe_nodes(&client, &inspector, &dns, &program_ids, &sync, config).await {
+                Ok((probed, online)) => {
+                    let duration_ms = started.elapsed().as_millis() as u64;
+                    info!(probed, online, duration_ms, "URI probe pass complete");
+                }
+                Err(e) => warn!(error = %e, "URI probe pass failed"),
+            }
+            sleep(config.interval).await;
+        }
+    }
+    .instrument(info_span!("liveness_probe"))
+    .await
+}
+
+/// Probes every node's URI and records the results. Returns how many were probed and online.
+async fn probe_nodes(
+    client: &reqwest::Client,
+    inspector: &reqwest::Client,
+    dns: &DnsCache,
+    program_ids: &[Pubkey],
+    sync: &SyncContext,
+    config: LivenessConfig,
+) -> Result<(usize, usize), AppError> {
+    let program_ids: Vec<String> = program_ids.iter().map(Pubkey::to_string).collect();
+    let nodes: Vec<(String, String)> = sqlx::query_as(
+        "SELECT pubkey, uri FROM nodes WHERE (program_id = ANY($1) OR program_id IS NULL) AND deleted_at IS NULL",
+    )
+    .bind(&program_ids)
+    .fetch_all(&sync.pool)
+    .await?;
+    // Only valid URIs, which are web URLs, can be probed; others (IPFS, free text) stay unknown.
+    let mut pending = nodes.into_iter().filter(|(_, uri)| uri::is_valid(uri));
+
+    let (mut probed, mut online) = (0, 0);
+    let mut probes = JoinSet::new();
+    loop {
+        while probes.len() < config.concurrency
+            && let Some((pubkey, uri)) = pending.next()
+        {
+            let (client, inspector, dns) = (client.clone(), inspector.clone(), dns.clone());
+            probes.spawn(async move {
+                let probe = probe_uri(&client, &inspector, &dns, &uri).await;
+                (pubkey, uri, probe)
+            });
+        }
+        let Some(joined) = probes.join_next().await else { break };
+        let (pubkey, uri, probe) = joined?;
+        // Dropping `probes` on the way out cancels the probes still running.
+        let Some(_write) = sync.pause.begin_write().await else {
+            info!("Ingestion is paused; ending URI probe pass early");
+            break;
+        };
+        record_probe(sync, &pubkey, &uri, &probe).await?;
+        debug!(%pubkey, %uri, online = probe.online, status_code = probe.status_code, "Probed node URI");
+        probed += 1;
+        online += probe.online as usize;
+    }
+    // Buckets older than the longest reported window are no longer needed.
+    sqlx::query("DELETE FROM node_uptime_hourly WHERE hour < NOW() - INTERVAL '30 days'")
+        .execute(&sync.pool)
+        .await?;
+    Ok((probed, online))
+}
+
+async fn probe_uri(client: &reqwest::Client, inspector: &reqwest::Client, dns: &DnsCache, uri: &str) -> Probe {
+    let resolution = resolve_host(dns, uri).await;
+    let ips = match &resolution {
+        Ok(ips) => ips,
+        Err(e) => return Probe::failed(format!("DNS resolution failed: {}", e), resolution),
+    };
+    if !ips.iter().any(|ip| dns::is_public(*ip)) {
+        return Probe::failed("Host has no public address".to_string(), resolution);
+    }
+    // Resolution is timed apart, so a cold cache doesn't count against the host's latency.
+    let started = Instant::now();
+    // Only the status line and headers are awaited; the body is never read.
+    let (mut probe, verified, presented) = match client.get(uri).send().await {
+        Ok(response) => {
+            let status = response.status();
+            let probe = Probe {
+                online: status.is_success(),
+                status_code: Some(status.as_u16() as i32),
+                latency_ms: Some(started.elapsed().as_millis().min(i32::MAX as u128) as i32),
+                error: (!status.is_success()).then(|| format!("HTTP {}", status)),
+                certificate: None,
+                resolution,
+            };
+            // Any response means the URI's host passed verification, but the certificate at
+            // hand is another host's if a redirect led there.
+            let same_host = reqwest::Url::parse(uri).is_ok_and(|url| {
+                url.host_str() == response.url().host_str()
+                    && url.port_or_known_default() == response.url().port_or_known_default()
+            });
+            (probe, true, same_host.then(|| peer_certificate(&response)).flatten())
+        }
+        Err(e) => (Probe::failed(describe_request_error(&e), resolution), false, None),
+    };
+    probe.certificate = check_certificate(inspector, uri, verified, presented).await;
+    probe
+}
+
+/// Addresses of `uri`'s host: itself for an IP address, else through `dns`.
+async fn resolve_host(dns: &DnsCache, uri: &str) -> Result<Vec<IpAddr>, String> {
+    let url = reqwest::Url::parse(uri).map_err(|e| e.to_string())?;
+    let host = url.host_str().ok_or("URI has no host")?;
+    // IPv6 hosts come bracketed in URLs.
+    match host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        Ok(ip) => Ok(vec![ip]),
+        Err(_) => dns.lookup(host).await,
+    }
+}
+
+/// The certificate check of an https `uri`, from the probe's handshake when it passed
+/// verification (`verified`) and left the certificate at hand, or else from one that accepts any
+/// certificate. `None` for http URIs and hosts that present none.
+async fn check_certificate(
+    inspector: &reqwest::Client,
+    uri: &str,
+    verified: bool,
+    presented: Option<Vec<u8>>,
+) -> Option<CertificateCheck> {
+    let url = reqwest::Url::parse(uri).ok().filter(|url| url.scheme() == "https")?;
+    let host = url.host_str()?.to_string();
+    let certificate = match presented {
+        Some(certificate) => certificate,
+        None => peer_certificate(&inspector.get(url).send().await.ok()?)?,
+    };
+    CertificateCheck::inspect(&certificate, &host, verified)
+}
+
+/// The DER-encoded leaf certificate the server of `response` presented.
+fn peer_certificate(response: &reqwest::Response) -> Option<Vec<u8>> {
+    response.extensions().get::<reqwest::tls::TlsInfo>()?.peer_certificate().map(<[u8]>::to_vec)
+}
+
+/// reqwest's own message leaves out the cause (DNS, TLS, timeout, refused), so append the
+/// source chain.
+pub(crate) fn describe_request_error(e: &reqwest::Error) -> String {
+    let mut error = e.to_string();
+    let mut source = std::error::Error::source(e);
+    while let Some(cause) = source {
+        error = format!("{}: {}", error, cause);
+        source = cause.source();
+    }
+    error
+}
+
+async fn record_probe(sync: &SyncContext, pubkey: &str, uri: &str, probe: &Probe) -> Result<(), AppError> {
+    let mut tx = sync.pool.begin().await?;
+    let was_online: Option<bool> =
+        sqlx::query_scalar("SELECT online FROM node_liveness WHERE pubkey = $1 AND uri = $2 FOR UPDATE")
+            .bind(pubkey)
+            .bind(uri)
+            .fetch_optional(&mut *tx)
+            .await?;
+    // The node may have been pruned while it was probed, which would violate the foreign keys.
+    sqlx::query(
+        r#"
+        INSERT INTO node_liveness
+            (pubkey, uri, online, status_code, latency_ms, error, checked_at, last_seen_online,
+             cert_valid, cert_hostname_match, cert_expires_at, dns_ok, resolved_ips, dns_error)
+        SELECT $1, $2, $3, $4, $5, $6, NOW(), CASE WHEN $3 THEN NOW() END, $7, $8, $9, $10, $11, $12
+        WHERE EXISTS (SELECT 1 FROM nodes WHERE pubkey = $1)
+        ON CONFLICT (pubkey) DO UPDATE
+        SET uri = EXCLUDED.uri,
+            online = EXCLUDED.online,
+            status_code = EXCLUDED.status_code,
+            latency_ms = EXCLUDED.latency_ms,
+            error = EXCLUDED.error,
+            checked_at = EXCLUDED.checked_at,
+            cert_valid = EXCLUDED.cert_valid,
+            cert_hostname_match = EXCLUDED.cert_hostname_match,
+            cert_expires_at = EXCLUDED.cert_expires_at,
+            dns_ok = EXCLUDED.dns_ok,
+            resolved_ips = EXCLUDED.resolved_ips,
+            dns_error = EXCLUDED.dns_error,
+            -- A new URI starts without an online history.
+            last_seen_online = CASE
+                WHEN EXCLUDED.online THEN EXCLUDED.checked_at
+                WHEN node_liveness.uri = EXCLUDED.uri THEN node_liveness.last_seen_online
+            END
+        "#,
+    )
+    .bind(pubkey)
+    .bind(uri)
+    .bind(probe.online)
+    .bind(probe.status_code)
+    .bind(probe.latency_ms)
+    .bind(&probe.error)
+    .bind(probe.certificate.map(|certificate| certificate.valid))
+    .bind(probe.certificate.map(|certificate| certificate.hostname_match))
+    .bind(probe.certificate.and_then(|certificate| certificate.expires_at))
+    .bind(probe.resolution.is_ok())
+    .bind(probe.resolution.as_ref().ok().map(|ips| ips.iter().map(IpAddr::to_string).collect::<Vec<_>>()))
+    .bind(probe.resolution.as_ref().err())
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        INSERT INTO node_uptime_hourly (pubkey, hour, checks, online_checks, latency_ms_total, latency_checks)
+        SELECT $1, date_trunc('hour', NOW()), 1, CASE WHEN $2 THEN 1 ELSE 0 END,
+               COALESCE($3, 0), CASE WHEN $3 IS NULL THEN 0 ELSE 1 END
+        WHERE EXISTS (SELECT 1 FROM nodes WHERE pubkey = $1)
+        ON CONFLICT (pubkey, hour) DO UPDATE
+        SET checks = node_uptime_hourly.checks + 1,
+            online_checks = node_uptime_hourly.online_checks + EXCLUDED.online_checks,
+            latency_ms_total = node_uptime_hourly.latency_ms_total + EXCLUDED.latency_ms_total,
+            latency_checks = node_uptime_hourly.latency_checks + EXCLUDED.latency_checks
+        "#,
+    )
+    .bind(pubkey)
+    .bind(probe.online)
+    .bind(probe.latency_ms.map(i64::from))
+    .execute(&mut *tx)
+    .await?;
+    // Only a transition alerts, so a node that stays down or was never up doesn't repeat it.
+    if was_online == Some(true) && !probe.online {
+        let queued = enqueue_alerts(&mut *tx, pubkey, AlertEvent::Offline, probe.error.as_deref()).await?;
+        debug!(%pubkey, queued, "Node went offline");
+    }
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Local development mode, what `serve` runs with a `sqlite:` `DATABASE_URL`: each program is
+//! polled with getProgramAccounts every `POLL_INTERVAL_SECS` into a [`Storage`], and the core node
+//! routes (`/healthz`, `/nodes`, `/nodes/:pubkey`) are served from it. Subscriptions, history,
+//! changes, events, liveness probing and auth all need Postgres and are left out.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    middleware,
+    routing::get,
+    Json, Router,
+};
+use serde::Deserialize;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_sdk::pubkey::Pubkey;
+use tokio::net::TcpListener;
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::api::{NodeWithUptime, NodesPage, PageParams, TotalCount};
+use crate::decode::deserialize_node_device;
+use crate::error::{self, ApiError, Path, Query};
+use crate::liveness::NodeUptime;
+use crate::request_id;
+use crate::rpc::SolanaRpc;
+use crate::storage::Storage;
+use crate::store::{ApiNode, NodeRecord, RawAccount};
+use crate::sync::{SyncConfig, GPA_SHARD_OFFSET};
+use crate::AppError;
+
+/// Migrates `store`, then polls every program into it and serves the node routes on `listener`
+/// until one of them fails.
+pub async fn run(
+    store: Arc<dyn Storage>,
+    rpc: Arc<SolanaRpc>,
+    program_ids: Vec<Pubkey>,
+    config: SyncConfig,
+    listener: TcpListener,
+) -> Result<(), AppError> {
+    store.migrate().await?;
+    let config = Arc::new(config);
+    for program_id in program_ids {
+        let (store, rpc, config) = (store.clone(), rpc.clone(), config.clone());
+        let span = info_span!("local_sync", %program_id);
+        tokio::spawn(async move { poll(store, rpc, program_id, config).await }.instrument(span));
+    }
+    info!(addr = %listener.local_addr()?, "Serving the local node API");
+    axum::serve(listener, router(store)).await?;
+    Ok(())
+}
+
+async fn poll(store: Arc<dyn Storage>, rpc: Arc<SolanaRpc>, program_id: Pubkey, config: Arc<SyncConfig>) {
+    loop {
+        if let Err(e) = sync_program(store.as_ref(), &rpc, &program_id, &config).await {
+            error!(error = %e, "Local sync failed; retrying next cycle");
+        }
+        tokio::time::sleep(config.poll_interval).await;
+    }
+}
+
+/// One full snapshot of `program_id`'s NodeDevice accounts, written over what `store` holds.
+async fn sync_program(
+    store: &dyn Storage,
+    rpc: &SolanaRpc,
+    program_id: &Pubkey,
+    config: &SyncConfig,
+) -> Result<(), AppError> {
+    let request = RpcProgramAccountsConfig {
+        filters: Some(config.filters.clone()),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(config.commitment),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let (context_slot, accounts) =
+        rpc.get_program_accounts_sharded(program_id, request, GPA_SHARD_OFFSET, config.gpa_shard_bytes).await?;
+    let slot = match context_slot {
+        Some(slot) => slot,
+        None => rpc.get_slot().await?,
+    };
+    let seed = config.node_device_seed.as_deref();
+    let mut records = Vec::with_capacity(accounts.len());
+    for (pubkey, account) in accounts {
+        // Without a decode_failures table, undecodable accounts are only logged.
+        let node = match deserialize_node_device(&account.data) {
+            Ok(node) => node,
+            Err(e) => {
+                warn!(%pubkey, error = %e, "Failed to decode account");
+                continue;
+            }
+        };
+        let raw = RawAccount {
+            data: account.data,
+            lamports: account.lamports,
+            owner: account.owner,
+            rent_epoch: account.rent_epoch,
+        };
+        records.push(NodeRecord::new(ApiNode::observed(pubkey.to_string(), node, program_id, None, slot, seed), raw));
+    }
+    let summary = store.replace_nodes(program_id, &records, slot).await?;
+    info!(slot, nodes = records.len(), written = summary.written, removed = summary.removed, "Synced nodes");
+    Ok(())
+}
+
+fn router(store: Arc<dyn Storage>) -> Router {
+    Router::new()
+        .route("/healthz", get(|| async { Json(serde_json::json!({ "status": "ok" })) }))
+        .route("/nodes", get(list_nodes))
+        .route("/nodes/:pubkey", get(get_node))
+        .fallback(error::route_not_found)
+        .layer(middleware::from_fn(request_id::assign))
+        .with_state(store)
+}
+
+#[derive(Deserialize)]
+struct LocalNodesQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+    /// Only nodes of this program.
+    program: Option<String>,
+    /// Also list nodes whose URI failed validation.
+    include_invalid: Option<bool>,
+    /// Also list nodes whose address isn't their expected PDA.
+    include_unverified: Option<bool>,
+    /// Only nodes that are (`true`) or aren't (`false`) rent-exempt.
+    rent_exempt: Option<bool>,
+}
+
+/// Uptime and operator metadata are always `null`: nothing probes URIs in local mode, and
+/// operators can't sign in.
+fn without_uptime(node: ApiNode) -> NodeWithUptime {
+    NodeWithUptime { node, uptime: NodeUptime::default(), operator_metadata: None }
+}
+
+async fn list_nodes(
+    State(store): State<Arc<dyn Storage>>,
+    Query(query): Query<LocalNodesQuery>,
+) -> Result<(TotalCount, Json<NodesPage>), ApiError> {
+    if let Some(program) = &query.program
+        && Pubkey::from_str(program).is_err()
+    {
+        return Err(ApiError::invalid_pubkey(program));
+    }
+    let (limit, offset) = PageParams { limit: query.limit, offset: query.offset }.resolve();
+    let (nodes, total) = store
+        .page_nodes(
+            query.program.as_deref(),
+            query.include_invalid.unwrap_or(false),
+            query.include_unverified.unwrap_or(false),
+            query.rent_exempt,
+            limit,
+            offset,
+        )
+        .await
+        .map_err(|e| ApiError::database(e, "Failed to fetch nodes from database"))?;
+    let next_offset = (offset + (nodes.len() as i64) < total).then(|| offset + nodes.len() as i64);
+    let nodes = nodes.into_iter().map(without_uptime).collect();
+    Ok((TotalCount(total), Json(NodesPage { nodes, total, limit, offset, next_offset })))
+}
+
+async fn get_node(
+    State(store): State<Arc<dyn Storage>>,
+    Path(pubkey): Path<String>,
+) -> Result<Json<NodeWithUptime>, ApiError> {
+    if Pubkey::from_str(&pubkey).is_err() {
+        return Err(ApiError::invalid_pubkey(&pubkey));
+    }
+    match store.get_node(&pubkey).await.map_err(|e| ApiError::database(e, "Failed to fetch node from database"))? {
+        Some(node) => Ok(Json(without_uptime(node))),
+        None => Err(ApiError::not_found("node_not_found", format!("No node indexed with pubkey {}", pubkey))),
+    }
+}
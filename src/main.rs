@@ -1,209 +1,559 @@
-use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
-    routing::get,
-    Router,
-};
-use borsh::BorshDeserialize;
-use serde::Serialize;
-use solana_client::rpc_client::RpcClient;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand, ValueEnum};
 use solana_sdk::pubkey::Pubkey;
-use sqlx::postgres::{PgPool, PgPoolOptions};
-use std::str::FromStr;
-use tokio::time::{sleep, Duration};
-use tower_http::cors::{Any, CorsLayer};
+use sqlx::postgres::PgPoolOptions;
+use tokio::time::Duration;
+use tracing::info;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
+use indexer::alerts::AlertEmailConfig;
+use indexer::auth::{create_api_key, AuthConfig};
+use indexer::cluster::{self, ClusterConfig};
+use indexer::compression::CompressionConfig;
+use indexer::cors::CorsConfig;
+use indexer::fixtures::RpcFixtures;
+use indexer::config::{self, DEFAULT_CONFIG_PATH};
+#[cfg(feature = "grpc")]
+use indexer::grpc::GrpcConfig;
+#[cfg(feature = "kafka")]
+use indexer::kafka::KafkaConfig;
+#[cfg(feature = "otel")]
+use indexer::telemetry::{self, Telemetry};
+#[cfg(feature = "sentry")]
+use indexer::reporting;
+use indexer::rpc::{RateLimit, RetryPolicy, SolanaRpc};
+use indexer::store::{self, ApiNode};
+use indexer::sync::{SyncConfig, DEFAULT_READY_MAX_SYNC_AGE_SECS};
+use indexer::response_cache::ResponseCacheConfig;
+use indexer::throttle::ClientRateLimit;
+use indexer::lag::LagConfig;
+use indexer::enrichment::EnrichmentConfig;
+use indexer::liveness::LivenessConfig;
+use indexer::refresh::RefreshConfig;
+#[cfg(feature = "nats")]
+use indexer::nats::NatsConfig;
+use indexer::pool::PoolConfig;
+use indexer::storage;
+use indexer::transactions::TransactionHistoryConfig;
+use indexer::verify::DriftReport;
+use indexer::{AppError, IndexerBuilder, IngestionBackend, DEFAULT_RPC_URL, DEFAULT_STARTUP_TIMEOUT_SECS};
 
-// --- Type alias for our thread-safe error type ---
-type AppError = Box<dyn std::error::Error + Send + Sync>;
+// --- Program indexed when neither --program-id nor PROGRAM_ID is given (devnet) ---
+const DEFAULT_PROGRAM_ID: &str = "5LzZhK83HbsJPTC877hRcfCZLg1cZvqDUQgLL3BxLYb4";
 
+/// Indexes a Solana program's NodeDevice accounts into Postgres and serves them over HTTP.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Config file; environment variables override its values [default: ./config.toml if present]
+    #[arg(long, global = true, env = "CONFIG_FILE")]
+    config: Option<PathBuf>,
+    /// Programs to index, comma-separated, each optionally `<cluster>:<id>` for a cluster in CLUSTERS
+    /// [default: PROGRAM_ID, then the devnet deployment]
+    #[arg(long, global = true, value_delimiter = ',')]
+    program_id: Vec<String>,
+    /// What `serve` runs; several `api` processes can share one `indexer` [default: RUN_MODE, then all]
+    #[arg(long, global = true, value_enum)]
+    mode: Option<RunMode>,
+    /// Replay account snapshots from this fixture file instead of calling RPC_URL; polling only
+    #[arg(long, global = true, env = "MOCK_RPC", value_name = "FIXTURES")]
+    mock_rpc: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
 
-#[derive(BorshDeserialize, Debug)]
-pub struct NetworkStats {
-    pub total_nodes: u64,
+#[derive(Subcommand)]
+enum Command {
+    /// Run ingestion and/or the API server, as selected by --mode (the default).
+    Serve,
+    /// Run one reconciliation cycle per program and exit.
+    SyncOnce {
+        /// Log the nodes each cycle would add, update and prune without writing; skips migrations.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Compare the indexed nodes with the chain and report drift; exits non-zero on drift.
+    Verify {
+        /// Print the report as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write the indexed node list to stdout or a file.
+    Dump {
+        #[arg(long, value_enum, default_value_t = DumpFormat::Json)]
+        format: DumpFormat,
+        /// Output file [default: stdout]
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+        /// Include soft-deleted nodes.
+        #[arg(long)]
+        include_deleted: bool,
+    },
+    /// Create an API key and print it; only its hash is stored.
+    CreateApiKey {
+        /// Unique label for the key's owner.
+        #[arg(long)]
+        name: String,
+        /// Allow the key to use the /admin routes.
+        #[arg(long)]
+        admin: bool,
+    },
 }
 
-#[derive(BorshDeserialize, Debug, Serialize)]
-pub struct NodeDevice {
-    pub authority: Pubkey,
-    pub uri: String,
+#[derive(Clone, Copy, ValueEnum)]
+enum RunMode {
+    /// HTTP API only, following the database another process syncs.
+    Api,
+    /// Ingestion only, no HTTP server.
+    Indexer,
+    /// Both in one process.
+    All,
 }
 
-#[derive(Serialize, sqlx::FromRow)]
-pub struct ApiNode {
-    pub pubkey: String,
-    pub authority: String,
-    pub uri: String,
+#[derive(Clone, Copy, ValueEnum)]
+enum DumpFormat {
+    Json,
+    Csv,
 }
 
-async fn get_nodes(
-    State(pool): State<PgPool>,
-) -> Result<Json<Vec<ApiNode>>, (StatusCode, String)> {
-    println!("=> GET /nodes - Fetching nodes from database...");
+/// Loads the config file named by `--config <PATH>` or `CONFIG_FILE`, or `config.toml` in
+/// the working directory when it exists. Environment variables still override its values.
+fn load_config_file(explicit: Option<PathBuf>) -> Result<(), AppError> {
+    let required = explicit.is_some();
+    let path = explicit.unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+    if config::load(&path, required)? {
+        info!(path = %path.display(), "Loaded config file");
+    }
+    Ok(())
+}
+
+/// Reads the programs to index from `--program-id <IDS>` or `PROGRAM_ID` (comma-separated),
+/// falling back to the devnet deployment, and rejects anything that is not a valid pubkey.
+/// Each comes with the cluster its entry names, if any.
+fn resolve_program_ids(from_flag: Vec<String>) -> Result<Vec<(Option<String>, Pubkey)>, AppError> {
+    let raw = if from_flag.is_empty() {
+        config::var("PROGRAM_ID").unwrap_or_else(|_| DEFAULT_PROGRAM_ID.to_string())
+    } else {
+        from_flag.join(",")
+    };
+
+    let mut program_ids = Vec::new();
+    for entry in raw.split(',').map(str::trim).filter(|id| !id.is_empty()) {
+        let program = cluster::parse_program(entry)?;
+        if !program_ids.contains(&program) {
+            program_ids.push(program);
+        }
+    }
+    if program_ids.is_empty() {
+        return Err("At least one program ID must be configured".into());
+    }
+    Ok(program_ids)
+}
 
-    let nodes = sqlx::query_as::<_, ApiNode>("SELECT pubkey, authority, uri FROM nodes")
-        .fetch_all(&pool)
-        .await
-        .map_err(|e| {
-            eprintln!("🔥 Database query failed: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch nodes from database".to_string())
-        })?;
+/// Installs the global tracing subscriber. Levels come from `RUST_LOG` (default `info`);
+/// `LOG_FORMAT=json` switches to newline-delimited JSON for log aggregation. One-shot
+/// commands log to stderr so their stdout output stays clean. With `--features otel` and an
+/// OTLP endpoint configured, the same spans are exported too.
+fn init_tracing(to_stderr: bool) -> Result<TracingGuard, AppError> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let writer = if to_stderr { BoxMakeWriter::new(std::io::stderr) } else { BoxMakeWriter::new(std::io::stdout) };
+    let fmt = tracing_subscriber::fmt::layer().with_writer(writer);
+    let fmt = if std::env::var("LOG_FORMAT").is_ok_and(|format| format == "json") {
+        fmt.json().boxed()
+    } else {
+        fmt.boxed()
+    };
+    let registry = tracing_subscriber::registry().with(filter).with(fmt);
+    #[cfg(feature = "otel")]
+    let (registry, telemetry) = {
+        let (otel, telemetry) = telemetry::layer()?.unzip();
+        (registry.with(otel), telemetry)
+    };
+    #[cfg(feature = "sentry")]
+    let (registry, sentry) = {
+        let (breadcrumbs, sentry) = reporting::init()?.unzip();
+        (registry.with(breadcrumbs), sentry)
+    };
+    registry.init();
+    Ok(TracingGuard {
+        #[cfg(feature = "otel")]
+        _telemetry: telemetry,
+        #[cfg(feature = "sentry")]
+        _sentry: sentry,
+    })
+}
 
-    println!("<= GET /nodes - Responding with {} nodes.", nodes.len());
-    Ok(Json(nodes))
+/// Held by `main` for the life of the process; dropping it flushes exported spans and
+/// pending error reports.
+struct TracingGuard {
+    #[cfg(feature = "otel")]
+    _telemetry: Option<Telemetry>,
+    #[cfg(feature = "sentry")]
+    _sentry: Option<sentry::ClientInitGuard>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), AppError> {
-    let rpc_url = std::env::var("RPC_URL").unwrap_or_else(|_| "https://api.devnet.solana.com".to_string());
-    let client = RpcClient::new(rpc_url.to_string());
-    let program_id = "5LzZhK83HbsJPTC877hRcfCZLg1cZvqDUQgLL3BxLYb4";
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-
-    let pool = PgPoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await?;
-    println!("✅ Successfully connected to the database!");
-
-    let slot = client.get_slot()?;
-    println!("✅ Connected to Solana! Current slot: {}", slot);
-
-    let pool_clone = pool.clone();
-    tokio::spawn(async move {
-        loop {
-            println!("\n🔄 [Background Task] Polling Solana program accounts...");
-            if let Err(e) = fetch_program_accounts(&rpc_url, program_id, &pool_clone).await {
-                eprintln!("⚠️ [Background Task] Error during fetch: {}", e);
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Serve);
+    let _tracing = init_tracing(!matches!(command, Command::Serve))?;
+    load_config_file(cli.config)?;
+    let programs = resolve_program_ids(cli.program_id)?;
+    for (cluster, program_id) in &programs {
+        info!(%program_id, cluster = cluster.as_deref(), "Indexing program");
+    }
+    let program_ids: Vec<Pubkey> = programs.iter().map(|(_, program_id)| *program_id).collect();
+    let database_url = config::var("DATABASE_URL").map_err(|_| "DATABASE_URL (or database.url) must be set")?;
+    if storage::is_sqlite(&database_url) && !matches!(command, Command::Serve | Command::Dump { .. }) {
+        return Err("A sqlite: DATABASE_URL only supports the serve and dump commands".into());
+    }
+    if storage::is_sqlite(&database_url)
+        && matches!(command, Command::Serve)
+        && (config::var("CLUSTERS").is_ok() || programs.iter().any(|(cluster, _)| cluster.is_some()))
+    {
+        return Err("A sqlite: DATABASE_URL indexes RPC_URL's cluster only; CLUSTERS needs Postgres".into());
+    }
+
+    match command {
+        Command::Serve if storage::is_sqlite(&database_url) => {
+            serve_local(&database_url, program_ids, cli.mock_rpc.as_deref()).await
+        }
+        Command::Serve => {
+            let mode = match cli.mode {
+                Some(mode) => mode,
+                None => match config::var("RUN_MODE") {
+                    Ok(value) => RunMode::from_str(&value, true).map_err(|_| {
+                        format!("Invalid {} '{}': expected api, indexer or all", config::source("RUN_MODE"), value)
+                    })?,
+                    Err(_) => RunMode::All,
+                },
+            };
+            #[cfg(feature = "grpc")]
+            let grpc = GrpcConfig::from_env()?;
+            #[cfg(not(feature = "grpc"))]
+            if config::var("GRPC_PORT").is_ok() {
+                return Err(format!("{} requires building with `--features grpc`", config::source("GRPC_PORT")).into());
+            }
+            let builder = indexer_builder(database_url, programs, cli.mock_rpc.as_deref())?;
+            if let RunMode::Indexer = mode {
+                return builder.build().await?.run_ingestion().await;
             }
-            println!("✅ [Background Task] Polling cycle complete. Sleeping for 10 seconds...");
-            sleep(Duration::from_secs(10)).await;
+            // Bound before connecting, so /healthz answers while startup retries.
+            let port = config::var("PORT").unwrap_or_else(|_| "8081".to_string());
+            let listener = std::net::TcpListener::bind(format!("0.0.0.0:{}", port))?;
+            listener.set_nonblocking(true)?;
+            let indexer = indexer::while_starting(&listener, builder.build()).await??;
+            let listener = tokio::net::TcpListener::from_std(listener)?;
+            let http = {
+                let indexer = indexer.clone();
+                async move {
+                    match mode {
+                        RunMode::Api => indexer.serve_api(listener).await,
+                        _ => indexer.serve(listener).await,
+                    }
+                }
+            };
+            #[cfg(feature = "grpc")]
+            if let Some(grpc) = grpc {
+                let grpc_listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", grpc.port)).await?;
+                return tokio::try_join!(http, indexer.serve_grpc(grpc_listener)).map(|_| ());
+            }
+            http.await
+        }
+        Command::SyncOnce { dry_run } => {
+            let mut builder = indexer_builder(database_url, programs, cli.mock_rpc.as_deref())?;
+            if dry_run {
+                let sync_config = SyncConfig { dry_run: true, ..SyncConfig::from_env()? };
+                builder = builder.sync_config(sync_config).run_migrations(false);
+            }
+            builder.build().await?.sync_once().await
         }
-    });
+        Command::Verify { json } => {
+            let indexer = indexer_builder(database_url, programs, cli.mock_rpc.as_deref())?.build().await?;
+            let reports = indexer.verify().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&reports)?);
+            } else {
+                print_drift(&reports);
+            }
+            let drifted = reports.iter().filter(|report| report.has_drift()).count();
+            if drifted > 0 {
+                return Err(format!("Drift detected in {} of {} programs", drifted, reports.len()).into());
+            }
+            Ok(())
+        }
+        Command::Dump { format, output, include_deleted } => {
+            // Only reads the database, so neither RPC nor migrations are involved.
+            let pool_config = PoolConfig { max_connections: 1, ..PoolConfig::default() };
+            let nodes = storage::connect(&database_url, &pool_config)
+                .await?
+                .list_nodes(&program_ids, include_deleted)
+                .await?;
+            let mut out: Box<dyn Write> = match &output {
+                Some(path) => Box::new(std::fs::File::create(path)?),
+                None => Box::new(std::io::stdout().lock()),
+            };
+            match format {
+                DumpFormat::Json => {
+                    serde_json::to_writer_pretty(&mut out, &nodes)?;
+                    writeln!(out)?;
+                }
+                DumpFormat::Csv => write_csv(&mut out, &nodes)?,
+            }
+            out.flush()?;
+            info!(nodes = nodes.len(), "Dumped nodes");
+            Ok(())
+        }
+        Command::CreateApiKey { name, admin } => {
+            let pool = PgPoolOptions::new().max_connections(1).connect(&database_url).await?;
+            if run_migrations() {
+                store::migrate(&pool).await?;
+            }
+            let (record, key) = create_api_key(&pool, &name, admin)
+                .await
+                .map_err(|e| format!("Failed to create API key '{}': {}", name, e))?;
+            info!(id = record.id, name = %record.name, admin, "Created API key");
+            println!("{}", key);
+            Ok(())
+        }
+    }
+}
+
+/// `serve` against SQLite: the local development mode of [`indexer::local`], polling the chain
+/// (or the `--mock-rpc` fixtures) and serving the core node routes on `PORT` without Postgres.
+async fn serve_local(database_url: &str, program_ids: Vec<Pubkey>, mock_rpc: Option<&Path>) -> Result<(), AppError> {
+    let sync_config = SyncConfig::from_env()?;
+    let rpc = match mock_rpc {
+        Some(path) => SolanaRpc::from_fixtures(RpcFixtures::load(path)?, sync_config.commitment),
+        None => rpc_client(&sync_config)?,
+    };
+    let store = storage::connect(database_url, &PoolConfig::default()).await?;
+    let port = config::var("PORT").unwrap_or_else(|_| "8081".to_string());
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
+    indexer::local::run(store, std::sync::Arc::new(rpc), program_ids, sync_config, listener).await
+}
 
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+/// A client for the `RPC_URL` endpoints.
+fn rpc_client(sync_config: &SyncConfig) -> Result<SolanaRpc, AppError> {
+    let rpc_urls: Vec<String> = config::var("RPC_URL")
+        .unwrap_or_else(|_| DEFAULT_RPC_URL.to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(String::from)
+        .collect();
+    if rpc_urls.is_empty() {
+        return Err(format!("{} lists no endpoints", config::source("RPC_URL")).into());
+    }
+    Ok(SolanaRpc::new(&rpc_urls, sync_config.commitment, RetryPolicy::from_env()?, RateLimit::from_env()?))
+}
 
-    let app = Router::new()
-        .route("/nodes", get(get_nodes))
-        .with_state(pool)
-        .layer(cors);
+/// Assembles the builder from the environment and config file, shared by every command that
+/// connects to the chain.
+fn indexer_builder(
+    database_url: String,
+    programs: Vec<(Option<String>, Pubkey)>,
+    mock_rpc: Option<&Path>,
+) -> Result<IndexerBuilder, AppError> {
+    let mut builder = IndexerBuilder::new()
+        .database_url(database_url)
+        .pool_config(PoolConfig::from_env()?)
+        .sync_config(SyncConfig::from_env()?)
+        .retry_policy(RetryPolicy::from_env()?)
+        .rate_limit(RateLimit::from_env()?)
+        .transaction_history(TransactionHistoryConfig::from_env()?)
+        .liveness(LivenessConfig::from_env()?)
+        .enrichment(EnrichmentConfig::from_env()?)
+        .lag(LagConfig::from_env()?)
+        .targeted_refresh(RefreshConfig::from_env()?)
+        .alert_email(AlertEmailConfig::from_env()?);
 
-    let port = std::env::var("PORT").unwrap_or_else(|_| "8081".to_string());
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
-    println!("\n🚀 API server listening on http://{}", listener.local_addr()?);
-    println!("   Try accessing https://indexer-o06a.onrender.com/nodes in your browser.");
-    axum::serve(listener, app).await?;
+    // RPC_URL may list several endpoints (comma-separated); the first is preferred.
+    let rpc_urls = config::var("RPC_URL").unwrap_or_else(|_| DEFAULT_RPC_URL.to_string());
+    for rpc_url in rpc_urls.split(',').map(str::trim).filter(|url| !url.is_empty()) {
+        builder = builder.rpc_url(rpc_url);
+    }
+    // Programs prefixed with another cluster's name are read from that cluster instead.
+    let default_cluster = cluster::default_cluster_from_env()?;
+    let mut clusters = ClusterConfig::from_env()?;
+    for (cluster, program_id) in programs {
+        match cluster {
+            None => builder = builder.program_id(program_id),
+            Some(name) if default_cluster.as_ref() == Some(&name) => builder = builder.program_id(program_id),
+            Some(name) => {
+                let cluster = clusters.iter_mut().find(|cluster| cluster.name == name).ok_or_else(|| {
+                    let source = config::source("CLUSTERS");
+                    format!("Program {} names cluster '{}', which {} doesn't list", program_id, name, source)
+                })?;
+                if !cluster.program_ids.contains(&program_id) {
+                    cluster.program_ids.push(program_id);
+                }
+            }
+        }
+    }
+    if mock_rpc.is_some() && !clusters.is_empty() {
+        return Err(format!("--mock-rpc can't be combined with {}", config::source("CLUSTERS")).into());
+    }
+    if let Some(name) = default_cluster {
+        builder = builder.cluster_name(name);
+    }
+    for cluster in clusters {
+        builder = builder.cluster(cluster);
+    }
+    // API reads go to the primary unless a replica is configured.
+    if let Ok(read_database_url) = config::var("DATABASE_READ_URL") {
+        builder = builder.read_database_url(read_database_url);
+    }
+    #[cfg(feature = "kafka")]
+    {
+        builder = builder.kafka(KafkaConfig::from_env()?);
+    }
+    #[cfg(not(feature = "kafka"))]
+    if config::var("KAFKA_BROKERS").is_ok() {
+        return Err(format!("{} requires building with `--features kafka`", config::source("KAFKA_BROKERS")).into());
+    }
+    #[cfg(feature = "nats")]
+    {
+        builder = builder.nats(NatsConfig::from_env()?);
+    }
+    #[cfg(not(feature = "nats"))]
+    if config::var("NATS_URL").is_ok() {
+        return Err(format!("{} requires building with `--features nats`", config::source("NATS_URL")).into());
+    }
 
-    Ok(())
+    let anchor_events = config::var("INDEX_EVENTS").is_ok_and(|value| value == "true" || value == "1");
+    let leader_election = config::var("LEADER_ELECTION")
+        .map(|value| value != "false" && value != "0")
+        .unwrap_or(true);
+    let max_sync_age_secs = match config::var("READY_MAX_SYNC_AGE_SECS") {
+        Ok(value) => value.parse::<u64>().map_err(|_| {
+            let source = config::source("READY_MAX_SYNC_AGE_SECS");
+            format!("Invalid {} '{}': expected a number of seconds", source, value)
+        })?,
+        Err(_) => DEFAULT_READY_MAX_SYNC_AGE_SECS,
+    };
+    let startup_timeout_secs = match config::var("STARTUP_TIMEOUT_SECS") {
+        Ok(value) => value.parse::<u64>().map_err(|_| {
+            let source = config::source("STARTUP_TIMEOUT_SECS");
+            format!("Invalid {} '{}': expected a number of seconds", source, value)
+        })?,
+        Err(_) => DEFAULT_STARTUP_TIMEOUT_SECS,
+    };
+    builder = builder
+        .anchor_events(anchor_events)
+        .leader_election(leader_election)
+        .auth(AuthConfig::from_env())
+        .compression(CompressionConfig::from_env()?)
+        .cors(CorsConfig::from_env()?)
+        .client_rate_limit(ClientRateLimit::from_env()?)
+        .response_cache(ResponseCacheConfig::from_env()?)
+        .run_migrations(run_migrations())
+        .max_sync_age(Duration::from_secs(max_sync_age_secs))
+        .startup_timeout(Duration::from_secs(startup_timeout_secs))
+        .backend(ingestion_backend()?);
+    // Fixtures hold snapshots only, so there is nothing to subscribe to.
+    if let Some(path) = mock_rpc {
+        let conflict = if config::var("INGESTION_BACKEND").is_ok_and(|backend| backend != "rpc") {
+            Some("INGESTION_BACKEND")
+        } else {
+            anchor_events.then_some("INDEX_EVENTS")
+        };
+        if let Some(setting) = conflict {
+            return Err(format!("--mock-rpc can't be combined with {}", config::source(setting)).into());
+        }
+        builder = builder.rpc_fixtures(RpcFixtures::load(path)?).backend(IngestionBackend::PollOnly);
+    }
+    Ok(builder)
 }
 
+/// `RUN_MIGRATIONS` (default `true`): whether commands that need the schema apply migrations.
+fn run_migrations() -> bool {
+    config::var("RUN_MIGRATIONS").map(|value| value != "false" && value != "0").unwrap_or(true)
+}
 
-fn skip_anchor_discriminator(data: &[u8]) -> &[u8] {
-    &data[8..]
-}
-
-fn deserialize_node_device(data: &[u8]) -> Result<NodeDevice, AppError> {
-    let mut slice = skip_anchor_discriminator(data);
-    let authority_bytes: [u8; 32] = slice[0..32].try_into()?;
-    let authority = Pubkey::new_from_array(authority_bytes);
-    slice = &slice[32..];
-    let uri_len = u32::from_le_bytes(slice[0..4].try_into()?) as usize;
-    slice = &slice[4..];
-    let uri = String::from_utf8(slice[0..uri_len].to_vec())?;
-    Ok(NodeDevice { authority, uri })
-}
-
-fn deserialize_network_stats(data: &[u8]) -> Result<NetworkStats, AppError> {
-    let stats = NetworkStats::try_from_slice(skip_anchor_discriminator(data))?;
-    Ok(stats)
-}
-
-// V-- MODIFIED FUNCTION --V
-async fn fetch_program_accounts(
-    rpc_url: &str,
-    program_id: &str,
-    pool: &sqlx::PgPool,
-) -> Result<(), AppError> {
-    let client = RpcClient::new(rpc_url.to_string());
-    let program_pubkey = Pubkey::from_str(program_id)?;
-
-    let accounts = client.get_program_accounts(&program_pubkey)?;
-    println!("[Background Task] Found {} accounts for program {}", accounts.len(), program_id);
-
-    // V-- NEW --V
-    // Step 1: Collect all pubkeys of valid NodeDevice accounts currently on the blockchain.
-    let mut on_chain_node_pubkeys: Vec<String> = Vec::new();
-
-    for (pubkey, account) in accounts {
-        let data_len = account.data.len();
-
-        // This logic identifies a NodeDevice account based on its data length.
-        if data_len > 40 {
-            if let Ok(node) = deserialize_node_device(&account.data) {
-                // V-- NEW --V: Add the valid pubkey to our list.
-                on_chain_node_pubkeys.push(pubkey.to_string());
-                
-                println!("[Background Task] Upserting NodeDevice: {}", pubkey);
-                // Step 2: Upsert the account data into the database. This ensures new and updated nodes are synced.
-                sqlx::query(
-                    r#"
-                    INSERT INTO nodes (pubkey, authority, uri)
-                    VALUES ($1, $2, $3)
-                    ON CONFLICT (pubkey) DO UPDATE
-                    SET authority = EXCLUDED.authority,
-                        uri = EXCLUDED.uri
-                    "#,
-                )
-                .bind(pubkey.to_string())
-                .bind(node.authority.to_string())
-                .bind(node.uri)
-                .execute(pool)
-                .await?;
+/// Picks the real-time backend from `INGESTION_BACKEND` (`rpc` or `geyser`).
+fn ingestion_backend() -> Result<IngestionBackend, AppError> {
+    let backend = config::var("INGESTION_BACKEND").unwrap_or_else(|_| "rpc".to_string());
+    match backend.as_str() {
+        "rpc" => {
+            let subscriptions_enabled = config::var("ENABLE_PROGRAM_SUBSCRIBE")
+                .map(|value| value != "false" && value != "0")
+                .unwrap_or(true);
+            if subscriptions_enabled {
+                Ok(IngestionBackend::ProgramSubscribe { ws_url: config::var("WS_URL").ok() })
             } else {
-                println!("[Background Task] Failed to deserialize NodeDevice for account {}", pubkey);
+                Ok(IngestionBackend::PollOnly)
             }
         }
-        // ... (the rest of your account type checks for NetworkStats, etc., remain the same)
-    }
-    
-    // V-- NEW --V
-    // Step 3: Delete nodes from the database that are NOT in the on-chain list.
-    // This removes nodes that have been deregistered from the blockchain.
-    println!("[Background Task] Pruning stale nodes from the database...");
-    let deleted_rows = sqlx::query(
-        // This query deletes all rows from 'nodes' where the pubkey is NOT present in the provided list.
-        "DELETE FROM nodes WHERE pubkey <> ALL($1)"
-    )
-    .bind(&on_chain_node_pubkeys)
-    .execute(pool)
-    .await?
-    .rows_affected();
-
-    if deleted_rows > 0 {
-        println!("[Background Task] Pruned {} stale node(s).", deleted_rows);
-    }
-    
-    // This final part will now correctly reflect the total count AFTER the pruning.
-    let total_nodes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM nodes")
-        .fetch_one(pool)
-        .await?;
-
-    println!("[Background Task] Updating network_stats.total_nodes to {}", total_nodes);
-    sqlx::query(
-        r#"
-        INSERT INTO network_stats (id, total_nodes)
-        VALUES (1, $1)
-        ON CONFLICT (id) DO UPDATE
-        SET total_nodes = EXCLUDED.total_nodes
-        "#,
-    )
-    .bind(total_nodes)
-    .execute(pool)
-    .await?;
+        #[cfg(feature = "geyser")]
+        "geyser" => Ok(IngestionBackend::Geyser {
+            endpoint: config::var("GEYSER_ENDPOINT")
+                .map_err(|_| "GEYSER_ENDPOINT (or geyser.endpoint) must be set for the geyser backend")?,
+            x_token: config::var("GEYSER_X_TOKEN").ok(),
+        }),
+        #[cfg(not(feature = "geyser"))]
+        "geyser" => Err("INGESTION_BACKEND=geyser requires building with `--features geyser`".into()),
+        other => {
+            let source = config::source("INGESTION_BACKEND");
+            Err(format!("Unknown {} '{}', expected 'rpc' or 'geyser'", source, other).into())
+        }
+    }
+}
 
+/// Human-readable drift summary, one block per program.
+fn print_drift(reports: &[DriftReport]) {
+    for report in reports {
+        let slot = report.snapshot_slot.map_or_else(|| "unknown".to_string(), |slot| slot.to_string());
+        println!("{} (slot {}): {} on chain, {} indexed", report.program_id, slot, report.on_chain, report.indexed);
+        for pubkey in &report.missing {
+            println!("  missing     {}", pubkey);
+        }
+        for pubkey in &report.stale {
+            println!("  stale       {}", pubkey);
+        }
+        for mismatch in &report.mismatched {
+            println!(
+                "  mismatched  {} {}: indexed {:?}, on chain {:?}",
+                mismatch.pubkey, mismatch.field, mismatch.indexed, mismatch.on_chain
+            );
+        }
+        for pubkey in &report.undecodable {
+            println!("  undecodable {}", pubkey);
+        }
+        if !report.has_drift() {
+            println!("  no drift");
+        }
+    }
+}
+
+/// Writes `nodes` as CSV with a header row, quoting fields that need it.
+fn write_csv(out: &mut impl Write, nodes: &[ApiNode]) -> std::io::Result<()> {
+    fn field(value: &str) -> String {
+        if value.contains([',', '"', '\n', '\r']) {
+            format!("\"{}\"", value.replace('"', "\"\""))
+        } else {
+            value.to_string()
+        }
+    }
+    writeln!(out, "pubkey,authority,uri,program_id,first_seen_at,updated_at,last_seen_slot,deleted_at")?;
+    for node in nodes {
+        let time = |at: Option<chrono::DateTime<chrono::Utc>>| at.map(|at| at.to_rfc3339()).unwrap_or_default();
+        let row = [
+            field(&node.pubkey),
+            field(&node.authority),
+            field(&node.uri),
+            field(node.program_id.as_deref().unwrap_or_default()),
+            time(node.first_seen_at),
+            time(node.updated_at),
+            node.last_seen_slot.map(|slot| slot.to_string()).unwrap_or_default(),
+            time(node.deleted_at),
+        ];
+        writeln!(out, "{}", row.join(","))?;
+    }
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,144 @@
+//! Node moderation. An admin can quarantine a node, e.g. one registered with a malicious URI,
+//! to hide it from every public read and from the change feeds. The quarantine lives in
+//! `node_moderation` rather than on the node's row, so the sync loop keeps indexing the
+//! account as usual and no cycle can undo it. Quarantining a visible node records and
+//! publishes its removal; lifting the quarantine publishes the node again.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::postgres::PgExecutor;
+use utoipa::ToSchema;
+
+use crate::events::NodeEvent;
+use crate::store::{record_changes, ApiNode, NODE_COLUMNS};
+use crate::sync::SyncContext;
+use crate::AppError;
+
+// --- SQL condition leaving out quarantined nodes, on any row with a `pubkey` column ---
+pub const NOT_QUARANTINED: &str = "pubkey NOT IN (SELECT pubkey FROM node_moderation)";
+
+/// One row of `node_moderation`, as listed by `GET /admin/quarantine`.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Debug)]
+pub struct NodeModeration {
+    pub pubkey: String,
+    pub reason: Option<String>,
+    /// Name of the admin key that quarantined the node.
+    pub quarantined_by: Option<String>,
+    pub quarantined_at: DateTime<Utc>,
+}
+
+// --- Columns selected into `NodeModeration` ---
+pub const MODERATION_COLUMNS: &str = "pubkey, reason, quarantined_by, quarantined_at";
+
+/// Quarantines `pubkey`, indexed or not yet, and returns its row. Quarantining it again only
+/// updates the reason.
+pub async fn quarantine(
+    sync: &SyncContext,
+    pubkey: &str,
+    reason: Option<&str>,
+    quarantined_by: Option<&str>,
+) -> Result<NodeModeration, AppError> {
+    let mut tx = sync.pool.begin().await?;
+    let visible = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE pubkey = $1 AND deleted_at IS NULL AND {}",
+        NODE_COLUMNS, NOT_QUARANTINED
+    ))
+    .bind(pubkey)
+    .fetch_all(&mut *tx)
+    .await?;
+    let record = sqlx::query_as::<_, NodeModeration>(&format!(
+        "INSERT INTO node_moderation (pubkey, reason, quarantined_by) VALUES ($1, $2, $3) \
+         ON CONFLICT (pubkey) DO UPDATE SET reason = EXCLUDED.reason RETURNING {}",
+        MODERATION_COLUMNS
+    ))
+    .bind(pubkey)
+    .bind(reason)
+    .bind(quarantined_by)
+    .fetch_one(&mut *tx)
+    .await?;
+    // To subscribers, webhooks and followers the node is simply gone, on every cluster.
+    let events: Vec<_> = visible
+        .into_iter()
+        .map(|node| {
+            let slot = node.last_seen_slot.unwrap_or(0) as u64;
+            (NodeEvent::Removed { pubkey: node.pubkey, cluster: node.cluster }, slot)
+        })
+        .collect();
+    for (event, slot) in &events {
+        record_changes(&mut *tx, std::slice::from_ref(event), *slot, sync.outbox).await?;
+    }
+    tx.commit().await?;
+    for (event, _) in events {
+        sync.events.publish(event);
+    }
+    Ok(record)
+}
+
+/// Lifts the quarantine of `pubkey`, returning the row it had; `None` if it wasn't quarantined.
+pub async fn unquarantine(sync: &SyncContext, pubkey: &str) -> Result<Option<NodeModeration>, AppError> {
+    let mut tx = sync.pool.begin().await?;
+    let record = sqlx::query_as::<_, NodeModeration>(&format!(
+        "DELETE FROM node_moderation WHERE pubkey = $1 RETURNING {}",
+        MODERATION_COLUMNS
+    ))
+    .bind(pubkey)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(record) = record else { return Ok(None) };
+    let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE pubkey = $1 AND deleted_at IS NULL",
+        NODE_COLUMNS
+    ))
+    .bind(pubkey)
+    .fetch_all(&mut *tx)
+    .await?;
+    let events: Vec<_> = nodes
+        .into_iter()
+        .map(|node| {
+            let slot = node.last_seen_slot.unwrap_or(0) as u64;
+            (NodeEvent::Added { node }, slot)
+        })
+        .collect();
+    for (event, slot) in &events {
+        record_changes(&mut *tx, std::slice::from_ref(event), *slot, sync.outbox).await?;
+    }
+    tx.commit().await?;
+    for (event, _) in events {
+        sync.events.publish(event);
+    }
+    Ok(Some(record))
+}
+
+/// Quarantined nodes, most recently quarantined first.
+pub async fn list_quarantined(executor: impl PgExecutor<'_>) -> Result<Vec<NodeModeration>, sqlx::Error> {
+    sqlx::query_as::<_, NodeModeration>(&format!(
+        "SELECT {} FROM node_moderation ORDER BY quarantined_at DESC, pubkey",
+        MODERATION_COLUMNS
+    ))
+    .fetch_all(executor)
+    .await
+}
+
+pub async fn is_quarantined(executor: impl PgExecutor<'_>, pubkey: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM node_moderation WHERE pubkey = $1)")
+        .bind(pubkey)
+        .fetch_one(executor)
+        .await
+}
+
+/// Drops the events of quarantined nodes, so a cycle neither records nor publishes them.
+pub async fn withhold_quarantined(executor: impl PgExecutor<'_>, events: &mut Vec<NodeEvent>) -> Result<(), AppError> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let pubkeys: Vec<&str> = events.iter().map(NodeEvent::pubkey).collect();
+    let quarantined: Vec<String> =
+        sqlx::query_scalar("SELECT pubkey FROM node_moderation WHERE pubkey = ANY($1)")
+            .bind(&pubkeys)
+            .fetch_all(executor)
+            .await?;
+    if !quarantined.is_empty() {
+        events.retain(|event| !quarantined.iter().any(|pubkey| pubkey == event.pubkey()));
+    }
+    Ok(())
+}
@@ -0,0 +1,174 @@
+//! NATS JetStream publisher: every node change is published to `<prefix>.upsert` or
+//! `<prefix>.delete` with the same JSON body as a `/ws` message.
+//!
+//! Changes are read from `node_changes` behind a cursor kept in `publisher_cursors`, which
+//! only advances once JetStream has acknowledged every message of a batch. Each message
+//! carries `Nats-Msg-Id: <change id>`, so a batch resent after a failure is deduplicated by the
+//! stream (within its duplicate window). A stream capturing the subjects must already exist.
+//!
+//! Published with async-nats: `tls://` servers are connected to over TLS, and credentials come
+//! from the server URL or `NATS_TOKEN`.
+
+use async_nats::jetstream::{self, message::PublishMessage};
+use async_nats::{ConnectOptions, ServerAddr};
+use sqlx::postgres::PgPool;
+use tokio::time::{sleep, Duration};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::config;
+use crate::events::{NodeEvent, SequencedEvent};
+use crate::store::changes_after;
+use crate::sync::SyncContext;
+use crate::AppError;
+
+// --- Default NATS_SUBJECT_PREFIX ---
+const DEFAULT_NATS_SUBJECT_PREFIX: &str = "nodes";
+
+// --- Row in `publisher_cursors` holding this publisher's position ---
+const NATS_CURSOR_NAME: &str = "nats";
+
+// --- Pause between checks for new changes ---
+const NATS_POLL_INTERVAL_MILLIS: u64 = 500;
+
+// --- Changes published per batch ---
+const NATS_BATCH_SIZE: i64 = 200;
+
+// --- Time the server gets to answer a connect or acknowledge a batch ---
+const NATS_TIMEOUT_SECS: u64 = 10;
+
+// --- Upper bound for the retry backoff after a failed batch ---
+const MAX_NATS_BACKOFF_SECS: u64 = 60;
+
+/// Where to publish changes (`NATS_URL`, `NATS_SUBJECT_PREFIX`, `NATS_TOKEN`).
+#[derive(Clone, Debug)]
+pub struct NatsConfig {
+    /// Servers of the cluster; the client picks one and fails over to the others.
+    pub servers: Vec<ServerAddr>,
+    pub subject_prefix: String,
+    pub token: Option<String>,
+}
+
+impl NatsConfig {
+    /// `None` when `NATS_URL` is unset, leaving the publisher off.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Ok(urls) = config::var("NATS_URL") else { return Ok(None) };
+        let mut servers = Vec::new();
+        for url in urls.split(',').map(str::trim).filter(|url| !url.is_empty()) {
+            let server = url.parse::<ServerAddr>().map_err(|e| {
+                let source = config::source("NATS_URL");
+                format!("Invalid {} '{}': expected nats://host[:port] or tls://host[:port] ({})", source, url, e)
+            })?;
+            servers.push(server);
+        }
+        if servers.is_empty() {
+            return Err(format!("{} must list at least one server", config::source("NATS_URL")).into());
+        }
+        let subject_prefix =
+            config::var("NATS_SUBJECT_PREFIX").unwrap_or_else(|_| DEFAULT_NATS_SUBJECT_PREFIX.to_string());
+        if subject_prefix.is_empty() || subject_prefix.contains(|c: char| c.is_whitespace() || c == '*' || c == '>')
+        {
+            let source = config::source("NATS_SUBJECT_PREFIX");
+            return Err(format!("Invalid {} '{}': expected a literal subject", source, subject_prefix).into());
+        }
+        Ok(Some(Self { servers, subject_prefix, token: config::var("NATS_TOKEN").ok() }))
+    }
+
+    fn subject(&self, event: &NodeEvent) -> String {
+        let action = match event {
+            NodeEvent::Added { .. } | NodeEvent::Updated { .. } => "upsert",
+            NodeEvent::Removed { .. } => "delete",
+        };
+        format!("{}.{}", self.subject_prefix, action)
+    }
+}
+
+/// Publishes new changes to JetStream forever, reconnecting with backoff when a batch fails.
+pub async fn run_nats_publisher(config: NatsConfig, sync: SyncContext) {
+    async move {
+        let mut connection: Option<jetstream::Context> = None;
+        let mut backoff_secs = 1;
+        loop {
+            match publish_pending(&config, &mut connection, &sync).await {
+                Ok(published) => {
+                    backoff_secs = 1;
+                    // A full batch means more is waiting; keep going without pausing.
+                    if published < NATS_BATCH_SIZE as usize {
+                        sleep(Duration::from_millis(NATS_POLL_INTERVAL_MILLIS)).await;
+                    }
+                }
+                Err(e) => {
+                    warn!(error = %e, backoff_secs, "Failed to publish changes to NATS");
+                    connection = None;
+                    sleep(Duration::from_secs(backoff_secs)).await;
+                    backoff_secs = (backoff_secs * 2).min(MAX_NATS_BACKOFF_SECS);
+                }
+            }
+        }
+    }
+    .instrument(info_span!("nats"))
+    .await
+}
+
+/// Publishes the changes after the cursor and advances it once all of them are acknowledged.
+async fn publish_pending(
+    config: &NatsConfig,
+    connection: &mut Option<jetstream::Context>,
+    sync: &SyncContext,
+) -> Result<usize, AppError> {
+    let Some(_write) = sync.pause.begin_write().await else { return Ok(0) };
+    let cursor = load_cursor(&sync.pool).await?;
+    let changes = changes_after(&sync.pool, cursor, NATS_BATCH_SIZE).await?;
+    let Some(&(last_id, _)) = changes.last() else { return Ok(0) };
+
+    let context = match connection {
+        Some(context) => context,
+        None => connection.insert(connect(config).await?),
+    };
+    // Send the whole batch before waiting on any acknowledgement.
+    let mut acks = Vec::with_capacity(changes.len());
+    for (change_id, event) in changes {
+        let subject = config.subject(&event);
+        let body = serde_json::to_vec(&SequencedEvent { id: change_id as u64, event })?;
+        let message = PublishMessage::build().payload(body.into()).message_id(change_id.to_string());
+        acks.push(context.send_publish(subject, message).await?);
+    }
+    let published = acks.len();
+    for ack in acks {
+        // A missing stream shows up here as a "no responders" error.
+        ack.await.map_err(|e| format!("JetStream did not acknowledge a change: {}", e))?;
+    }
+
+    sqlx::query("UPDATE publisher_cursors SET cursor = $2, updated_at = NOW() WHERE name = $1")
+        .bind(NATS_CURSOR_NAME)
+        .bind(last_id)
+        .execute(&sync.pool)
+        .await?;
+    debug!(published, cursor = last_id, "Published changes to NATS");
+    Ok(published)
+}
+
+/// This publisher's cursor, created at the newest change on first start so only changes made
+/// from then on are published.
+async fn load_cursor(pool: &PgPool) -> Result<i64, AppError> {
+    let cursor = sqlx::query_scalar(
+        "INSERT INTO publisher_cursors (name, cursor) \
+         VALUES ($1, (SELECT COALESCE(MAX(id), 0) FROM node_changes)) \
+         ON CONFLICT (name) DO UPDATE SET name = EXCLUDED.name RETURNING cursor",
+    )
+    .bind(NATS_CURSOR_NAME)
+    .fetch_one(pool)
+    .await?;
+    Ok(cursor)
+}
+
+async fn connect(config: &NatsConfig) -> Result<jetstream::Context, AppError> {
+    let mut options = ConnectOptions::new().name("indexer").connection_timeout(Duration::from_secs(NATS_TIMEOUT_SECS));
+    if let Some(token) = &config.token {
+        options = options.token(token.clone());
+    }
+    let client = options.connect(config.servers.clone()).await?;
+    info!(servers = config.servers.len(), "Connected to NATS");
+    let mut context = jetstream::new(client);
+    context.set_timeout(Duration::from_secs(NATS_TIMEOUT_SECS));
+    Ok(context)
+}
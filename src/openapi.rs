@@ -0,0 +1,187 @@
+//! OpenAPI 3.1 description of the HTTP API, served at `/openapi.json`, and a Swagger UI page
+//! for it at `/docs`. The UI is bundled into the binary, so `/docs` works offline.
+//!
+//! The document is derived from the `#[utoipa::path]` attribute on each handler in
+//! [`crate::api`] and the `ToSchema` types they take and return; list new handlers in
+//! [`ApiDoc`]'s `paths`.
+
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{Content, OpenApi as Document, Ref, RefOr};
+use utoipa::{Modify, OpenApi, ToResponse};
+use utoipa_swagger_ui::{Config, SwaggerUi};
+
+use crate::api;
+use crate::auth::API_KEY_HEADER;
+use crate::error::{Problem, PROBLEM_CONTENT_TYPE};
+use crate::graphql;
+use crate::subscriptions::{ClientMessage, SubscriptionFilter};
+
+/// The API's OpenAPI document.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Indexer API",
+        description = "Indexed NodeDevice accounts of the configured Solana programs, their change history, and \
+            live change feeds. Send an API key in the X-API-Key header (or the `api_key` query parameter for \
+            WebSocket and SSE clients); it is optional unless the server requires keys, except on /admin routes, \
+            which need an admin key.",
+    ),
+    security(("apiKey" = []), ()),
+    tags(
+        (name = "nodes", description = "Indexed nodes and their history"),
+        (name = "feeds", description = "Change and event feeds"),
+        (name = "status", description = "Stats and probes"),
+        (name = "graphql", description = "GraphQL API; see /graphql/schema for the schema, or introspect it"),
+        (name = "watchlists", description = "Changes of only the nodes an API key client follows"),
+        (name = "operators", description = "Node authorities managing their own nodes"),
+        (name = "admin", description = "Operations that need an admin API key"),
+    ),
+    paths(
+        api::get_nodes,
+        api::get_node_count,
+        api::search_nodes,
+        api::get_nodes_by_uri,
+        api::post_nodes_batch,
+        api::get_node_certificates,
+        api::get_node,
+        api::get_node_history,
+        api::get_node_transactions,
+        api::get_node_liveness,
+        api::get_leaderboard,
+        api::get_authorities,
+        api::get_changes,
+        api::get_diff,
+        api::get_program_events,
+        api::ws_handler,
+        api::sse_handler,
+        api::get_stats,
+        api::get_stats_history,
+        api::get_stats_growth,
+        api::healthz,
+        api::readyz,
+        api::metrics,
+        graphql::get_graphql,
+        graphql::post_graphql,
+        graphql::get_graphql_schema,
+        graphql::graphql_ws,
+        api::get_watchlists,
+        api::post_watchlist,
+        api::get_watchlist,
+        api::put_watchlist,
+        api::delete_watchlist,
+        api::get_watchlist_nodes,
+        api::get_watchlist_changes,
+        api::watchlist_ws_handler,
+        api::post_challenge,
+        api::post_session,
+        api::put_operator_metadata,
+        api::delete_operator_metadata,
+        api::get_operator_alert_targets,
+        api::post_operator_alert_target,
+        api::delete_operator_alert_target,
+        api::list_api_keys,
+        api::post_api_key,
+        api::revoke_api_key,
+        api::list_webhooks,
+        api::post_webhook,
+        api::delete_webhook,
+        api::list_alert_targets,
+        api::post_alert_target,
+        api::get_alert_target,
+        api::delete_alert_target,
+        api::list_alerts,
+        api::list_decode_failures,
+        api::post_decode_retry,
+        api::post_resync,
+        api::list_quarantined,
+        api::post_quarantine,
+        api::post_unquarantine,
+        api::post_pause,
+        api::post_resume,
+    ),
+    components(
+        // The WebSocket messages are only named in the operations' descriptions.
+        schemas(Problem, ClientMessage, SubscriptionFilter),
+        responses(Unauthorized, Forbidden, RateLimited, DatabaseError, InvalidPubkey),
+    ),
+    modifiers(&SecuritySchemes, &ProblemResponses),
+)]
+pub struct ApiDoc;
+
+/// Routes serving the document at `/openapi.json` and Swagger UI over it at `/docs`.
+pub(crate) fn routes() -> SwaggerUi {
+    SwaggerUi::new("/docs")
+        .url("/openapi.json", ApiDoc::openapi())
+        .config(Config::default().persist_authorization(true))
+}
+
+// --- Problem responses shared by many operations ---
+
+#[derive(ToResponse)]
+#[response(description = "Missing, unknown or revoked API key", content_type = "application/problem+json")]
+pub struct Unauthorized(pub Problem<'static>);
+
+#[derive(ToResponse)]
+#[response(description = "The API key has no admin access", content_type = "application/problem+json")]
+pub struct Forbidden(pub Problem<'static>);
+
+#[derive(ToResponse)]
+#[response(
+    description = "The client is over its request budget; see Retry-After",
+    content_type = "application/problem+json"
+)]
+pub struct RateLimited(pub Problem<'static>);
+
+#[derive(ToResponse)]
+#[response(description = "The database query failed", content_type = "application/problem+json")]
+pub struct DatabaseError(pub Problem<'static>);
+
+#[derive(ToResponse)]
+#[response(description = "The pubkey is not valid base58", content_type = "application/problem+json")]
+pub struct InvalidPubkey(pub Problem<'static>);
+
+/// Declares the `apiKey` and `operatorSession` schemes the operations' `security` names.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut Document) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let api_key = ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER));
+        components.add_security_scheme("apiKey", SecurityScheme::ApiKey(api_key));
+        let session = HttpBuilder::new()
+            .scheme(HttpAuthScheme::Bearer)
+            .description(Some("Session token from POST /my/session"))
+            .build();
+        components.add_security_scheme("operatorSession", SecurityScheme::Http(session));
+    }
+}
+
+/// Gives every operation behind the API key and rate limit layers their `401` and `429`
+/// answers, and every error response declared without a body a [`Problem`] one.
+struct ProblemResponses;
+
+impl Modify for ProblemResponses {
+    fn modify(&self, openapi: &mut Document) {
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [&mut item.get, &mut item.put, &mut item.post, &mut item.delete];
+            for operation in operations.into_iter().flatten() {
+                let responses = &mut operation.responses.responses;
+                for (status, response) in responses.iter_mut() {
+                    if let RefOr::T(response) = response
+                        && (status.starts_with('4') || status.starts_with('5'))
+                        && response.content.is_empty()
+                    {
+                        let schema = Ref::from_schema_name("Problem");
+                        response.content.insert(PROBLEM_CONTENT_TYPE.to_string(), Content::new(Some(schema)));
+                    }
+                }
+                // Probes opt out of security, which also leaves them outside both layers.
+                if operation.security.as_ref().is_some_and(Vec::is_empty) {
+                    continue;
+                }
+                responses.entry("401".to_string()).or_insert_with(|| Ref::from_response_name("Unauthorized").into());
+                responses.insert("429".to_string(), Ref::from_response_name("RateLimited").into());
+            }
+        }
+    }
+}
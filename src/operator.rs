@@ -0,0 +1,227 @@
+//! Operator self-service. An authority proves it holds its keypair by signing a challenge from
+//! `POST /my/challenge`, the way wallets' `signMessage` signs the raw text, and trades the
+//! signature for a session token at `POST /my/session`. With the token as a bearer
+//! `Authorization` header, `PUT /my/nodes/:pubkey/metadata` attaches a display name, contact
+//! and description to a node it is the authority of. The node API returns them with the node as
+//! `operator_metadata`, for as long as the node's authority stays the one that wrote them.
+//! `/my/alert-targets` registers, lists and removes the authority's own alert targets, webhooks
+//! only (see [`crate::alerts`]).
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use sqlx::postgres::{PgExecutor, PgPool};
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+
+// --- How long a challenge can be signed and exchanged for a session ---
+const CHALLENGE_TTL_SECS: i64 = 300;
+
+// --- How long a session token stays valid ---
+const SESSION_TTL_SECS: i64 = 86_400;
+
+// --- Prefix of session tokens, so leaked ones are easy to recognise ---
+const SESSION_TOKEN_PREFIX: &str = "opsess_";
+
+/// A challenge for `authority` to sign, as returned by `POST /my/challenge`.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct Challenge {
+    pub authority: String,
+    pub nonce: String,
+    /// The exact text to sign, UTF-8 encoded.
+    pub message: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A session token, as returned by `POST /my/session`. The token itself is never stored.
+#[derive(Serialize, ToSchema, Debug)]
+pub struct Session {
+    pub token: String,
+    pub authority: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// The authority a request's session belongs to, stored in the request extensions.
+#[derive(Clone, Debug)]
+pub struct Operator {
+    pub authority: String,
+}
+
+/// Metadata an authority attached to one of its nodes.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Clone, Debug)]
+pub struct OperatorMetadata {
+    #[serde(skip)]
+    pub pubkey: String,
+    pub display_name: Option<String>,
+    pub contact: Option<String>,
+    pub description: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// --- Columns selected into `OperatorMetadata` ---
+const OPERATOR_METADATA_COLUMNS: &str = "pubkey, display_name, contact, description, updated_at";
+
+fn random_hex() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// Issues a single-use challenge for `authority`. Expired challenges are cleared on the way.
+pub async fn create_challenge(pool: &PgPool, authority: &str) -> Result<Challenge, sqlx::Error> {
+    sqlx::query("DELETE FROM operator_challenges WHERE expires_at <= NOW()").execute(pool).await?;
+    let nonce = random_hex();
+    let expires_at = Utc::now() + Duration::seconds(CHALLENGE_TTL_SECS);
+    let message = format!(
+        "Sign in to the node indexer as {}\n\nNonce: {}\nExpires at: {}",
+        authority,
+        nonce,
+        expires_at.to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+    sqlx::query("INSERT INTO operator_challenges (nonce, authority, message, expires_at) VALUES ($1, $2, $3, $4)")
+        .bind(&nonce)
+        .bind(authority)
+        .bind(&message)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+    Ok(Challenge { authority: authority.to_string(), nonce, message, expires_at })
+}
+
+/// Exchanges `authority`'s `signature` of challenge `nonce` for a session. `None` when the
+/// challenge is unknown, expired, already used or not `authority`'s, or the signature doesn't
+/// match it; the challenge is used up either way, so each allows one attempt.
+pub async fn sign_in(
+    pool: &PgPool,
+    authority: &Pubkey,
+    nonce: &str,
+    signature: &Signature,
+) -> Result<Option<Session>, sqlx::Error> {
+    let authority_text = authority.to_string();
+    let message = sqlx::query_as::<_, (String, bool)>(
+        "DELETE FROM operator_challenges WHERE nonce = $1 AND authority = $2 RETURNING message, expires_at > NOW()",
+    )
+    .bind(nonce)
+    .bind(&authority_text)
+    .fetch_optional(pool)
+    .await?
+    .and_then(|(message, live)| live.then_some(message));
+    let Some(message) = message else { return Ok(None) };
+    if !signature.verify(authority.as_ref(), message.as_bytes()) {
+        return Ok(None);
+    }
+
+    sqlx::query("DELETE FROM operator_sessions WHERE expires_at <= NOW()").execute(pool).await?;
+    let token = format!("{}{}", SESSION_TOKEN_PREFIX, random_hex());
+    let expires_at = Utc::now() + Duration::seconds(SESSION_TTL_SECS);
+    sqlx::query("INSERT INTO operator_sessions (token_hash, authority, expires_at) VALUES ($1, $2, $3)")
+        .bind(hash_token(&token))
+        .bind(&authority_text)
+        .bind(expires_at)
+        .execute(pool)
+        .await?;
+    Ok(Some(Session { token, authority: authority_text, expires_at }))
+}
+
+/// The authority of the live session `token` belongs to, if any.
+pub async fn lookup_session(executor: impl PgExecutor<'_>, token: &str) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT authority FROM operator_sessions WHERE token_hash = $1 AND expires_at > NOW()")
+        .bind(hash_token(token))
+        .fetch_optional(executor)
+        .await
+}
+
+fn rejection(code: &'static str, detail: &str) -> Response {
+    ApiError::new(StatusCode::UNAUTHORIZED, code, detail).into_response()
+}
+
+/// Lets only requests with a live session token through, recording its [`Operator`].
+pub async fn require_session(State(pool): State<PgPool>, mut request: Request, next: Next) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string());
+    let Some(token) = token else {
+        return rejection("missing_session", "A session token from POST /my/session is required");
+    };
+
+    match lookup_session(&pool, &token).await {
+        Ok(Some(authority)) => {
+            request.extensions_mut().insert(Operator { authority });
+            next.run(request).await
+        }
+        Ok(None) => rejection("invalid_session", "Unknown or expired session token"),
+        Err(e) => ApiError::database(e, "Failed to check session token").into_response(),
+    }
+}
+
+/// Sets the metadata `authority` attached to `pubkey`, replacing any it had.
+pub async fn put_metadata(
+    executor: impl PgExecutor<'_>,
+    pubkey: &str,
+    authority: &str,
+    display_name: Option<&str>,
+    contact: Option<&str>,
+    description: Option<&str>,
+) -> Result<OperatorMetadata, sqlx::Error> {
+    sqlx::query_as::<_, OperatorMetadata>(&format!(
+        r#"
+        INSERT INTO node_operator_metadata (pubkey, authority, display_name, contact, description)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (pubkey) DO UPDATE SET
+            authority = EXCLUDED.authority, display_name = EXCLUDED.display_name, contact = EXCLUDED.contact,
+            description = EXCLUDED.description, updated_at = NOW()
+        RETURNING {}
+        "#,
+        OPERATOR_METADATA_COLUMNS
+    ))
+    .bind(pubkey)
+    .bind(authority)
+    .bind(display_name)
+    .bind(contact)
+    .bind(description)
+    .fetch_one(executor)
+    .await
+}
+
+/// Removes the metadata attached to `pubkey`; whether there was any.
+pub async fn delete_metadata(executor: impl PgExecutor<'_>, pubkey: &str) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query("DELETE FROM node_operator_metadata WHERE pubkey = $1")
+        .bind(pubkey)
+        .execute(executor)
+        .await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// The metadata shown with each of `pubkeys`, by pubkey: only that written by the node's
+/// current authority on one of the clusters it's indexed from.
+pub async fn operator_metadata(
+    executor: impl PgExecutor<'_>,
+    pubkeys: &[String],
+) -> Result<HashMap<String, OperatorMetadata>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, OperatorMetadata>(&format!(
+        "SELECT {} FROM node_operator_metadata m WHERE m.pubkey = ANY($1) \
+         AND EXISTS (SELECT 1 FROM nodes n WHERE n.pubkey = m.pubkey AND n.authority = m.authority)",
+        OPERATOR_METADATA_COLUMNS
+    ))
+    .bind(pubkeys)
+    .fetch_all(executor)
+    .await?;
+    Ok(rows.into_iter().map(|row| (row.pubkey.clone(), row)).collect())
+}
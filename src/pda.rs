@@ -0,0 +1,35 @@
+//! PDA verification of NodeDevice accounts. The program derives each account's address from
+//! `[NODE_DEVICE_SEED, authority]`, so an account anywhere else wasn't created by the program's
+//! registration instruction for that authority and may be a spoof. Such accounts are stored
+//! with `pda_verified` false and left out of node lists unless `?include_unverified=true` is
+//! passed. Without `NODE_DEVICE_SEED`, nothing is verified and `pda_verified` stays null.
+
+use solana_sdk::pubkey::{Pubkey, MAX_SEED_LEN};
+
+use crate::config;
+use crate::AppError;
+
+/// `NODE_DEVICE_SEED` as bytes, or `None` when unset.
+pub fn seed_from_env() -> Result<Option<Vec<u8>>, AppError> {
+    let Ok(seed) = config::var("NODE_DEVICE_SEED") else { return Ok(None) };
+    if seed.is_empty() || seed.len() > MAX_SEED_LEN {
+        let source = config::source("NODE_DEVICE_SEED");
+        return Err(format!("Invalid {} '{}': expected 1 to {} bytes", source, seed, MAX_SEED_LEN).into());
+    }
+    Ok(Some(seed.into_bytes()))
+}
+
+/// Whether `pubkey` is the canonical PDA of `program_id` for `authority` under `seed`.
+pub fn is_node_device_address(pubkey: &str, authority: &Pubkey, program_id: &Pubkey, seed: &[u8]) -> bool {
+    let (expected, _) = Pubkey::find_program_address(&[seed, authority.as_ref()], program_id);
+    expected.to_string() == pubkey
+}
+
+/// `pda_verified` for a row, from its stored strings; `None` without a seed or when the row
+/// predates `program_id` or holds a malformed key.
+pub fn verify(pubkey: &str, authority: &str, program_id: Option<&str>, seed: Option<&[u8]>) -> Option<bool> {
+    let seed = seed?;
+    let authority = authority.parse::<Pubkey>().ok()?;
+    let program_id = program_id?.parse::<Pubkey>().ok()?;
+    Some(is_node_device_address(pubkey, &authority, &program_id, seed))
+}
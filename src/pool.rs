@@ -0,0 +1,142 @@
+//! Postgres connection pool settings (`DATABASE_MAX_CONNECTIONS`, `DATABASE_MIN_CONNECTIONS`,
+//! `DATABASE_ACQUIRE_TIMEOUT_SECS`, `DATABASE_IDLE_TIMEOUT_SECS`, `DATABASE_STATEMENT_TIMEOUT_MS`)
+//! and the utilization `/stats` reports. A read replica gets a pool with the same settings.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Serialize;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions};
+use utoipa::ToSchema;
+
+use crate::config;
+use crate::AppError;
+
+// --- Defaults for the DATABASE_* pool settings left unset ---
+const DEFAULT_MAX_CONNECTIONS: u32 = 5;
+const DEFAULT_ACQUIRE_TIMEOUT_SECS: u64 = 30;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 600;
+
+/// How many connections a pool keeps and how long it waits on them.
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    /// Connections kept open even while idle.
+    pub min_connections: u32,
+    /// How long a query waits for a free connection before failing.
+    pub acquire_timeout: Duration,
+    /// Connections idle this long above `min_connections` are closed; `None` keeps them.
+    pub idle_timeout: Option<Duration>,
+    /// Server-side `statement_timeout` for every connection, migrations and sync writes
+    /// included; `None` leaves the server's setting.
+    pub statement_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(DEFAULT_ACQUIRE_TIMEOUT_SECS),
+            idle_timeout: Some(Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS)),
+            statement_timeout: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Reads the `DATABASE_*` pool settings. A zero `DATABASE_IDLE_TIMEOUT_SECS` or
+    /// `DATABASE_STATEMENT_TIMEOUT_MS` turns that timeout off.
+    pub fn from_env() -> Result<Self, AppError> {
+        fn env_number<T: FromStr>(name: &str, default: T) -> Result<T, AppError> {
+            match config::var(name) {
+                Ok(value) => value
+                    .parse()
+                    .map_err(|_| format!("Invalid {} '{}': expected a number", config::source(name), value).into()),
+                Err(_) => Ok(default),
+            }
+        }
+        let max_connections = env_number("DATABASE_MAX_CONNECTIONS", DEFAULT_MAX_CONNECTIONS)?;
+        if max_connections == 0 {
+            return Err(format!("{} must be at least 1", config::source("DATABASE_MAX_CONNECTIONS")).into());
+        }
+        let min_connections = env_number("DATABASE_MIN_CONNECTIONS", 0)?;
+        if min_connections > max_connections {
+            let message = format!(
+                "{} ({}) can't exceed {} ({})",
+                config::source("DATABASE_MIN_CONNECTIONS"),
+                min_connections,
+                config::source("DATABASE_MAX_CONNECTIONS"),
+                max_connections
+            );
+            return Err(message.into());
+        }
+        let acquire_timeout_secs = env_number("DATABASE_ACQUIRE_TIMEOUT_SECS", DEFAULT_ACQUIRE_TIMEOUT_SECS)?;
+        if acquire_timeout_secs == 0 {
+            return Err(format!("{} must be at least 1", config::source("DATABASE_ACQUIRE_TIMEOUT_SECS")).into());
+        }
+        let idle_timeout_secs = env_number("DATABASE_IDLE_TIMEOUT_SECS", DEFAULT_IDLE_TIMEOUT_SECS)?;
+        let statement_timeout_ms = env_number("DATABASE_STATEMENT_TIMEOUT_MS", 0u64)?;
+        Ok(Self {
+            max_connections,
+            min_connections,
+            acquire_timeout: Duration::from_secs(acquire_timeout_secs),
+            idle_timeout: (idle_timeout_secs > 0).then(|| Duration::from_secs(idle_timeout_secs)),
+            statement_timeout: (statement_timeout_ms > 0).then(|| Duration::from_millis(statement_timeout_ms)),
+        })
+    }
+
+    /// Opens a pool to `database_url` with these settings.
+    pub async fn connect(&self, database_url: &str) -> Result<PgPool, AppError> {
+        let mut options = PgConnectOptions::from_str(database_url)?;
+        if let Some(statement_timeout) = self.statement_timeout {
+            options = options.options([("statement_timeout", statement_timeout.as_millis())]);
+        }
+        let pool = PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+            .connect_with(options)
+            .await?;
+        Ok(pool)
+    }
+}
+
+/// Connections of one pool at the moment `/stats` was asked.
+#[derive(Serialize, ToSchema)]
+pub struct PoolMetrics {
+    /// Open connections, busy or idle.
+    pub size: u32,
+    pub idle: usize,
+    /// Connections running a query or held by a transaction.
+    pub in_use: usize,
+    pub max_connections: u32,
+}
+
+impl PoolMetrics {
+    pub fn of(pool: &PgPool) -> Self {
+        let (size, idle) = (pool.size(), pool.num_idle());
+        Self {
+            size,
+            idle,
+            in_use: (size as usize).saturating_sub(idle),
+            max_connections: pool.options().get_max_connections(),
+        }
+    }
+}
+
+/// Utilization of the primary pool and of the read replica's, when one is configured.
+#[derive(Serialize, ToSchema)]
+pub struct DatabaseMetrics {
+    pub primary: PoolMetrics,
+    pub read_replica: Option<PoolMetrics>,
+}
+
+impl DatabaseMetrics {
+    pub fn of(pool: &PgPool, read_pool: &PgPool) -> Self {
+        // Clones of one pool share its options, so this tells a replica from the primary.
+        let replica = !std::sync::Arc::ptr_eq(&pool.connect_options(), &read_pool.connect_options());
+        Self { primary: PoolMetrics::of(pool), read_replica: replica.then(|| PoolMetrics::of(read_pool)) }
+    }
+}
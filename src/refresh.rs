@@ -0,0 +1,267 @@
+//! Targeted refresh between full scans. Every `REFRESH_INTERVAL_SECS`, nodes that changed within
+//! the last `REFRESH_RECENT_SECS` and the pubkeys listed in `REFRESH_WATCH` are re-read with
+//! batched getMultipleAccounts, which costs far less than a getProgramAccounts scan, and applied
+//! like streamed updates. When more are queued than `REFRESH_MAX_PUBKEYS`, a priority queue picks
+//! watched pubkeys first, least recently refreshed first, then the most recently changed nodes.
+//!
+//! Changes are learned from the event hub, so a node stays queued while it keeps changing and
+//! drops out once it has been quiet for the recent window. A watched pubkey is refreshed under
+//! whichever of the cluster's programs owns it.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+use tracing::{debug, info_span, warn, Instrument};
+
+use crate::cluster::ProgramKey;
+use crate::config;
+use crate::events::NodeEvent;
+use crate::rpc::{SolanaRpc, MAX_MULTIPLE_ACCOUNTS};
+use crate::sync::{apply_account_update, AccountUpdate, SyncContext};
+use crate::AppError;
+
+// --- Default REFRESH_RECENT_SECS a changed node stays queued after its last change ---
+const DEFAULT_REFRESH_RECENT_SECS: u64 = 600;
+
+// --- Default REFRESH_MAX_PUBKEYS re-read per refresh pass ---
+const DEFAULT_REFRESH_MAX_PUBKEYS: usize = 1000;
+
+// --- Recently changed nodes tracked per cluster; changes beyond it wait for the next scan ---
+const MAX_REFRESH_QUEUE: usize = 10_000;
+
+/// Settings for targeted refresh (`REFRESH_INTERVAL_SECS`, `REFRESH_RECENT_SECS`,
+/// `REFRESH_MAX_PUBKEYS`, `REFRESH_WATCH`).
+#[derive(Clone, Debug)]
+pub struct RefreshConfig {
+    /// Pause between refresh passes.
+    pub interval: Duration,
+    /// How long after its last change a node keeps being refreshed.
+    pub recent: Duration,
+    /// Pubkeys re-read per pass, across however many getMultipleAccounts requests they take.
+    pub max_pubkeys: usize,
+    /// Refreshed every pass regardless of changes.
+    pub watch: Vec<Pubkey>,
+}
+
+impl RefreshConfig {
+    /// `None` unless `REFRESH_INTERVAL_SECS` is set.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Ok(value) = config::var("REFRESH_INTERVAL_SECS") else { return Ok(None) };
+        let interval_secs = value.parse::<u64>().ok().filter(|secs| *secs > 0).ok_or_else(|| {
+            let source = config::source("REFRESH_INTERVAL_SECS");
+            format!("Invalid {} '{}': expected a positive number of seconds", source, value)
+        })?;
+        let recent_secs = match config::var("REFRESH_RECENT_SECS") {
+            Ok(value) => value.parse::<u64>().ok().ok_or_else(|| {
+                let source = config::source("REFRESH_RECENT_SECS");
+                format!("Invalid {} '{}': expected a number of seconds", source, value)
+            })?,
+            Err(_) => DEFAULT_REFRESH_RECENT_SECS,
+        };
+        let max_pubkeys = match config::var("REFRESH_MAX_PUBKEYS") {
+            Ok(value) => value.parse::<usize>().ok().filter(|max| *max > 0).ok_or_else(|| {
+                let source = config::source("REFRESH_MAX_PUBKEYS");
+                format!("Invalid {} '{}': expected a positive number", source, value)
+            })?,
+            Err(_) => DEFAULT_REFRESH_MAX_PUBKEYS,
+        };
+        let mut watch = Vec::new();
+        if let Ok(value) = config::var("REFRESH_WATCH") {
+            for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+                let pubkey = Pubkey::from_str(entry).map_err(|e| {
+                    format!("Invalid {} entry '{}': {}", config::source("REFRESH_WATCH"), entry, e)
+                })?;
+                if !watch.contains(&pubkey) {
+                    watch.push(pubkey);
+                }
+            }
+        }
+        Ok(Some(Self {
+            interval: Duration::from_secs(interval_secs),
+            recent: Duration::from_secs(recent_secs),
+            max_pubkeys,
+            watch,
+        }))
+    }
+}
+
+/// Why a pubkey is queued; later variants are refreshed first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Priority {
+    /// Most recent change first.
+    Changed(Instant),
+    /// Least recently refreshed first.
+    Watched(Reverse<u64>),
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct Candidate {
+    priority: Priority,
+    pubkey: Pubkey,
+}
+
+/// The pubkeys one cluster refreshes.
+struct RefreshQueue {
+    /// Recently changed nodes and when they last changed.
+    changed: HashMap<Pubkey, Instant>,
+    /// Watched pubkeys and the pass they were last refreshed in.
+    watched: HashMap<Pubkey, u64>,
+    /// The program each pubkey was last seen owned by.
+    programs: HashMap<Pubkey, ProgramKey>,
+    passes: u64,
+}
+
+impl RefreshQueue {
+    fn new(watch: &[Pubkey]) -> Self {
+        let watched = watch.iter().map(|pubkey| (*pubkey, 0)).collect();
+        Self { changed: HashMap::new(), watched, programs: HashMap::new(), passes: 0 }
+    }
+
+    /// Queues the node `event` changed when it is of one of `programs`, all on the cluster
+    /// named `cluster`.
+    fn record(&mut self, event: &NodeEvent, programs: &[ProgramKey], cluster: Option<&str>) {
+        if event.cluster() != cluster {
+            return;
+        }
+        let (NodeEvent::Added { node } | NodeEvent::Updated { node }) = event else {
+            if let Ok(pubkey) = Pubkey::from_str(event.pubkey()) {
+                self.changed.remove(&pubkey);
+            }
+            return;
+        };
+        let program_id = node.program_id.as_deref().and_then(|id| Pubkey::from_str(id).ok());
+        let (Ok(pubkey), Some(program_id)) = (Pubkey::from_str(&node.pubkey), program_id) else { return };
+        let Some(program) = programs.iter().find(|program| program.program_id == program_id) else { return };
+        self.programs.insert(pubkey, *program);
+        if self.watched.contains_key(&pubkey) {
+            return;
+        }
+        if self.changed.len() >= MAX_REFRESH_QUEUE && !self.changed.contains_key(&pubkey) {
+            debug!(%pubkey, "Refresh queue is full; leaving the change to the next scan");
+            return;
+        }
+        self.changed.insert(pubkey, Instant::now());
+    }
+
+    /// Drops quiet nodes, then takes the `limit` highest-priority pubkeys for this pass.
+    fn next_pass(&mut self, recent: Duration, limit: usize) -> Vec<Pubkey> {
+        self.changed.retain(|_, changed_at| changed_at.elapsed() < recent);
+        let watched = self.watched.iter().map(|(pubkey, pass)| (Priority::Watched(Reverse(*pass)), *pubkey));
+        let changed = self.changed.iter().map(|(pubkey, changed_at)| (Priority::Changed(*changed_at), *pubkey));
+        let mut queue: BinaryHeap<Candidate> =
+            watched.chain(changed).map(|(priority, pubkey)| Candidate { priority, pubkey }).collect();
+        self.passes += 1;
+        let mut pubkeys = Vec::with_capacity(limit.min(queue.len()));
+        while pubkeys.len() < limit
+            && let Some(candidate) = queue.pop()
+        {
+            if let Some(pass) = self.watched.get_mut(&candidate.pubkey) {
+                *pass = self.passes;
+            }
+            pubkeys.push(candidate.pubkey);
+        }
+        pubkeys
+    }
+}
+
+/// Refreshes one cluster's recently changed and watched pubkeys every `config.interval`;
+/// `programs` are the cluster's.
+pub async fn run_targeted_refresh(
+    client: Arc<SolanaRpc>,
+    programs: Vec<ProgramKey>,
+    sync: SyncContext,
+    config: RefreshConfig,
+) {
+    let cluster = sync.clusters.name_of(&programs[0]).map(str::to_string);
+    let span = info_span!("targeted_refresh", cluster = cluster.as_deref().unwrap_or("default"));
+    async move {
+        let mut events = sync.events.subscribe();
+        let mut queue = RefreshQueue::new(&config.watch);
+        let mut ticks = interval(config.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        ticks.tick().await;
+        loop {
+            tokio::select! {
+                received = events.recv() => match received {
+                    Ok(event) => queue.record(&event.event, &programs, cluster.as_deref()),
+                    Err(RecvError::Lagged(missed)) => {
+                        debug!(missed, "Refresh fell behind the change feed; the next scan covers what it missed");
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = ticks.tick() => {
+                    if sync.pause.is_paused() {
+                        continue;
+                    }
+                    let pubkeys = queue.next_pass(config.recent, config.max_pubkeys);
+                    if pubkeys.is_empty() {
+                        continue;
+                    }
+                    let started = Instant::now();
+                    match refresh(&client, &programs, &sync, &mut queue, &pubkeys).await {
+                        Ok(()) => {
+                            let duration_ms = started.elapsed().as_millis() as u64;
+                            debug!(pubkeys = pubkeys.len(), duration_ms, "Targeted refresh pass complete");
+                        }
+                        Err(e) => warn!(error = %e, "Targeted refresh pass failed"),
+                    }
+                }
+            }
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+/// Re-reads `pubkeys` in getMultipleAccounts batches and applies each account as a streamed
+/// update would be.
+async fn refresh(
+    client: &SolanaRpc,
+    programs: &[ProgramKey],
+    sync: &SyncContext,
+    queue: &mut RefreshQueue,
+    pubkeys: &[Pubkey],
+) -> Result<(), AppError> {
+    for batch in pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let (slot, accounts) = client.get_multiple_accounts(batch).await?;
+        for (pubkey, account) in batch.iter().zip(accounts) {
+            let owner = account.as_ref().and_then(|account| {
+                programs.iter().find(|program| program.program_id == account.owner).copied()
+            });
+            let update = match (account, owner) {
+                (Some(account), Some(program)) => {
+                    queue.programs.insert(*pubkey, program);
+                    AccountUpdate {
+                        program,
+                        pubkey: pubkey.to_string(),
+                        lamports: account.lamports,
+                        data: account.data,
+                        owner: account.owner,
+                        rent_epoch: account.rent_epoch,
+                        slot,
+                    }
+                }
+                // Closed, or no longer owned by the program: gone from its index either way.
+                _ => {
+                    let Some(program) = queue.programs.remove(pubkey) else { continue };
+                    AccountUpdate {
+                        program,
+                        pubkey: pubkey.to_string(),
+                        lamports: 0,
+                        data: Vec::new(),
+                        owner: program.program_id,
+                        rent_epoch: 0,
+                        slot,
+                    }
+                }
+            };
+            apply_account_update(sync, update, "Refresh").await?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,17 @@
+//! Rent exemption of NodeDevice accounts. An account holding less than the rent-exempt minimum
+//! for its size pays rent out of its balance until the runtime reclaims it, taking the node's
+//! registration with it. Each row stores the account's `lamports` and whether they cover that
+//! minimum, so `?rent_exempt=false` lists the nodes whose operators should top them up.
+//! Both are null for rows not written since the raw account was first stored.
+
+use solana_sdk::rent::Rent;
+
+/// Lamports an account of `data_len` bytes needs to be rent-exempt, at the default rent.
+pub fn minimum_balance(data_len: usize) -> u64 {
+    Rent::default().minimum_balance(data_len)
+}
+
+/// Whether `lamports` keep an account of `data_len` bytes rent-exempt.
+pub fn is_exempt(lamports: u64, data_len: usize) -> bool {
+    Rent::default().is_exempt(lamports, data_len)
+}
@@ -0,0 +1,64 @@
+//! Error reporting (`--features sentry`). Once `SENTRY_DSN` is set, failed sync cycles, streamed
+//! updates that couldn't be applied, panics (including those of background tasks) and `5xx`
+//! API responses are sent to Sentry, tagged with the program ID, cluster, pubkey, slot or
+//! request they concern. The lines logged before each report are attached as breadcrumbs.
+//! `SENTRY_ENVIRONMENT`, `SENTRY_RELEASE` and the client's other variables are read from the
+//! environment rather than the config file. Without the feature or a DSN, [`report`] does nothing.
+
+use std::fmt::Display;
+
+#[cfg(feature = "sentry")]
+use sentry::integrations::tracing::EventFilter;
+#[cfg(feature = "sentry")]
+use tracing::{Level, Subscriber};
+#[cfg(feature = "sentry")]
+use tracing_subscriber::registry::LookupSpan;
+
+#[cfg(feature = "sentry")]
+use crate::AppError;
+
+/// Reports a failure as one Sentry event, grouped by `what` rather than by the error text so
+/// that the same failure with different pubkeys or slots lands in one issue.
+pub fn report(what: &str, error: &dyn Display, tags: &[(&str, String)]) {
+    #[cfg(feature = "sentry")]
+    sentry::with_scope(
+        |scope| {
+            scope.set_fingerprint(Some(&[what]));
+            for (key, value) in tags {
+                scope.set_tag(key, value);
+            }
+        },
+        || sentry::capture_message(&format!("{}: {}", what, error), sentry::Level::Error),
+    );
+    #[cfg(not(feature = "sentry"))]
+    let _ = (what, error, tags);
+}
+
+/// The tracing layer adding log lines to reports as breadcrumbs.
+#[cfg(feature = "sentry")]
+pub type BreadcrumbLayer<S> = sentry::integrations::tracing::SentryLayer<S>;
+
+/// The breadcrumb layer, with the client sending reports until the guard is dropped, or `None`
+/// when `SENTRY_DSN` isn't set.
+#[cfg(feature = "sentry")]
+pub fn init<S>() -> Result<Option<(BreadcrumbLayer<S>, sentry::ClientInitGuard)>, AppError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let Some(dsn) = std::env::var("SENTRY_DSN").ok().filter(|dsn| !dsn.is_empty()) else { return Ok(None) };
+    // The client would ignore a malformed DSN without a word.
+    let dsn = dsn.parse::<sentry::types::Dsn>().map_err(|e| format!("Invalid SENTRY_DSN: {}", e))?;
+    let mut options = sentry::ClientOptions::default();
+    options.dsn = Some(dsn);
+    options.release = std::env::var("SENTRY_RELEASE").ok().map(Into::into).or_else(|| sentry::release_name!());
+    // SENTRY_ENVIRONMENT is read by the client itself; panics are captured by default.
+    let guard = sentry::init(options);
+    // Events only come from `report` and panics, so an error line doesn't become a second issue.
+    let layer = sentry::integrations::tracing::layer()
+        .event_filter(|metadata| match *metadata.level() {
+            Level::ERROR | Level::WARN | Level::INFO => EventFilter::Breadcrumb,
+            _ => EventFilter::Ignore,
+        })
+        .span_filter(|_| false);
+    Ok(Some((layer, guard)))
+}
@@ -0,0 +1,101 @@
+//! Request IDs and request logging. Each API request is tagged with the caller's
+//! `X-Request-Id`, or a fresh random one, which is echoed back in the response header and in
+//! error bodies. Everything logged while handling the request carries the ID, and one line per
+//! request records its method, path, status and latency, so a user's report can be matched
+//! with the server's logs.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tokio::time::Instant;
+use tracing::{debug, field, info, info_span, warn, Instrument};
+
+use crate::error::ServerError;
+use crate::reporting;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// --- Paths whose successful requests are only logged at debug level ---
+const PROBE_PATHS: &[&str] = &["/healthz", "/readyz"];
+
+// --- Longest caller-supplied ID that is kept rather than replaced ---
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The ID of the request being handled, when called from inside [`assign`].
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Keeps a caller-supplied ID made of letters, digits, `-`, `_` and `.`; anything else could
+/// be used to forge log lines, so it is replaced.
+fn accepted(value: &HeaderValue) -> Option<String> {
+    let id = value.to_str().ok()?;
+    let valid = !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'));
+    valid.then(|| id.to_string())
+}
+
+fn generate() -> String {
+    let bytes: [u8; 16] = rand::random();
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Assigns the request its ID for the rest of its handling, runs it inside a `request` span,
+/// logs its outcome and sets `X-Request-Id` on the response. Latency runs until the response
+/// headers are ready, so for WebSocket and SSE streams it excludes the stream itself.
+pub async fn assign(request: Request, next: Next) -> Response {
+    let id = request.headers().get(REQUEST_ID_HEADER).and_then(accepted).unwrap_or_else(generate);
+    // The query string is left out: it may hold an `api_key`.
+    let path = request.uri().path().to_string();
+    let method = request.method().to_string();
+    // `otel.status_code` marks failed requests in exported traces (`--features otel`).
+    let span = info_span!(
+        "request",
+        request_id = %id,
+        method = %method,
+        path = %path,
+        otel.status_code = field::Empty,
+    );
+    let started = Instant::now();
+
+    let mut response = REQUEST_ID.scope(id.clone(), next.run(request)).instrument(span.clone()).await;
+
+    let status = response.status().as_u16();
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    span.in_scope(|| {
+        if response.status().is_server_error() {
+            warn!(status, latency_ms, "Request failed");
+        } else if PROBE_PATHS.contains(&path.as_str()) {
+            // Orchestrators poll these every few seconds.
+            debug!(status, latency_ms, "Request served");
+        } else {
+            info!(status, latency_ms, "Request served");
+        }
+    });
+    if response.status().is_server_error() {
+        span.record("otel.status_code", "ERROR");
+        let failure = response.extensions().get::<ServerError>();
+        let code = failure.map_or_else(|| status.to_string(), |failure| failure.code.to_string());
+        let what = format!("API request failed with {}", code);
+        let tags = [
+            ("request_id", id.clone()),
+            ("method", method),
+            ("path", path.clone()),
+            ("status", status.to_string()),
+        ];
+        let error = failure.map_or_else(|| response.status().to_string(), |failure| failure.error.clone());
+        span.in_scope(|| reporting::report(&what, &error, &tags));
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,165 @@
+//! In-process cache of hot GET responses (`API_CACHE_TTLS`, `API_CACHE_MAX_ENTRIES`). Each
+//! listed route keeps its `200` responses for its own TTL, or until the sync loop publishes a
+//! change event, whichever comes first; the TTL also bounds data that changes without one,
+//! such as `/stats` after a quiet cycle.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    extract::{MatchedPath, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::time::Instant;
+
+use crate::config;
+use crate::error::ApiError;
+use crate::events::EventHub;
+use crate::AppError;
+
+// --- Tells clients whether a response came from the cache ---
+pub const CACHE_STATUS_HEADER: HeaderName = HeaderName::from_static("x-cache");
+
+// --- Responses kept unless API_CACHE_MAX_ENTRIES says otherwise ---
+const DEFAULT_MAX_ENTRIES: usize = 1024;
+
+// --- Larger bodies are passed through uncached ---
+const MAX_CACHED_BODY_BYTES: u64 = 1024 * 1024;
+
+/// Which routes are cached and for how long.
+#[derive(Clone, Debug)]
+pub struct ResponseCacheConfig {
+    /// TTL per route, keyed by its path pattern, e.g. `/nodes/:pubkey`.
+    pub ttls: HashMap<String, Duration>,
+    /// Responses kept across all routes; once full, only expired ones make room.
+    pub max_entries: usize,
+}
+
+impl ResponseCacheConfig {
+    /// `None` when `API_CACHE_TTLS` is unset, leaving every request to the database.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let Ok(value) = config::var("API_CACHE_TTLS") else { return Ok(None) };
+        let mut ttls = HashMap::new();
+        for entry in value.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let parsed = entry.split_once('=').and_then(|(route, secs)| {
+                let route = route.trim();
+                let secs = secs.trim().parse::<f64>().ok().filter(|secs| secs.is_finite() && *secs > 0.0)?;
+                route.starts_with('/').then(|| (route.to_string(), Duration::from_secs_f64(secs)))
+            });
+            let (route, ttl) = parsed.ok_or_else(|| {
+                let source = config::source("API_CACHE_TTLS");
+                format!("Invalid {} entry '{}': expected a route and seconds such as /nodes=5", source, entry)
+            })?;
+            ttls.insert(route, ttl);
+        }
+        let max_entries = match config::var("API_CACHE_MAX_ENTRIES") {
+            Ok(value) => value.parse::<usize>().ok().filter(|max| *max > 0).ok_or_else(|| {
+                let source = config::source("API_CACHE_MAX_ENTRIES");
+                format!("Invalid {} '{}': expected a positive number of responses", source, value)
+            })?,
+            Err(_) => DEFAULT_MAX_ENTRIES,
+        };
+        Ok(Some(Self { ttls, max_entries }))
+    }
+}
+
+struct CachedResponse {
+    headers: HeaderMap,
+    body: Bytes,
+    /// [`EventHub::next_id`] when the handler started.
+    generation: u64,
+    stored_at: Instant,
+    ttl: Duration,
+}
+
+impl CachedResponse {
+    fn fresh(&self, generation: u64) -> bool {
+        self.generation == generation && self.stored_at.elapsed() < self.ttl
+    }
+}
+
+/// Cached responses by path and query, invalidated by the change events of `events`.
+pub struct ResponseCache {
+    config: ResponseCacheConfig,
+    events: EventHub,
+    entries: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl ResponseCache {
+    pub fn new(config: ResponseCacheConfig, events: EventHub) -> Self {
+        Self { config, events, entries: Mutex::new(HashMap::new()) }
+    }
+
+    fn lookup(&self, key: &str, generation: u64) -> Option<Response> {
+        let entries = self.entries.lock().unwrap();
+        let cached = entries.get(key).filter(|cached| cached.fresh(generation))?;
+        let mut response = Response::new(Body::from(cached.body.clone()));
+        *response.headers_mut() = cached.headers.clone();
+        Some(response)
+    }
+
+    fn store(&self, key: String, cached: CachedResponse) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.config.max_entries && !entries.contains_key(&key) {
+            let generation = self.events.next_id();
+            entries.retain(|_, entry| entry.fresh(generation));
+        }
+        if entries.len() < self.config.max_entries || entries.contains_key(&key) {
+            entries.insert(key, cached);
+        }
+    }
+}
+
+/// The cache key: the path and query minus `api_key`, which doesn't change the response.
+fn cache_key(request: &Request) -> String {
+    let uri = request.uri();
+    let query: Vec<&str> = uri
+        .query()
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty() && *pair != "api_key" && !pair.starts_with("api_key="))
+        .collect();
+    if query.is_empty() { uri.path().to_string() } else { format!("{}?{}", uri.path(), query.join("&")) }
+}
+
+/// Answers GET requests to the configured routes from the cache when it holds a fresh
+/// response, and caches the handler's `200` otherwise. Layered inside authentication and
+/// throttling, so only requests allowed through are answered.
+pub async fn cache(State(cache): State<Arc<ResponseCache>>, request: Request, next: Next) -> Response {
+    let route = request.extensions().get::<MatchedPath>().map(MatchedPath::as_str);
+    let ttl = route.and_then(|route| cache.config.ttls.get(route)).copied();
+    let Some(ttl) = ttl.filter(|_| request.method() == Method::GET) else { return next.run(request).await };
+
+    let key = cache_key(&request);
+    // Read before the handler runs, so a change it may have missed invalidates the entry.
+    let generation = cache.events.next_id();
+    if let Some(mut response) = cache.lookup(&key, generation) {
+        response.headers_mut().insert(CACHE_STATUS_HEADER, HeaderValue::from_static("hit"));
+        return response;
+    }
+
+    let response = next.run(request).await;
+    let cacheable = response.status() == StatusCode::OK
+        && response.body().size_hint().upper().is_some_and(|size| size <= MAX_CACHED_BODY_BYTES);
+    if !cacheable {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let Ok(body) = axum::body::to_bytes(body, MAX_CACHED_BODY_BYTES as usize).await else {
+        let detail = "The response body could not be read";
+        return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "response_failed", detail).into_response();
+    };
+    cache.store(key, CachedResponse {
+        headers: parts.headers.clone(),
+        body: body.clone(),
+        generation,
+        stored_at: Instant::now(),
+        ttl,
+    });
+    parts.headers.insert(CACHE_STATUS_HEADER, HeaderValue::from_static("miss"));
+    Response::from_parts(parts, Body::from(body))
+}
@@ -0,0 +1,542 @@
+//! RPC access for the sync loop: retries, endpoint failover and client-side rate limiting.
+
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use solana_client::client_error::Result as ClientResult;
+use solana_client::client_error::{reqwest, ClientError, ClientErrorKind};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClientConfig};
+use solana_client::rpc_config::{RpcProgramAccountsConfig, RpcTransactionConfig};
+use solana_client::rpc_filter::{Memcmp, RpcFilterType};
+use solana_client::rpc_request::{RpcError, RpcRequest};
+use solana_client::rpc_response::{OptionalContext, RpcConfirmedTransactionStatusWithSignature, RpcKeyedAccount};
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{debug, field, info_span, warn, Instrument};
+use utoipa::ToSchema;
+
+use crate::config;
+use crate::fixtures::{FixtureSender, RpcFixtures};
+use crate::AppError;
+
+// --- Default RPC retry policy: attempts after the first, and the backoff window in ms ---
+const DEFAULT_RPC_MAX_RETRIES: u32 = 5;
+const DEFAULT_RPC_RETRY_BASE_MS: u64 = 250;
+const DEFAULT_RPC_RETRY_MAX_MS: u64 = 10_000;
+
+// --- How long an RPC endpoint is left alone after it answers 429 Too Many Requests ---
+const RATE_LIMIT_COOLDOWN: Duration = Duration::from_secs(30);
+
+// --- Pubkeys per getMultipleAccounts request (the RPC maximum) ---
+pub const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Retry settings for RPC calls (`RPC_MAX_RETRIES`, `RPC_RETRY_BASE_MS`, `RPC_RETRY_MAX_MS`).
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts made after the first one fails.
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: DEFAULT_RPC_MAX_RETRIES,
+            base_delay: Duration::from_millis(DEFAULT_RPC_RETRY_BASE_MS),
+            max_delay: Duration::from_millis(DEFAULT_RPC_RETRY_MAX_MS),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Result<Self, AppError> {
+        fn env_number<T: FromStr>(name: &str, default: T) -> Result<T, AppError> {
+            match config::var(name) {
+                Ok(value) => value
+                    .parse()
+                    .map_err(|_| format!("Invalid {} '{}': expected a number", config::source(name), value).into()),
+                Err(_) => Ok(default),
+            }
+        }
+        Ok(Self {
+            max_retries: env_number("RPC_MAX_RETRIES", DEFAULT_RPC_MAX_RETRIES)?,
+            base_delay: Duration::from_millis(env_number("RPC_RETRY_BASE_MS", DEFAULT_RPC_RETRY_BASE_MS)?),
+            max_delay: Duration::from_millis(env_number("RPC_RETRY_MAX_MS", DEFAULT_RPC_RETRY_MAX_MS)?),
+        })
+    }
+
+    /// Full-jitter exponential backoff: a random delay up to `base * 2^attempt`, capped at `max_delay`.
+    fn delay(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(1u32.checked_shl(attempt).unwrap_or(u32::MAX))
+            .min(self.max_delay);
+        ceiling.mul_f64(rand::random::<f64>())
+    }
+}
+
+/// Client-side request budget per RPC endpoint (`RPC_RATE_LIMIT_RPS`, `RPC_RATE_LIMIT_BURST`).
+#[derive(Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub requests_per_sec: f64,
+    pub burst: f64,
+}
+
+impl RateLimit {
+    /// `None` when `RPC_RATE_LIMIT_RPS` is unset, leaving requests unthrottled.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let parse = |name: &str, value: String| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| *number > 0.0)
+                .ok_or_else(|| format!("Invalid {} '{}': expected a positive number", config::source(name), value))
+        };
+        let Ok(rps) = config::var("RPC_RATE_LIMIT_RPS") else { return Ok(None) };
+        let requests_per_sec = parse("RPC_RATE_LIMIT_RPS", rps)?;
+        let burst = match config::var("RPC_RATE_LIMIT_BURST") {
+            Ok(value) => parse("RPC_RATE_LIMIT_BURST", value)?,
+            Err(_) => requests_per_sec.max(1.0),
+        };
+        Ok(Some(Self { requests_per_sec, burst }))
+    }
+}
+
+/// Token bucket holding `burst` tokens, refilled at `requests_per_sec`.
+struct TokenBucket {
+    limit: RateLimit,
+    state: tokio::sync::Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self { limit, state: tokio::sync::Mutex::new((limit.burst, Instant::now())) }
+    }
+
+    /// Waits until a token is available and takes it.
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let (tokens, refilled_at) = &mut *state;
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*refilled_at).as_secs_f64() * self.limit.requests_per_sec)
+                    .min(self.limit.burst);
+                *refilled_at = now;
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - *tokens) / self.limit.requests_per_sec)
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+/// Whether the endpoint turned the request away for exceeding its rate limit, either with an
+/// HTTP 429 or a JSON-RPC error carrying the same code (some providers do the latter).
+fn is_rate_limited(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Reqwest(e) => e.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => *code == 429,
+        _ => false,
+    }
+}
+
+/// Running totals of RPC calls made by the indexer, reported by `/stats`.
+#[derive(Default)]
+struct RpcMetrics {
+    requests: AtomicU64,
+    retries: AtomicU64,
+    failures: AtomicU64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(as = RpcMetrics)]
+pub struct RpcMetricsSnapshot {
+    /// Individual RPC attempts, including retries.
+    pub requests: u64,
+    pub retries: u64,
+    /// Calls that still failed after exhausting their retries.
+    pub failures: u64,
+    pub endpoints: Vec<RpcEndpointStatus>,
+}
+
+/// Health of one configured RPC endpoint, as reported by `/stats`.
+#[derive(Serialize, ToSchema)]
+#[schema(as = RpcEndpoint)]
+pub struct RpcEndpointStatus {
+    /// Scheme and host only; paths and query strings often carry provider API keys.
+    pub url: String,
+    pub active: bool,
+    pub requests: u64,
+    pub failures: u64,
+    pub consecutive_failures: u64,
+    /// Responses that were 429 Too Many Requests.
+    pub rate_limited: u64,
+    /// `true` while the endpoint is backing off after a 429.
+    pub cooling_down: bool,
+}
+
+struct RpcEndpoint {
+    url: String,
+    client: RpcClient,
+    limiter: Option<TokenBucket>,
+    /// Set after a 429; requests to this endpoint wait until it has passed.
+    cooldown_until: Mutex<Option<Instant>>,
+    requests: AtomicU64,
+    failures: AtomicU64,
+    consecutive_failures: AtomicU64,
+    rate_limited: AtomicU64,
+}
+
+impl RpcEndpoint {
+    fn cooldown_remaining(&self) -> Option<Duration> {
+        let until = (*self.cooldown_until.lock().unwrap())?;
+        let remaining = until.saturating_duration_since(Instant::now());
+        (!remaining.is_zero()).then_some(remaining)
+    }
+
+    /// Holds the request back until the endpoint is out of cooldown and has budget left.
+    async fn ready(&self) {
+        if let Some(remaining) = self.cooldown_remaining() {
+            sleep(remaining).await;
+        }
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+    }
+}
+
+/// The RPC client used by the sync loop. Transient failures are retried with jittered
+/// backoff so a brief outage delays a cycle instead of skipping it, and every failed
+/// attempt rotates to the next configured endpoint.
+///
+/// Requests are optionally throttled per endpoint with a token bucket. The underlying HTTP
+/// sender already honours `Retry-After` for a handful of 429s; once it gives up, the endpoint
+/// is put in cooldown for `RATE_LIMIT_COOLDOWN` so the retry goes elsewhere or waits it out.
+pub struct SolanaRpc {
+    endpoints: Vec<RpcEndpoint>,
+    active: AtomicUsize,
+    retry: RetryPolicy,
+    metrics: RpcMetrics,
+}
+
+impl SolanaRpc {
+    /// `urls` must not be empty; the first entry starts out active.
+    pub fn new(
+        urls: &[String],
+        commitment: CommitmentConfig,
+        retry: RetryPolicy,
+        rate_limit: Option<RateLimit>,
+    ) -> Self {
+        assert!(!urls.is_empty(), "SolanaRpc needs at least one endpoint");
+        let endpoints = urls
+            .iter()
+            .map(|url| RpcEndpoint {
+                url: redact_url(url),
+                client: RpcClient::new_with_commitment(url.clone(), commitment),
+                limiter: rate_limit.map(TokenBucket::new),
+                cooldown_until: Mutex::new(None),
+                requests: AtomicU64::new(0),
+                failures: AtomicU64::new(0),
+                consecutive_failures: AtomicU64::new(0),
+                rate_limited: AtomicU64::new(0),
+            })
+            .collect();
+        Self { endpoints, active: AtomicUsize::new(0), retry, metrics: RpcMetrics::default() }
+    }
+
+    /// A client answered from `fixtures` rather than a cluster. Fixture errors are permanent,
+    /// so nothing is retried.
+    pub fn from_fixtures(fixtures: RpcFixtures, commitment: CommitmentConfig) -> Self {
+        let sender = FixtureSender::new(fixtures);
+        let endpoint = RpcEndpoint {
+            url: sender.source().to_string(),
+            client: RpcClient::new_sender(sender, RpcClientConfig::with_commitment(commitment)),
+            limiter: None,
+            cooldown_until: Mutex::new(None),
+            requests: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            consecutive_failures: AtomicU64::new(0),
+            rate_limited: AtomicU64::new(0),
+        };
+        let retry = RetryPolicy { max_retries: 0, ..RetryPolicy::default() };
+        Self { endpoints: vec![endpoint], active: AtomicUsize::new(0), retry, metrics: RpcMetrics::default() }
+    }
+
+    /// The currently active client, for calls that should not be retried.
+    pub fn client(&self) -> &RpcClient {
+        &self.endpoints[self.active.load(Ordering::Relaxed)].client
+    }
+
+    pub fn metrics(&self) -> RpcMetricsSnapshot {
+        let active = self.active.load(Ordering::Relaxed);
+        RpcMetricsSnapshot {
+            requests: self.metrics.requests.load(Ordering::Relaxed),
+            retries: self.metrics.retries.load(Ordering::Relaxed),
+            failures: self.metrics.failures.load(Ordering::Relaxed),
+            endpoints: self
+                .endpoints
+                .iter()
+                .enumerate()
+                .map(|(index, endpoint)| RpcEndpointStatus {
+                    url: endpoint.url.clone(),
+                    active: index == active,
+                    requests: endpoint.requests.load(Ordering::Relaxed),
+                    failures: endpoint.failures.load(Ordering::Relaxed),
+                    consecutive_failures: endpoint.consecutive_failures.load(Ordering::Relaxed),
+                    rate_limited: endpoint.rate_limited.load(Ordering::Relaxed),
+                    cooling_down: endpoint.cooldown_remaining().is_some(),
+                })
+                .collect(),
+        }
+    }
+
+    pub async fn get_slot(&self) -> ClientResult<u64> {
+        self.with_retry("getSlot", |client| client.get_slot()).await
+    }
+
+    /// The highest finalized slot, whatever the client's commitment.
+    pub async fn get_finalized_slot(&self) -> ClientResult<u64> {
+        self.with_retry("getSlot", |client| client.get_slot_with_commitment(CommitmentConfig::finalized())).await
+    }
+
+    /// getBlocks at finalized commitment: the slots from `start` to `end` inclusive that hold a
+    /// finalized block. Slots left out were skipped or belonged to an abandoned fork.
+    pub async fn get_finalized_blocks(&self, start: u64, end: u64) -> ClientResult<Vec<u64>> {
+        self.with_retry("getBlocks", |client| {
+            client.get_blocks_with_commitment(start, Some(end), CommitmentConfig::finalized())
+        })
+        .await
+    }
+
+    /// getAccountInfo at the client's commitment; `None` if the account doesn't exist.
+    pub async fn get_account(&self, pubkey: &Pubkey) -> ClientResult<Option<Account>> {
+        let response = self
+            .with_retry("getAccountInfo", |client| client.get_account_with_commitment(pubkey, client.commitment()))
+            .await?;
+        Ok(response.value)
+    }
+
+    /// getMultipleAccounts for up to [`MAX_MULTIPLE_ACCOUNTS`] pubkeys, returning the slot they
+    /// were read at and each account in request order (`None` where it doesn't exist).
+    pub async fn get_multiple_accounts(&self, pubkeys: &[Pubkey]) -> ClientResult<(u64, Vec<Option<Account>>)> {
+        let response = self
+            .with_retry("getMultipleAccounts", |client| {
+                client.get_multiple_accounts_with_commitment(pubkeys, client.commitment())
+            })
+            .await?;
+        Ok((response.context.slot, response.value))
+    }
+
+    /// One page of getSignaturesForAddress, newest first: up to `limit` signatures older than
+    /// `before` and newer than `until`.
+    pub async fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        until: Option<Signature>,
+        limit: usize,
+    ) -> ClientResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.with_retry("getSignaturesForAddress", |client| {
+            let config = GetConfirmedSignaturesForAddress2Config {
+                before,
+                until,
+                limit: Some(limit),
+                commitment: Some(history_commitment(client.commitment())),
+            };
+            client.get_signatures_for_address_with_config(address, config)
+        })
+        .await
+    }
+
+    /// getTransaction in binary encoding, so the message can be decoded locally.
+    pub async fn get_transaction(
+        &self,
+        signature: &Signature,
+    ) -> ClientResult<EncodedConfirmedTransactionWithStatusMeta> {
+        self.with_retry("getTransaction", |client| {
+            let config = RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(history_commitment(client.commitment())),
+                max_supported_transaction_version: Some(0),
+            };
+            client.get_transaction_with_config(signature, config)
+        })
+        .await
+    }
+
+    /// getProgramAccounts with `withContext`, returning the slot the snapshot was served at
+    /// alongside the accounts. The slot is `None` if the node ignored `withContext`.
+    pub async fn get_program_accounts_with_context(
+        &self,
+        program_id: &Pubkey,
+        mut config: RpcProgramAccountsConfig,
+    ) -> ClientResult<(Option<u64>, Vec<(Pubkey, Account)>)> {
+        config.with_context = Some(true);
+        let response = self
+            .with_retry("getProgramAccounts", |client| {
+                let mut config = config.clone();
+                config.account_config.commitment.get_or_insert_with(|| client.commitment());
+                client.send::<OptionalContext<Vec<RpcKeyedAccount>>>(
+                    RpcRequest::GetProgramAccounts,
+                    serde_json::json!([program_id.to_string(), config]),
+                )
+            })
+            .await?;
+        let (slot, keyed_accounts) = match response {
+            OptionalContext::Context(response) => (Some(response.context.slot), response.value),
+            OptionalContext::NoContext(value) => (None, value),
+        };
+
+        let mut accounts = Vec::with_capacity(keyed_accounts.len());
+        for RpcKeyedAccount { pubkey, account } in keyed_accounts {
+            let parse_error = |what: &str| {
+                let error = RpcError::ParseError(what.to_string());
+                ClientError::new_with_request(error.into(), RpcRequest::GetProgramAccounts)
+            };
+            let pubkey = Pubkey::from_str(&pubkey).map_err(|_| parse_error("Pubkey"))?;
+            let account = account.decode().ok_or_else(|| parse_error("Account from rpc"))?;
+            accounts.push((pubkey, account));
+        }
+        Ok((slot, accounts))
+    }
+
+    /// [`Self::get_program_accounts_with_context`] as one request per value of the
+    /// `prefix_bytes` bytes at `offset` (256 per byte), one after another, for programs whose
+    /// whole response is more than a provider will return. Accounts too short to have those
+    /// bytes match no request. The slot is the newest any request was served at, and `None`
+    /// if any ignored `withContext`.
+    pub async fn get_program_accounts_sharded(
+        &self,
+        program_id: &Pubkey,
+        config: RpcProgramAccountsConfig,
+        offset: usize,
+        prefix_bytes: u8,
+    ) -> ClientResult<(Option<u64>, Vec<(Pubkey, Account)>)> {
+        if prefix_bytes == 0 {
+            return self.get_program_accounts_with_context(program_id, config).await;
+        }
+        let shards = 1u32 << (8 * u32::from(prefix_bytes));
+        let mut slot = Some(0);
+        let mut accounts = Vec::new();
+        for shard in 0..shards {
+            let prefix = shard.to_be_bytes()[4 - usize::from(prefix_bytes)..].to_vec();
+            let mut config = config.clone();
+            let filter = RpcFilterType::Memcmp(Memcmp::new_raw_bytes(offset, prefix));
+            config.filters.get_or_insert_with(Vec::new).push(filter);
+            let (shard_slot, shard_accounts) = self.get_program_accounts_with_context(program_id, config).await?;
+            slot = slot.zip(shard_slot).map(|(slot, shard_slot)| slot.max(shard_slot));
+            accounts.extend(shard_accounts);
+        }
+        debug!(shards, accounts = accounts.len(), "Fetched program accounts in shards");
+        Ok((slot, accounts))
+    }
+
+    /// Moves off `failed` to the next endpoint, unless a concurrent call already did.
+    fn fail_over(&self, failed: usize) {
+        if self.endpoints.len() < 2 {
+            return;
+        }
+        let next = (failed + 1) % self.endpoints.len();
+        if self.active.compare_exchange(failed, next, Ordering::Relaxed, Ordering::Relaxed).is_ok() {
+            let (from, to) = (&self.endpoints[failed].url, &self.endpoints[next].url);
+            warn!(%from, %to, "Failing over to next RPC endpoint");
+        }
+    }
+
+    async fn with_retry<'a, T, F, Fut>(&'a self, method: &'static str, mut call: F) -> ClientResult<T>
+    where
+        F: FnMut(&'a RpcClient) -> Fut,
+        Fut: Future<Output = ClientResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let index = self.active.load(Ordering::Relaxed);
+            let endpoint = &self.endpoints[index];
+            endpoint.ready().await;
+            self.metrics.requests.fetch_add(1, Ordering::Relaxed);
+            endpoint.requests.fetch_add(1, Ordering::Relaxed);
+            let span = info_span!("rpc_call", method, attempt, otel.kind = "client", otel.status_code = field::Empty);
+            let result = call(&endpoint.client).instrument(span.clone()).await;
+            if result.is_err() {
+                span.record("otel.status_code", "ERROR");
+            }
+            match &result {
+                Ok(_) => endpoint.consecutive_failures.store(0, Ordering::Relaxed),
+                Err(e) => {
+                    endpoint.failures.fetch_add(1, Ordering::Relaxed);
+                    endpoint.consecutive_failures.fetch_add(1, Ordering::Relaxed);
+                    if is_rate_limited(e) {
+                        endpoint.rate_limited.fetch_add(1, Ordering::Relaxed);
+                        *endpoint.cooldown_until.lock().unwrap() = Some(Instant::now() + RATE_LIMIT_COOLDOWN);
+                        warn!(
+                            endpoint = %endpoint.url,
+                            cooldown_secs = RATE_LIMIT_COOLDOWN.as_secs(),
+                            "RPC endpoint rate limited"
+                        );
+                    }
+                    self.fail_over(index);
+                }
+            }
+            match result {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt < self.retry.max_retries => {
+                    let delay = self.retry.delay(attempt);
+                    attempt += 1;
+                    self.metrics.retries.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        method,
+                        endpoint = %endpoint.url,
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %e,
+                        "RPC call failed, retrying"
+                    );
+                    sleep(delay).await;
+                }
+                Err(e) => {
+                    self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+/// Transaction history isn't served below `confirmed`, so `processed` is bumped up to it.
+fn history_commitment(commitment: CommitmentConfig) -> CommitmentConfig {
+    if commitment.is_at_least_confirmed() { commitment } else { CommitmentConfig::confirmed() }
+}
+
+/// Reduces an RPC URL to its scheme and host so it can be logged and reported safely.
+pub(crate) fn redact_url(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority.rsplit_once('@').map_or(authority, |(_, host)| host);
+    if scheme.is_empty() { host.to_string() } else { format!("{}://{}", scheme, host) }
+}
+
+/// Derives the pubsub endpoint from an RPC URL, following the validator's
+/// convention of serving WebSockets on the RPC port + 1.
+pub fn ws_url_from_rpc(rpc_url: &str) -> String {
+    let ws_url = if let Some(rest) = rpc_url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = rpc_url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        rpc_url.to_string()
+    };
+    ws_url.replace(":8899", ":8900")
+}
@@ -0,0 +1,626 @@
+//! Storage backends for the standalone modes, behind the [`Storage`] trait and chosen by the
+//! `DATABASE_URL` scheme: the [`crate::local`] development mode and `dump` run on Postgres or
+//! SQLite (`--features sqlite`, e.g. `sqlite://indexer.db`) through it, and a test can mock it.
+//! SQLite's schema lives in `migrations_sqlite`: the Postgres migrations rely on types,
+//! functions and extensions SQLite doesn't have.
+//!
+//! The trait covers those modes only; the full indexer is Postgres-only and doesn't go through
+//! it. The sync loop's writes share one transaction per cycle with the accounts, registered
+//! decoders and decode failures, and the API's reads lean on `pg_trgm`, `jsonb` and
+//! `date_trunc`, none of which a trait over every backend can express. Both call
+//! [`crate::store`] and their own queries directly. [`PgStorage`] runs the same `store`
+//! queries, so the modes stay in step with the indexer; another backend for the indexer would
+//! need those paths ported first.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::PgPool;
+
+use crate::events::NodeEvent;
+use crate::pool::PoolConfig;
+use crate::store::{self, ApiNode, NodeRecord, NODE_COLUMNS};
+use crate::AppError;
+
+/// Future returned by [`Storage`] methods.
+pub type StorageFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, AppError>> + Send + 'a>>;
+
+/// What [`Storage::replace_nodes`] changed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReplaceSummary {
+    /// Rows added, or rewritten because their account data changed.
+    pub written: usize,
+    /// Rows deleted because their account was missing from the snapshot.
+    pub removed: usize,
+}
+
+/// The node reads and writes every storage backend provides.
+pub trait Storage: Send + Sync {
+    /// Creates or updates the schema.
+    fn migrate(&self) -> StorageFuture<'_, ()>;
+
+    /// Every node of `program_ids` (plus rows from before multi-program support), ordered by
+    /// pubkey. Soft-deleted rows are only included with `include_deleted`.
+    fn list_nodes<'a>(&'a self, program_ids: &'a [Pubkey], include_deleted: bool) -> StorageFuture<'a, Vec<ApiNode>>;
+
+    /// The `limit`/`offset` page of live nodes by pubkey, only `program`'s when given, and
+    /// how many there are in all. Nodes with an invalid URI or an unverified address are only
+    /// included with `include_invalid` or `include_unverified`; `rent_exempt` keeps only the
+    /// nodes that are or aren't rent-exempt.
+    fn page_nodes<'a>(
+        &'a self,
+        program: Option<&'a str>,
+        include_invalid: bool,
+        include_unverified: bool,
+        rent_exempt: Option<bool>,
+        limit: i64,
+        offset: i64,
+    ) -> StorageFuture<'a, (Vec<ApiNode>, i64)>;
+
+    /// The live node indexed under `pubkey`.
+    fn get_node<'a>(&'a self, pubkey: &'a str) -> StorageFuture<'a, Option<ApiNode>>;
+
+    /// Inserts or rewrites `records`, undeleting any that were soft-deleted. Pubkeys must be
+    /// unique within `records`; rows last written from a later slot than their record are kept.
+    fn upsert_nodes<'a>(&'a self, records: &'a [NodeRecord]) -> StorageFuture<'a, ()>;
+
+    /// Deletes (or, with `soft_delete`, marks deleted) `program_id`'s live rows whose pubkey
+    /// isn't in `keep`, a snapshot taken at `slot`, returning the pruned pubkeys. Rows written
+    /// from a later slot are kept.
+    fn prune_nodes<'a>(
+        &'a self,
+        program_id: &'a Pubkey,
+        keep: &'a [String],
+        slot: u64,
+        soft_delete: bool,
+    ) -> StorageFuture<'a, Vec<String>>;
+
+    /// Appends one history entry per event; `previous` holds the rows the events replaced.
+    fn record_history<'a>(
+        &'a self,
+        events: &'a [NodeEvent],
+        previous: &'a HashMap<String, ApiNode>,
+        slot: u64,
+    ) -> StorageFuture<'a, ()>;
+
+    /// Appends one change-feed entry per event, copying each into the outbox as well when
+    /// `outbox` is set.
+    fn record_changes<'a>(&'a self, events: &'a [NodeEvent], slot: u64, outbox: bool) -> StorageFuture<'a, ()>;
+
+    /// Change-feed entries after `cursor` (at most `limit`), with the events that wrote them.
+    fn changes_after(&self, cursor: i64, limit: i64) -> StorageFuture<'_, Vec<(i64, NodeEvent)>>;
+
+    /// Makes `program_id`'s rows match a full snapshot of its accounts taken at `slot`: writes
+    /// the `records` whose data changed and deletes rows for accounts not among them, in one
+    /// transaction. Only the nodes are written; history and changes are left to the caller.
+    fn replace_nodes<'a>(
+        &'a self,
+        program_id: &'a Pubkey,
+        records: &'a [NodeRecord],
+        slot: u64,
+    ) -> StorageFuture<'a, ReplaceSummary>;
+}
+
+/// Whether `database_url` selects the SQLite backend.
+pub fn is_sqlite(database_url: &str) -> bool {
+    database_url.starts_with("sqlite:")
+}
+
+/// Opens the backend `database_url` names; `pool` sizes a Postgres pool.
+pub async fn connect(database_url: &str, pool: &PoolConfig) -> Result<Arc<dyn Storage>, AppError> {
+    if is_sqlite(database_url) {
+        #[cfg(feature = "sqlite")]
+        return Ok(Arc::new(SqliteStorage::connect(database_url).await?));
+        #[cfg(not(feature = "sqlite"))]
+        return Err("A sqlite: DATABASE_URL requires building with `--features sqlite`".into());
+    }
+    Ok(Arc::new(PgStorage::new(pool.connect(database_url).await?)))
+}
+
+/// Stored data hash and lamports of each live row, by pubkey.
+type KnownNodes = HashMap<String, (Option<Vec<u8>>, Option<i64>)>;
+
+/// Rows in `known` that `records` would change, in their data or their balance.
+fn changed<'a>(records: &'a [NodeRecord], known: &KnownNodes) -> Vec<&'a NodeRecord> {
+    records
+        .iter()
+        .filter(|record| match known.get(&record.node.pubkey) {
+            Some((Some(hash), lamports)) => *hash != record.data_hash || *lamports != record.node.lamports,
+            _ => true,
+        })
+        .collect()
+}
+
+/// The Postgres backend, over the same tables the full indexer keeps.
+pub struct PgStorage {
+    pool: PgPool,
+}
+
+impl PgStorage {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+impl Storage for PgStorage {
+    fn migrate(&self) -> StorageFuture<'_, ()> {
+        Box::pin(store::migrate(&self.pool))
+    }
+
+    fn list_nodes<'a>(&'a self, program_ids: &'a [Pubkey], include_deleted: bool) -> StorageFuture<'a, Vec<ApiNode>> {
+        Box::pin(store::list_nodes(&self.pool, program_ids, include_deleted))
+    }
+
+    fn page_nodes<'a>(
+        &'a self,
+        program: Option<&'a str>,
+        include_invalid: bool,
+        include_unverified: bool,
+        rent_exempt: Option<bool>,
+        limit: i64,
+        offset: i64,
+    ) -> StorageFuture<'a, (Vec<ApiNode>, i64)> {
+        Box::pin(async move {
+            let total: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM nodes \
+                 WHERE ($1::text IS NULL OR program_id = $1) AND deleted_at IS NULL AND ($2 OR uri_valid) \
+                   AND ($3 OR pda_verified IS NOT FALSE) AND ($4::boolean IS NULL OR rent_exempt = $4)",
+            )
+            .bind(program)
+            .bind(include_invalid)
+            .bind(include_unverified)
+            .bind(rent_exempt)
+            .fetch_one(&self.pool)
+            .await?;
+            let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+                "SELECT {} FROM nodes WHERE ($1::text IS NULL OR program_id = $1) AND deleted_at IS NULL \
+                   AND ($4 OR uri_valid) AND ($5 OR pda_verified IS NOT FALSE) \
+                   AND ($6::boolean IS NULL OR rent_exempt = $6) ORDER BY pubkey LIMIT $2 OFFSET $3",
+                NODE_COLUMNS
+            ))
+            .bind(program)
+            .bind(limit)
+            .bind(offset)
+            .bind(include_invalid)
+            .bind(include_unverified)
+            .bind(rent_exempt)
+            .fetch_all(&self.pool)
+            .await?;
+            Ok((nodes, total))
+        })
+    }
+
+    fn get_node<'a>(&'a self, pubkey: &'a str) -> StorageFuture<'a, Option<ApiNode>> {
+        Box::pin(async move {
+            // Resolves a pubkey indexed from several clusters as `GET /nodes/:pubkey` does.
+            let node = sqlx::query_as::<_, ApiNode>(&format!(
+                "SELECT {} FROM nodes WHERE pubkey = $1 AND deleted_at IS NULL ORDER BY cluster NULLS FIRST LIMIT 1",
+                NODE_COLUMNS
+            ))
+            .bind(pubkey)
+            .fetch_optional(&self.pool)
+            .await?;
+            Ok(node)
+        })
+    }
+
+    fn upsert_nodes<'a>(&'a self, records: &'a [NodeRecord]) -> StorageFuture<'a, ()> {
+        Box::pin(async move {
+            store::upsert_nodes(&self.pool, records).await?;
+            Ok(())
+        })
+    }
+
+    fn prune_nodes<'a>(
+        &'a self,
+        program_id: &'a Pubkey,
+        keep: &'a [String],
+        slot: u64,
+        soft_delete: bool,
+    ) -> StorageFuture<'a, Vec<String>> {
+        // Local mode indexes a single, unnamed cluster.
+        Box::pin(store::prune_nodes(&self.pool, program_id, None, keep, slot, soft_delete))
+    }
+
+    fn record_history<'a>(
+        &'a self,
+        events: &'a [NodeEvent],
+        previous: &'a HashMap<String, ApiNode>,
+        slot: u64,
+    ) -> StorageFuture<'a, ()> {
+        Box::pin(store::record_history(&self.pool, events, |pubkey| previous.get(pubkey), slot))
+    }
+
+    fn record_changes<'a>(&'a self, events: &'a [NodeEvent], slot: u64, outbox: bool) -> StorageFuture<'a, ()> {
+        Box::pin(store::record_changes(&self.pool, events, slot, outbox))
+    }
+
+    fn changes_after(&self, cursor: i64, limit: i64) -> StorageFuture<'_, Vec<(i64, NodeEvent)>> {
+        Box::pin(store::changes_after(&self.pool, cursor, limit))
+    }
+
+    fn replace_nodes<'a>(
+        &'a self,
+        program_id: &'a Pubkey,
+        records: &'a [NodeRecord],
+        slot: u64,
+    ) -> StorageFuture<'a, ReplaceSummary> {
+        Box::pin(async move {
+            let mut tx = self.pool.begin().await?;
+            let known: KnownNodes = sqlx::query_as::<_, (String, Option<Vec<u8>>, Option<i64>)>(
+                "SELECT pubkey, data_hash, lamports FROM nodes \
+                 WHERE ((program_id = $1 AND cluster IS NULL) OR program_id IS NULL) AND deleted_at IS NULL",
+            )
+            .bind(program_id.to_string())
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|(pubkey, hash, lamports)| (pubkey, (hash, lamports)))
+            .collect();
+            let changed: Vec<NodeRecord> = changed(records, &known).into_iter().cloned().collect();
+            let written = store::upsert_nodes(&mut *tx, &changed).await?;
+            let pubkeys: Vec<String> = records.iter().map(|record| record.node.pubkey.clone()).collect();
+            let removed = store::prune_nodes(&mut *tx, program_id, None, &pubkeys, slot, false).await?;
+            tx.commit().await?;
+            Ok(ReplaceSummary { written: written.len(), removed: removed.len() })
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStorage;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use chrono::Utc;
+    use solana_sdk::pubkey::Pubkey;
+    use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+
+    use super::{changed, KnownNodes, ReplaceSummary, Storage, StorageFuture};
+    use crate::events::NodeEvent;
+    use crate::store::{ApiNode, ChangeRow, NodeRecord, NODE_COLUMNS};
+    use crate::AppError;
+
+    /// The SQLite backend. Its tables have the same columns as the Postgres ones, with
+    /// timestamps stored as text.
+    pub struct SqliteStorage {
+        pool: SqlitePool,
+    }
+
+    impl SqliteStorage {
+        /// Opens the database file `database_url` names, creating it if it doesn't exist.
+        pub async fn connect(database_url: &str) -> Result<Self, AppError> {
+            // WAL lets API reads go on while a sync writes.
+            let options = SqliteConnectOptions::from_str(database_url)?
+                .create_if_missing(true)
+                .journal_mode(SqliteJournalMode::Wal);
+            let pool = SqlitePoolOptions::new().connect_with(options).await?;
+            Ok(Self { pool })
+        }
+    }
+
+    /// `values` as a JSON array, for `IN (SELECT value FROM json_each(?))`.
+    fn json_list<T: ToString>(values: impl IntoIterator<Item = T>) -> String {
+        let values: Vec<String> = values.into_iter().map(|value| value.to_string()).collect();
+        serde_json::to_string(&values).expect("strings serialize")
+    }
+
+    /// Writes `records` one statement each, as SQLite has no array parameters. A new row's
+    /// `first_seen_at` is its `updated_at`, which [`Storage::changes_after`] relies on to tell
+    /// adds from updates.
+    async fn upsert(connection: &mut SqliteConnection, records: &[&NodeRecord]) -> Result<(), AppError> {
+        for NodeRecord { node, data_hash, raw } in records {
+            sqlx::query(
+                r#"
+                INSERT INTO nodes (pubkey, authority, uri, program_id, data_hash, first_seen_at, updated_at,
+                                   last_seen_slot, name, registered_at, layout_version, raw_data, lamports, owner,
+                                   rent_epoch, cluster, uri_valid, pda_verified, rent_exempt)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)
+                ON CONFLICT (pubkey) DO UPDATE
+                SET authority = excluded.authority,
+                    uri = excluded.uri,
+                    program_id = excluded.program_id,
+                    cluster = excluded.cluster,
+                    data_hash = excluded.data_hash,
+                    updated_at = excluded.updated_at,
+                    last_seen_slot = excluded.last_seen_slot,
+                    name = excluded.name,
+                    registered_at = excluded.registered_at,
+                    layout_version = excluded.layout_version,
+                    raw_data = excluded.raw_data,
+                    lamports = excluded.lamports,
+                    owner = excluded.owner,
+                    rent_epoch = excluded.rent_epoch,
+                    uri_valid = excluded.uri_valid,
+                    pda_verified = excluded.pda_verified,
+                    rent_exempt = excluded.rent_exempt,
+                    finalized = FALSE,
+                    deleted_at = NULL
+                WHERE nodes.last_seen_slot IS NULL OR nodes.last_seen_slot <= excluded.last_seen_slot
+                "#,
+            )
+            .bind(&node.pubkey)
+            .bind(&node.authority)
+            .bind(&node.uri)
+            .bind(&node.program_id)
+            .bind(data_hash)
+            .bind(node.updated_at.unwrap_or_else(Utc::now))
+            .bind(node.last_seen_slot)
+            .bind(&node.name)
+            .bind(node.registered_at)
+            .bind(node.layout_version)
+            .bind(&raw.data)
+            .bind(raw.lamports as i64)
+            .bind(raw.owner.to_string())
+            // Text, as with Postgres' NUMERIC: rent-exempt accounts report u64::MAX.
+            .bind(raw.rent_epoch.to_string())
+            .bind(&node.cluster)
+            .bind(node.uri_valid)
+            .bind(node.pda_verified)
+            .bind(node.rent_exempt)
+            .execute(&mut *connection)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn prune(
+        connection: &mut SqliteConnection,
+        program_id: &Pubkey,
+        keep: &[String],
+        slot: u64,
+        soft_delete: bool,
+    ) -> Result<Vec<String>, AppError> {
+        let statement = if soft_delete { "UPDATE nodes SET deleted_at = ?3" } else { "DELETE FROM nodes" };
+        let pruned = sqlx::query_scalar(&format!(
+            "{} WHERE (program_id = ?1 OR program_id IS NULL) AND deleted_at IS NULL \
+             AND pubkey NOT IN (SELECT value FROM json_each(?2)) \
+             AND (last_seen_slot IS NULL OR last_seen_slot <= ?4) RETURNING pubkey",
+            statement
+        ))
+        .bind(program_id.to_string())
+        .bind(json_list(keep))
+        .bind(Utc::now())
+        .bind(slot as i64)
+        .fetch_all(&mut *connection)
+        .await?;
+        Ok(pruned)
+    }
+
+    impl Storage for SqliteStorage {
+        fn migrate(&self) -> StorageFuture<'_, ()> {
+            Box::pin(async move {
+                sqlx::migrate!("./migrations_sqlite").run(&self.pool).await?;
+                Ok(())
+            })
+        }
+
+        fn list_nodes<'a>(
+            &'a self,
+            program_ids: &'a [Pubkey],
+            include_deleted: bool,
+        ) -> StorageFuture<'a, Vec<ApiNode>> {
+            Box::pin(async move {
+                let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+                    "SELECT {} FROM nodes \
+                     WHERE (program_id IN (SELECT value FROM json_each(?1)) OR program_id IS NULL) \
+                       AND (?2 OR deleted_at IS NULL) \
+                     ORDER BY pubkey",
+                    NODE_COLUMNS
+                ))
+                .bind(json_list(program_ids))
+                .bind(include_deleted)
+                .fetch_all(&self.pool)
+                .await?;
+                Ok(nodes)
+            })
+        }
+
+        fn page_nodes<'a>(
+            &'a self,
+            program: Option<&'a str>,
+            include_invalid: bool,
+            include_unverified: bool,
+            rent_exempt: Option<bool>,
+            limit: i64,
+            offset: i64,
+        ) -> StorageFuture<'a, (Vec<ApiNode>, i64)> {
+            Box::pin(async move {
+                let total: i64 = sqlx::query_scalar(
+                    "SELECT COUNT(*) FROM nodes \
+                     WHERE (?1 IS NULL OR program_id = ?1) AND deleted_at IS NULL AND (?2 OR uri_valid) \
+                       AND (?3 OR pda_verified IS NOT FALSE) AND (?4 IS NULL OR rent_exempt = ?4)",
+                )
+                .bind(program)
+                .bind(include_invalid)
+                .bind(include_unverified)
+                .bind(rent_exempt)
+                .fetch_one(&self.pool)
+                .await?;
+                let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+                    "SELECT {} FROM nodes WHERE (?1 IS NULL OR program_id = ?1) AND deleted_at IS NULL \
+                       AND (?4 OR uri_valid) AND (?5 OR pda_verified IS NOT FALSE) \
+                       AND (?6 IS NULL OR rent_exempt = ?6) ORDER BY pubkey LIMIT ?2 OFFSET ?3",
+                    NODE_COLUMNS
+                ))
+                .bind(program)
+                .bind(limit)
+                .bind(offset)
+                .bind(include_invalid)
+                .bind(include_unverified)
+                .bind(rent_exempt)
+                .fetch_all(&self.pool)
+                .await?;
+                Ok((nodes, total))
+            })
+        }
+
+        fn get_node<'a>(&'a self, pubkey: &'a str) -> StorageFuture<'a, Option<ApiNode>> {
+            Box::pin(async move {
+                let node = sqlx::query_as::<_, ApiNode>(&format!(
+                    "SELECT {} FROM nodes WHERE pubkey = ?1 AND deleted_at IS NULL",
+                    NODE_COLUMNS
+                ))
+                .bind(pubkey)
+                .fetch_optional(&self.pool)
+                .await?;
+                Ok(node)
+            })
+        }
+
+        fn upsert_nodes<'a>(&'a self, records: &'a [NodeRecord]) -> StorageFuture<'a, ()> {
+            Box::pin(async move {
+                let mut tx = self.pool.begin().await?;
+                upsert(&mut tx, &records.iter().collect::<Vec<_>>()).await?;
+                tx.commit().await?;
+                Ok(())
+            })
+        }
+
+        fn prune_nodes<'a>(
+            &'a self,
+            program_id: &'a Pubkey,
+            keep: &'a [String],
+            slot: u64,
+            soft_delete: bool,
+        ) -> StorageFuture<'a, Vec<String>> {
+            Box::pin(async move {
+                let mut connection = self.pool.acquire().await?;
+                prune(&mut connection, program_id, keep, slot, soft_delete).await
+            })
+        }
+
+        fn record_history<'a>(
+            &'a self,
+            events: &'a [NodeEvent],
+            previous: &'a HashMap<String, ApiNode>,
+            slot: u64,
+        ) -> StorageFuture<'a, ()> {
+            Box::pin(async move {
+                let mut tx = self.pool.begin().await?;
+                let now = Utc::now();
+                for event in events {
+                    let (pubkey, change_type, new) = match event {
+                        NodeEvent::Added { node } => (node.pubkey.as_str(), "added", Some(node)),
+                        NodeEvent::Updated { node } => (node.pubkey.as_str(), "updated", Some(node)),
+                        NodeEvent::Removed { pubkey, .. } => (pubkey.as_str(), "removed", None),
+                    };
+                    let old = previous.get(pubkey);
+                    let node = new.or(old);
+                    sqlx::query(
+                        "INSERT INTO nodes_history (pubkey, change_type, old_authority, new_authority, old_uri, \
+                         new_uri, slot, changed_at, program_id, cluster) \
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    )
+                    .bind(pubkey)
+                    .bind(change_type)
+                    .bind(old.map(|node| &node.authority))
+                    .bind(new.map(|node| &node.authority))
+                    .bind(old.map(|node| &node.uri))
+                    .bind(new.map(|node| &node.uri))
+                    .bind(slot as i64)
+                    .bind(now)
+                    .bind(node.and_then(|node| node.program_id.as_deref()))
+                    .bind(node.and_then(|node| node.cluster.as_deref()))
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                tx.commit().await?;
+                Ok(())
+            })
+        }
+
+        fn record_changes<'a>(&'a self, events: &'a [NodeEvent], slot: u64, outbox: bool) -> StorageFuture<'a, ()> {
+            Box::pin(async move {
+                if outbox {
+                    return Err("The SQLite backend has no change outbox".into());
+                }
+                // SQLite serializes writers, so ids already become visible in order.
+                let mut tx = self.pool.begin().await?;
+                let now = Utc::now();
+                for event in events {
+                    let (pubkey, change_type, node) = match event {
+                        NodeEvent::Added { node } | NodeEvent::Updated { node } => {
+                            (node.pubkey.as_str(), "upsert", Some(node))
+                        }
+                        NodeEvent::Removed { pubkey, .. } => (pubkey.as_str(), "delete", None),
+                    };
+                    sqlx::query(
+                        "INSERT INTO node_changes (pubkey, change_type, authority, uri, program_id, slot, changed_at, \
+                         cluster) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    )
+                    .bind(pubkey)
+                    .bind(change_type)
+                    .bind(node.map(|node| &node.authority))
+                    .bind(node.map(|node| &node.uri))
+                    .bind(node.and_then(|node| node.program_id.as_deref()))
+                    .bind(slot as i64)
+                    // The write's own time, matching the row's first_seen_at when it added the node.
+                    .bind(node.and_then(|node| node.updated_at).unwrap_or(now))
+                    .bind(event.cluster())
+                    .execute(&mut *tx)
+                    .await?;
+                }
+                tx.commit().await?;
+                Ok(())
+            })
+        }
+
+        fn changes_after(&self, cursor: i64, limit: i64) -> StorageFuture<'_, Vec<(i64, NodeEvent)>> {
+            Box::pin(async move {
+                let rows = sqlx::query_as::<_, ChangeRow>(
+                    r#"
+                    SELECT c.id, c.pubkey, c.change_type, c.authority, c.uri, c.program_id, c.slot, c.changed_at,
+                           c.cluster, n.first_seen_at, n.name, n.registered_at,
+                           COALESCE(n.layout_version, 1) AS layout_version,
+                           COALESCE(n.finalized AND n.last_seen_slot = c.slot, FALSE) AS finalized, n.pda_verified,
+                           n.lamports, n.rent_exempt
+                    FROM node_changes c
+                    LEFT JOIN nodes n ON n.pubkey = c.pubkey AND n.cluster IS c.cluster
+                    WHERE c.id > ?1
+                    ORDER BY c.id
+                    LIMIT ?2
+                    "#,
+                )
+                .bind(cursor)
+                .bind(limit)
+                .fetch_all(&self.pool)
+                .await?;
+                Ok(rows.into_iter().map(ChangeRow::into_change).collect())
+            })
+        }
+
+        fn replace_nodes<'a>(
+            &'a self,
+            program_id: &'a Pubkey,
+            records: &'a [NodeRecord],
+            slot: u64,
+        ) -> StorageFuture<'a, ReplaceSummary> {
+            Box::pin(async move {
+                let mut tx = self.pool.begin().await?;
+                let known: KnownNodes = sqlx::query_as::<_, (String, Option<Vec<u8>>, Option<i64>)>(
+                    "SELECT pubkey, data_hash, lamports FROM nodes \
+                     WHERE (program_id = ?1 OR program_id IS NULL) AND deleted_at IS NULL",
+                )
+                .bind(program_id.to_string())
+                .fetch_all(&mut *tx)
+                .await?
+                .into_iter()
+                .map(|(pubkey, hash, lamports)| (pubkey, (hash, lamports)))
+                .collect();
+                let changed = changed(records, &known);
+                upsert(&mut tx, &changed).await?;
+                let pubkeys: Vec<String> = records.iter().map(|record| record.node.pubkey.clone()).collect();
+                let removed = prune(&mut tx, program_id, &pubkeys, slot, false).await?;
+                tx.commit().await?;
+                Ok(ReplaceSummary { written: changed.len(), removed: removed.len() })
+            })
+        }
+    }
+}
@@ -0,0 +1,851 @@
+//! Postgres persistence: the `nodes` table and everything written alongside it. The sync loop
+//! and the API use it directly rather than through [`crate::storage::Storage`], which only the
+//! standalone modes go through.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::{PgExecutor, PgPool};
+use utoipa::ToSchema;
+
+use crate::decode::{DecodeError, NodeDevice};
+use crate::events::NodeEvent;
+use crate::pda;
+use crate::rent;
+use crate::uri;
+use crate::watchlists;
+use crate::AppError;
+
+// --- Columns selected into `ApiNode`, shared by every node query ---
+pub const NODE_COLUMNS: &str =
+    "pubkey, authority, uri, program_id, cluster, first_seen_at, updated_at, last_seen_slot, deleted_at, name, \
+     registered_at, layout_version, finalized, uri_valid, pda_verified, lamports, rent_exempt";
+
+// --- Advisory lock serializing node_changes writers so cursors become visible in order ---
+const NODE_CHANGES_LOCK_KEY: i64 = 0x6e6f64655f6368;
+
+// --- NOTIFY channel told the highest new node_changes id when a write commits ---
+pub const NODE_CHANGES_CHANNEL: &str = "node_changes";
+
+// --- Advisory lock doing the same for program_events cursors ---
+const PROGRAM_EVENTS_LOCK_KEY: i64 = 0x70726f675f6576;
+
+/// Applies the embedded migrations in `./migrations`.
+pub async fn migrate(pool: &PgPool) -> Result<(), AppError> {
+    sqlx::migrate!("./migrations").run(pool).await?;
+    Ok(())
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow, Clone, Debug, PartialEq)]
+#[schema(as = Node)]
+pub struct ApiNode {
+    pub pubkey: String,
+    pub authority: String,
+    pub uri: String,
+    /// Program that owns the account; `None` for rows indexed before multi-program support.
+    pub program_id: Option<String>,
+    /// Cluster the account was indexed from (see [`crate::cluster`]); `None` when it is unnamed.
+    pub cluster: Option<String>,
+    /// When the indexer first saw this account.
+    pub first_seen_at: Option<DateTime<Utc>>,
+    /// When the stored on-chain data last changed.
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Slot of the snapshot or notification the stored data was last written from.
+    pub last_seen_slot: Option<i64>,
+    /// Set when the account was pruned while soft-delete mode is on.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Display name from a V2 account; `None` for V1 accounts.
+    pub name: Option<String>,
+    /// When the program registered the device, from a V2 account; `None` for V1 accounts.
+    pub registered_at: Option<DateTime<Utc>>,
+    /// On-chain layout the account was decoded from (see [`crate::decode::NodeDeviceLayout`]).
+    pub layout_version: i16,
+    /// Whether `last_seen_slot` is finalized, so no fork can roll the stored data back.
+    pub finalized: bool,
+    /// Whether `uri` passed the checks made on ingest (see [`crate::uri`]).
+    pub uri_valid: bool,
+    /// Whether the account sits at its expected PDA (see [`crate::pda`]); `None` when that
+    /// isn't checked.
+    pub pda_verified: Option<bool>,
+    /// Balance of the account as of its last write; `None` for rows not written since it was stored.
+    pub lamports: Option<i64>,
+    /// Whether `lamports` cover the rent-exempt minimum for the account's size (see [`crate::rent`]).
+    pub rent_exempt: Option<bool>,
+}
+
+impl ApiNode {
+    /// Builds the row for a freshly decoded account of `program_id` on `cluster`, observed at `slot`.
+    /// Its address is verified against the PDA for `seed` when one is configured.
+    pub fn observed(
+        pubkey: String,
+        node: NodeDevice,
+        program_id: &Pubkey,
+        cluster: Option<&str>,
+        slot: u64,
+        seed: Option<&[u8]>,
+    ) -> Self {
+        let uri = uri::sanitize(node.uri);
+        let pda_verified = seed.map(|seed| pda::is_node_device_address(&pubkey, &node.authority, program_id, seed));
+        Self {
+            pubkey,
+            authority: node.authority.to_string(),
+            uri_valid: uri::is_valid(&uri),
+            uri,
+            program_id: Some(program_id.to_string()),
+            cluster: cluster.map(str::to_string),
+            first_seen_at: None,
+            updated_at: Some(Utc::now()),
+            last_seen_slot: Some(slot as i64),
+            deleted_at: None,
+            name: node.name,
+            // Out-of-range timestamps are left out rather than failing the whole account.
+            registered_at: node.registered_at.and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            layout_version: node.layout.version(),
+            finalized: false,
+            pda_verified,
+            lamports: None,
+            rent_exempt: None,
+        }
+    }
+
+    /// Compares only the fields that come from the chain, ignoring bookkeeping timestamps.
+    pub fn same_on_chain_data(&self, other: &ApiNode) -> bool {
+        self.pubkey == other.pubkey
+            && self.authority == other.authority
+            && self.uri == other.uri
+            && self.program_id == other.program_id
+            && self.name == other.name
+            && self.registered_at == other.registered_at
+            && self.layout_version == other.layout_version
+    }
+}
+
+/// Every node of `program_ids` (plus rows from before multi-program support), ordered by
+/// pubkey. Soft-deleted rows are only included with `include_deleted`.
+pub async fn list_nodes(
+    executor: impl PgExecutor<'_>,
+    program_ids: &[Pubkey],
+    include_deleted: bool,
+) -> Result<Vec<ApiNode>, AppError> {
+    let program_ids: Vec<String> = program_ids.iter().map(Pubkey::to_string).collect();
+    let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE (program_id = ANY($1) OR program_id IS NULL) AND ($2 OR deleted_at IS NULL) \
+         ORDER BY pubkey",
+        NODE_COLUMNS
+    ))
+    .bind(&program_ids)
+    .bind(include_deleted)
+    .fetch_all(executor)
+    .await?;
+    Ok(nodes)
+}
+
+/// Leading clause of the statement that removes pruned rows; callers append the WHERE clause.
+pub fn prune_statement(soft_delete: bool) -> &'static str {
+    if soft_delete {
+        "UPDATE nodes SET deleted_at = NOW()"
+    } else {
+        "DELETE FROM nodes"
+    }
+}
+
+/// Deletes (or soft-deletes) the live rows of `program_id` on `cluster` whose pubkey isn't in
+/// `keep`, a snapshot taken at `slot`, returning the pruned pubkeys. Rows written from a later
+/// slot, e.g. by programSubscribe while the snapshot was being read, are newer than it and kept.
+/// Rows without a program_id predate multi-program support and are reconciled by whichever program runs first.
+pub async fn prune_nodes(
+    executor: impl PgExecutor<'_>,
+    program_id: &Pubkey,
+    cluster: Option<&str>,
+    keep: &[String],
+    slot: u64,
+    soft_delete: bool,
+) -> Result<Vec<String>, AppError> {
+    let pruned = sqlx::query_scalar(&format!(
+        "{} WHERE ((program_id = $2 AND cluster IS NOT DISTINCT FROM $4) OR program_id IS NULL) \
+         AND deleted_at IS NULL AND pubkey <> ALL($1) AND (last_seen_slot IS NULL OR last_seen_slot <= $3) \
+         RETURNING pubkey",
+        prune_statement(soft_delete)
+    ))
+    .bind(keep)
+    .bind(program_id.to_string())
+    .bind(slot as i64)
+    .bind(cluster)
+    .fetch_all(executor)
+    .await?;
+    Ok(pruned)
+}
+
+/// Stores an undecodable account in the dead-letter table instead of dropping it.
+pub async fn record_decode_failure(
+    executor: impl PgExecutor<'_>,
+    program_id: &Pubkey,
+    pubkey: &str,
+    data: &[u8],
+    error: &DecodeError,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO decode_failures (pubkey, program_id, raw_data, error, first_seen_at, last_seen_at)
+        VALUES ($1, $2, $3, $4, NOW(), NOW())
+        ON CONFLICT (pubkey) DO UPDATE
+        SET program_id = EXCLUDED.program_id,
+            raw_data = EXCLUDED.raw_data,
+            error = EXCLUDED.error,
+            last_seen_at = EXCLUDED.last_seen_at
+        "#,
+    )
+    .bind(pubkey)
+    .bind(program_id.to_string())
+    .bind(data)
+    .bind(error.to_string())
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Removes accounts that decode again (e.g. after a decoder fix) from the dead-letter table.
+pub async fn clear_decode_failures(executor: impl PgExecutor<'_>, pubkeys: &[String]) -> Result<(), AppError> {
+    sqlx::query("DELETE FROM decode_failures WHERE pubkey = ANY($1)")
+        .bind(pubkeys)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// A decoded node plus the bookkeeping columns that are stored but not served by the API.
+#[derive(Clone, Debug)]
+pub struct NodeRecord {
+    pub node: ApiNode,
+    /// SHA-256 of the raw account data, used to skip rewriting unchanged accounts.
+    pub data_hash: Vec<u8>,
+    pub raw: RawAccount,
+}
+
+impl NodeRecord {
+    /// Pairs `node` with its account, filling in the balance columns from `raw`.
+    pub fn new(mut node: ApiNode, raw: RawAccount) -> Self {
+        node.lamports = Some(raw.lamports as i64);
+        node.rent_exempt = Some(rent::is_exempt(raw.lamports, raw.data.len()));
+        Self { node, data_hash: data_hash(&raw.data), raw }
+    }
+}
+
+/// The account as the RPC node or Geyser reported it, kept so rows can be decoded again after
+/// a decoder fix without refetching them.
+#[derive(Clone, Debug)]
+pub struct RawAccount {
+    pub data: Vec<u8>,
+    pub lamports: u64,
+    pub owner: Pubkey,
+    pub rent_epoch: u64,
+}
+
+pub fn data_hash(data: &[u8]) -> Vec<u8> {
+    Sha256::digest(data).to_vec()
+}
+
+/// Upserts one node; whether it was written, which it isn't when the stored row is newer.
+pub async fn upsert_node(executor: impl PgExecutor<'_>, record: &NodeRecord) -> Result<bool, AppError> {
+    Ok(!upsert_nodes(executor, std::slice::from_ref(record)).await?.is_empty())
+}
+
+/// Upserts many nodes in one statement by passing each column as an array and
+/// expanding them server-side with UNNEST. Records must all be of one cluster, with unique
+/// pubkeys. A stored row last written from a later slot than its record is left alone, so a
+/// snapshot can't roll back what programSubscribe wrote while it was being read; returns the pubkeys written.
+pub async fn upsert_nodes(executor: impl PgExecutor<'_>, records: &[NodeRecord]) -> Result<Vec<String>, AppError> {
+    let mut pubkeys = Vec::with_capacity(records.len());
+    let mut authorities = Vec::with_capacity(records.len());
+    let mut uris = Vec::with_capacity(records.len());
+    let mut program_ids = Vec::with_capacity(records.len());
+    let mut clusters = Vec::with_capacity(records.len());
+    let mut hashes = Vec::with_capacity(records.len());
+    let mut slots = Vec::with_capacity(records.len());
+    let mut names = Vec::with_capacity(records.len());
+    let mut registered_ats = Vec::with_capacity(records.len());
+    let mut layout_versions = Vec::with_capacity(records.len());
+    let mut raw_data = Vec::with_capacity(records.len());
+    let mut lamports = Vec::with_capacity(records.len());
+    let mut owners = Vec::with_capacity(records.len());
+    let mut rent_epochs = Vec::with_capacity(records.len());
+    let mut uri_valid = Vec::with_capacity(records.len());
+    let mut pda_verified = Vec::with_capacity(records.len());
+    let mut rent_exempt = Vec::with_capacity(records.len());
+    for NodeRecord { node, data_hash, raw } in records {
+        pubkeys.push(node.pubkey.as_str());
+        authorities.push(node.authority.as_str());
+        uris.push(node.uri.as_str());
+        program_ids.push(node.program_id.as_deref());
+        clusters.push(node.cluster.as_deref());
+        hashes.push(data_hash.as_slice());
+        slots.push(node.last_seen_slot);
+        names.push(node.name.as_deref());
+        registered_ats.push(node.registered_at);
+        layout_versions.push(node.layout_version);
+        raw_data.push(raw.data.as_slice());
+        lamports.push(raw.lamports as i64);
+        owners.push(raw.owner.to_string());
+        // Rent-exempt accounts report u64::MAX, which only fits a NUMERIC column.
+        rent_epochs.push(raw.rent_epoch.to_string());
+        uri_valid.push(node.uri_valid);
+        pda_verified.push(node.pda_verified);
+        rent_exempt.push(node.rent_exempt);
+    }
+
+    let written = sqlx::query_scalar(
+        r#"
+        INSERT INTO nodes (pubkey, authority, uri, program_id, data_hash, last_seen_slot, name, registered_at,
+                           layout_version, raw_data, lamports, owner, rent_epoch, cluster, uri_valid, pda_verified,
+                           rent_exempt)
+        SELECT * FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::bytea[], $6::bigint[], $7::text[],
+                             $8::timestamptz[], $9::smallint[], $10::bytea[], $11::bigint[], $12::text[],
+                             $13::text[]::numeric[], $14::text[], $15::boolean[], $16::boolean[], $17::boolean[])
+        ON CONFLICT (pubkey, cluster) DO UPDATE
+        SET authority = EXCLUDED.authority,
+            uri = EXCLUDED.uri,
+            program_id = EXCLUDED.program_id,
+            data_hash = EXCLUDED.data_hash,
+            last_seen_slot = EXCLUDED.last_seen_slot,
+            name = EXCLUDED.name,
+            registered_at = EXCLUDED.registered_at,
+            layout_version = EXCLUDED.layout_version,
+            raw_data = EXCLUDED.raw_data,
+            lamports = EXCLUDED.lamports,
+            owner = EXCLUDED.owner,
+            rent_epoch = EXCLUDED.rent_epoch,
+            uri_valid = EXCLUDED.uri_valid,
+            pda_verified = EXCLUDED.pda_verified,
+            rent_exempt = EXCLUDED.rent_exempt,
+            finalized = FALSE,
+            updated_at = NOW(),
+            deleted_at = NULL
+        WHERE nodes.last_seen_slot IS NULL OR nodes.last_seen_slot <= EXCLUDED.last_seen_slot
+        RETURNING pubkey
+        "#,
+    )
+    .bind(&pubkeys)
+    .bind(&authorities)
+    .bind(&uris)
+    .bind(&program_ids)
+    .bind(&hashes)
+    .bind(&slots)
+    .bind(&names)
+    .bind(&registered_ats)
+    .bind(&layout_versions)
+    .bind(&raw_data)
+    .bind(&lamports)
+    .bind(&owners)
+    .bind(&rent_epochs)
+    .bind(&clusters)
+    .bind(&uri_valid)
+    .bind(&pda_verified)
+    .bind(&rent_exempt)
+    .fetch_all(executor)
+    .await?;
+    Ok(written)
+}
+
+/// Stores new balances for `cluster`'s accounts whose data didn't change, which are otherwise
+/// left alone. `balances` pairs each pubkey with its lamports and data length.
+pub async fn update_balances(
+    executor: impl PgExecutor<'_>,
+    cluster: Option<&str>,
+    balances: &[(String, u64, usize)],
+) -> Result<(), AppError> {
+    if balances.is_empty() {
+        return Ok(());
+    }
+    let pubkeys: Vec<&str> = balances.iter().map(|(pubkey, _, _)| pubkey.as_str()).collect();
+    let lamports: Vec<i64> = balances.iter().map(|(_, lamports, _)| *lamports as i64).collect();
+    let rent_exempt: Vec<bool> = balances.iter().map(|(_, lamports, len)| rent::is_exempt(*lamports, *len)).collect();
+    sqlx::query(
+        "UPDATE nodes SET lamports = u.lamports, rent_exempt = u.rent_exempt \
+         FROM UNNEST($1::text[], $2::bigint[], $3::boolean[]) AS u(pubkey, lamports, rent_exempt) \
+         WHERE nodes.pubkey = u.pubkey AND nodes.cluster IS NOT DISTINCT FROM $4",
+    )
+    .bind(&pubkeys)
+    .bind(&lamports)
+    .bind(&rent_exempt)
+    .bind(cluster)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Checks every stored URI, address and balance again and corrects the `uri_valid`,
+/// `pda_verified` and `rent_exempt` flags that disagree: those of rows written before a flag
+/// existed, under older rules or with another `seed`. Returns how many rows changed.
+pub async fn recheck_nodes(pool: &PgPool, seed: Option<&[u8]>) -> Result<u64, AppError> {
+    #[derive(sqlx::FromRow)]
+    struct StoredNode {
+        pubkey: String,
+        cluster: Option<String>,
+        authority: String,
+        uri: String,
+        program_id: Option<String>,
+        uri_valid: bool,
+        pda_verified: Option<bool>,
+        lamports: Option<i64>,
+        data_len: Option<i32>,
+        rent_exempt: Option<bool>,
+    }
+    let stored = sqlx::query_as::<_, StoredNode>(
+        "SELECT pubkey, cluster, authority, uri, program_id, uri_valid, pda_verified, lamports, \
+         octet_length(raw_data) AS data_len, rent_exempt FROM nodes",
+    )
+    .fetch_all(pool)
+    .await?;
+    let (mut pubkeys, mut clusters, mut uri_valid, mut pda_verified, mut rent_exempt) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for node in stored {
+        let valid = uri::is_valid(&node.uri);
+        let verified = pda::verify(&node.pubkey, &node.authority, node.program_id.as_deref(), seed);
+        let exempt = node.lamports.zip(node.data_len);
+        let exempt = exempt.map(|(lamports, len)| rent::is_exempt(lamports as u64, len as usize));
+        if valid != node.uri_valid || verified != node.pda_verified || exempt != node.rent_exempt {
+            pubkeys.push(node.pubkey);
+            clusters.push(node.cluster);
+            uri_valid.push(valid);
+            pda_verified.push(verified);
+            rent_exempt.push(exempt);
+        }
+    }
+    if pubkeys.is_empty() {
+        return Ok(0);
+    }
+    let result = sqlx::query(
+        "UPDATE nodes SET uri_valid = u.uri_valid, pda_verified = u.pda_verified, rent_exempt = u.rent_exempt \
+         FROM UNNEST($1::text[], $2::boolean[], $3::boolean[], $4::boolean[], $5::text[]) \
+         AS u(pubkey, uri_valid, pda_verified, rent_exempt, cluster) \
+         WHERE nodes.pubkey = u.pubkey AND nodes.cluster IS NOT DISTINCT FROM u.cluster",
+    )
+    .bind(&pubkeys)
+    .bind(&uri_valid)
+    .bind(&pda_verified)
+    .bind(&rent_exempt)
+    .bind(&clusters)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// A program account decoded through its Anchor IDL, bound for the `accounts` table.
+pub struct AccountRecord {
+    pub pubkey: String,
+    pub account_type: String,
+    pub data: serde_json::Value,
+}
+
+/// Lowest `last_seen_slot` among the live rows of `program_ids` on `cluster` that aren't
+/// finalized yet; `None` when all are. Rows without a program_id count too when `unowned` is set.
+pub async fn oldest_unfinalized_slot(
+    executor: impl PgExecutor<'_>,
+    program_ids: &[String],
+    cluster: Option<&str>,
+    unowned: bool,
+) -> Result<Option<i64>, AppError> {
+    let slot = sqlx::query_scalar(
+        "SELECT MIN(last_seen_slot) FROM nodes WHERE NOT finalized AND deleted_at IS NULL \
+         AND ((program_id = ANY($1) AND cluster IS NOT DISTINCT FROM $3) OR (program_id IS NULL AND $2))",
+    )
+    .bind(program_ids)
+    .bind(unowned)
+    .bind(cluster)
+    .fetch_one(executor)
+    .await?;
+    Ok(slot)
+}
+
+/// Marks rows of `program_ids` on `cluster` (and, with `unowned`, rows without a program_id)
+/// finalized whose data was last written from one of `finalized_slots`, or from before
+/// `settled_before`, which is too old for a fork to still replace. Slots are per cluster, so
+/// they must come from `cluster`. Returns how many.
+pub async fn mark_finalized(
+    executor: impl PgExecutor<'_>,
+    program_ids: &[String],
+    cluster: Option<&str>,
+    unowned: bool,
+    finalized_slots: &[i64],
+    settled_before: i64,
+) -> Result<u64, AppError> {
+    let result = sqlx::query(
+        "UPDATE nodes SET finalized = TRUE \
+         WHERE NOT finalized AND (last_seen_slot = ANY($1) OR last_seen_slot < $2 OR last_seen_slot IS NULL) \
+           AND ((program_id = ANY($3) AND cluster IS NOT DISTINCT FROM $5) OR (program_id IS NULL AND $4))",
+    )
+    .bind(finalized_slots)
+    .bind(settled_before)
+    .bind(program_ids)
+    .bind(unowned)
+    .bind(cluster)
+    .execute(executor)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Moves the unfinalized rows among `pubkeys` on `cluster`, whose data a snapshot at `slot` saw
+/// unchanged, to that slot. A row written from a fork that was abandoned keeps its data when the
+/// chain that won agrees, and this lets it become final with the new slot.
+pub async fn reconfirm_unfinalized(
+    executor: impl PgExecutor<'_>,
+    cluster: Option<&str>,
+    pubkeys: &[String],
+    slot: u64,
+) -> Result<(), AppError> {
+    sqlx::query(
+        "UPDATE nodes SET last_seen_slot = $2 \
+         WHERE pubkey = ANY($1) AND cluster IS NOT DISTINCT FROM $3 AND NOT finalized AND last_seen_slot < $2",
+    )
+    .bind(pubkeys)
+    .bind(slot as i64)
+    .bind(cluster)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Writes IDL-decoded accounts of `program_id` on `cluster` in one statement. Rows whose
+/// decoded JSON is unchanged are left alone, so `updated_at` and `slot` mark the last observed change.
+pub async fn upsert_accounts(
+    executor: impl PgExecutor<'_>,
+    program_id: &Pubkey,
+    cluster: Option<&str>,
+    records: &[AccountRecord],
+    slot: u64,
+) -> Result<(), AppError> {
+    let pubkeys: Vec<&str> = records.iter().map(|record| record.pubkey.as_str()).collect();
+    let account_types: Vec<&str> = records.iter().map(|record| record.account_type.as_str()).collect();
+    let data: Vec<String> = records.iter().map(|record| record.data.to_string()).collect();
+
+    sqlx::query(
+        r#"
+        INSERT INTO accounts (pubkey, program_id, account_type, data, slot, cluster)
+        SELECT pubkey, $4, account_type, data::jsonb, $5, $6
+        FROM UNNEST($1::text[], $2::text[], $3::text[]) AS t(pubkey, account_type, data)
+        ON CONFLICT (pubkey, cluster) DO UPDATE
+        SET program_id = EXCLUDED.program_id,
+            account_type = EXCLUDED.account_type,
+            data = EXCLUDED.data,
+            slot = EXCLUDED.slot,
+            updated_at = NOW()
+        WHERE accounts.data IS DISTINCT FROM EXCLUDED.data
+           OR accounts.account_type IS DISTINCT FROM EXCLUDED.account_type
+        "#,
+    )
+    .bind(&pubkeys)
+    .bind(&account_types)
+    .bind(&data)
+    .bind(program_id.to_string())
+    .bind(slot as i64)
+    .bind(cluster)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Works out which event (if any) an upsert of `node` represents given the row it replaced.
+pub fn node_event(previous: Option<&ApiNode>, mut node: ApiNode) -> Option<NodeEvent> {
+    match previous {
+        None => {
+            node.first_seen_at = node.updated_at;
+            Some(NodeEvent::Added { node })
+        }
+        Some(existing) if !existing.same_on_chain_data(&node) => {
+            node.first_seen_at = existing.first_seen_at;
+            Some(NodeEvent::Updated { node })
+        }
+        Some(_) => None,
+    }
+}
+
+/// Appends one `nodes_history` row per event. `previous` looks up the row each event replaced.
+pub async fn record_history<'a>(
+    executor: impl PgExecutor<'_>,
+    events: &[NodeEvent],
+    previous: impl Fn(&str) -> Option<&'a ApiNode>,
+    slot: u64,
+) -> Result<(), AppError> {
+    let mut pubkeys = Vec::with_capacity(events.len());
+    let mut change_types = Vec::with_capacity(events.len());
+    let mut old_authorities = Vec::with_capacity(events.len());
+    let mut new_authorities = Vec::with_capacity(events.len());
+    let mut old_uris = Vec::with_capacity(events.len());
+    let mut new_uris = Vec::with_capacity(events.len());
+    let mut program_ids = Vec::with_capacity(events.len());
+    let mut clusters = Vec::with_capacity(events.len());
+    for event in events {
+        let (pubkey, change_type, new) = match event {
+            NodeEvent::Added { node } => (node.pubkey.as_str(), "added", Some(node)),
+            NodeEvent::Updated { node } => (node.pubkey.as_str(), "updated", Some(node)),
+            NodeEvent::Removed { pubkey, .. } => (pubkey.as_str(), "removed", None),
+        };
+        let old = previous(pubkey);
+        pubkeys.push(pubkey);
+        change_types.push(change_type);
+        old_authorities.push(old.map(|node| node.authority.as_str()));
+        new_authorities.push(new.map(|node| node.authority.as_str()));
+        old_uris.push(old.map(|node| node.uri.as_str()));
+        new_uris.push(new.map(|node| node.uri.as_str()));
+        // A removal has only the row it removed to say which program and cluster it was of.
+        let node = new.or(old);
+        program_ids.push(node.and_then(|node| node.program_id.as_deref()));
+        clusters.push(node.and_then(|node| node.cluster.as_deref()));
+    }
+
+    // Removals also alert the targets registered for the removed node's authority (see
+    // src/alerts.rs).
+    sqlx::query(
+        r#"
+        WITH history AS (
+            INSERT INTO nodes_history (pubkey, change_type, old_authority, new_authority, old_uri, new_uri,
+                                       program_id, cluster, slot)
+            SELECT u.*, $7::bigint
+            FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $8::text[],
+                        $9::text[]) AS u
+            RETURNING pubkey, change_type, old_authority, old_uri
+        )
+        INSERT INTO alerts (target_id, event, pubkey, authority, uri)
+        SELECT t.id, 'pruned', h.pubkey, h.old_authority, h.old_uri
+        FROM history h JOIN alert_targets t ON t.authority = h.old_authority
+        WHERE h.change_type = 'removed'
+        "#,
+    )
+    .bind(&pubkeys)
+    .bind(&change_types)
+    .bind(&old_authorities)
+    .bind(&new_authorities)
+    .bind(&old_uris)
+    .bind(&new_uris)
+    .bind(slot as i64)
+    .bind(&program_ids)
+    .bind(&clusters)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Appends one `node_changes` row per event for `GET /changes` consumers, copying each into
+/// `change_outbox` as well when `outbox` is set.
+pub async fn record_changes(
+    executor: impl PgExecutor<'_>,
+    events: &[NodeEvent],
+    slot: u64,
+    outbox: bool,
+) -> Result<(), AppError> {
+    let mut pubkeys = Vec::with_capacity(events.len());
+    let mut change_types = Vec::with_capacity(events.len());
+    let mut authorities = Vec::with_capacity(events.len());
+    let mut uris = Vec::with_capacity(events.len());
+    let mut program_ids = Vec::with_capacity(events.len());
+    let mut clusters = Vec::with_capacity(events.len());
+    for event in events {
+        let (pubkey, change_type, node) = match event {
+            NodeEvent::Added { node } | NodeEvent::Updated { node } => (node.pubkey.as_str(), "upsert", Some(node)),
+            NodeEvent::Removed { pubkey, .. } => (pubkey.as_str(), "delete", None),
+        };
+        pubkeys.push(pubkey);
+        change_types.push(change_type);
+        authorities.push(node.map(|node| node.authority.as_str()));
+        uris.push(node.map(|node| node.uri.as_str()));
+        program_ids.push(node.and_then(|node| node.program_id.as_deref()));
+        clusters.push(event.cluster());
+    }
+
+    // Sequence values are handed out before commit, so concurrent writers could otherwise make a
+    // higher cursor visible first and a consumer polling in between would skip the lower one.
+    // The transaction-scoped lock is released on commit, keeping cursor order equal to commit order.
+    // Postgres holds the NOTIFY back until the same commit, so listeners never wake too early.
+    sqlx::query(
+        r#"
+        WITH lock AS (SELECT pg_advisory_xact_lock($7)),
+        changes AS (
+            INSERT INTO node_changes (pubkey, change_type, authority, uri, program_id, cluster, slot)
+            SELECT u.*, $6::bigint
+            FROM lock, UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[], $10::text[]) AS u
+            RETURNING id, pubkey, change_type, authority, uri, program_id, cluster, slot, changed_at
+        ),
+        outbox AS (
+            INSERT INTO change_outbox (change_id, pubkey, change_type, authority, uri, program_id, cluster, slot,
+                                       changed_at)
+            SELECT * FROM changes WHERE $8
+        )
+        SELECT pg_notify($9, MAX(id)::text) FROM changes HAVING COUNT(*) > 0
+        "#,
+    )
+    .bind(&pubkeys)
+    .bind(&change_types)
+    .bind(&authorities)
+    .bind(&uris)
+    .bind(&program_ids)
+    .bind(slot as i64)
+    .bind(NODE_CHANGES_LOCK_KEY)
+    .bind(outbox)
+    .bind(NODE_CHANGES_CHANNEL)
+    .bind(&clusters)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// `node_changes` rows after `cursor` (at most `limit`), rebuilt into the events that produced
+/// them, for processes that didn't run the sync themselves. An upsert counts as an add when it
+/// is the write that created the row: both timestamps come from that transaction's `NOW()`.
+pub async fn changes_after(
+    executor: impl PgExecutor<'_>,
+    cursor: i64,
+    limit: i64,
+) -> Result<Vec<(i64, NodeEvent)>, AppError> {
+    let rows = sqlx::query_as::<_, ChangeRow>(&format!("{} WHERE c.id > $1 ORDER BY c.id LIMIT $2", CHANGE_ROWS))
+        .bind(cursor)
+        .bind(limit)
+        .fetch_all(executor)
+        .await?;
+    Ok(rows.into_iter().map(ChangeRow::into_change).collect())
+}
+
+// --- `node_changes` rows joined with the node's current row, as `ChangeRow` reads them ---
+// `node_changes` only logs authority and URI, so the V2 fields and PDA flag come from the current row,
+// and a change is only as final as the row's latest write when it is that write.
+const CHANGE_ROWS: &str = r#"
+    SELECT c.id, c.pubkey, c.change_type, c.authority, c.uri, c.program_id, c.slot, c.changed_at, c.cluster,
+           n.first_seen_at, n.name, n.registered_at, COALESCE(n.layout_version, 1::smallint) AS layout_version,
+           COALESCE(n.finalized AND n.last_seen_slot = c.slot, FALSE) AS finalized, n.pda_verified,
+           n.lamports, n.rent_exempt
+    FROM node_changes c
+    LEFT JOIN nodes n ON n.pubkey = c.pubkey AND n.cluster IS NOT DISTINCT FROM c.cluster
+"#;
+
+/// Like [`changes_after`], but only the changes watchlist `watchlist_id` matches, together with
+/// the cursor the scan got to: the last match when `limit` were found, the latest change
+/// otherwise, so a consumer can move past changes that didn't match.
+pub async fn watched_changes_after(
+    pool: &PgPool,
+    watchlist_id: i64,
+    cursor: i64,
+    limit: i64,
+) -> Result<(Vec<(i64, NodeEvent)>, i64), AppError> {
+    // Read first: changes become visible in id order, so none at or below it can still appear.
+    let latest: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM node_changes").fetch_one(pool).await?;
+    let rows = sqlx::query_as::<_, ChangeRow>(&format!(
+        "{} JOIN watchlists w ON w.id = $3 WHERE c.id > $1 AND c.id <= $4 AND {} ORDER BY c.id LIMIT $2",
+        CHANGE_ROWS,
+        watchlists::MATCHES_CHANGE
+    ))
+    .bind(cursor)
+    .bind(limit)
+    .bind(watchlist_id)
+    .bind(latest)
+    .fetch_all(pool)
+    .await?;
+    let scanned = match rows.last() {
+        Some(last) if rows.len() as i64 == limit => last.id,
+        _ => latest,
+    };
+    Ok((rows.into_iter().map(ChangeRow::into_change).collect(), scanned))
+}
+
+/// A `node_changes` row joined with the node's current row, as [`changes_after`] reads it.
+#[derive(sqlx::FromRow)]
+pub(crate) struct ChangeRow {
+    id: i64,
+    pubkey: String,
+    change_type: String,
+    authority: Option<String>,
+    uri: Option<String>,
+    program_id: Option<String>,
+    slot: Option<i64>,
+    changed_at: DateTime<Utc>,
+    cluster: Option<String>,
+    first_seen_at: Option<DateTime<Utc>>,
+    name: Option<String>,
+    registered_at: Option<DateTime<Utc>>,
+    layout_version: i16,
+    finalized: bool,
+    pda_verified: Option<bool>,
+    lamports: Option<i64>,
+    rent_exempt: Option<bool>,
+}
+
+impl ChangeRow {
+    /// The cursor and the event that wrote this row.
+    pub(crate) fn into_change(self) -> (i64, NodeEvent) {
+        if self.change_type == "delete" {
+            return (self.id, NodeEvent::Removed { pubkey: self.pubkey, cluster: self.cluster });
+        }
+        let added = self.first_seen_at == Some(self.changed_at);
+        // The change logs the URI it wrote, which the row may have moved on from since.
+        let uri = self.uri.unwrap_or_default();
+        let node = ApiNode {
+            pubkey: self.pubkey,
+            authority: self.authority.unwrap_or_default(),
+            program_id: self.program_id,
+            cluster: self.cluster,
+            first_seen_at: self.first_seen_at,
+            updated_at: Some(self.changed_at),
+            last_seen_slot: self.slot,
+            deleted_at: None,
+            name: self.name,
+            registered_at: self.registered_at,
+            layout_version: self.layout_version,
+            finalized: self.finalized,
+            uri_valid: uri::is_valid(&uri),
+            uri,
+            pda_verified: self.pda_verified,
+            lamports: self.lamports,
+            rent_exempt: self.rent_exempt,
+        };
+        let event = if added { NodeEvent::Added { node } } else { NodeEvent::Updated { node } };
+        (self.id, event)
+    }
+}
+
+/// One Anchor event from a transaction's logs, bound for `program_events`.
+pub struct ProgramEventRecord {
+    /// Position among the program's events in the transaction.
+    pub event_index: i32,
+    /// IDL event name, or the hex discriminator when no IDL describes it.
+    pub name: String,
+    /// Decoded fields; `None` without an IDL entry for the event.
+    pub data: Option<serde_json::Value>,
+    pub raw_data: Vec<u8>,
+}
+
+/// Stores a transaction's events. Events already stored (seen by both the log stream and the
+/// transaction history pass) are skipped.
+pub async fn record_program_events(
+    executor: impl PgExecutor<'_>,
+    program_id: &Pubkey,
+    signature: &str,
+    slot: u64,
+    events: &[ProgramEventRecord],
+) -> Result<(), AppError> {
+    let indexes: Vec<i32> = events.iter().map(|event| event.event_index).collect();
+    let names: Vec<&str> = events.iter().map(|event| event.name.as_str()).collect();
+    let data: Vec<Option<String>> =
+        events.iter().map(|event| event.data.as_ref().map(|data| data.to_string())).collect();
+    let raw_data: Vec<&[u8]> = events.iter().map(|event| event.raw_data.as_slice()).collect();
+
+    // Same cursor-ordering lock as `record_changes`.
+    sqlx::query(
+        r#"
+        WITH lock AS (SELECT pg_advisory_xact_lock($8))
+        INSERT INTO program_events (program_id, signature, slot, event_index, name, data, raw_data)
+        SELECT $5, $6, $7, u.event_index, u.name, u.data::jsonb, u.raw_data
+        FROM lock, UNNEST($1::int[], $2::text[], $3::text[], $4::bytea[]) AS u(event_index, name, data, raw_data)
+        ON CONFLICT (signature, program_id, event_index) DO NOTHING
+        "#,
+    )
+    .bind(&indexes)
+    .bind(&names)
+    .bind(&data)
+    .bind(&raw_data)
+    .bind(program_id.to_string())
+    .bind(signature)
+    .bind(slot as i64)
+    .bind(PROGRAM_EVENTS_LOCK_KEY)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
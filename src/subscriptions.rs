@@ -0,0 +1,231 @@
+//! Filtered `/ws` subscriptions. A client sends `{"type":"subscribe","filter":{...}}` to only
+//! receive the changes of nodes matching the filter, checked by the server, and sends it again
+//! to replace the filter or `{"type":"unsubscribe"}` to receive every change again; each is
+//! acknowledged with a `subscribed` or `unsubscribed` message, or answered with an `error` one
+//! that leaves the filter as it was. Until it subscribes, a client receives every change.
+//!
+//! An update is sent when the node matches after it, or matched before it, so the client also
+//! learns of nodes leaving the filter; a removal when the node last matched. `status` is the
+//! prober's verdict on the node's URI when the change is sent.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::PgPool;
+use utoipa::ToSchema;
+
+use crate::events::NodeEvent;
+use crate::liveness::LivenessStatus;
+use crate::moderation::NOT_QUARANTINED;
+use crate::store::ApiNode;
+
+// --- Most pubkeys one filter may list ---
+pub const MAX_FILTER_PUBKEYS: usize = 1000;
+
+// --- Longest URI pattern a filter may give ---
+const MAX_URI_PATTERN_LEN: usize = 200;
+
+/// Which changes a subscribed client receives; every given condition must hold.
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SubscriptionFilter {
+    /// Only nodes under this authority.
+    pub authority: Option<String>,
+    /// Only these nodes.
+    pub pubkeys: Option<Vec<String>>,
+    /// Only nodes whose URI matches this pattern, where `*` stands for any run of characters;
+    /// case-insensitive, e.g. `https://*.example.com/*`.
+    pub uri: Option<String>,
+    /// Only nodes whose URI is `online`, `offline` or `unknown` to the prober.
+    pub status: Option<LivenessStatus>,
+}
+
+/// A message from a `/ws` client.
+#[derive(Deserialize, ToSchema, Debug)]
+#[schema(as = SubscriptionMessage)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe {
+        #[serde(default)]
+        filter: SubscriptionFilter,
+    },
+    Unsubscribe,
+}
+
+/// A reply to a [`ClientMessage`].
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Subscribed { filter: SubscriptionFilter },
+    Unsubscribed,
+    Error { message: String },
+}
+
+/// A client's filter, with the nodes that currently match it by cluster and pubkey.
+pub struct Subscription {
+    filter: SubscriptionFilter,
+    pubkeys: Option<HashSet<String>>,
+    uri: Option<String>,
+    matching: HashSet<(Option<String>, String)>,
+}
+
+impl Subscription {
+    /// Checks `filter` and loads the visible nodes that match it now. Subscribe to the events
+    /// first, so none falls between the two.
+    pub async fn start(pool: &PgPool, filter: SubscriptionFilter) -> Result<Self, String> {
+        if let Some(authority) = &filter.authority
+            && Pubkey::from_str(authority).is_err()
+        {
+            return Err(format!("`authority` '{}' is not a valid base58 pubkey", authority));
+        }
+        if let Some(pubkeys) = &filter.pubkeys {
+            if pubkeys.len() > MAX_FILTER_PUBKEYS {
+                return Err(format!("`pubkeys` may list at most {} pubkeys", MAX_FILTER_PUBKEYS));
+            }
+            if let Some(pubkey) = pubkeys.iter().find(|pubkey| Pubkey::from_str(pubkey).is_err()) {
+                return Err(format!("`pubkeys` entry '{}' is not a valid base58 pubkey", pubkey));
+            }
+        }
+        if filter.uri.as_ref().is_some_and(|uri| uri.chars().count() > MAX_URI_PATTERN_LEN) {
+            return Err(format!("`uri` must be at most {} characters", MAX_URI_PATTERN_LEN));
+        }
+
+        let uri = filter.uri.as_ref().map(|uri| uri.to_lowercase());
+        // The same pattern for ILIKE: `*` becomes `%`, and LIKE's own wildcards are escaped.
+        let like = uri.as_ref().map(|uri| {
+            uri.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_").replace('*', "%")
+        });
+        let matching: Vec<(Option<String>, String)> = sqlx::query_as(&format!(
+            "SELECT cluster, pubkey FROM nodes WHERE ($1::text IS NULL OR authority = $1) \
+             AND ($2::text[] IS NULL OR pubkey = ANY($2)) AND ($3::text IS NULL OR uri ILIKE $3) \
+             AND {} AND deleted_at IS NULL AND {}",
+            filter.status.map_or("TRUE", LivenessStatus::condition),
+            NOT_QUARANTINED
+        ))
+        .bind(&filter.authority)
+        .bind(&filter.pubkeys)
+        .bind(&like)
+        .fetch_all(pool)
+        .await
+        .map_err(|e| format!("Failed to load the matching nodes: {}", e))?;
+
+        Ok(Self {
+            pubkeys: filter.pubkeys.as_ref().map(|pubkeys| pubkeys.iter().cloned().collect()),
+            uri,
+            matching: matching.into_iter().collect(),
+            filter,
+        })
+    }
+
+    pub fn filter(&self) -> &SubscriptionFilter {
+        &self.filter
+    }
+
+    /// Whether to send `event`, checking the node's URI status in `pool` when filtered on.
+    pub async fn matches(&mut self, pool: &PgPool, event: &NodeEvent) -> Result<bool, sqlx::Error> {
+        let node = match event {
+            NodeEvent::Added { node } | NodeEvent::Updated { node } => node,
+            NodeEvent::Removed { pubkey, cluster } => {
+                return Ok(self.matching.remove(&(cluster.clone(), pubkey.clone())));
+            }
+        };
+        let key = (node.cluster.clone(), node.pubkey.clone());
+        if self.matches_node(pool, node).await? {
+            self.matching.insert(key);
+            Ok(true)
+        } else {
+            Ok(self.matching.remove(&key))
+        }
+    }
+
+    async fn matches_node(&self, pool: &PgPool, node: &ApiNode) -> Result<bool, sqlx::Error> {
+        if self.filter.authority.as_ref().is_some_and(|authority| *authority != node.authority)
+            || self.pubkeys.as_ref().is_some_and(|pubkeys| !pubkeys.contains(&node.pubkey))
+            || self.uri.as_ref().is_some_and(|pattern| !matches_pattern(pattern, &node.uri.to_lowercase()))
+        {
+            return Ok(false);
+        }
+        let Some(status) = self.filter.status else { return Ok(true) };
+        let online: Option<bool> =
+            sqlx::query_scalar("SELECT online FROM node_liveness WHERE pubkey = $1 AND uri = $2")
+                .bind(&node.pubkey)
+                .bind(&node.uri)
+                .fetch_optional(pool)
+                .await?;
+        Ok(match status {
+            LivenessStatus::Online => online == Some(true),
+            LivenessStatus::Offline => online == Some(false),
+            LivenessStatus::Unknown => online.is_none(),
+        })
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of characters.
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else { return false };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else { return rest.is_empty() };
+    // Taking each middle part at its first occurrence leaves the most room for the rest.
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_without_a_star_match_exactly() {
+        assert!(matches_pattern("https://a.example.com", "https://a.example.com"));
+        assert!(!matches_pattern("https://a.example.com", "https://a.example.com/"));
+        assert!(!matches_pattern("https://a.example.com", "http://a.example.com"));
+        assert!(matches_pattern("", ""));
+        assert!(!matches_pattern("", "a"));
+    }
+
+    #[test]
+    fn stars_match_any_run_of_characters() {
+        assert!(matches_pattern("*.example.com", "https://a.example.com"));
+        assert!(matches_pattern("*.example.com", ".example.com"));
+        assert!(!matches_pattern("*.example.com", "https://a.example.org"));
+        assert!(matches_pattern("https://*", "https://a.example.com"));
+        assert!(matches_pattern("https://*", "https://"));
+        assert!(!matches_pattern("https://*", "http://a.example.com"));
+        assert!(matches_pattern("*", "anything"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("**", ""));
+    }
+
+    #[test]
+    fn middle_parts_leave_room_for_the_rest() {
+        assert!(matches_pattern("a*b*b", "abbb"));
+        assert!(matches_pattern("a*b*b", "abb"));
+        assert!(!matches_pattern("a*b*b", "ab"));
+        assert!(matches_pattern("a*bb*b", "abbb"));
+        assert!(!matches_pattern("a*bb*b", "abb"));
+        // The first and last parts can't share characters.
+        assert!(!matches_pattern("ab*ba", "aba"));
+        assert!(matches_pattern("*a*", "banana"));
+        assert!(!matches_pattern("a*", ""));
+        assert!(!matches_pattern("*a*", ""));
+    }
+
+    #[test]
+    fn like_wildcards_match_literally() {
+        assert!(matches_pattern("node_%", "node_%"));
+        assert!(!matches_pattern("node_%", "node1"));
+        assert!(!matches_pattern("node_%", "node_abc"));
+        assert!(matches_pattern("*100%*", "https://100%.example.com"));
+        assert!(!matches_pattern("*100%*", "https://1000.example.com"));
+        assert!(!matches_pattern("a_c", "abc"));
+    }
+}
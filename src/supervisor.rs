@@ -0,0 +1,84 @@
+//! Supervision of background tasks. A panic in a sync loop would otherwise end it for good
+//! while the API kept serving an index that no longer moves. A supervised task that panics is
+//! logged and restarted after a backoff that doubles with each panic in a row; the panic
+//! itself is reported by the panic hook (`--features sentry`). `/readyz` fails from the panic
+//! until the restarted task has stayed up for [`RECOVERED_AFTER_SECS`].
+
+use std::any::Any;
+use std::future::Future;
+
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{error, info, warn};
+
+use crate::sync::SyncHealth;
+
+// --- Delay before restarting a panicked task, doubled per panic in a row up to the max ---
+const RESTART_BACKOFF_SECS: u64 = 1;
+const MAX_RESTART_BACKOFF_SECS: u64 = 60;
+
+// --- Time a restarted task has to stay up before it counts as recovered ---
+pub const RECOVERED_AFTER_SECS: u64 = 60;
+
+/// Aborts the running attempt when the supervisor itself is aborted, e.g. on losing leadership.
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Spawns `start()` and restarts it with a fresh `start()` whenever it panics. The returned
+/// handle only finishes once an attempt returns, which the ingestion loops never do.
+pub fn supervise<F, Fut>(health: &SyncHealth, task: String, mut start: F) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let health = health.clone();
+    tokio::spawn(async move {
+        let mut restarted = false;
+        loop {
+            let started = Instant::now();
+            let mut attempt = AbortOnDrop(tokio::spawn(start()));
+            // A restarted attempt recovers once it has stayed up long enough.
+            let outcome = if restarted {
+                tokio::select! {
+                    outcome = &mut attempt.0 => outcome,
+                    _ = sleep(Duration::from_secs(RECOVERED_AFTER_SECS)) => {
+                        info!(task, "Restarted background task recovered");
+                        health.record_recovered(&task);
+                        (&mut attempt.0).await
+                    }
+                }
+            } else {
+                (&mut attempt.0).await
+            };
+            let panic = match outcome {
+                Ok(()) => {
+                    warn!(task, "Background task stopped");
+                    return;
+                }
+                Err(e) if e.is_panic() => e.into_panic(),
+                // Only the guard aborts an attempt, and it is still held.
+                Err(_) => return,
+            };
+            let message = panic_message(&*panic);
+            let panics = health.record_panic(&task, message.clone());
+            restarted = true;
+            let backoff = RESTART_BACKOFF_SECS.saturating_mul(1 << (panics - 1).min(6)).min(MAX_RESTART_BACKOFF_SECS);
+            let uptime_secs = started.elapsed().as_secs();
+            error!(task, panic = %message, panics, uptime_secs, backoff_secs = backoff, "Background task panicked");
+            sleep(Duration::from_secs(backoff)).await;
+        }
+    })
+}
+
+/// The message a panic was raised with, when it was given one.
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    match panic.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => panic.downcast_ref::<String>().cloned().unwrap_or_else(|| "Box<dyn Any>".to_string()),
+    }
+}
@@ -0,0 +1,1569 @@
+//! Ingestion: periodic getProgramAccounts reconciliation plus streamed account updates.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::RpcFilterType;
+use solana_commitment_config::CommitmentConfig;
+use solana_sdk::account::Account;
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::{PgListener, PgPool};
+use sqlx::PgConnection;
+use tokio::sync::{mpsc, oneshot, RwLock, RwLockReadGuard, Semaphore};
+use tokio::time::{interval, sleep, Duration, Instant, MissedTickBehavior};
+use tokio_stream::StreamExt;
+use tracing::{debug, info, info_span, instrument, warn, Instrument};
+use utoipa::ToSchema;
+
+use crate::cluster::{Clusters, ProgramKey};
+use crate::config;
+use crate::decode::{
+    deserialize_node_device, discriminator_filter, node_device_discriminator, node_device_filters,
+    read_discriminator, AccountContext, DecodeError, DecoderRegistry, ErasedDecoder,
+};
+use crate::events::{EventHub, NodeEvent};
+use crate::idl::{decode_account, IdlDecoder, IdlRegistry, IdlSource};
+use crate::moderation;
+use crate::pda;
+use crate::rent;
+use crate::reporting;
+use crate::rpc::SolanaRpc;
+use crate::store::{
+    changes_after, clear_decode_failures, data_hash, mark_finalized, node_event, oldest_unfinalized_slot, prune_nodes,
+    prune_statement, reconfirm_unfinalized, record_changes, record_decode_failure, record_history, update_balances,
+    upsert_accounts, upsert_node, upsert_nodes, AccountRecord, ApiNode, NodeRecord, RawAccount, NODE_CHANGES_CHANNEL,
+    NODE_COLUMNS,
+};
+use crate::AppError;
+
+// --- Default PRUNE_MAX_PERCENT: refuse to prune more than half a program's rows at once ---
+const DEFAULT_PRUNE_MAX_PERCENT: f64 = 50.0;
+
+// --- Default POLL_INTERVAL_SECS between reconciliation cycles ---
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 10;
+
+// --- Upper bound for the programSubscribe reconnect backoff ---
+pub(crate) const MAX_SUBSCRIBE_BACKOFF_SECS: u64 = 60;
+
+// --- getProgramAccounts responses buffered between the fetch and decode stages of a sync cycle ---
+const FETCH_CHANNEL_CAPACITY: usize = 2;
+
+// --- Decoded nodes buffered between the decode and write stages of a sync cycle ---
+const DECODE_CHANNEL_CAPACITY: usize = 256;
+
+// --- GPA_SHARD_BYTES: the NodeDevice scan is sharded by the bytes right after the discriminator ---
+pub(crate) const GPA_SHARD_OFFSET: usize = 8;
+const MAX_GPA_SHARD_BYTES: u8 = 2;
+
+// --- Accounts one decode worker takes at a time ---
+const DECODE_CHUNK_SIZE: usize = 1024;
+
+// --- Rows written per multi-row UNNEST upsert ---
+const UPSERT_BATCH_SIZE: usize = 1000;
+
+// --- How often an API-only process reloads sync status, and new changes while not listening ---
+const FOLLOW_INTERVAL_MILLIS: u64 = 1000;
+
+// --- Changes are still polled this often while listening, in case a notification is lost ---
+const FOLLOW_FALLBACK_SECS: u64 = 30;
+
+// --- Pause before reconnecting a lost change notification listener ---
+const LISTEN_RETRY_SECS: u64 = 5;
+
+// --- node_changes rows republished per follower poll ---
+const FOLLOW_BATCH_SIZE: i64 = 1000;
+
+// --- Resync requests queued per reconciliation loop ---
+const RESYNC_QUEUE_CAPACITY: usize = 16;
+
+// --- Pause between passes marking rows whose slot has been finalized ---
+const FINALIZE_INTERVAL_SECS: u64 = 5;
+
+// --- Most slots one getBlocks call may span; older rows are taken as settled ---
+const MAX_FINALIZED_BLOCKS_RANGE: u64 = 500_000;
+
+// --- Rows a program's cycle on a cluster reconciles, taking the program ID as $1 and the cluster as $2 ---
+// Rows without a program_id predate multi-program support and go to whichever program runs first.
+pub(crate) const OWNED_ROWS: &str = "((program_id = $1 AND cluster IS NOT DISTINCT FROM $2) OR program_id IS NULL)";
+
+// --- Default READY_MAX_SYNC_AGE_SECS: /readyz fails once a program hasn't synced for this long ---
+pub const DEFAULT_READY_MAX_SYNC_AGE_SECS: u64 = 60;
+
+/// Completion time and snapshot slot of a program's last successful cycle.
+#[derive(Clone, Copy)]
+struct SyncSuccess {
+    at: DateTime<Utc>,
+    snapshot_slot: u64,
+}
+
+/// A background task that panicked and hasn't yet stayed up long enough after its restart.
+#[derive(Clone, Debug)]
+pub struct TaskPanic {
+    pub at: DateTime<Utc>,
+    pub message: String,
+    /// Panics in a row, each followed by a restart.
+    pub panics: u32,
+}
+
+/// Tracks when each program last completed a reconciliation cycle, for `/readyz`, and how far
+/// that trails its cluster's current slot.
+#[derive(Clone)]
+pub struct SyncHealth {
+    last_success: Arc<Mutex<HashMap<ProgramKey, SyncSuccess>>>,
+    /// Latest slot of each program's cluster, as last read by the lag monitor.
+    cluster_slots: Arc<Mutex<HashMap<ProgramKey, u64>>>,
+    /// Supervised tasks recovering from a panic, by name; `/readyz` fails while any are.
+    panicked: Arc<Mutex<BTreeMap<String, TaskPanic>>>,
+    max_sync_age: chrono::Duration,
+}
+
+impl SyncHealth {
+    pub fn new(max_sync_age: Duration) -> Self {
+        Self {
+            last_success: Arc::new(Mutex::new(HashMap::new())),
+            cluster_slots: Arc::new(Mutex::new(HashMap::new())),
+            panicked: Arc::new(Mutex::new(BTreeMap::new())),
+            max_sync_age: chrono::Duration::from_std(max_sync_age).unwrap_or(chrono::Duration::MAX),
+        }
+    }
+
+    pub fn record_success(&self, program: ProgramKey, snapshot_slot: u64) {
+        self.record_snapshot(program, Utc::now(), snapshot_slot);
+    }
+
+    /// Records a cycle that completed `at`, possibly in another process.
+    pub fn record_snapshot(&self, program: ProgramKey, at: DateTime<Utc>, snapshot_slot: u64) {
+        self.last_success.lock().unwrap().insert(program, SyncSuccess { at, snapshot_slot });
+    }
+
+    pub fn last_success(&self, program: &ProgramKey) -> Option<DateTime<Utc>> {
+        self.last_success.lock().unwrap().get(program).map(|success| success.at)
+    }
+
+    /// Snapshot slot of the program's last successful cycle.
+    pub fn last_synced_slot(&self, program: &ProgramKey) -> Option<u64> {
+        self.last_success.lock().unwrap().get(program).map(|success| success.snapshot_slot)
+    }
+
+    /// Oldest snapshot slot among the programs synced so far: every program's index
+    /// reflects the chain at least as of this slot.
+    pub fn indexed_slot(&self) -> Option<u64> {
+        self.last_success.lock().unwrap().values().map(|success| success.snapshot_slot).min()
+    }
+
+    pub fn record_cluster_slot(&self, program: ProgramKey, slot: u64) {
+        self.cluster_slots.lock().unwrap().insert(program, slot);
+    }
+
+    /// Current slot of `program`'s cluster, once the lag monitor has read it.
+    pub fn cluster_slot(&self, program: &ProgramKey) -> Option<u64> {
+        self.cluster_slots.lock().unwrap().get(program).copied()
+    }
+
+    /// Slots between `program`'s cluster's current slot and its last synced snapshot.
+    pub fn lag_slots(&self, program: &ProgramKey) -> Option<u64> {
+        let synced = self.last_synced_slot(program)?;
+        Some(self.cluster_slot(program)?.saturating_sub(synced))
+    }
+
+    /// `true` once `program` has synced successfully within the configured age.
+    pub fn is_fresh(&self, program: &ProgramKey) -> bool {
+        self.last_success(program).is_some_and(|at| Utc::now() - at <= self.max_sync_age)
+    }
+
+    /// Records a panic of the supervised task `task`, returning how many it has had in a row.
+    pub fn record_panic(&self, task: &str, message: String) -> u32 {
+        let mut panicked = self.panicked.lock().unwrap();
+        let panics = panicked.get(task).map_or(0, |panic| panic.panics) + 1;
+        panicked.insert(task.to_string(), TaskPanic { at: Utc::now(), message, panics });
+        panics
+    }
+
+    /// Records that `task` has run long enough since its last restart to count as recovered.
+    pub fn record_recovered(&self, task: &str) {
+        self.panicked.lock().unwrap().remove(task);
+    }
+
+    /// Supervised tasks still recovering from a panic, by name.
+    pub fn panicked_tasks(&self) -> Vec<(String, TaskPanic)> {
+        self.panicked.lock().unwrap().iter().map(|(task, panic)| (task.clone(), panic.clone())).collect()
+    }
+}
+
+/// What one reconciliation cycle did, as returned by `POST /admin/resync`.
+#[derive(Clone, Copy, Serialize, ToSchema, Debug)]
+pub struct CycleSummary {
+    /// Slot the getProgramAccounts snapshot was served at.
+    pub snapshot_slot: u64,
+    /// NodeDevice accounts in the snapshot.
+    pub accounts: usize,
+    /// Nodes written because they were new or their data changed.
+    pub upserted: usize,
+    /// Rows deleted (or soft-deleted) because their account is gone.
+    pub pruned: usize,
+}
+
+type ResyncReply = oneshot::Sender<Result<CycleSummary, AppError>>;
+
+/// Lets the API run a cycle right away in this process's reconciliation loops instead of
+/// waiting for the poll timer.
+#[derive(Clone, Default)]
+pub struct ResyncTrigger {
+    loops: Arc<Mutex<HashMap<ProgramKey, mpsc::Sender<ResyncReply>>>>,
+}
+
+impl ResyncTrigger {
+    fn register(&self, program: ProgramKey) -> mpsc::Receiver<ResyncReply> {
+        let (sender, receiver) = mpsc::channel(RESYNC_QUEUE_CAPACITY);
+        self.loops.lock().unwrap().insert(program, sender);
+        receiver
+    }
+
+    /// Runs a full cycle for `program` as soon as its loop is idle and returns the result.
+    /// `None` when no loop for it runs here, e.g. in an API-only or standby replica.
+    pub async fn resync(&self, program: &ProgramKey) -> Option<Result<CycleSummary, AppError>> {
+        let sender = self.loops.lock().unwrap().get(program).cloned()?;
+        let (reply, response) = oneshot::channel();
+        sender.send(reply).await.ok()?;
+        // Dropped unanswered when leadership is lost mid-cycle and the loop is aborted.
+        response.await.ok()
+    }
+}
+
+/// Stops the indexer writing to the database without stopping the API, e.g. during database
+/// maintenance. Streamed updates that arrive while paused are dropped; the first
+/// reconciliation cycle after resuming brings `nodes` back in line with the chain.
+#[derive(Clone, Default)]
+pub struct IngestionPause {
+    paused: Arc<AtomicBool>,
+    /// Held shared by every write, so pausing can wait for the ones in flight.
+    writes: Arc<RwLock<()>>,
+}
+
+impl IngestionPause {
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Pauses ingestion and returns once writes already in progress have finished.
+    pub async fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        drop(self.writes.write().await);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    /// Permission to write, held until the write is done. `None` while paused.
+    pub async fn begin_write(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        let guard = self.writes.read().await;
+        (!self.is_paused()).then_some(guard)
+    }
+}
+
+/// Settings for the reconciliation cycle, read once at startup.
+pub struct SyncConfig {
+    /// getProgramAccounts filters selecting NodeDevice accounts.
+    pub filters: Vec<RpcFilterType>,
+    /// Largest share of a program's indexed rows one cycle may prune (0-100). Cycles that
+    /// would delete more are assumed to be looking at a partial RPC response and skip the prune.
+    pub prune_max_percent: f64,
+    /// Mark pruned rows with `deleted_at` instead of deleting them (`SOFT_DELETE=true`).
+    pub soft_delete: bool,
+    /// Pause between reconciliation cycles (`POLL_INTERVAL_SECS`).
+    pub poll_interval: Duration,
+    /// Commitment used for RPC reads and subscriptions (`COMMITMENT`). Defaults to `finalized`,
+    /// which never indexes accounts from a fork that later gets rolled back.
+    pub commitment: CommitmentConfig,
+    /// Where to load the Anchor IDL for generic decoding into `accounts` (`IDL_SOURCE`).
+    pub idl_source: Option<IdlSource>,
+    /// Blocking threads decoding a cycle's accounts in parallel (`DECODE_WORKERS`). Defaults
+    /// to one per CPU.
+    pub decode_workers: usize,
+    /// Split the NodeDevice scan into 256 getProgramAccounts requests per byte, selected by
+    /// the first 1 or 2 bytes after the discriminator (`GPA_SHARD_BYTES`), for programs too
+    /// large for one response. Off (0) by default.
+    pub gpa_shard_bytes: u8,
+    /// Log the adds, updates and prunes each cycle would write, worked out from reads alone,
+    /// instead of writing them (`sync-once --dry-run`).
+    pub dry_run: bool,
+    /// Seed NodeDevice addresses are derived from with the authority (`NODE_DEVICE_SEED`);
+    /// `None` leaves addresses unverified (see [`crate::pda`]).
+    pub node_device_seed: Option<Vec<u8>>,
+}
+
+impl SyncConfig {
+    pub fn from_env() -> Result<Self, AppError> {
+        let prune_max_percent = match config::var("PRUNE_MAX_PERCENT") {
+            Ok(value) => value
+                .parse::<f64>()
+                .ok()
+                .filter(|percent| (0.0..=100.0).contains(percent))
+                .ok_or_else(|| {
+                    let source = config::source("PRUNE_MAX_PERCENT");
+                    format!("Invalid {} '{}': expected a number from 0 to 100", source, value)
+                })?,
+            Err(_) => DEFAULT_PRUNE_MAX_PERCENT,
+        };
+        let soft_delete = config::var("SOFT_DELETE")
+            .map(|value| value == "true" || value == "1")
+            .unwrap_or(false);
+        let poll_interval_secs = match config::var("POLL_INTERVAL_SECS") {
+            Ok(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or_else(|| {
+                    let source = config::source("POLL_INTERVAL_SECS");
+                    format!("Invalid {} '{}': expected a positive number of seconds", source, value)
+                })?,
+            Err(_) => DEFAULT_POLL_INTERVAL_SECS,
+        };
+        let commitment = match config::var("COMMITMENT") {
+            Ok(value) => CommitmentConfig::from_str(&value).map_err(|_| {
+                let source = config::source("COMMITMENT");
+                format!("Invalid {} '{}': expected processed, confirmed or finalized", source, value)
+            })?,
+            Err(_) => CommitmentConfig::finalized(),
+        };
+        let decode_workers = match config::var("DECODE_WORKERS") {
+            Ok(value) => value
+                .parse::<usize>()
+                .ok()
+                .filter(|workers| *workers > 0)
+                .ok_or_else(|| {
+                    let source = config::source("DECODE_WORKERS");
+                    format!("Invalid {} '{}': expected a positive number of threads", source, value)
+                })?,
+            Err(_) => std::thread::available_parallelism().map_or(1, usize::from),
+        };
+        let gpa_shard_bytes = match config::var("GPA_SHARD_BYTES") {
+            Ok(value) => value
+                .parse::<u8>()
+                .ok()
+                .filter(|bytes| *bytes <= MAX_GPA_SHARD_BYTES)
+                .ok_or_else(|| {
+                    let source = config::source("GPA_SHARD_BYTES");
+                    format!("Invalid {} '{}': expected 0 to {} bytes", source, value, MAX_GPA_SHARD_BYTES)
+                })?,
+            Err(_) => 0,
+        };
+        Ok(Self {
+            filters: node_device_filters()?,
+            prune_max_percent,
+            soft_delete,
+            poll_interval: Duration::from_secs(poll_interval_secs),
+            commitment,
+            idl_source: IdlSource::from_env(),
+            decode_workers,
+            gpa_shard_bytes,
+            dry_run: false,
+            node_device_seed: pda::seed_from_env()?,
+        })
+    }
+}
+
+/// Everything the ingestion paths need to write and publish changes.
+#[derive(Clone)]
+pub struct SyncContext {
+    pub pool: PgPool,
+    pub events: EventHub,
+    pub config: Arc<SyncConfig>,
+    /// Decoders for the account types other than `NodeDevice`.
+    pub decoders: Arc<DecoderRegistry>,
+    /// Anchor IDLs loaded for generic decoding, refreshed by the reconciliation loop.
+    pub idls: IdlRegistry,
+    /// Set by `POST /admin/pause`; every ingestion path checks it before writing.
+    pub pause: IngestionPause,
+    /// Queue every change in `change_outbox` for the Kafka sink.
+    pub outbox: bool,
+    /// The cluster each program is read from, and its RPC client.
+    pub clusters: Clusters,
+}
+
+/// Decides whether deleting `stale` of `indexed` rows looks like a genuine deregistration
+/// rather than an empty or truncated RPC response.
+pub(crate) fn prune_is_safe(stale: usize, indexed: usize, on_chain: usize, max_percent: f64) -> bool {
+    if stale == 0 || max_percent >= 100.0 {
+        return true;
+    }
+    // An empty snapshot against a populated table is never trusted.
+    if on_chain == 0 {
+        return false;
+    }
+    // Small tables always allow a single removal, otherwise one deregistration could trip the guard.
+    stale == 1 || (stale as f64) <= (indexed as f64) * max_percent / 100.0
+}
+
+/// A single account change delivered by a streaming ingestion backend.
+pub struct AccountUpdate {
+    pub program: ProgramKey,
+    pub pubkey: String,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub owner: Pubkey,
+    pub rent_epoch: u64,
+    pub slot: u64,
+}
+
+/// Applies a single streamed account update (from programSubscribe or Geyser) to the
+/// database and publishes the resulting change event, if any.
+#[instrument(skip_all, fields(source = source, pubkey = %update.pubkey, slot = update.slot))]
+pub async fn apply_account_update(sync: &SyncContext, update: AccountUpdate, source: &str) -> Result<(), AppError> {
+    let tags = [
+        ("program_id", update.program.program_id.to_string()),
+        ("cluster", sync.clusters.name_of(&update.program).unwrap_or("default").to_string()),
+        ("pubkey", update.pubkey.clone()),
+        ("slot", update.slot.to_string()),
+        ("source", source.to_string()),
+    ];
+    let applied = write_account_update(sync, update).await;
+    if let Err(e) = &applied {
+        reporting::report("Failed to apply account update", e, &tags);
+    }
+    applied
+}
+
+async fn write_account_update(sync: &SyncContext, update: AccountUpdate) -> Result<(), AppError> {
+    let SyncContext { pool, events, config, decoders, idls, pause, outbox, clusters } = sync;
+    let AccountUpdate { program, pubkey, lamports, data, owner, rent_epoch, slot } = update;
+    let (program_id, cluster) = (program.program_id, clusters.name_of(&program));
+    let Some(_write) = pause.begin_write().await else {
+        debug!("Ingestion is paused; dropping account update");
+        return Ok(());
+    };
+    let mut tx = pool.begin().await?;
+
+    // Most notifications are for accounts whose bytes didn't change; skip them outright, or
+    // store the new balance when that is all that moved.
+    let hash = data_hash(&data);
+    #[derive(sqlx::FromRow)]
+    struct Stored {
+        data_hash: Option<Vec<u8>>,
+        lamports: Option<i64>,
+        rent_exempt: Option<bool>,
+    }
+    let stored = sqlx::query_as::<_, Stored>(
+        "SELECT data_hash, lamports, rent_exempt FROM nodes \
+         WHERE pubkey = $1 AND cluster IS NOT DISTINCT FROM $2 AND deleted_at IS NULL",
+    )
+    .bind(&pubkey)
+    .bind(cluster)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if !data.is_empty()
+        && lamports > 0
+        && let Some(stored) = stored
+        && stored.data_hash.as_ref() == Some(&hash)
+    {
+        if stored.lamports != Some(lamports as i64) {
+            update_balances(&mut *tx, cluster, &[(pubkey.clone(), lamports, data.len())]).await?;
+            tx.commit().await?;
+            warn_if_rent_lapsed(stored.rent_exempt, &pubkey, lamports, data.len());
+        }
+        return Ok(());
+    }
+
+    // A closed account is reported with zero lamports and no data.
+    if lamports == 0 || data.is_empty() {
+        sqlx::query("DELETE FROM accounts WHERE pubkey = $1 AND cluster IS NOT DISTINCT FROM $2")
+            .bind(&pubkey)
+            .bind(cluster)
+            .execute(&mut *tx)
+            .await?;
+        let deleted = sqlx::query_as::<_, ApiNode>(&format!(
+            "{} WHERE pubkey = $1 AND cluster IS NOT DISTINCT FROM $2 {} RETURNING {}",
+            prune_statement(config.soft_delete),
+            if config.soft_delete { "AND deleted_at IS NULL" } else { "" },
+            NODE_COLUMNS
+        ))
+        .bind(&pubkey)
+        .bind(cluster)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(previous) = deleted else {
+            tx.commit().await?;
+            return Ok(());
+        };
+        let event = NodeEvent::Removed { pubkey, cluster: previous.cluster.clone() };
+        record_history(&mut *tx, std::slice::from_ref(&event), |_| Some(&previous), slot).await?;
+        // A quarantined node already looks removed to everyone else.
+        let published = !moderation::is_quarantined(&mut *tx, &previous.pubkey).await?;
+        if published {
+            record_changes(&mut *tx, std::slice::from_ref(&event), slot, *outbox).await?;
+        }
+        tx.commit().await?;
+        info!(pubkey = %previous.pubkey, "Removed closed NodeDevice");
+        if published {
+            events.publish(event);
+        }
+        return Ok(());
+    }
+
+    let generic = idls.get(&program).and_then(|idl| decode_account(&idl, &pubkey, &data));
+    let stored_generic = generic.is_some();
+    if let Some(record) = generic {
+        upsert_accounts(&mut *tx, &program_id, cluster, std::slice::from_ref(&record), slot).await?;
+    }
+
+    // NodeDevice accounts feed `nodes` below; any other registered account type stores itself.
+    let decoded = match read_discriminator(&data) {
+        Ok(discriminator) if discriminator == node_device_discriminator() => deserialize_node_device(&data),
+        Ok(discriminator) => match decoders.find(&discriminator) {
+            Some(decoder) => {
+                let context = AccountContext { program_id: &program_id, pubkey: &pubkey, slot };
+                match store_account(decoder, &mut tx, context, &data).await? {
+                    Ok(()) => {
+                        clear_decode_failures(&mut *tx, std::slice::from_ref(&pubkey)).await?;
+                        tx.commit().await?;
+                        debug!(account = decoder.name(), "Stored account");
+                        return Ok(());
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+            // Types only the IDL knows about are fully handled by the `accounts` row above.
+            None if stored_generic => {
+                clear_decode_failures(&mut *tx, std::slice::from_ref(&pubkey)).await?;
+                tx.commit().await?;
+                debug!("Stored IDL-decoded account");
+                return Ok(());
+            }
+            None => Err(DecodeError::UnknownDiscriminator(discriminator)),
+        },
+        Err(e) => Err(e),
+    };
+    let node = match decoded {
+        Ok(node) => node,
+        Err(e) => {
+            warn!(error = %e, "Failed to decode account");
+            record_decode_failure(&mut *tx, &program_id, &pubkey, &data, &e).await?;
+            tx.commit().await?;
+            return Ok(());
+        }
+    };
+    clear_decode_failures(&mut *tx, std::slice::from_ref(&pubkey)).await?;
+
+    let previous = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE pubkey = $1 AND cluster IS NOT DISTINCT FROM $2 AND deleted_at IS NULL",
+        NODE_COLUMNS
+    ))
+    .bind(&pubkey)
+    .bind(cluster)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let seed = sync.config.node_device_seed.as_deref();
+    let api_node = ApiNode::observed(pubkey, node, &program_id, cluster, slot, seed);
+    let data_len = data.len();
+    let record = NodeRecord::new(api_node, RawAccount { data, lamports, owner, rent_epoch });
+    if !upsert_node(&mut *tx, &record).await? {
+        // A snapshot or notification from a later slot already wrote the account.
+        tx.commit().await?;
+        debug!("Skipped NodeDevice update older than the stored row");
+        return Ok(());
+    }
+    let api_node = record.node;
+    let mut event = node_event(previous.as_ref(), api_node.clone());
+    if let Some(change) = &event {
+        record_history(&mut *tx, std::slice::from_ref(change), |_| previous.as_ref(), slot).await?;
+        if moderation::is_quarantined(&mut *tx, &api_node.pubkey).await? {
+            event = None;
+        }
+    }
+    if let Some(event) = &event {
+        record_changes(&mut *tx, std::slice::from_ref(event), slot, *outbox).await?;
+    }
+    tx.commit().await?;
+    debug!("Upserted NodeDevice");
+    warn_if_rent_lapsed(previous.and_then(|node| node.rent_exempt), &api_node.pubkey, lamports, data_len);
+
+    if let Some(event) = event {
+        events.publish(event);
+    }
+    Ok(())
+}
+
+/// Warns when an account that was rent-exempt, or wasn't known to be otherwise, no longer holds
+/// enough `lamports` for its size, so its operator can top it up before it is reclaimed.
+fn warn_if_rent_lapsed(was_exempt: Option<bool>, pubkey: &str, lamports: u64, data_len: usize) {
+    if was_exempt != Some(false) && !rent::is_exempt(lamports, data_len) {
+        let minimum = rent::minimum_balance(data_len);
+        warn!(%pubkey, lamports, minimum, "NodeDevice account is no longer rent-exempt");
+    }
+}
+
+/// Decodes and stores an account of a registered type. Decode errors are returned in the inner
+/// `Result` so callers can dead-letter the account; database errors abort the cycle.
+async fn store_account(
+    decoder: &dyn ErasedDecoder,
+    conn: &mut PgConnection,
+    context: AccountContext<'_>,
+    data: &[u8],
+) -> Result<Result<(), DecodeError>, AppError> {
+    match decoder.decode_and_store(conn, context, data) {
+        Ok(store) => store.await.map(Ok),
+        Err(e) => Ok(Err(e)),
+    }
+}
+
+/// Keeps a `programSubscribe` stream open, reconnecting with backoff, and applies
+/// every account notification to the database as it arrives.
+#[instrument(skip_all, fields(program_id = %program.program_id, cluster = sync.clusters.name_of(&program)))]
+pub async fn run_program_subscription(ws_url: String, program: ProgramKey, sync: SyncContext) {
+    let mut backoff_secs = 1;
+    loop {
+        info!(%ws_url, "Connecting to programSubscribe");
+        match subscribe_program_accounts(&ws_url, &program, &sync).await {
+            Ok(()) => {
+                warn!("Subscription stream ended, reconnecting");
+                backoff_secs = 1;
+            }
+            Err(e) => warn!(error = %e, backoff_secs, "Subscription failed"),
+        }
+        sleep(Duration::from_secs(backoff_secs)).await;
+        backoff_secs = (backoff_secs * 2).min(MAX_SUBSCRIBE_BACKOFF_SECS);
+    }
+}
+
+async fn subscribe_program_accounts(
+    ws_url: &str,
+    program: &ProgramKey,
+    sync: &SyncContext,
+) -> Result<(), AppError> {
+    let program_id = &program.program_id;
+    let client = PubsubClient::new(ws_url).await?;
+    // No memcmp filter here: closure notifications carry empty data and would be filtered out.
+    let config = RpcProgramAccountsConfig {
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(sync.config.commitment),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let (mut stream, unsubscribe) = client.program_subscribe(program_id, Some(config)).await?;
+    info!("Subscribed to program accounts");
+
+    while let Some(update) = stream.next().await {
+        let account = update.value.account;
+        let update = AccountUpdate {
+            program: *program,
+            pubkey: update.value.pubkey,
+            lamports: account.lamports,
+            data: account.data.decode().unwrap_or_default(),
+            owner: account.owner.parse().unwrap_or(*program_id),
+            rent_epoch: account.rent_epoch,
+            slot: update.context.slot,
+        };
+        apply_account_update(sync, update, "Subscription").await?;
+    }
+
+    unsubscribe().await;
+    Ok(())
+}
+
+/// Output of the fetch stage of a sync cycle, one getProgramAccounts response each. The
+/// NodeDevice snapshot always comes first, so the later stages know the cycle's slot.
+enum Fetched {
+    Nodes { slot: u64, accounts: Vec<(Pubkey, Account)> },
+    /// Accounts of the type registered under `discriminator`.
+    Registered { discriminator: [u8; 8], accounts: Vec<(Pubkey, Account)> },
+    /// Accounts of a type only the IDL describes, stored in `accounts` alone.
+    IdlOnly(Vec<(Pubkey, Account)>),
+}
+
+/// Output of the decode stage of a sync cycle.
+enum Decoded {
+    /// Sent first: the snapshot's slot and how many NodeDevice accounts it holds.
+    Snapshot { slot: u64, accounts: usize },
+    /// Every pubkey of a fetched batch, decodable or not, so its `accounts` rows aren't pruned.
+    Fetched(Vec<String>),
+    Node(Box<NodeRecord>),
+    Account(AccountRecord),
+    /// An account of a registered type, which its decoder stores from the write stage.
+    Registered { discriminator: [u8; 8], pubkey: String, data: Vec<u8> },
+    Failed { pubkey: String, data: Vec<u8>, error: DecodeError },
+}
+
+/// One reconciliation cycle as a pipeline: the fetch stage's getProgramAccounts responses go
+/// to the decode stage, which spreads them over `decode_workers` blocking threads, and their
+/// records go to the write stage, each step over a bounded channel. A slow database backs the
+/// decoders up, and the decoders the fetches, while decoding overlaps with both.
+pub async fn fetch_program_accounts(
+    client: &SolanaRpc,
+    program: &ProgramKey,
+    sync: &SyncContext,
+) -> Result<CycleSummary, AppError> {
+    let program_id = &program.program_id;
+    let idl = sync.idls.get(program);
+    let (fetched_tx, fetched_rx) = mpsc::channel(FETCH_CHANNEL_CAPACITY);
+    let (decoded_tx, decoded_rx) = mpsc::channel(DECODE_CHANNEL_CAPACITY);
+    let decode = DecodeContext {
+        owner: *program_id,
+        cluster: sync.clusters.name_of(program).map(str::to_string),
+        idl: idl.clone(),
+        seed: sync.config.node_device_seed.clone(),
+    };
+    // Any stage failing drops the others, rolling back the cycle's transaction.
+    let ((), (), summary) = tokio::try_join!(
+        fetch_stage(client, program_id, sync, idl.as_deref(), fetched_tx).instrument(info_span!("fetch")),
+        decode_stage(fetched_rx, decoded_tx, Arc::new(decode), sync.config.decode_workers)
+            .instrument(info_span!("decode")),
+        write_stage(program, sync, idl.as_deref(), decoded_rx).instrument(info_span!("write")),
+    )?;
+    Ok(summary)
+}
+
+/// Fetch stage: the NodeDevice snapshot, then every registered or IDL-only account type. Each
+/// request waits for room in `fetched` first, so a backed-up pipeline holds further ones off.
+async fn fetch_stage(
+    client: &SolanaRpc,
+    program_id: &Pubkey,
+    sync: &SyncContext,
+    idl: Option<&IdlDecoder>,
+    fetched: mpsc::Sender<Fetched>,
+) -> Result<(), AppError> {
+    let SyncContext { config: sync_config, decoders, .. } = sync;
+    let account_config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(sync_config.commitment),
+        ..RpcAccountInfoConfig::default()
+    };
+    // The decode stage only goes away early when the cycle is already failing.
+    let Ok(permit) = fetched.reserve().await else { return Ok(()) };
+    let config = RpcProgramAccountsConfig {
+        filters: Some(sync_config.filters.clone()),
+        account_config: account_config.clone(),
+        ..RpcProgramAccountsConfig::default()
+    };
+    let shard_bytes = sync_config.gpa_shard_bytes;
+    let (context_slot, accounts) =
+        client.get_program_accounts_sharded(program_id, config, GPA_SHARD_OFFSET, shard_bytes).await?;
+    // Nodes that ignore withContext only let us bound the snapshot from above.
+    let slot = match context_slot {
+        Some(slot) => slot,
+        None => client.get_slot().await?,
+    };
+    info!(slot, accounts = accounts.len(), "Fetched program accounts");
+    permit.send(Fetched::Nodes { slot, accounts });
+
+    let fetch_type = |discriminator: &[u8; 8]| {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![discriminator_filter(discriminator)]),
+            account_config: account_config.clone(),
+            ..RpcProgramAccountsConfig::default()
+        };
+        client.get_program_accounts_with_context(program_id, config)
+    };
+    // Every registered account type gets its own filtered fetch.
+    for (discriminator, decoder) in decoders.iter() {
+        let Ok(permit) = fetched.reserve().await else { return Ok(()) };
+        let (_, accounts) = fetch_type(discriminator).await?;
+        debug!(account = decoder.name(), accounts = accounts.len(), "Fetched registered accounts");
+        permit.send(Fetched::Registered { discriminator: *discriminator, accounts });
+    }
+    // Account types only the IDL describes are fetched the same way, for `accounts` alone.
+    for (discriminator, name) in idl.iter().flat_map(|idl| idl.account_types()) {
+        if *discriminator == node_device_discriminator() || decoders.find(discriminator).is_some() {
+            continue;
+        }
+        let Ok(permit) = fetched.reserve().await else { return Ok(()) };
+        let (_, accounts) = fetch_type(discriminator).await?;
+        debug!(account = name, accounts = accounts.len(), "Fetched IDL accounts");
+        permit.send(Fetched::IdlOnly(accounts));
+    }
+    Ok(())
+}
+
+/// What the decode workers need besides the accounts themselves.
+struct DecodeContext {
+    owner: Pubkey,
+    cluster: Option<String>,
+    idl: Option<Arc<IdlDecoder>>,
+    seed: Option<Vec<u8>>,
+}
+
+/// How a decode worker treats the accounts of its chunk.
+#[derive(Clone, Copy)]
+enum AccountKind {
+    Node,
+    Registered([u8; 8]),
+    IdlOnly,
+}
+
+/// Decode stage: splits each fetched response into chunks of `DECODE_CHUNK_SIZE` accounts
+/// and decodes up to `workers` of them at a time on blocking threads, so Borsh and IDL
+/// decoding overlap with the fetches before them and the database round-trips after them.
+/// Workers send their records to the write stage as they go, in no particular order.
+async fn decode_stage(
+    mut fetched: mpsc::Receiver<Fetched>,
+    decoded: mpsc::Sender<Decoded>,
+    context: Arc<DecodeContext>,
+    workers: usize,
+) -> Result<(), AppError> {
+    let permits = Arc::new(Semaphore::new(workers));
+    let mut running = Vec::new();
+    let mut slot = 0;
+    while let Some(batch) = fetched.recv().await {
+        let (accounts, kind) = match batch {
+            Fetched::Nodes { slot: snapshot_slot, accounts } => {
+                slot = snapshot_slot;
+                // Ahead of every record, so the write stage knows the slot before it opens.
+                if decoded.send(Decoded::Snapshot { slot, accounts: accounts.len() }).await.is_err() {
+                    break;
+                }
+                (accounts, AccountKind::Node)
+            }
+            Fetched::Registered { discriminator, accounts } => (accounts, AccountKind::Registered(discriminator)),
+            Fetched::IdlOnly(accounts) => (accounts, AccountKind::IdlOnly),
+        };
+        if decoded.send(Decoded::Fetched(pubkeys_of(&accounts))).await.is_err() {
+            break;
+        }
+        let mut accounts = accounts.into_iter().peekable();
+        while accounts.peek().is_some() {
+            let chunk: Vec<(Pubkey, Account)> = accounts.by_ref().take(DECODE_CHUNK_SIZE).collect();
+            let permit = permits.clone().acquire_owned().await?;
+            let (decoded, context) = (decoded.clone(), context.clone());
+            let span = info_span!("decode_chunk", accounts = chunk.len());
+            running.push(tokio::task::spawn_blocking(move || {
+                // An error only means the write stage is gone, and it reports why.
+                let _ = span.in_scope(|| decode_chunk(chunk, kind, slot, &context, &decoded));
+                drop(permit);
+            }));
+        }
+    }
+    // Surfaces a worker's panic before `decoded` closes, so the write stage never takes the
+    // stream's end for a complete snapshot.
+    for worker in running {
+        worker.await?;
+    }
+    drop(decoded);
+    Ok(())
+}
+
+/// Decodes one chunk of a fetched response. Fails only once the write stage is gone.
+fn decode_chunk(
+    accounts: Vec<(Pubkey, Account)>,
+    kind: AccountKind,
+    slot: u64,
+    context: &DecodeContext,
+    decoded: &mpsc::Sender<Decoded>,
+) -> Result<(), ()> {
+    let send = |item| decoded.blocking_send(item).map_err(drop);
+    let idl = context.idl.as_deref();
+    for (pubkey, account) in accounts {
+        if let Some(record) = idl.and_then(|idl| decode_account(idl, &pubkey.to_string(), &account.data)) {
+            send(Decoded::Account(record))?;
+        }
+        match kind {
+            AccountKind::IdlOnly => {}
+            AccountKind::Registered(discriminator) => {
+                send(Decoded::Registered { discriminator, pubkey: pubkey.to_string(), data: account.data })?;
+            }
+            // The memcmp filter already selected NodeDevice accounts; decoding still
+            // rejects anything whose discriminator doesn't match instead of mis-parsing it.
+            AccountKind::Node => match deserialize_node_device(&account.data) {
+                Ok(node) => {
+                    let cluster = context.cluster.as_deref();
+                    let seed = context.seed.as_deref();
+                    let api_node = ApiNode::observed(pubkey.to_string(), node, &context.owner, cluster, slot, seed);
+                    let raw = RawAccount {
+                        data: account.data,
+                        lamports: account.lamports,
+                        owner: account.owner,
+                        rent_epoch: account.rent_epoch,
+                    };
+                    send(Decoded::Node(Box::new(NodeRecord::new(api_node, raw))))?;
+                }
+                Err(error) => {
+                    send(Decoded::Failed { pubkey: pubkey.to_string(), data: account.data, error })?;
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+fn pubkeys_of(accounts: &[(Pubkey, Account)]) -> Vec<String> {
+    accounts.iter().map(|(pubkey, _)| pubkey.to_string()).collect()
+}
+
+/// Write stage: applies the decoded snapshot in one transaction, so readers never see a
+/// half-synced table, then prunes what it didn't contain. Events are only published once
+/// it has committed.
+async fn write_stage(
+    program: &ProgramKey,
+    sync: &SyncContext,
+    idl: Option<&IdlDecoder>,
+    mut decoded: mpsc::Receiver<Decoded>,
+) -> Result<CycleSummary, AppError> {
+    let SyncContext { pool, events, config: sync_config, decoders, clusters, .. } = sync;
+    let (program_id, cluster) = (&program.program_id, clusters.name_of(program));
+    // Waiting for the snapshot before opening the transaction keeps its connection free
+    // during the main getProgramAccounts call.
+    let Some(Decoded::Snapshot { slot, accounts: account_count }) = decoded.recv().await else {
+        // The stage that stopped reports why; this just ends the cycle with it.
+        return Err("The sync pipeline stopped before the snapshot".into());
+    };
+    if sync_config.dry_run {
+        return dry_run_stage(program, sync, slot, account_count, decoded).await;
+    }
+
+    let mut tx = pool.begin().await?;
+    let mut pending_events = Vec::new();
+
+    // Unchanged accounts aren't rewritten, so rows from before the program's cluster was named
+    // (or renamed) pick it up here. A program indexed from several clusters has rows on each,
+    // so its rows can't be told apart from another cluster's and are left as they are.
+    let clusters_indexing = clusters.programs().iter().filter(|other| other.program_id == *program_id).count();
+    if clusters_indexing == 1 {
+        sqlx::query(
+            "UPDATE nodes SET cluster = $2 WHERE program_id = $1 AND cluster IS DISTINCT FROM $2 \
+             AND NOT EXISTS (SELECT 1 FROM nodes named WHERE named.pubkey = nodes.pubkey \
+                             AND named.cluster IS NOT DISTINCT FROM $2)",
+        )
+        .bind(program_id.to_string())
+        .bind(cluster)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Snapshot what we already have so we can tell adds from updates and skip no-op events.
+    let (known, known_hashes) = known_nodes(&mut tx, program_id, cluster).await?;
+
+    // Step 1: Collect all pubkeys of valid NodeDevice accounts currently on the blockchain.
+    let mut on_chain_node_pubkeys: Vec<String> = Vec::new();
+    // Everything fetched this cycle, so `accounts` rows that failed to decode aren't pruned.
+    let mut fetched_pubkeys: Vec<String> = Vec::new();
+    let mut stored_pubkeys = Vec::new();
+    let mut batch: Vec<NodeRecord> = Vec::with_capacity(UPSERT_BATCH_SIZE);
+    let mut account_batch: Vec<AccountRecord> = Vec::new();
+    let mut unfinalized = Vec::new();
+    // Accounts whose data is unchanged but whose balance moved.
+    let mut rebalanced = Vec::new();
+    let mut written: HashSet<String> = HashSet::new();
+
+    while let Some(decoded) = decoded.recv().await {
+        let record = match decoded {
+            Decoded::Node(record) => *record,
+            Decoded::Snapshot { .. } => continue,
+            Decoded::Fetched(pubkeys) => {
+                fetched_pubkeys.extend(pubkeys);
+                continue;
+            }
+            Decoded::Account(record) => {
+                account_batch.push(record);
+                if account_batch.len() >= UPSERT_BATCH_SIZE {
+                    upsert_accounts(&mut *tx, program_id, cluster, &account_batch, slot).await?;
+                    account_batch.clear();
+                }
+                continue;
+            }
+            Decoded::Registered { discriminator, pubkey, data } => {
+                let decoder = decoders.find(&discriminator).expect("fetched for a registered decoder");
+                let context = AccountContext { program_id, pubkey: &pubkey, slot };
+                match store_account(decoder, &mut tx, context, &data).await? {
+                    Ok(()) => stored_pubkeys.push(pubkey),
+                    Err(error) => {
+                        warn!(%pubkey, %error, account = decoder.name(), "Failed to decode account");
+                        record_decode_failure(&mut *tx, program_id, &pubkey, &data, &error).await?;
+                    }
+                }
+                continue;
+            }
+            Decoded::Failed { pubkey, data, error } => {
+                warn!(%pubkey, %error, "Failed to deserialize NodeDevice");
+                record_decode_failure(&mut *tx, program_id, &pubkey, &data, &error).await?;
+                continue;
+            }
+        };
+
+        on_chain_node_pubkeys.push(record.node.pubkey.clone());
+
+        let stored = known.get(&record.node.pubkey);
+        let was_exempt = stored.and_then(|node| node.rent_exempt);
+        warn_if_rent_lapsed(was_exempt, &record.node.pubkey, record.raw.lamports, record.raw.data.len());
+
+        // Accounts whose raw bytes haven't changed since the last write are left alone, apart
+        // from their balance.
+        if known_hashes.get(&record.node.pubkey) == Some(&record.data_hash) {
+            if stored.is_some_and(|node| node.lamports != record.node.lamports) {
+                rebalanced.push((record.node.pubkey.clone(), record.raw.lamports, record.raw.data.len()));
+            }
+            if stored.is_some_and(|node| !node.finalized) {
+                unfinalized.push(record.node.pubkey);
+            }
+            continue;
+        }
+        if let Some(event) = node_event(known.get(&record.node.pubkey), record.node.clone()) {
+            pending_events.push(event);
+        }
+
+        // Step 2: Upsert the account data into the database in batches. This ensures new and updated nodes are synced.
+        batch.push(record);
+        if batch.len() >= UPSERT_BATCH_SIZE {
+            debug!(batch = batch.len(), "Upserting NodeDevice batch");
+            written.extend(upsert_nodes(&mut *tx, &batch).await?);
+            batch.clear();
+        }
+    }
+    if !batch.is_empty() {
+        debug!(batch = batch.len(), "Upserting NodeDevice batch");
+        written.extend(upsert_nodes(&mut *tx, &batch).await?);
+    }
+    // Rows programSubscribe wrote from a later slot were left alone, so their events are dropped.
+    pending_events.retain(|event| written.contains(event.pubkey()));
+    let upserted = written.len();
+    if !account_batch.is_empty() {
+        upsert_accounts(&mut *tx, program_id, cluster, &account_batch, slot).await?;
+    }
+    reconfirm_unfinalized(&mut *tx, cluster, &unfinalized, slot).await?;
+    update_balances(&mut *tx, cluster, &rebalanced).await?;
+
+    clear_decode_failures(&mut *tx, &on_chain_node_pubkeys).await?;
+    clear_decode_failures(&mut *tx, &stored_pubkeys).await?;
+
+    // Step 3: Delete nodes from the database that are NOT in the on-chain list.
+    // This removes nodes that have been deregistered from the blockchain.
+    // Step 3a: Guard against an empty or partial snapshot wiping the table.
+    let stale = stale_nodes(&known, &on_chain_node_pubkeys, slot).len();
+    let prune_allowed = prune_is_safe(stale, known.len(), on_chain_node_pubkeys.len(), sync_config.prune_max_percent);
+
+    let deleted_pubkeys: Vec<String> = if prune_allowed {
+        debug!(stale, "Pruning stale nodes");
+        prune_nodes(&mut *tx, program_id, cluster, &on_chain_node_pubkeys, slot, sync_config.soft_delete).await?
+    } else {
+        warn!(
+            stale,
+            indexed = known.len(),
+            max_percent = sync_config.prune_max_percent,
+            "Refusing to prune; the RPC snapshot looks incomplete"
+        );
+        Vec::new()
+    };
+    let deleted_rows = deleted_pubkeys.len();
+
+    // `accounts` follows the same guard: a snapshot too partial to prune nodes is too partial here.
+    if idl.is_some() && prune_allowed {
+        sqlx::query("DELETE FROM accounts WHERE program_id = $1 AND cluster IS NOT DISTINCT FROM $3 AND pubkey <> ALL($2)")
+            .bind(program_id.to_string())
+            .bind(&fetched_pubkeys)
+            .bind(cluster)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    let removed = |pubkey| NodeEvent::Removed { pubkey, cluster: cluster.map(str::to_string) };
+    pending_events.extend(deleted_pubkeys.into_iter().map(removed));
+
+    if !pending_events.is_empty() {
+        record_history(&mut *tx, &pending_events, |pubkey| known.get(pubkey), slot).await?;
+        moderation::withhold_quarantined(&mut *tx, &mut pending_events).await?;
+        record_changes(&mut *tx, &pending_events, slot, sync.outbox).await?;
+    }
+
+    if deleted_rows > 0 {
+        info!(pruned = deleted_rows, "Pruned stale nodes");
+    }
+
+    // Counted after the prune, so the stats include only the nodes this cycle keeps.
+    let total_nodes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM nodes WHERE deleted_at IS NULL")
+        .fetch_one(&mut *tx)
+        .await?;
+
+    debug!(total_nodes, slot, "Updating network_stats");
+    sqlx::query(
+        r#"
+        INSERT INTO network_stats (id, total_nodes, last_synced_at, last_indexed_slot)
+        VALUES (1, $1, NOW(), $2)
+        ON CONFLICT (id) DO UPDATE
+        SET total_nodes = EXCLUDED.total_nodes,
+            last_synced_at = EXCLUDED.last_synced_at,
+            last_indexed_slot = EXCLUDED.last_indexed_slot
+        "#,
+    )
+    .bind(total_nodes)
+    .bind(slot as i64)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO network_stats_history (total_nodes, online_nodes, slot)
+        SELECT $1,
+               CASE WHEN EXISTS (SELECT 1 FROM node_liveness) THEN (
+                   SELECT COUNT(*) FROM nodes n
+                   JOIN node_liveness l ON l.pubkey = n.pubkey AND l.uri = n.uri AND l.online
+                   WHERE n.deleted_at IS NULL
+               ) END,
+               $2
+        "#,
+    )
+    .bind(total_nodes)
+    .bind(slot as i64)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO program_snapshots (program_id, cluster, snapshot_slot, account_count, synced_at)
+        VALUES ($1, $4, $2, $3, NOW())
+        ON CONFLICT (program_id, cluster) DO UPDATE
+        SET snapshot_slot = EXCLUDED.snapshot_slot,
+            account_count = EXCLUDED.account_count,
+            synced_at = EXCLUDED.synced_at
+        "#,
+    )
+    .bind(program_id.to_string())
+    .bind(slot as i64)
+    .bind(account_count as i64)
+    .bind(cluster)
+    .execute(&mut *tx)
+    .await?;
+
+    // Committed with the data, so the checkpoint never runs ahead of what was written.
+    sqlx::query(
+        r#"
+        INSERT INTO sync_state (program_id, cluster, last_synced_slot, last_synced_at)
+        VALUES ($1, $3, $2, NOW())
+        ON CONFLICT (program_id, cluster) DO UPDATE
+        SET last_synced_slot = EXCLUDED.last_synced_slot,
+            last_synced_at = EXCLUDED.last_synced_at,
+            consecutive_failures = 0,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(program_id.to_string())
+    .bind(slot as i64)
+    .bind(cluster)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    for event in pending_events {
+        events.publish(event);
+    }
+
+    Ok(CycleSummary { snapshot_slot: slot, accounts: account_count, upserted, pruned: deleted_rows })
+}
+
+/// The live rows of `program_id` on `cluster` (and rows without a program) by pubkey, with the
+/// hashes of their account data.
+async fn known_nodes(
+    conn: &mut PgConnection,
+    program_id: &Pubkey,
+    cluster: Option<&str>,
+) -> Result<(HashMap<String, ApiNode>, HashMap<String, Vec<u8>>), AppError> {
+    let known = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE {} AND deleted_at IS NULL",
+        NODE_COLUMNS, OWNED_ROWS
+    ))
+    .bind(program_id.to_string())
+    .bind(cluster)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|node| (node.pubkey.clone(), node))
+    .collect();
+    let known_hashes = sqlx::query_as::<_, (String, Vec<u8>)>(&format!(
+        "SELECT pubkey, data_hash FROM nodes WHERE {} AND data_hash IS NOT NULL AND deleted_at IS NULL",
+        OWNED_ROWS
+    ))
+    .bind(program_id.to_string())
+    .bind(cluster)
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .collect();
+    Ok((known, known_hashes))
+}
+
+/// Pubkeys of the `known` rows a snapshot at `slot` holding `on_chain` would prune: those it
+/// lacks, unless written from a later slot.
+fn stale_nodes(known: &HashMap<String, ApiNode>, on_chain: &[String], slot: u64) -> Vec<String> {
+    let on_chain: HashSet<&String> = on_chain.iter().collect();
+    known
+        .values()
+        .filter(|node| !on_chain.contains(&node.pubkey) && node.last_seen_slot.is_none_or(|seen| seen <= slot as i64))
+        .map(|node| node.pubkey.clone())
+        .collect()
+}
+
+/// Write stage of a dry run: works out the adds, updates and prunes the snapshot calls for
+/// against the stored rows, which it only reads, and logs them. Nothing is written and no
+/// lock is taken, so it can run beside a live deployment without holding up its leader.
+async fn dry_run_stage(
+    program: &ProgramKey,
+    sync: &SyncContext,
+    slot: u64,
+    account_count: usize,
+    mut decoded: mpsc::Receiver<Decoded>,
+) -> Result<CycleSummary, AppError> {
+    let cluster = sync.clusters.name_of(program);
+    let mut conn = sync.pool.acquire().await?;
+    let (known, known_hashes) = known_nodes(&mut conn, &program.program_id, cluster).await?;
+    drop(conn);
+
+    let mut events = Vec::new();
+    let mut on_chain = Vec::new();
+    let mut failed = 0;
+    while let Some(decoded) = decoded.recv().await {
+        match decoded {
+            Decoded::Node(record) => {
+                on_chain.push(record.node.pubkey.clone());
+                let stored = known.get(&record.node.pubkey);
+                // Rows written from a later slot would be left alone, as would unchanged ones.
+                let newer = stored.and_then(|node| node.last_seen_slot).is_some_and(|seen| seen > slot as i64);
+                if newer || known_hashes.get(&record.node.pubkey) == Some(&record.data_hash) {
+                    continue;
+                }
+                events.extend(node_event(stored, record.node));
+            }
+            Decoded::Failed { pubkey, error, .. } => {
+                warn!(%pubkey, %error, "Failed to deserialize NodeDevice");
+                failed += 1;
+            }
+            Decoded::Snapshot { .. } | Decoded::Fetched(_) | Decoded::Account(_) | Decoded::Registered { .. } => {}
+        }
+    }
+    let upserted = events.len();
+
+    let stale = stale_nodes(&known, &on_chain, slot);
+    let pruned = if prune_is_safe(stale.len(), known.len(), on_chain.len(), sync.config.prune_max_percent) {
+        stale.len()
+    } else {
+        let stale = stale.len();
+        warn!(stale, indexed = known.len(), "Dry run: would refuse to prune; the RPC snapshot looks incomplete");
+        0
+    };
+    if pruned > 0 {
+        let removed = |pubkey| NodeEvent::Removed { pubkey, cluster: cluster.map(str::to_string) };
+        events.extend(stale.into_iter().map(removed));
+    }
+    log_dry_run(&events, &known);
+    info!(upserted, pruned, failed, "Dry run: wrote nothing");
+    Ok(CycleSummary { snapshot_slot: slot, accounts: account_count, upserted, pruned })
+}
+
+/// Logs each change a dry-run cycle would have written, with the fields an update changes.
+fn log_dry_run(events: &[NodeEvent], known: &HashMap<String, ApiNode>) {
+    for event in events {
+        match event {
+            NodeEvent::Added { node } => {
+                info!(pubkey = %node.pubkey, authority = %node.authority, uri = %node.uri, "Dry run: would add node");
+            }
+            NodeEvent::Updated { node } => {
+                let diff = known.get(&node.pubkey).map(|old| node_diff(old, node)).unwrap_or_default();
+                info!(pubkey = %node.pubkey, diff = diff.join(", "), "Dry run: would update node");
+            }
+            NodeEvent::Removed { pubkey, .. } => info!(%pubkey, "Dry run: would prune node"),
+        }
+    }
+}
+
+/// `field: old -> new` for every on-chain field that differs between `old` and `new`.
+fn node_diff(old: &ApiNode, new: &ApiNode) -> Vec<String> {
+    fn field<T: PartialEq + std::fmt::Debug>(diff: &mut Vec<String>, name: &str, old: &T, new: &T) {
+        if old != new {
+            diff.push(format!("{}: {:?} -> {:?}", name, old, new));
+        }
+    }
+    let mut diff = Vec::new();
+    field(&mut diff, "authority", &old.authority, &new.authority);
+    field(&mut diff, "uri", &old.uri, &new.uri);
+    field(&mut diff, "program_id", &old.program_id, &new.program_id);
+    field(&mut diff, "name", &old.name, &new.name);
+    field(&mut diff, "registered_at", &old.registered_at, &new.registered_at);
+    field(&mut diff, "layout_version", &old.layout_version, &new.layout_version);
+    diff
+}
+
+/// Reconciles `program` against a full getProgramAccounts snapshot every poll interval, forever.
+/// Requests from `resync` run a cycle early; ones arriving mid-cycle get the next one. Cycles
+/// are skipped while ingestion is paused.
+pub async fn run_reconciliation(
+    client: Arc<SolanaRpc>,
+    program: ProgramKey,
+    sync: SyncContext,
+    health: SyncHealth,
+    resync: ResyncTrigger,
+) {
+    let mut requests = resync.register(program);
+    let mut reply: Option<ResyncReply> = None;
+    loop {
+        // Failures are logged by the cycle itself; the next one simply tries again.
+        let cycle = match sync.pause.begin_write().await {
+            Some(_write) => reconcile_once(&client, &program, &sync, &health).await,
+            None => {
+                debug!(program_id = %program.program_id, "Ingestion is paused; skipping reconciliation cycle");
+                Err("Ingestion is paused".into())
+            }
+        };
+        if let Some(reply) = reply.take() {
+            let _ = reply.send(cycle);
+        }
+        tokio::select! {
+            _ = sleep(sync.config.poll_interval) => {}
+            Some(request) = requests.recv() => reply = Some(request),
+        }
+    }
+}
+
+/// Marks `nodes` rows finalized once the slot their data was written from is, so
+/// `?finalized_only=true` readers never see data a fork could still roll back. Rows written
+/// from a slot that never finalizes stay unfinalized until a later snapshot writes or
+/// reconfirms them, or prunes them when the account went with the fork.
+///
+/// Covers the `program_ids` of `client`'s cluster, named `cluster`; `unowned` adds the rows
+/// from before multi-program support, which are left to the first cluster.
+pub async fn run_finalization(
+    client: Arc<SolanaRpc>,
+    program_ids: Vec<Pubkey>,
+    cluster: Option<String>,
+    unowned: bool,
+    sync: SyncContext,
+) {
+    let program_ids: Vec<String> = program_ids.iter().map(Pubkey::to_string).collect();
+    let mut ticks = interval(Duration::from_secs(FINALIZE_INTERVAL_SECS));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        if let Err(e) = finalize_once(&client, &program_ids, cluster.as_deref(), unowned, &sync).await {
+            warn!(error = %e, "Finalization pass failed");
+        }
+    }
+}
+
+async fn finalize_once(
+    client: &SolanaRpc,
+    program_ids: &[String],
+    cluster: Option<&str>,
+    unowned: bool,
+    sync: &SyncContext,
+) -> Result<(), AppError> {
+    let oldest = oldest_unfinalized_slot(&sync.pool, program_ids, cluster, unowned).await?;
+    let Some(oldest) = oldest else { return Ok(()) };
+    let finalized_slot = client.get_finalized_slot().await?;
+    let start = (oldest.max(0) as u64).max(finalized_slot.saturating_sub(MAX_FINALIZED_BLOCKS_RANGE));
+    if start > finalized_slot {
+        return Ok(());
+    }
+    let blocks: Vec<i64> =
+        client.get_finalized_blocks(start, finalized_slot).await?.into_iter().map(|slot| slot as i64).collect();
+    let Some(_write) = sync.pause.begin_write().await else { return Ok(()) };
+    let marked = mark_finalized(&sync.pool, program_ids, cluster, unowned, &blocks, start as i64).await?;
+    if marked > 0 {
+        debug!(marked, finalized_slot, "Marked nodes finalized");
+    }
+    Ok(())
+}
+
+/// One reconciliation cycle: refreshes the program's IDL, then syncs it against a full
+/// getProgramAccounts snapshot.
+pub async fn reconcile_once(
+    client: &SolanaRpc,
+    program: &ProgramKey,
+    sync: &SyncContext,
+    health: &SyncHealth,
+) -> Result<CycleSummary, AppError> {
+    let (program_id, cluster) = (&program.program_id, sync.clusters.name_of(program));
+    async move {
+        // A failed IDL refresh keeps the previous IDL; node indexing never depends on it.
+        if let Some(source) = &sync.config.idl_source
+            && let Err(e) = sync.idls.refresh(client, program, source).await
+        {
+            warn!(error = %e, "Failed to refresh Anchor IDL");
+        }
+        let started = Instant::now();
+        let cycle = fetch_program_accounts(client, program, sync)
+            .instrument(info_span!("sync_cycle"))
+            .await;
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match &cycle {
+            Ok(summary) => {
+                health.record_success(*program, summary.snapshot_slot);
+                info!(duration_ms, upserted = summary.upserted, pruned = summary.pruned, "Polling cycle complete");
+            }
+            Err(e) => {
+                warn!(duration_ms, error = %e, "Polling cycle failed");
+                let mut tags = vec![
+                    ("program_id", program_id.to_string()),
+                    ("cluster", cluster.unwrap_or("default").to_string()),
+                ];
+                if let Some(slot) = health.last_synced_slot(program) {
+                    tags.push(("last_synced_slot", slot.to_string()));
+                }
+                reporting::report("Sync cycle failed", e, &tags);
+                if !sync.config.dry_run
+                    && let Err(e) = record_sync_failure(&sync.pool, program_id, cluster, &e.to_string()).await
+                {
+                    warn!(error = %e, "Failed to record the failed cycle in sync_state");
+                }
+            }
+        }
+        cycle
+    }
+    .instrument(info_span!("reconcile", %program_id, cluster))
+    .await
+}
+
+/// Counts a failed cycle against the program's checkpoint, so monitors see it stall.
+async fn record_sync_failure(
+    pool: &PgPool,
+    program_id: &Pubkey,
+    cluster: Option<&str>,
+    error: &str,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO sync_state (program_id, cluster, consecutive_failures, last_error, last_failed_at)
+        VALUES ($1, $3, 1, $2, NOW())
+        ON CONFLICT (program_id, cluster) DO UPDATE
+        SET consecutive_failures = sync_state.consecutive_failures + 1,
+            last_error = EXCLUDED.last_error,
+            last_failed_at = EXCLUDED.last_failed_at,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(program_id.to_string())
+    .bind(error)
+    .bind(cluster)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Loads each program's `sync_state` checkpoint into `health`, so readiness and lag reflect
+/// cycles completed by earlier runs or by another process.
+pub async fn load_checkpoints(pool: &PgPool, clusters: &Clusters, health: &SyncHealth) -> Result<(), AppError> {
+    let programs = clusters.programs();
+    let program_id_strings: Vec<String> = programs.iter().map(|program| program.program_id.to_string()).collect();
+    let checkpoints: Vec<(String, Option<String>, i64, DateTime<Utc>)> = sqlx::query_as(
+        "SELECT program_id, cluster, last_synced_slot, last_synced_at FROM sync_state \
+         WHERE program_id = ANY($1) AND last_synced_at IS NOT NULL",
+    )
+    .bind(&program_id_strings)
+    .fetch_all(pool)
+    .await?;
+    for (program_id, cluster, last_synced_slot, last_synced_at) in checkpoints {
+        let program = Pubkey::from_str(&program_id).ok().and_then(|id| clusters.find(cluster.as_deref(), &id));
+        if let Some(program) = program {
+            health.record_snapshot(program, last_synced_at, last_synced_slot as u64);
+        }
+    }
+    Ok(())
+}
+
+/// Keeps an API-only process in step with the indexer writing to the same database: each
+/// program's checkpoint is loaded into `health`, and `node_changes` rows written after
+/// startup are republished on `events` for WebSocket and SSE subscribers, and so drop cached
+/// counts and responses. New rows are fetched when the writer's NOTIFY arrives; while no
+/// listener is connected, they are polled every [`FOLLOW_INTERVAL_MILLIS`] instead.
+pub async fn run_database_follower(pool: PgPool, clusters: Clusters, events: EventHub, health: SyncHealth) {
+    async move {
+        let listening = AtomicBool::new(false);
+        let (wake, mut woken) = mpsc::channel(1);
+        let follow = async {
+            let mut cursor = None;
+            let mut ticks = interval(Duration::from_millis(FOLLOW_INTERVAL_MILLIS));
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            let mut last_fetch = Instant::now();
+            loop {
+                let fetch = tokio::select! {
+                    _ = ticks.tick() => {
+                        if let Err(e) = load_checkpoints(&pool, &clusters, &health).await {
+                            warn!(error = %e, "Failed to reload sync status");
+                        }
+                        let fallback = last_fetch.elapsed() >= Duration::from_secs(FOLLOW_FALLBACK_SECS);
+                        cursor.is_none() || !listening.load(Ordering::Relaxed) || fallback
+                    }
+                    Some(()) = woken.recv() => true,
+                };
+                if fetch {
+                    last_fetch = Instant::now();
+                    if let Err(e) = republish_changes(&pool, &events, &mut cursor).await {
+                        warn!(error = %e, "Failed to follow the database");
+                    }
+                }
+            }
+        };
+        tokio::join!(listen_for_changes(&pool, &listening, wake), follow);
+    }
+    .instrument(info_span!("database_follower"))
+    .await
+}
+
+/// Wakes the follower on every [`NODE_CHANGES_CHANNEL`] notification, and once each time the
+/// listener (re)connects so rows committed while it was away are caught up. `listening` is
+/// cleared while disconnected, which makes the follower poll.
+async fn listen_for_changes(pool: &PgPool, listening: &AtomicBool, wake: mpsc::Sender<()>) {
+    let mut failure_logged = false;
+    loop {
+        let connected = async {
+            let mut listener = PgListener::connect_with(pool).await?;
+            listener.listen(NODE_CHANGES_CHANNEL).await?;
+            Ok::<_, sqlx::Error>(listener)
+        };
+        match connected.await {
+            Ok(mut listener) => {
+                info!("Listening for change notifications");
+                failure_logged = false;
+                listening.store(true, Ordering::Relaxed);
+                // A full channel already holds a wake-up, which will fetch everything new.
+                let _ = wake.try_send(());
+                loop {
+                    match listener.try_recv().await {
+                        Ok(Some(_)) => {
+                            let _ = wake.try_send(());
+                        }
+                        Ok(None) => {
+                            warn!("Lost the change notification connection; polling until it is back");
+                            break;
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "Change notification listener failed; polling until it is back");
+                            break;
+                        }
+                    }
+                }
+                listening.store(false, Ordering::Relaxed);
+            }
+            Err(e) if !failure_logged => {
+                warn!(error = %e, "Failed to listen for change notifications; polling instead");
+                failure_logged = true;
+            }
+            Err(e) => debug!(error = %e, "Failed to listen for change notifications"),
+        }
+        sleep(Duration::from_secs(LISTEN_RETRY_SECS)).await;
+    }
+}
+
+async fn republish_changes(pool: &PgPool, events: &EventHub, cursor: &mut Option<i64>) -> Result<(), AppError> {
+    // Subscribers only get changes from now on; earlier ones are served by `GET /changes`.
+    let mut after = match *cursor {
+        Some(after) => after,
+        None => sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM node_changes").fetch_one(pool).await?,
+    };
+    *cursor = Some(after);
+    loop {
+        let changes = changes_after(pool, after, FOLLOW_BATCH_SIZE).await?;
+        let complete = (changes.len() as i64) < FOLLOW_BATCH_SIZE;
+        for (id, event) in changes {
+            events.publish(event);
+            after = id;
+        }
+        *cursor = Some(after);
+        if complete {
+            return Ok(());
+        }
+    }
+}
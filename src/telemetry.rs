@@ -0,0 +1,62 @@
+//! OpenTelemetry trace export (`--features otel`). Once an OTLP endpoint is configured, every
+//! span is also exported: API requests, each reconciliation cycle with its fetch, decode and
+//! write stages, individual RPC calls and streamed account writes. Configuration follows the
+//! standard variables, read from the environment rather than the config file:
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` (or `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) turns export on,
+//! `OTEL_EXPORTER_OTLP_PROTOCOL` picks `http/protobuf` or `http/json`, and `OTEL_SERVICE_NAME`,
+//! `OTEL_RESOURCE_ATTRIBUTES`, `OTEL_TRACES_SAMPLER`, `OTEL_EXPORTER_OTLP_HEADERS` and the rest
+//! are honoured as usual. `OTEL_SDK_DISABLED=true` turns it back off.
+
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use opentelemetry_sdk::Resource;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::AppError;
+
+// --- service.name reported unless OTEL_SERVICE_NAME or OTEL_RESOURCE_ATTRIBUTES sets one ---
+const DEFAULT_SERVICE_NAME: &str = "indexer";
+
+/// The tracing layer handing spans to the exporter.
+pub type OtlpLayer<S> = OpenTelemetryLayer<S, SdkTracer>;
+
+/// Exports spans until dropped, then flushes whatever is still buffered.
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(e) = self.provider.shutdown() {
+            eprintln!("Failed to flush OpenTelemetry spans: {}", e);
+        }
+    }
+}
+
+/// The layer exporting spans over OTLP, or `None` when no endpoint is configured.
+pub fn layer<S>() -> Result<Option<(OtlpLayer<S>, Telemetry)>, AppError>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    let configured = ["OTEL_EXPORTER_OTLP_ENDPOINT", "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT"]
+        .iter()
+        .any(|name| std::env::var(name).is_ok_and(|value| !value.is_empty()));
+    let disabled = std::env::var("OTEL_SDK_DISABLED").is_ok_and(|value| value.eq_ignore_ascii_case("true"));
+    if !configured || disabled {
+        return Ok(None);
+    }
+    let exporter = SpanExporter::builder().build().map_err(|e| format!("Invalid OTLP exporter settings: {}", e))?;
+    let named = std::env::var("OTEL_SERVICE_NAME").is_ok()
+        || std::env::var("OTEL_RESOURCE_ATTRIBUTES").is_ok_and(|attributes| attributes.contains("service.name="));
+    let resource = if named {
+        Resource::builder().build()
+    } else {
+        Resource::builder().with_service_name(DEFAULT_SERVICE_NAME).build()
+    };
+    let provider = SdkTracerProvider::builder().with_batch_exporter(exporter).with_resource(resource).build();
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    Ok(Some((tracing_opentelemetry::layer().with_tracer(tracer), Telemetry { provider })))
+}
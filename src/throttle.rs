@@ -0,0 +1,331 @@
+//! Per-client request limits for the API: one token bucket per API key, or per client address
+//! (an IPv6 client's /64) for anonymous requests, so a single scraper can't starve everyone
+//! else. Addresses are limited before the API key is looked up, so failed authentications are
+//! budgeted too.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tokio::time::Instant;
+
+use crate::auth::{self, ApiKey};
+use crate::config;
+use crate::error::ApiError;
+use crate::AppError;
+
+// --- Buckets tracked at most; clients seen while it is reached share one until the next sweep ---
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+// --- How often buckets idle long enough to have refilled are forgotten ---
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Request budget per client (`API_RATE_LIMIT_RPM`, `API_RATE_LIMIT_BURST`).
+#[derive(Clone, Copy, Debug)]
+pub struct ClientRateLimit {
+    pub requests_per_minute: f64,
+    /// Requests a client may make back to back after being idle.
+    pub burst: f64,
+    /// Key anonymous clients by the last `X-Forwarded-For` address, the one the proxy in front
+    /// appended, instead of the peer address (`API_TRUST_FORWARDED_FOR`). Only safe behind a
+    /// proxy that appends it; the entries before it come from the client.
+    pub trust_forwarded_for: bool,
+}
+
+impl ClientRateLimit {
+    /// `None` when `API_RATE_LIMIT_RPM` is unset, leaving clients unthrottled.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let parse = |name: &str, value: String| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| *number > 0.0)
+                .ok_or_else(|| format!("Invalid {} '{}': expected a positive number", config::source(name), value))
+        };
+        let Ok(rpm) = config::var("API_RATE_LIMIT_RPM") else { return Ok(None) };
+        let requests_per_minute = parse("API_RATE_LIMIT_RPM", rpm)?;
+        let burst = match config::var("API_RATE_LIMIT_BURST") {
+            Ok(value) => parse("API_RATE_LIMIT_BURST", value)?,
+            Err(_) => requests_per_minute.max(1.0),
+        };
+        let trust_forwarded_for =
+            config::var("API_TRUST_FORWARDED_FOR").is_ok_and(|value| value == "true" || value == "1");
+        Ok(Some(Self { requests_per_minute, burst, trust_forwarded_for }))
+    }
+
+    fn refill_per_sec(&self) -> f64 {
+        self.requests_per_minute / 60.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Client {
+    ApiKey(i64),
+    /// Requests without a key, by [`network_of`] their address.
+    Ip(IpAddr),
+    /// Requests whose key failed authentication, by [`network_of`] their address.
+    FailedAuth(IpAddr),
+    /// Every client first seen while [`MAX_TRACKED_CLIENTS`] buckets are tracked.
+    Overflow,
+}
+
+/// One client's tokens as of `refilled_at`.
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &ClientRateLimit, now: Instant) -> Self {
+        Self { tokens: limit.burst, refilled_at: now }
+    }
+
+    /// Tokens available at `now`.
+    fn tokens_at(&self, limit: &ClientRateLimit, now: Instant) -> f64 {
+        let refilled = now.saturating_duration_since(self.refilled_at).as_secs_f64() * limit.refill_per_sec();
+        (self.tokens + refilled).min(limit.burst)
+    }
+
+    /// Seconds until a token is available at `now`; `None` if one already is.
+    fn wait_secs(&self, limit: &ClientRateLimit, now: Instant) -> Option<u64> {
+        let tokens = self.tokens_at(limit, now);
+        (tokens < 1.0).then(|| ((1.0 - tokens) / limit.refill_per_sec()).ceil() as u64)
+    }
+
+    /// Takes a token at `now`, or returns how many seconds until one is available.
+    fn take(&mut self, limit: &ClientRateLimit, now: Instant) -> Result<(), u64> {
+        self.tokens = self.tokens_at(limit, now);
+        self.refilled_at = now;
+        if let Some(wait) = self.wait_secs(limit, now) {
+            return Err(wait);
+        }
+        self.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+struct Buckets {
+    clients: HashMap<Client, Bucket>,
+    swept_at: Instant,
+}
+
+/// Token buckets for every client seen recently.
+pub struct ClientRateLimiter {
+    limit: ClientRateLimit,
+    buckets: Mutex<Buckets>,
+}
+
+impl ClientRateLimiter {
+    pub fn new(limit: ClientRateLimit) -> Self {
+        let buckets = Buckets { clients: HashMap::new(), swept_at: Instant::now() };
+        Self { limit, buckets: Mutex::new(buckets) }
+    }
+
+    /// Takes a token for `client`, or returns how many seconds until one is available.
+    fn try_acquire(&self, client: Client) -> Result<(), u64> {
+        let (limit, now) = (&self.limit, Instant::now());
+        let mut buckets = self.buckets.lock().unwrap();
+        // Full buckets are no different from untracked ones, so dropping them changes nothing.
+        if now.duration_since(buckets.swept_at) >= SWEEP_INTERVAL {
+            buckets.clients.retain(|_, bucket| bucket.tokens_at(limit, now) < limit.burst);
+            buckets.swept_at = now;
+        }
+        let client = if buckets.clients.len() >= MAX_TRACKED_CLIENTS && !buckets.clients.contains_key(&client) {
+            Client::Overflow
+        } else {
+            client
+        };
+        buckets.clients.entry(client).or_insert_with(|| Bucket::full(limit, now)).take(limit, now)
+    }
+
+    /// How many seconds until `client` has a token, without taking it.
+    fn check(&self, client: Client) -> Result<(), u64> {
+        let buckets = self.buckets.lock().unwrap();
+        match buckets.clients.get(&client).and_then(|bucket| bucket.wait_secs(&self.limit, Instant::now())) {
+            Some(wait) => Err(wait),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes a token for a request that didn't pass through [`throttle`], such as a gRPC call.
+    /// Clients are told apart as for HTTP requests.
+    pub fn acquire(
+        &self,
+        key: Option<&ApiKey>,
+        forwarded_for: Option<&str>,
+        addr: Option<SocketAddr>,
+    ) -> Result<(), u64> {
+        let client = match key {
+            Some(key) => Some(Client::ApiKey(key.id)),
+            None => self.address(forwarded_for, addr).map(Client::Ip),
+        };
+        client.map_or(Ok(()), |client| self.try_acquire(client))
+    }
+
+    /// Whether a key from this address may be looked up: not while its failed authentications
+    /// are over budget. Checked before each lookup, as [`throttle_by_address`] does.
+    pub fn check_failed_auth(&self, forwarded_for: Option<&str>, addr: Option<SocketAddr>) -> Result<(), u64> {
+        self.address(forwarded_for, addr).map_or(Ok(()), |address| self.check(Client::FailedAuth(address)))
+    }
+
+    /// Charges a failed authentication to the address it came from.
+    pub fn record_failed_auth(&self, forwarded_for: Option<&str>, addr: Option<SocketAddr>) {
+        if let Some(address) = self.address(forwarded_for, addr) {
+            let _ = self.try_acquire(Client::FailedAuth(address));
+        }
+    }
+
+    /// The [`network_of`] the client's address: the last `X-Forwarded-For` entry when trusted,
+    /// else the peer's.
+    fn address(&self, forwarded_for: Option<&str>, addr: Option<SocketAddr>) -> Option<IpAddr> {
+        let forwarded_ip = forwarded_for
+            .filter(|_| self.limit.trust_forwarded_for)
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        forwarded_ip.or(addr.map(|addr| addr.ip())).map(network_of)
+    }
+}
+
+/// What clients are told apart by: IPv4 addresses, and IPv6 ones by their /64, since a single
+/// subscriber is usually handed a whole /64 and can rotate through it at will.
+fn network_of(ip: IpAddr) -> IpAddr {
+    match ip.to_canonical() {
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from(u128::from(ip) & !u128::from(u64::MAX))),
+        ip => ip,
+    }
+}
+
+/// The request's `X-Forwarded-For` and peer address, for [`ClientRateLimiter::address`].
+fn origin_of(request: &Request) -> (Option<&str>, Option<SocketAddr>) {
+    // A proxy appends to the last of several headers.
+    let forwarded_for =
+        request.headers().get_all("x-forwarded-for").iter().next_back().and_then(|value| value.to_str().ok());
+    let addr = request.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| *addr);
+    (forwarded_for, addr)
+}
+
+fn too_many_requests(retry_after_secs: u64) -> Response {
+    let error = ApiError::new(StatusCode::TOO_MANY_REQUESTS, "rate_limited", "Too many requests; retry later");
+    let mut response = error.into_response();
+    response.headers_mut().insert("retry-after", HeaderValue::from(retry_after_secs.max(1)));
+    response
+}
+
+/// Rejects keyed requests over their key's budget with `429 Too Many Requests` and
+/// `Retry-After`. Layered inside the API key check, so keyed clients are limited by key rather
+/// than address; [`throttle_by_address`] limits the rest.
+pub async fn throttle(State(limiter): State<Arc<ClientRateLimiter>>, request: Request, next: Next) -> Response {
+    let Some(key) = request.extensions().get::<ApiKey>() else {
+        return next.run(request).await;
+    };
+    match limiter.try_acquire(Client::ApiKey(key.id)) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after_secs) => too_many_requests(retry_after_secs),
+    }
+}
+
+/// Limits requests by address ahead of the API key check, so neither anonymous requests nor
+/// guessed keys reach it over budget. Requests without a key take a token from their address's
+/// bucket. Those with one are turned away while their address's failed authentications are
+/// over budget, and each `401` draws on that.
+pub async fn throttle_by_address(
+    State(limiter): State<Arc<ClientRateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (forwarded_for, addr) = origin_of(&request);
+    // Without connection info (an embedder not serving with it) there is nothing to key on.
+    let Some(address) = limiter.address(forwarded_for, addr) else {
+        return next.run(request).await;
+    };
+    if !auth::presents_api_key(&request) {
+        return match limiter.try_acquire(Client::Ip(address)) {
+            Ok(()) => next.run(request).await,
+            Err(retry_after_secs) => too_many_requests(retry_after_secs),
+        };
+    }
+    if let Err(retry_after_secs) = limiter.check(Client::FailedAuth(address)) {
+        return too_many_requests(retry_after_secs);
+    }
+    let response = next.run(request).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        let _ = limiter.try_acquire(Client::FailedAuth(address));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(requests_per_minute: f64, burst: f64) -> ClientRateLimit {
+        ClientRateLimit { requests_per_minute, burst, trust_forwarded_for: false }
+    }
+
+    #[test]
+    fn buckets_spend_the_burst_then_refill_at_the_rate() {
+        let (limit, start) = (limit(60.0, 3.0), Instant::now());
+        let mut bucket = Bucket::full(&limit, start);
+        for _ in 0..3 {
+            assert_eq!(bucket.take(&limit, start), Ok(()));
+        }
+        assert_eq!(bucket.take(&limit, start), Err(1));
+        // Half a token is still short of one; the wait rounds up.
+        assert_eq!(bucket.take(&limit, start + Duration::from_millis(500)), Err(1));
+        assert_eq!(bucket.take(&limit, start + Duration::from_secs(1)), Ok(()));
+        assert_eq!(bucket.tokens_at(&limit, start + Duration::from_secs(3600)), 3.0);
+        // A clock reading older than the last refill adds nothing.
+        assert_eq!(bucket.wait_secs(&limit, start), Some(1));
+    }
+
+    #[test]
+    fn waits_are_whole_seconds_until_the_next_token() {
+        let (limit, start) = (limit(6.0, 1.0), Instant::now());
+        let mut bucket = Bucket::full(&limit, start);
+        assert_eq!(bucket.wait_secs(&limit, start), None);
+        assert_eq!(bucket.take(&limit, start), Ok(()));
+        assert_eq!(bucket.take(&limit, start), Err(10));
+        assert_eq!(bucket.wait_secs(&limit, start + Duration::from_millis(2500)), Some(8));
+    }
+
+    #[test]
+    fn ipv6_clients_are_keyed_by_their_64() {
+        let network = |ip: &str| network_of(ip.parse().unwrap());
+        assert_eq!(network("2001:db8:1:2:aaaa::1"), network("2001:db8:1:2:bbbb::2"));
+        assert_eq!(network("2001:db8:1:2:aaaa::1"), "2001:db8:1:2::".parse::<IpAddr>().unwrap());
+        assert_ne!(network("2001:db8:1:2::1"), network("2001:db8:1:3::1"));
+        assert_eq!(network("::ffff:192.0.2.7"), "192.0.2.7".parse::<IpAddr>().unwrap());
+        assert_ne!(network("192.0.2.7"), network("192.0.2.8"));
+    }
+
+    #[test]
+    fn forwarded_addresses_count_only_when_trusted() {
+        let peer = Some("198.51.100.1:4000".parse().unwrap());
+        let forwarded = Some("203.0.113.9, 192.0.2.7");
+        let untrusted = ClientRateLimiter::new(limit(60.0, 1.0));
+        assert_eq!(untrusted.address(forwarded, peer), Some("198.51.100.1".parse().unwrap()));
+        let trusted = ClientRateLimiter::new(ClientRateLimit { trust_forwarded_for: true, ..limit(60.0, 1.0) });
+        assert_eq!(trusted.address(forwarded, peer), Some("192.0.2.7".parse().unwrap()));
+        assert_eq!(trusted.address(Some("not an address"), peer), Some("198.51.100.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn failed_authentications_have_their_own_budget() {
+        let limiter = ClientRateLimiter::new(limit(60.0, 1.0));
+        let peer = Some("[2001:db8::1]:4000".parse().unwrap());
+        let neighbour = Some("[2001:db8::2]:4000".parse().unwrap());
+        assert_eq!(limiter.check_failed_auth(None, peer), Ok(()));
+        limiter.record_failed_auth(None, peer);
+        assert_eq!(limiter.check_failed_auth(None, neighbour), Err(1));
+        assert_eq!(limiter.acquire(None, None, neighbour), Ok(()));
+        assert_eq!(limiter.acquire(None, None, peer), Err(1));
+    }
+}
@@ -0,0 +1,249 @@
+//! Per-node transaction history: who registered, updated or closed each NodeDevice, and when.
+//!
+//! A background loop walks getSignaturesForAddress for every indexed node, fetches the new
+//! transactions and records which of the program's instructions touched the node in
+//! `node_transactions`.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::EncodedConfirmedTransactionWithStatusMeta;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use crate::anchor_events::parse_events;
+use crate::cluster::ProgramKey;
+use crate::config;
+use crate::idl::IdlDecoder;
+use crate::rpc::SolanaRpc;
+use crate::store::{record_program_events, ProgramEventRecord};
+use crate::sync::{SyncContext, OWNED_ROWS};
+use crate::AppError;
+
+// --- Default TX_HISTORY_POLL_SECS between transaction history passes ---
+const DEFAULT_TX_HISTORY_POLL_SECS: u64 = 300;
+
+// --- Signatures requested per getSignaturesForAddress page (the RPC maximum) ---
+const SIGNATURES_PAGE_LIMIT: usize = 1000;
+
+/// Settings for transaction history indexing (`INDEX_TRANSACTIONS`, `TX_HISTORY_POLL_SECS`).
+#[derive(Clone, Copy, Debug)]
+pub struct TransactionHistoryConfig {
+    /// Pause between passes over every indexed node.
+    pub poll_interval: Duration,
+}
+
+impl TransactionHistoryConfig {
+    /// `None` unless `INDEX_TRANSACTIONS=true`: each pass costs an RPC call per node plus one
+    /// per new transaction, so it is opt-in.
+    pub fn from_env() -> Result<Option<Self>, AppError> {
+        let enabled = config::var("INDEX_TRANSACTIONS").is_ok_and(|value| value == "true" || value == "1");
+        if !enabled {
+            return Ok(None);
+        }
+        let poll_interval_secs = match config::var("TX_HISTORY_POLL_SECS") {
+            Ok(value) => value.parse::<u64>().ok().filter(|secs| *secs > 0).ok_or_else(|| {
+                let source = config::source("TX_HISTORY_POLL_SECS");
+                format!("Invalid {} '{}': expected a positive number of seconds", source, value)
+            })?,
+            Err(_) => DEFAULT_TX_HISTORY_POLL_SECS,
+        };
+        Ok(Some(Self { poll_interval: Duration::from_secs(poll_interval_secs) }))
+    }
+}
+
+/// A transaction that touched a node, bound for `node_transactions`.
+struct NodeTransaction {
+    signature: String,
+    slot: u64,
+    block_time: Option<DateTime<Utc>>,
+    fee_payer: String,
+    signers: Vec<String>,
+    /// The program's top-level instructions that referenced the node: IDL names when an IDL
+    /// is loaded, otherwise the hex instruction discriminator.
+    instructions: Vec<String>,
+    error: Option<String>,
+    /// Anchor events the program emitted, which backfill `program_events`.
+    events: Vec<ProgramEventRecord>,
+}
+
+/// Periodically brings `node_transactions` up to date for every node of `program` on its cluster.
+pub async fn run_transaction_history(
+    client: Arc<SolanaRpc>,
+    program: ProgramKey,
+    sync: SyncContext,
+    config: TransactionHistoryConfig,
+) {
+    let cluster = sync.clusters.name_of(&program).map(str::to_string);
+    async move {
+        loop {
+            let started = Instant::now();
+            match index_program_transactions(&client, &program, &sync).await {
+                Ok(stored) => {
+                    let duration_ms = started.elapsed().as_millis() as u64;
+                    info!(stored, duration_ms, "Transaction history pass complete");
+                }
+                Err(e) => warn!(error = %e, "Transaction history pass failed"),
+            }
+            sleep(config.poll_interval).await;
+        }
+    }
+    .instrument(info_span!("transaction_history", program_id = %program.program_id, cluster = cluster.as_deref()))
+    .await
+}
+
+async fn index_program_transactions(
+    client: &SolanaRpc,
+    program: &ProgramKey,
+    sync: &SyncContext,
+) -> Result<usize, AppError> {
+    let program_id = &program.program_id;
+    // Soft-deleted nodes are included so their closing transaction is still picked up.
+    let pubkeys: Vec<String> = sqlx::query_scalar(&format!("SELECT pubkey FROM nodes WHERE {}", OWNED_ROWS))
+        .bind(program_id.to_string())
+        .bind(sync.clusters.name_of(program))
+        .fetch_all(&sync.pool)
+        .await?;
+    let idl = sync.idls.get(program);
+
+    let mut stored = 0;
+    for pubkey in pubkeys {
+        // Storing oldest first means stopping here leaves nothing to repair on resume.
+        let Some(_write) = sync.pause.begin_write().await else {
+            info!("Ingestion is paused; ending transaction history pass early");
+            break;
+        };
+        let node = Pubkey::from_str(&pubkey).map_err(|e| format!("Invalid node pubkey '{}': {}", pubkey, e))?;
+        // One node's failure shouldn't hold up the rest; it is retried on the next pass.
+        match index_node_transactions(client, program_id, &node, idl.as_deref(), sync).await {
+            Ok(count) => stored += count,
+            Err(e) => warn!(%pubkey, error = %e, "Failed to index node transactions"),
+        }
+    }
+    Ok(stored)
+}
+
+async fn index_node_transactions(
+    client: &SolanaRpc,
+    program_id: &Pubkey,
+    node: &Pubkey,
+    idl: Option<&IdlDecoder>,
+    sync: &SyncContext,
+) -> Result<usize, AppError> {
+    let pubkey = node.to_string();
+    let newest: Option<String> =
+        sqlx::query_scalar("SELECT signature FROM node_transactions WHERE pubkey = $1 ORDER BY slot DESC LIMIT 1")
+            .bind(&pubkey)
+            .fetch_optional(&sync.pool)
+            .await?;
+    let until = newest.as_deref().map(Signature::from_str).transpose()?;
+
+    // Page back to the newest stored signature first (or the node's first transaction)...
+    let mut signatures = Vec::new();
+    let mut before = None;
+    loop {
+        let page = client.get_signatures_for_address(node, before, until, SIGNATURES_PAGE_LIMIT).await?;
+        let complete = page.len() < SIGNATURES_PAGE_LIMIT;
+        before = page.last().map(|status| Signature::from_str(&status.signature)).transpose()?;
+        signatures.extend(page);
+        if complete || before.is_none() {
+            break;
+        }
+    }
+    if signatures.is_empty() {
+        return Ok(0);
+    }
+    debug!(%pubkey, signatures = signatures.len(), "Fetching new node transactions");
+
+    // ...then store oldest first, so a pass cut short never leaves a gap behind the newest row.
+    let mut stored = 0;
+    for status in signatures.into_iter().rev() {
+        let signature = Signature::from_str(&status.signature)?;
+        let confirmed = client.get_transaction(&signature).await?;
+        let Some(transaction) = parse_transaction(status.signature, program_id, node, idl, confirmed) else {
+            warn!(%pubkey, %signature, "Skipping transaction that could not be decoded");
+            continue;
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO node_transactions
+                (pubkey, signature, program_id, slot, block_time, fee_payer, signers, instructions, succeeded, error)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            ON CONFLICT (pubkey, signature) DO NOTHING
+            "#,
+        )
+        .bind(&pubkey)
+        .bind(&transaction.signature)
+        .bind(program_id.to_string())
+        .bind(transaction.slot as i64)
+        .bind(transaction.block_time)
+        .bind(&transaction.fee_payer)
+        .bind(&transaction.signers)
+        .bind(&transaction.instructions)
+        .bind(transaction.error.is_none())
+        .bind(&transaction.error)
+        .execute(&sync.pool)
+        .await?;
+        if !transaction.events.is_empty() {
+            record_program_events(&sync.pool, program_id, &transaction.signature, transaction.slot, &transaction.events)
+                .await?;
+        }
+        stored += 1;
+    }
+    Ok(stored)
+}
+
+/// Pulls the signers and the program instructions that referenced `node` out of a fetched
+/// transaction. `None` if the transaction couldn't be decoded.
+fn parse_transaction(
+    signature: String,
+    program_id: &Pubkey,
+    node: &Pubkey,
+    idl: Option<&IdlDecoder>,
+    confirmed: EncodedConfirmedTransactionWithStatusMeta,
+) -> Option<NodeTransaction> {
+    let EncodedConfirmedTransactionWithStatusMeta { slot, transaction, block_time } = confirmed;
+    let message = transaction.transaction.decode()?.message;
+
+    // Instructions index into the static keys followed by any address-lookup-table keys.
+    let mut keys = message.static_account_keys().to_vec();
+    if let Some(loaded) = transaction.meta.as_ref().and_then(|meta| meta.loaded_addresses.as_ref().map(Clone::clone)) {
+        let loaded = loaded.writable.iter().chain(&loaded.readonly);
+        keys.extend(loaded.filter_map(|key| Pubkey::from_str(key).ok()));
+    }
+
+    let num_signers = message.header().num_required_signatures as usize;
+    let signers: Vec<String> = keys.iter().take(num_signers).map(Pubkey::to_string).collect();
+    let instructions = message
+        .instructions()
+        .iter()
+        .filter(|instruction| keys.get(instruction.program_id_index as usize) == Some(program_id))
+        .filter(|instruction| instruction.accounts.iter().any(|index| keys.get(*index as usize) == Some(node)))
+        .map(|instruction| match idl.and_then(|idl| idl.instruction_name(&instruction.data)) {
+            Some(name) => name.to_string(),
+            None => instruction.data.iter().take(8).map(|byte| format!("{:02x}", byte)).collect(),
+        })
+        .collect();
+
+    let meta = transaction.meta;
+    let error = meta.as_ref().and_then(|meta| meta.err.as_ref()).map(|err| err.to_string());
+    // A failed transaction's events were rolled back with it.
+    let events = match meta.and_then(|meta| Option::<Vec<String>>::from(meta.log_messages)) {
+        Some(logs) if error.is_none() => parse_events(&logs, program_id, idl),
+        _ => Vec::new(),
+    };
+
+    Some(NodeTransaction {
+        signature,
+        slot,
+        block_time: block_time.and_then(|secs| DateTime::from_timestamp(secs, 0)),
+        fee_payer: signers.first()?.clone(),
+        signers,
+        instructions,
+        error,
+        events,
+    })
+}
@@ -0,0 +1,32 @@
+//! Checks on the URIs nodes register. The program accepts any string, so ingest sanitizes
+//! what Postgres can't store and flags the rest: a URI is valid when it parses as an absolute
+//! URL with a host, uses an allowed scheme, fits [`MAX_URI_LEN`] and has no control characters.
+//! Invalid URIs are still stored, with `uri_valid` false, and node lists leave them out unless
+//! `?include_invalid=true` is passed.
+
+// --- Schemes a node's URI may use ---
+pub const ALLOWED_URI_SCHEMES: &[&str] = &["http", "https"];
+
+// --- Longest valid URI, in bytes ---
+pub const MAX_URI_LEN: usize = 2048;
+
+/// The URI as stored: surrounding whitespace trimmed and NUL characters, which a `TEXT`
+/// column rejects, dropped.
+pub fn sanitize(uri: String) -> String {
+    let trimmed = uri.trim();
+    if trimmed.len() == uri.len() && !uri.contains('\0') {
+        return uri;
+    }
+    trimmed.chars().filter(|c| *c != '\0').collect()
+}
+
+/// Whether `uri` passes every check; see the module docs.
+pub fn is_valid(uri: &str) -> bool {
+    if uri.len() > MAX_URI_LEN || uri.chars().any(char::is_control) {
+        return false;
+    }
+    // The parser would quietly strip tabs and newlines, which were ruled out above.
+    reqwest::Url::parse(uri).is_ok_and(|url| {
+        ALLOWED_URI_SCHEMES.contains(&url.scheme()) && url.host_str().is_some_and(|host| !host.is_empty())
+    })
+}
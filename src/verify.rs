@@ -0,0 +1,139 @@
+//! Drift detection: compares the indexed `nodes` rows against a fresh getProgramAccounts
+//! snapshot without writing anything.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use tracing::info;
+
+use crate::cluster::ProgramKey;
+use crate::decode::deserialize_node_device;
+use crate::rpc::SolanaRpc;
+use crate::store::{ApiNode, NODE_COLUMNS};
+use crate::sync::{SyncContext, GPA_SHARD_OFFSET, OWNED_ROWS};
+use crate::AppError;
+
+/// How one program's indexed nodes differ from the chain.
+#[derive(Serialize, Debug)]
+pub struct DriftReport {
+    pub program_id: String,
+    /// Cluster the program was compared on; `None` when it is unnamed.
+    pub cluster: Option<String>,
+    /// Slot the compared getProgramAccounts snapshot was served at, when the node reported it.
+    pub snapshot_slot: Option<u64>,
+    pub on_chain: usize,
+    pub indexed: usize,
+    /// NodeDevice accounts on chain with no live row.
+    pub missing: Vec<String>,
+    /// Live rows whose account is no longer on chain.
+    pub stale: Vec<String>,
+    /// Rows whose stored fields differ from the account's current data.
+    pub mismatched: Vec<FieldMismatch>,
+    /// Accounts matching the NodeDevice filters that fail to decode.
+    pub undecodable: Vec<String>,
+}
+
+impl DriftReport {
+    pub fn has_drift(&self) -> bool {
+        !(self.missing.is_empty() && self.stale.is_empty() && self.mismatched.is_empty())
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct FieldMismatch {
+    pub pubkey: String,
+    pub field: &'static str,
+    pub indexed: String,
+    pub on_chain: String,
+}
+
+/// Fetches `program`'s NodeDevice accounts and diffs them against its live `nodes` rows on the
+/// same cluster.
+/// Rows written after the snapshot was taken can show up as drift, so a clean result needs
+/// ingestion to be quiet or stopped.
+pub async fn verify_program(
+    client: &SolanaRpc,
+    program: &ProgramKey,
+    sync: &SyncContext,
+) -> Result<DriftReport, AppError> {
+    let (program_id, cluster) = (&program.program_id, sync.clusters.name_of(program));
+    let config = RpcProgramAccountsConfig {
+        filters: Some(sync.config.filters.clone()),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(sync.config.commitment),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+    let shard_bytes = sync.config.gpa_shard_bytes;
+    let (snapshot_slot, accounts) =
+        client.get_program_accounts_sharded(program_id, config, GPA_SHARD_OFFSET, shard_bytes).await?;
+
+    let mut undecodable = Vec::new();
+    let mut on_chain = HashMap::new();
+    for (pubkey, account) in accounts {
+        match deserialize_node_device(&account.data) {
+            Ok(node) => {
+                on_chain.insert(pubkey.to_string(), node);
+            }
+            Err(_) => undecodable.push(pubkey.to_string()),
+        }
+    }
+
+    let indexed: HashMap<String, ApiNode> = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE {} AND deleted_at IS NULL",
+        NODE_COLUMNS, OWNED_ROWS
+    ))
+    .bind(program_id.to_string())
+    .bind(cluster)
+    .fetch_all(&sync.pool)
+    .await?
+    .into_iter()
+    .map(|node| (node.pubkey.clone(), node))
+    .collect();
+
+    let mut missing: Vec<String> = on_chain.keys().filter(|pubkey| !indexed.contains_key(*pubkey)).cloned().collect();
+    let mut stale: Vec<String> = indexed.keys().filter(|pubkey| !on_chain.contains_key(*pubkey)).cloned().collect();
+    let mut mismatched = Vec::new();
+    for (pubkey, node) in &on_chain {
+        let Some(row) = indexed.get(pubkey) else { continue };
+        let authority = node.authority.to_string();
+        let (indexed_name, name) = (row.name.clone().unwrap_or_default(), node.name.clone().unwrap_or_default());
+        let fields =
+            [("authority", &row.authority, &authority), ("uri", &row.uri, &node.uri), ("name", &indexed_name, &name)];
+        for (field, indexed, on_chain) in fields {
+            if indexed != on_chain {
+                let (indexed, on_chain) = (indexed.clone(), on_chain.clone());
+                mismatched.push(FieldMismatch { pubkey: pubkey.clone(), field, indexed, on_chain });
+            }
+        }
+    }
+    missing.sort();
+    stale.sort();
+    mismatched.sort_by(|a, b| (&a.pubkey, a.field).cmp(&(&b.pubkey, b.field)));
+    undecodable.sort();
+
+    let report = DriftReport {
+        program_id: program_id.to_string(),
+        cluster: cluster.map(str::to_string),
+        snapshot_slot,
+        on_chain: on_chain.len(),
+        indexed: indexed.len(),
+        missing,
+        stale,
+        mismatched,
+        undecodable,
+    };
+    info!(
+        %program_id,
+        cluster,
+        missing = report.missing.len(),
+        stale = report.stale.len(),
+        mismatched = report.mismatched.len(),
+        "Verified indexed nodes against the chain"
+    );
+    Ok(report)
+}
@@ -0,0 +1,208 @@
+//! Watchlists: pubkeys and authorities an API key client follows, so it gets the changes of
+//! those nodes without diffing the whole list. A change matches a watchlist when its node is
+//! listed by pubkey, or its authority before or after the change is listed, so a node moving
+//! to or away from a watched authority is seen both ways. Matching changes are served by
+//! `GET /watchlists/:id/changes`, streamed over `GET /watchlists/:id/ws` and, when the watchlist
+//! was created with a `webhook_url`, delivered to its webhook (see [`crate::webhooks`]).
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::postgres::{PgExecutor, PgPool};
+use utoipa::ToSchema;
+
+use crate::events::NodeEvent;
+use crate::moderation::NOT_QUARANTINED;
+use crate::store::{ApiNode, NODE_COLUMNS};
+use crate::webhooks::create_webhook;
+use crate::AppError;
+
+// --- SQL condition on a `node_changes c` row joined with its `watchlists w` row ---
+// Removals log no authority, and changes of authority only the new one, so the node's
+// authority before the change comes from its previous logged write.
+pub const MATCHES_CHANGE: &str = "(c.pubkey = ANY(w.pubkeys) OR c.authority = ANY(w.authorities) \
+    OR (SELECT p.authority FROM node_changes p WHERE p.pubkey = c.pubkey AND p.id < c.id AND p.authority IS NOT NULL \
+        ORDER BY p.id DESC LIMIT 1) = ANY(w.authorities))";
+
+/// One watchlist, as returned by the `/watchlists` routes.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Debug)]
+pub struct Watchlist {
+    pub id: i64,
+    pub name: String,
+    pub pubkeys: Vec<String>,
+    pub authorities: Vec<String>,
+    /// Webhook sent the matching changes, also listed by `GET /admin/webhooks`; `None` without one.
+    pub webhook_id: Option<i64>,
+    pub webhook_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// --- Columns selected into `Watchlist`, from `w` joined with its webhook `h` ---
+const WATCHLIST_COLUMNS: &str =
+    "w.id, w.name, w.pubkeys, w.authorities, h.id AS webhook_id, h.url AS webhook_url, w.created_at, w.updated_at";
+
+/// Creates a watchlist for API key `api_key_id`, with a webhook for `webhook_url` when set, and
+/// returns it with the webhook's signing secret.
+pub async fn create_watchlist(
+    pool: &PgPool,
+    api_key_id: i64,
+    name: &str,
+    pubkeys: &[String],
+    authorities: &[String],
+    webhook_url: Option<&str>,
+) -> Result<(Watchlist, Option<String>), AppError> {
+    let mut tx = pool.begin().await?;
+    let id: i64 = sqlx::query_scalar(
+        "INSERT INTO watchlists (api_key_id, name, pubkeys, authorities) VALUES ($1, $2, $3, $4) RETURNING id",
+    )
+    .bind(api_key_id)
+    .bind(name)
+    .bind(pubkeys)
+    .bind(authorities)
+    .fetch_one(&mut *tx)
+    .await?;
+    let secret = match webhook_url {
+        Some(url) => Some(create_webhook(&mut *tx, url, Some(id)).await?.1),
+        None => None,
+    };
+    let watchlist = get_watchlist(&mut *tx, api_key_id, id).await?.expect("the watchlist was just created");
+    tx.commit().await?;
+    Ok((watchlist, secret))
+}
+
+/// The watchlists of API key `api_key_id`, oldest first.
+pub async fn list_watchlists(executor: impl PgExecutor<'_>, api_key_id: i64) -> Result<Vec<Watchlist>, sqlx::Error> {
+    sqlx::query_as::<_, Watchlist>(&format!(
+        "SELECT {} FROM watchlists w LEFT JOIN webhooks h ON h.watchlist_id = w.id \
+         WHERE w.api_key_id = $1 ORDER BY w.id",
+        WATCHLIST_COLUMNS
+    ))
+    .bind(api_key_id)
+    .fetch_all(executor)
+    .await
+}
+
+/// Watchlist `id`, if API key `api_key_id` owns it.
+pub async fn get_watchlist(
+    executor: impl PgExecutor<'_>,
+    api_key_id: i64,
+    id: i64,
+) -> Result<Option<Watchlist>, sqlx::Error> {
+    sqlx::query_as::<_, Watchlist>(&format!(
+        "SELECT {} FROM watchlists w LEFT JOIN webhooks h ON h.watchlist_id = w.id \
+         WHERE w.id = $1 AND w.api_key_id = $2",
+        WATCHLIST_COLUMNS
+    ))
+    .bind(id)
+    .bind(api_key_id)
+    .fetch_optional(executor)
+    .await
+}
+
+/// Replaces the name and entries of watchlist `id`; `None` unless API key `api_key_id` owns it.
+pub async fn update_watchlist(
+    executor: impl PgExecutor<'_>,
+    api_key_id: i64,
+    id: i64,
+    name: &str,
+    pubkeys: &[String],
+    authorities: &[String],
+) -> Result<Option<Watchlist>, sqlx::Error> {
+    sqlx::query_as::<_, Watchlist>(&format!(
+        r#"
+        WITH w AS (
+            UPDATE watchlists SET name = $3, pubkeys = $4, authorities = $5, updated_at = NOW()
+            WHERE id = $1 AND api_key_id = $2
+            RETURNING *
+        )
+        SELECT {} FROM w LEFT JOIN webhooks h ON h.watchlist_id = w.id
+        "#,
+        WATCHLIST_COLUMNS
+    ))
+    .bind(id)
+    .bind(api_key_id)
+    .bind(name)
+    .bind(pubkeys)
+    .bind(authorities)
+    .fetch_optional(executor)
+    .await
+}
+
+/// Deletes watchlist `id` and its webhook; whether API key `api_key_id` owned it.
+pub async fn delete_watchlist(executor: impl PgExecutor<'_>, api_key_id: i64, id: i64) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query("DELETE FROM watchlists WHERE id = $1 AND api_key_id = $2")
+        .bind(id)
+        .bind(api_key_id)
+        .execute(executor)
+        .await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// The `limit`/`offset` page of the visible nodes `watchlist` lists, by pubkey, and how many
+/// there are in all.
+pub async fn watched_nodes(
+    pool: &PgPool,
+    watchlist: &Watchlist,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<ApiNode>, i64), sqlx::Error> {
+    let condition = format!(
+        "(pubkey = ANY($1) OR authority = ANY($2)) AND deleted_at IS NULL AND {}",
+        NOT_QUARANTINED
+    );
+    let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM nodes WHERE {}", condition))
+        .bind(&watchlist.pubkeys)
+        .bind(&watchlist.authorities)
+        .fetch_one(pool)
+        .await?;
+    let nodes = sqlx::query_as::<_, ApiNode>(&format!(
+        "SELECT {} FROM nodes WHERE {} ORDER BY pubkey LIMIT $3 OFFSET $4",
+        NODE_COLUMNS, condition
+    ))
+    .bind(&watchlist.pubkeys)
+    .bind(&watchlist.authorities)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    Ok((nodes, total))
+}
+
+/// Tells which live change events match a watchlist, for streaming them. Removals only carry a
+/// pubkey, so it keeps track of the nodes under a watched authority as events go by.
+pub struct WatchlistMatcher {
+    pubkeys: HashSet<String>,
+    authorities: HashSet<String>,
+    /// Nodes whose authority is currently a watched one.
+    under_authorities: HashSet<String>,
+}
+
+impl WatchlistMatcher {
+    /// Starts from the nodes currently under `watchlist`'s authorities. Subscribe to the events
+    /// first, so none falls between the two.
+    pub async fn load(executor: impl PgExecutor<'_>, watchlist: &Watchlist) -> Result<Self, sqlx::Error> {
+        let under_authorities: Vec<String> = sqlx::query_scalar("SELECT pubkey FROM nodes WHERE authority = ANY($1)")
+            .bind(&watchlist.authorities)
+            .fetch_all(executor)
+            .await?;
+        Ok(Self {
+            pubkeys: watchlist.pubkeys.iter().cloned().collect(),
+            authorities: watchlist.authorities.iter().cloned().collect(),
+            under_authorities: under_authorities.into_iter().collect(),
+        })
+    }
+
+    pub fn matches(&mut self, event: &NodeEvent) -> bool {
+        let watched_authority = match event {
+            NodeEvent::Added { node } | NodeEvent::Updated { node } if self.authorities.contains(&node.authority) => {
+                self.under_authorities.insert(node.pubkey.clone());
+                true
+            }
+            // The node was under a watched authority until this change, if it is tracked.
+            _ => self.under_authorities.remove(event.pubkey()),
+        };
+        watched_authority || self.pubkeys.contains(event.pubkey())
+    }
+}
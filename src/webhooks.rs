@@ -0,0 +1,297 @@
+//! Webhook notifications: every registered URL receives a signed `POST` for each node change,
+//! in `node_changes` order, with the same JSON body as a `/ws` message. A watchlist's webhook
+//! only receives the changes its entries match (see [`crate::watchlists`]).
+//!
+//! Each request carries `X-Webhook-Id` (the change cursor), `X-Webhook-Timestamp` (Unix
+//! seconds) and `X-Webhook-Signature: sha256=<hex>`, the HMAC-SHA256 of `<timestamp>.<body>`
+//! under the webhook's secret. A delivery succeeds on any 2xx response; otherwise it is
+//! retried with exponential backoff, holding back later changes for that webhook, until it is
+//! given up on after [`WEBHOOK_MAX_ATTEMPTS`].
+//!
+//! API key clients can attach a webhook to their watchlists, so endpoints are only reached at
+//! public addresses, like node URIs (see [`crate::dns`]).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use sqlx::postgres::{PgExecutor, PgPool};
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time::{sleep, Duration};
+use tracing::{debug, info_span, warn, Instrument};
+use utoipa::ToSchema;
+
+use crate::dns::{self, DnsCache};
+use crate::events::SequencedEvent;
+use crate::liveness::describe_request_error;
+use crate::store::{changes_after, watched_changes_after};
+use crate::sync::{IngestionPause, SyncContext};
+use crate::AppError;
+
+// --- Pause between checks for webhooks with changes to deliver ---
+const WEBHOOK_POLL_INTERVAL_MILLIS: u64 = 1000;
+
+// --- Changes sent to one webhook per round ---
+const WEBHOOK_BATCH_SIZE: i64 = 100;
+
+// --- Time a webhook endpoint gets to respond ---
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
+// --- How long the webhook client caches an endpoint's resolved addresses ---
+const WEBHOOK_DNS_TTL_SECS: u64 = 300;
+
+// --- Retry backoff after a failed delivery: doubles per attempt, capped ---
+const WEBHOOK_RETRY_BASE_SECS: i64 = 2;
+const WEBHOOK_RETRY_MAX_SECS: i64 = 3600;
+
+// --- Attempts per change before it is skipped (about 34 minutes of retries) ---
+pub const WEBHOOK_MAX_ATTEMPTS: i32 = 10;
+
+// --- Prefix of generated signing secrets ---
+const WEBHOOK_SECRET_PREFIX: &str = "whsec_";
+
+/// One row of `webhooks`, as listed by `GET /admin/webhooks`. The secret is only returned
+/// when the webhook is created.
+#[derive(Serialize, ToSchema, sqlx::FromRow, Debug)]
+#[schema(as = Webhook)]
+pub struct WebhookRecord {
+    pub id: i64,
+    pub url: String,
+    /// Watchlist whose changes are the only ones delivered; `None` for every change.
+    pub watchlist_id: Option<i64>,
+    /// Last change delivered (or given up on); compare with `GET /changes` to see the backlog.
+    pub cursor: i64,
+    /// Failed attempts at delivering the change after `cursor`.
+    pub failures: i32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub last_delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+// --- Columns selected into `WebhookRecord` ---
+pub const WEBHOOK_COLUMNS: &str =
+    "id, url, watchlist_id, cursor, failures, next_attempt_at, last_error, last_delivered_at, created_at";
+
+/// Registers `url`, for the changes `watchlist_id` matches when set, and returns its row with
+/// the generated signing secret. Only changes made from now on are delivered.
+pub async fn create_webhook(
+    executor: impl PgExecutor<'_>,
+    url: &str,
+    watchlist_id: Option<i64>,
+) -> Result<(WebhookRecord, String), AppError> {
+    let secret: [u8; 32] = rand::random();
+    let secret = format!(
+        "{}{}",
+        WEBHOOK_SECRET_PREFIX,
+        secret.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()
+    );
+    let record = sqlx::query_as::<_, WebhookRecord>(&format!(
+        "INSERT INTO webhooks (url, secret, watchlist_id, cursor) \
+         VALUES ($1, $2, $3, (SELECT COALESCE(MAX(id), 0) FROM node_changes)) RETURNING {}",
+        WEBHOOK_COLUMNS
+    ))
+    .bind(url)
+    .bind(&secret)
+    .bind(watchlist_id)
+    .fetch_one(executor)
+    .await?;
+    Ok((record, secret))
+}
+
+/// `sha256=<hex>` signature of `body` sent at `timestamp`, as put in `X-Webhook-Signature`.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    let signature = mac.finalize().into_bytes();
+    format!("sha256={}", signature.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+#[derive(sqlx::FromRow)]
+struct DueWebhook {
+    id: i64,
+    url: String,
+    secret: String,
+    watchlist_id: Option<i64>,
+    cursor: i64,
+    failures: i32,
+}
+
+/// Delivers new changes to every webhook that is due, forever.
+pub async fn run_webhook_delivery(sync: SyncContext) {
+    // Connects only to public addresses, wherever an endpoint's hostname resolves by send time.
+    let client = reqwest::Client::builder()
+        .dns_resolver(Arc::new(DnsCache::new(Duration::from_secs(WEBHOOK_DNS_TTL_SECS))))
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+        // A redirect could point the signed payload anywhere; the registered URL must answer.
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!("indexer/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .expect("Failed to build the webhook HTTP client");
+    async move {
+        // Rounds outlive the poll that started them, so a slow endpoint only holds up its own
+        // webhook; the others are picked up again on the next poll.
+        let mut deliveries = JoinSet::new();
+        let mut in_flight = HashMap::new();
+        loop {
+            while let Some(finished) = deliveries.try_join_next() {
+                if let Err(e) = finished {
+                    warn!(error = %e, "Webhook delivery task failed");
+                }
+            }
+            in_flight.retain(|_, round: &mut AbortHandle| !round.is_finished());
+            if let Err(e) = deliver_due(&client, &sync.pool, &sync.pause, &mut deliveries, &mut in_flight).await {
+                warn!(error = %e, "Failed to deliver webhooks");
+            }
+            sleep(Duration::from_millis(WEBHOOK_POLL_INTERVAL_MILLIS)).await;
+        }
+    }
+    .instrument(info_span!("webhooks"))
+    .await
+}
+
+/// Starts a delivery round for every due webhook that isn't in one already, tracking it in
+/// `in_flight` by webhook id.
+async fn deliver_due(
+    client: &reqwest::Client,
+    pool: &PgPool,
+    pause: &IngestionPause,
+    deliveries: &mut JoinSet<()>,
+    in_flight: &mut HashMap<i64, AbortHandle>,
+) -> Result<(), AppError> {
+    let busy: Vec<i64> = in_flight.keys().copied().collect();
+    let due = sqlx::query_as::<_, DueWebhook>(
+        "SELECT id, url, secret, watchlist_id, cursor, failures FROM webhooks \
+         WHERE next_attempt_at <= NOW() AND cursor < (SELECT COALESCE(MAX(id), 0) FROM node_changes) \
+           AND id <> ALL($1)",
+    )
+    .bind(&busy)
+    .fetch_all(pool)
+    .await?;
+    for webhook in due {
+        let (client, pool, pause) = (client.clone(), pool.clone(), pause.clone());
+        let id = webhook.id;
+        let round = deliveries.spawn(async move {
+            if let Err(e) = deliver_webhook(&client, &pool, &pause, webhook).await {
+                warn!(webhook = id, error = %e, "Webhook delivery round failed");
+            }
+        });
+        in_flight.insert(id, round);
+    }
+    Ok(())
+}
+
+/// Sends `webhook` the changes after its cursor in order, stopping at the first failure. A
+/// watchlist's webhook then moves its cursor past the changes that didn't match.
+async fn deliver_webhook(
+    client: &reqwest::Client,
+    pool: &PgPool,
+    pause: &IngestionPause,
+    webhook: DueWebhook,
+) -> Result<(), AppError> {
+    let mut failures = webhook.failures;
+    // Endpoints registered before URLs were checked could name a private address outright;
+    // their deliveries fail and back off like any other.
+    let url = dns::parse_delivery_url(&webhook.url);
+    let (changes, scanned) = match webhook.watchlist_id {
+        Some(watchlist_id) => {
+            let (changes, scanned) =
+                watched_changes_after(pool, watchlist_id, webhook.cursor, WEBHOOK_BATCH_SIZE).await?;
+            (changes, Some(scanned))
+        }
+        None => (changes_after(pool, webhook.cursor, WEBHOOK_BATCH_SIZE).await?, None),
+    };
+    for (change_id, event) in changes {
+        let Some(_write) = pause.begin_write().await else { return Ok(()) };
+        let body = serde_json::to_vec(&SequencedEvent { id: change_id as u64, event })?;
+        let timestamp = Utc::now().timestamp();
+        let error = match &url {
+            Ok(url) => {
+                let response = client
+                    .post(url.clone())
+                    .header("content-type", "application/json")
+                    .header("x-webhook-id", change_id)
+                    .header("x-webhook-timestamp", timestamp)
+                    .header("x-webhook-signature", sign_payload(&webhook.secret, timestamp, &body))
+                    .body(body)
+                    .send()
+                    .await;
+                match response {
+                    Ok(response) if response.status().is_success() => None,
+                    Ok(response) => Some(format!("HTTP {}", response.status())),
+                    Err(e) => Some(describe_request_error(&e)),
+                }
+            }
+            Err(e) => Some(e.clone()),
+        };
+
+        let Some(error) = error else {
+            debug!(webhook = webhook.id, change_id, "Delivered webhook");
+            sqlx::query(
+                "UPDATE webhooks SET cursor = $2, failures = 0, last_delivered_at = NOW(), last_error = NULL \
+                 WHERE id = $1",
+            )
+            .bind(webhook.id)
+            .bind(change_id)
+            .execute(pool)
+            .await?;
+            failures = 0;
+            continue;
+        };
+
+        failures += 1;
+        if failures >= WEBHOOK_MAX_ATTEMPTS {
+            warn!(webhook = webhook.id, change_id, %error, "Giving up on webhook delivery");
+            sqlx::query("UPDATE webhooks SET cursor = $2, failures = 0, last_error = $3 WHERE id = $1")
+                .bind(webhook.id)
+                .bind(change_id)
+                .bind(&error)
+                .execute(pool)
+                .await?;
+            failures = 0;
+            continue;
+        }
+        let backoff_secs = (WEBHOOK_RETRY_BASE_SECS << (failures - 1).min(20)).min(WEBHOOK_RETRY_MAX_SECS);
+        warn!(webhook = webhook.id, change_id, %error, failures, backoff_secs, "Webhook delivery failed");
+        sqlx::query(
+            "UPDATE webhooks SET failures = $2, last_error = $3, \
+             next_attempt_at = NOW() + make_interval(secs => $4) WHERE id = $1",
+        )
+        .bind(webhook.id)
+        .bind(failures)
+        .bind(&error)
+        .bind(backoff_secs as f64)
+        .execute(pool)
+        .await?;
+        return Ok(());
+    }
+    if let Some(scanned) = scanned {
+        sqlx::query("UPDATE webhooks SET cursor = $2 WHERE id = $1 AND cursor < $2")
+            .bind(webhook.id)
+            .bind(scanned)
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signatures_cover_the_timestamp_and_body() {
+        let body = br#"{"event":"node.updated"}"#;
+        assert_eq!(
+            sign_payload("whsec_test", 1_700_000_000, body),
+            "sha256=c1f7671a66c6595d98c5d7fd30aa11f16177c44eb81ca5a5b0c60979fafc70e9"
+        );
+        assert_ne!(sign_payload("whsec_test", 1_700_000_001, body), sign_payload("whsec_test", 1_700_000_000, body));
+        assert_ne!(sign_payload("whsec_other", 1_700_000_000, body), sign_payload("whsec_test", 1_700_000_000, body));
+    }
+}
@@ -0,0 +1,85 @@
+//! Runs sync cycles against the replayed RPC in `fixtures/sync_cycles.json` and checks the rows
+//! they leave in Postgres. Needs a server to create a scratch database on, named by
+//! `DATABASE_URL`; the test is skipped when it is unset.
+
+use std::path::Path;
+use std::str::FromStr;
+
+use indexer::fixtures::RpcFixtures;
+use indexer::sync::SyncConfig;
+use indexer::{AppError, IndexerBuilder, IngestionBackend};
+use solana_sdk::pubkey::Pubkey;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{ConnectOptions, Connection, PgPool};
+
+const PROGRAM_ID: &str = "5LzZhK83HbsJPTC877hRcfCZLg1cZvqDUQgLL3BxLYb4";
+// Nodes of the fixture: A and B are V1 accounts, C and D V2 ones. The second snapshot changes
+// A's URI, closes B and opens D.
+const NODE_A: &str = "4vJ9JU1bJJE96FWSJKvHsmmFADCg4gpZQff4P3bkLKi";
+const NODE_B: &str = "8qbHbw2BbbTHBW1sbeqakYXVKRQM8Ne7pLK7m6CVfeR";
+const NODE_C: &str = "CktRuQ2mttgRGkXJtyksdKHjUdc2C4TgDzyB98oEzy8";
+const NODE_D: &str = "GgBaCs3NCBuZN12kCJgAW63ydqohFkHEdfdEXBPzLHq";
+
+#[tokio::test]
+async fn sync_once_upserts_and_prunes_fixture_nodes() -> Result<(), AppError> {
+    let Ok(database_url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is unset; skipping");
+        return Ok(());
+    };
+    let server = PgConnectOptions::from_str(&database_url)?;
+    let database = format!("indexer_sync_test_{}", std::process::id());
+    let mut admin = server.connect().await?;
+    sqlx::query(&format!("DROP DATABASE IF EXISTS {}", database)).execute(&mut admin).await?;
+    sqlx::query(&format!("CREATE DATABASE {}", database)).execute(&mut admin).await?;
+
+    let pool = PgPoolOptions::new().max_connections(4).connect_with(server.database(&database)).await?;
+    let result = run_cycles(&pool).await;
+    pool.close().await;
+    sqlx::query(&format!("DROP DATABASE {} WITH (FORCE)", database)).execute(&mut admin).await?;
+    admin.close().await?;
+    result
+}
+
+async fn run_cycles(pool: &PgPool) -> Result<(), AppError> {
+    let fixtures = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/sync_cycles.json");
+    let indexer = IndexerBuilder::new()
+        .pool(pool.clone())
+        .rpc_fixtures(RpcFixtures::load(&fixtures)?)
+        .program_id(Pubkey::from_str(PROGRAM_ID)?)
+        .backend(IngestionBackend::PollOnly)
+        .sync_config(SyncConfig { soft_delete: false, ..SyncConfig::from_env()? })
+        .leader_election(false)
+        .build()
+        .await?;
+
+    indexer.sync_once().await?;
+    assert_eq!(
+        nodes(pool).await?,
+        vec![
+            (NODE_A.to_string(), "https://alpha.example.com".to_string(), 1),
+            (NODE_B.to_string(), "https://bravo.example.com".to_string(), 1),
+            (NODE_C.to_string(), "https://charlie.example.com".to_string(), 2),
+        ]
+    );
+
+    indexer.sync_once().await?;
+    assert_eq!(
+        nodes(pool).await?,
+        vec![
+            (NODE_A.to_string(), "https://alpha-2.example.com".to_string(), 1),
+            (NODE_C.to_string(), "https://charlie.example.com".to_string(), 2),
+            (NODE_D.to_string(), "https://delta.example.com".to_string(), 2),
+        ]
+    );
+    let name: Option<String> = sqlx::query_scalar("SELECT name FROM nodes WHERE pubkey = $1")
+        .bind(NODE_D)
+        .fetch_one(pool)
+        .await?;
+    assert_eq!(name.as_deref(), Some("delta"));
+    Ok(())
+}
+
+/// Pubkey, URI and layout version of every stored node, by pubkey.
+async fn nodes(pool: &PgPool) -> Result<Vec<(String, String, i16)>, AppError> {
+    Ok(sqlx::query_as("SELECT pubkey, uri, layout_version FROM nodes ORDER BY pubkey").fetch_all(pool).await?)
+}